@@ -1,5 +1,16 @@
 
+// The kernels read and write the field elements `Fr` in the memory
+// representation of the host field, so `Fr` must have the same size, be made
+// of the same little-endian 64-bit limbs in Montgomery form, and provide
+// `+`, `-`, `*`, a zero default constructor and a static
+// `__device__ bool has_modulus(const ulong *limbs)` reading its
+// `sizeof(Fr) / 8` limbs. `field.h` defines it for 4 limbs; a header defining
+// it for another field is included instead with `-DFIELD_HEADER='"path.h"'`.
+#if defined(FIELD_HEADER)
+#include FIELD_HEADER
+#else
 #include "field.h"
+#endif
 
 #define DEC_IDX                                                  \
     int blk_i = (blockIdx.z * gridDim.y * gridDim.x) +           \
@@ -17,8 +28,194 @@ typedef struct combined_t
     ulong r_next;
     ulong r_prev;
 } combined_t;
-static_assert(sizeof(combined_t) == 80);
+static_assert(sizeof(combined_t) == 2 * sizeof(Fr) + 16);
 
+// Writes to `result` whether `Fr` is the host field, of `field_bytes` bytes
+// and of modulus `modulus`, so that a module compiled for another field is
+// not used.
+extern "C" __global__ void check_field(
+    int *result,
+    const ulong *modulus,
+    const int field_bytes)
+{
+
+    DEC_IDX
+
+    if (thd_i == 0)
+    {
+        result[0] = sizeof(Fr) == field_bytes && Fr::has_modulus(modulus);
+    }
+}
+
+// The table value of the row `row` of the lookup of the `m` input and table
+// expressions at the columns `first..first + m` of `input_columns` and
+// `table_columns`, as `compute_lookup_table_values` computes it.
+__device__ Fr compress_lookup_row(
+    const Fr *input_columns,
+    const Fr *table_columns,
+    const Fr *theta_powers,
+    const Fr beta,
+    const Fr gamma,
+    const ulong first,
+    const ulong m,
+    const int row,
+    const int array_size)
+{
+    Fr input = beta;
+    Fr table = gamma;
+    for (ulong j = 0; j < m; j++)
+    {
+        const Fr power = theta_powers[m - 1 - j];
+        const ulong column = (first + j) * array_size + row;
+        input = input + power * input_columns[column];
+        table = table + power * table_columns[column];
+    }
+    return input * table;
+}
+
+// Writes the `table_value` of the `array_size` rows of each of the
+// `lookup_count` lookups of `combined_data_in` whose expressions are given:
+//   (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
+//   (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
+// for its `m` input expressions `a_j` and table expressions `s_j`, at the
+// columns `column_offsets[n]..column_offsets[n + 1]` of `input_columns` and
+// `table_columns`. `theta_powers` holds the powers of theta from 1 up to
+// `theta^{max_column_count - 1}`. The lookups without columns keep the value
+// computed on the host.
+extern "C" __global__ void compute_lookup_table_values(
+    combined_t *combined_data_in,
+    const Fr *input_columns,
+    const Fr *table_columns,
+    const ulong *column_offsets,
+    const Fr *theta_powers,
+    const Fr *y_beta_gamma_one,
+    const int lookup_count,
+    const int max_column_count,
+    const int array_size)
+{
+
+    DEC_IDX
+
+    const int stride = gridDim.x * gridDim.y * gridDim.z *
+                       blockDim.x * blockDim.y * blockDim.z;
+
+    const Fr beta = y_beta_gamma_one[1];
+    const Fr gamma = y_beta_gamma_one[2];
+
+    for (int i = thd_i; i < lookup_count * array_size; i += stride)
+    {
+        const int n = i / array_size;
+        const int row = i % array_size;
+        const ulong first = column_offsets[n];
+        const ulong m = column_offsets[n + 1] - first;
+        if (m == 0 || m > (ulong)max_column_count)
+        {
+            continue;
+        }
+
+        combined_data_in[i].table_value = compress_lookup_row(
+            input_columns, table_columns, theta_powers, beta, gamma, first, m, row, array_size);
+    }
+}
+
+// `compute_lookup_table_values` for the split layout, writing the table
+// values to `table_values`, which holds the `array_size` rows of each lookup.
+extern "C" __global__ void compute_lookup_table_values_split(
+    Fr *table_values,
+    const Fr *input_columns,
+    const Fr *table_columns,
+    const ulong *column_offsets,
+    const Fr *theta_powers,
+    const Fr *y_beta_gamma_one,
+    const int lookup_count,
+    const int max_column_count,
+    const int array_size)
+{
+
+    DEC_IDX
+
+    const int stride = gridDim.x * gridDim.y * gridDim.z *
+                       blockDim.x * blockDim.y * blockDim.z;
+
+    const Fr beta = y_beta_gamma_one[1];
+    const Fr gamma = y_beta_gamma_one[2];
+
+    for (int i = thd_i; i < lookup_count * array_size; i += stride)
+    {
+        const int n = i / array_size;
+        const int row = i % array_size;
+        const ulong first = column_offsets[n];
+        const ulong m = column_offsets[n + 1] - first;
+        if (m == 0 || m > (ulong)max_column_count)
+        {
+            continue;
+        }
+
+        table_values[i] = compress_lookup_row(
+            input_columns, table_columns, theta_powers, beta, gamma, first, m, row, array_size);
+    }
+}
+
+// Accumulates the five constraints of a lookup on the row `row` into `value`,
+// with the rows of its cosets given and the row `r_next` after it and
+// `r_prev` before it.
+__device__ Fr accumulate_lookup_row(
+    Fr value,
+    const Fr table_value,
+    const Fr a_minus_s,
+    const ulong r_next,
+    const ulong r_prev,
+    const Fr *product_coset,
+    const Fr *permuted_input_coset,
+    const Fr *permuted_table_coset,
+    const Fr l0_,
+    const Fr l_active_row_,
+    const Fr l_last_,
+    const Fr y,
+    const Fr beta,
+    const Fr gamma,
+    const Fr one,
+    const int row)
+{
+    // l_0(X) * (1 - z(X)) = 0
+    value = value * y + ((one - product_coset[row]) * l0_);
+    // l_last(X) * (z(X)^2 - z(X)) = 0
+    value = value * y +
+            ((product_coset[row] *
+                  product_coset[row] -
+              product_coset[row]) *
+             l_last_);
+    // (1 - (l_last(X) + l_blind(X))) * (
+    //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+    //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta) (\theta^{m-1}
+    //     s_0(X) + ... + s_{m-1}(X) + \gamma)
+    // ) = 0
+    value = value * y +
+            ((product_coset[r_next] *
+                  (permuted_input_coset[row] + beta) *
+                  (permuted_table_coset[row] + gamma) -
+              product_coset[row] * table_value) *
+             l_active_row_);
+    // Check that the first values in the permuted input expression and permuted
+    // fixed expression are the same.
+    // l_0(X) * (a'(X) - s'(X)) = 0
+    value = value * y + (a_minus_s * l0_);
+    // Check that each value in the permuted lookup input expression is either
+    // equal to the value above it, or the value at the same index in the
+    // permuted table expression.
+    // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) =
+    // 0
+    value = value * y +
+            (a_minus_s *
+             (permuted_input_coset[row] -
+              permuted_input_coset[r_prev]) *
+             l_active_row_);
+    return value;
+}
+
+// `values` holds `circuit_count` lists of `array_size` rows, and list `c` is
+// accumulated with the lookups `lookup_offsets[c]..lookup_offsets[c + 1]` of
+// the lookup lists.
 extern "C" __global__ void compute_evaluate_h_lookups_codeblock(
     Fr *values,
     const combined_t *combined_data_in,
@@ -29,71 +226,264 @@ extern "C" __global__ void compute_evaluate_h_lookups_codeblock(
     const Fr *l_active_row,
     const Fr *l_last,
     const Fr *y_beta_gamma_one,
-    const int lookup_count,
+    const ulong *lookup_offsets,
+    const int circuit_count,
+    const int array_size)
+{
+
+    DEC_IDX
+
+    // Any launch configuration covers all the values, each thread striding
+    // over them by the total number of threads.
+    const int stride = gridDim.x * gridDim.y * gridDim.z *
+                       blockDim.x * blockDim.y * blockDim.z;
+
+    const Fr y = y_beta_gamma_one[0];
+    const Fr beta = y_beta_gamma_one[1];
+    const Fr gamma = y_beta_gamma_one[2];
+    const Fr one = y_beta_gamma_one[3];
+
+    for (int i = thd_i; i < circuit_count * array_size; i += stride)
+    {
+        const int circuit = i / array_size;
+        const int row = i % array_size;
+
+        const Fr l0_ = l0[row];
+        const Fr l_active_row_ = l_active_row[row];
+        const Fr l_last_ = l_last[row];
+
+        Fr value = values[i];
+
+        for (ulong n = lookup_offsets[circuit]; n < lookup_offsets[circuit + 1]; n++)
+        {
+
+            const ulong list_offset = array_size * n;
+
+            const Fr table_value = combined_data_in[list_offset + row].table_value;
+            const Fr a_minus_s = combined_data_in[list_offset + row].a_minus;
+            const ulong r_next = combined_data_in[list_offset + row].r_next;
+            const ulong r_prev = combined_data_in[list_offset + row].r_prev;
+
+            const Fr *product_coset = &product_coset_list[list_offset];
+            const Fr *permuted_input_coset = &permuted_input_coset_list[list_offset];
+            const Fr *permuted_table_coset = &permuted_table_coset_list[list_offset];
+
+            value = accumulate_lookup_row(
+                value, table_value, a_minus_s, r_next, r_prev,
+                product_coset, permuted_input_coset, permuted_table_coset,
+                l0_, l_active_row_, l_last_, y, beta, gamma, one, row);
+        }
+
+
+        values[i] = value;
+    }
+}
+
+// `compute_evaluate_h_lookups_codeblock` for the split layout, reading the
+// table values and a'(X) - s'(X) of the rows from `table_values` and
+// `a_minus_s`. The rows after and before a row are `rot_scale` rows away on
+// the extended domain, which is larger than `rot_scale`.
+extern "C" __global__ void compute_evaluate_h_lookups_split(
+    Fr *values,
+    const Fr *table_values,
+    const Fr *a_minus_s_list,
+    const Fr *product_coset_list,
+    const Fr *permuted_input_coset_list,
+    const Fr *permuted_table_coset_list,
+    const Fr *l0,
+    const Fr *l_active_row,
+    const Fr *l_last,
+    const Fr *y_beta_gamma_one,
+    const ulong *lookup_offsets,
+    const int circuit_count,
+    const int rot_scale,
     const int array_size)
 {
 
     DEC_IDX
 
+    const int stride = gridDim.x * gridDim.y * gridDim.z *
+                       blockDim.x * blockDim.y * blockDim.z;
+
     const Fr y = y_beta_gamma_one[0];
     const Fr beta = y_beta_gamma_one[1];
     const Fr gamma = y_beta_gamma_one[2];
     const Fr one = y_beta_gamma_one[3];
 
-    const Fr l0_ = l0[thd_i];
-    const Fr l_active_row_ = l_active_row[thd_i];
-    const Fr l_last_ = l_last[thd_i];
+    for (int i = thd_i; i < circuit_count * array_size; i += stride)
+    {
+        const int circuit = i / array_size;
+        const int row = i % array_size;
+
+        const Fr l0_ = l0[row];
+        const Fr l_active_row_ = l_active_row[row];
+        const Fr l_last_ = l_last[row];
+        const ulong r_next = (row + rot_scale) % array_size;
+        const ulong r_prev = (row + array_size - rot_scale) % array_size;
+
+        Fr value = values[i];
+
+        for (ulong n = lookup_offsets[circuit]; n < lookup_offsets[circuit + 1]; n++)
+        {
+            const ulong list_offset = array_size * n;
+
+            value = accumulate_lookup_row(
+                value, table_values[list_offset + row], a_minus_s_list[list_offset + row],
+                r_next, r_prev,
+                &product_coset_list[list_offset],
+                &permuted_input_coset_list[list_offset],
+                &permuted_table_coset_list[list_offset],
+                l0_, l_active_row_, l_last_, y, beta, gamma, one, row);
+        }
+
+        values[i] = value;
+    }
+}
+
+// A value read by a calculation of an expression graph, see `DeviceSource`.
+typedef struct source_t
+{
+    uint kind;
+    uint index;
+    uint rotation;
+} source_t;
+static_assert(sizeof(source_t) == 12);
 
-    Fr value = values[thd_i];
+#define SOURCE_SCALAR 0
+#define SOURCE_INTERMEDIATE 1
+#define SOURCE_COLUMN 2
 
-    for (int n = 0; n < lookup_count; n++)
+// A calculation of an expression graph, see `DeviceCalculation`.
+typedef struct calculation_t
+{
+    uint op;
+    uint target;
+    source_t a;
+    source_t b;
+    source_t c;
+} calculation_t;
+static_assert(sizeof(calculation_t) == 44);
+
+#define OP_ADD 0
+#define OP_SUB 1
+#define OP_MUL 2
+#define OP_MUL_ADD 3
+
+__device__ Fr read_source(
+    const source_t source,
+    const int row,
+    const Fr *intermediates,
+    const Fr *columns,
+    const Fr *scalars,
+    const int *rotations,
+    const int rot_scale,
+    const int array_size)
+{
+    switch (source.kind)
+    {
+    case SOURCE_SCALAR:
+        return scalars[source.index];
+    case SOURCE_INTERMEDIATE:
+        return intermediates[(ulong)source.index * array_size + row];
+    default:
+    {
+        int rotated = (row + rotations[source.rotation] * rot_scale) % array_size;
+        if (rotated < 0)
+        {
+            rotated += array_size;
+        }
+        return columns[(ulong)source.index * array_size + rotated];
+    }
+    }
+}
+
+#define READ(source) \
+    read_source(source, row, intermediates, columns, scalars, rotations, rot_scale, array_size)
+
+// Evaluates the `num_calculations` calculations of an expression graph on each
+// of the `array_size` rows, writing the result of the last one to `values`.
+// The intermediate `k` of row `r` is `intermediates[k * array_size + r]`, and
+// column `k` of `columns` holds its rows at `k * array_size`.
+extern "C" __global__ void compute_evaluate_expression(
+    Fr *values,
+    Fr *intermediates,
+    const Fr *columns,
+    const Fr *scalars,
+    const int *rotations,
+    const calculation_t *calculations,
+    const int num_calculations,
+    const int rot_scale,
+    const int array_size)
+{
+
+    DEC_IDX
+
+    const int stride = gridDim.x * gridDim.y * gridDim.z *
+                       blockDim.x * blockDim.y * blockDim.z;
+
+    for (int row = thd_i; row < array_size; row += stride)
     {
+        for (int n = 0; n < num_calculations; n++)
+        {
+            const calculation_t calc = calculations[n];
+            const Fr a = READ(calc.a);
+            const Fr b = READ(calc.b);
 
-        const int list_offset = array_size * n;
-
-        const Fr table_value = combined_data_in[list_offset + thd_i].table_value;
-        const Fr a_minus_s = combined_data_in[list_offset + thd_i].a_minus;
-        const ulong r_next = combined_data_in[list_offset + thd_i].r_next;
-        const ulong r_prev = combined_data_in[list_offset + thd_i].r_prev;
-
-        const Fr *product_coset = &product_coset_list[list_offset];
-        const Fr *permuted_input_coset = &permuted_input_coset_list[list_offset];
-        const Fr *permuted_table_coset = &permuted_table_coset_list[list_offset];
-
-        // l_0(X) * (1 - z(X)) = 0
-        value = value * y + ((one - product_coset[thd_i]) * l0_);
-        // l_last(X) * (z(X)^2 - z(X)) = 0
-        value = value * y +
-                ((product_coset[thd_i] *
-                      product_coset[thd_i] -
-                  product_coset[thd_i]) *
-                 l_last_);
-        // (1 - (l_last(X) + l_blind(X))) * (
-        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
-        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta) (\theta^{m-1}
-        //     s_0(X) + ... + s_{m-1}(X) + \gamma)
-        // ) = 0
-        value = value * y +
-                ((product_coset[r_next] *
-                      (permuted_input_coset[thd_i] + beta) *
-                      (permuted_table_coset[thd_i] + gamma) -
-                  product_coset[thd_i] * table_value) *
-                 l_active_row_);
-        // Check that the first values in the permuted input expression and permuted
-        // fixed expression are the same.
-        // l_0(X) * (a'(X) - s'(X)) = 0
-        value = value * y + (a_minus_s * l0_);
-        // Check that each value in the permuted lookup input expression is either
-        // equal to the value above it, or the value at the same index in the
-        // permuted table expression.
-        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) =
-        // 0
-        value = value * y +
-                (a_minus_s *
-                 (permuted_input_coset[thd_i] -
-                  permuted_input_coset[r_prev]) *
-                 l_active_row_);
+            Fr result;
+            switch (calc.op)
+            {
+            case OP_ADD:
+                result = a + b;
+                break;
+            case OP_SUB:
+                result = a - b;
+                break;
+            case OP_MUL:
+                result = a * b;
+                break;
+            default:
+                result = a * b + READ(calc.c);
+                break;
+            }
+            intermediates[(ulong)calc.target * array_size + row] = result;
+        }
+
+        const uint result = calculations[num_calculations - 1].target;
+        values[row] = intermediates[(ulong)result * array_size + row];
     }
+}
+
+// Folds `values` with the powers of `r[0]`: chunk `c` of the `num_chunks`
+// chunks of `chunk_size` rows, which the host fixes whatever the launch,
+// writes the sum of its rows `j` times `r^j` to `partials[c]`, by Horner's
+// rule from its last row. The host then folds the partials with the powers of
+// `r^chunk_size`, so that the order of the operations does not depend on the
+// launch.
+extern "C" __global__ void reduce_values(
+    Fr *partials,
+    const Fr *values,
+    const Fr *r,
+    const int chunk_size,
+    const int num_chunks,
+    const int array_size)
+{
+
+    DEC_IDX
+
+    const int stride = gridDim.x * gridDim.y * gridDim.z *
+                       blockDim.x * blockDim.y * blockDim.z;
+    const Fr factor = r[0];
 
-    values[thd_i] = value;
-}
\ No newline at end of file
+    for (int chunk = thd_i; chunk < num_chunks; chunk += stride)
+    {
+        const int start = chunk * chunk_size;
+        const int end = min(start + chunk_size, array_size);
+
+        Fr value;
+        for (int row = end - 1; row >= start; row--)
+        {
+            value = value * factor + values[row];
+        }
+        partials[chunk] = value;
+    }
+}
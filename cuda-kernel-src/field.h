@@ -357,6 +357,13 @@ private:
     }
 
 public:
+    /// Whether `limbs`, little-endian, are the limbs of the modulus.
+    static inline __device__ bool has_modulus(const ulong *limbs)
+    {
+        return limbs[0] == modolus_x && limbs[1] == modolus_y &&
+               limbs[2] == modolus_z && limbs[3] == modolus_w;
+    }
+
     inline __device__ field()
     {
         self = make_ulong4(0, 0, 0, 0);
@@ -393,12 +400,30 @@ public:
     }
 };
 
+// The scalar field of the proofs, the BN256 one unless the kernels are
+// compiled with `-DFIELD_PASTA_FP` or `-DFIELD_PASTA_FQ`.
+#if defined(FIELD_PASTA_FP)
+typedef field<(ulong)0x992d30ed00000001,
+              (ulong)0x224698fc094cf91b,
+              (ulong)0x0000000000000000,
+              (ulong)0x4000000000000000,
+              (ulong)0x992d30ecffffffff>
+    Fr;
+#elif defined(FIELD_PASTA_FQ)
+typedef field<(ulong)0x8c46eb2100000001,
+              (ulong)0x224698fc0994a8dd,
+              (ulong)0x0000000000000000,
+              (ulong)0x4000000000000000,
+              (ulong)0x8c46eb20ffffffff>
+    Fr;
+#else
 typedef field<(ulong)0x43e1f593f0000001,
               (ulong)0x2833e84879b97091,
               (ulong)0xb85045b68181585d,
               (ulong)0x30644e72e131a029,
               (ulong)0xc2e1f593efffffff>
     Fr;
+#endif
 
 typedef field<(ulong)0x3c208c16d87cfd47,
               (ulong)0x97816a916871ca8d,
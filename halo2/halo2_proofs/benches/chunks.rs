@@ -0,0 +1,71 @@
+#[macro_use]
+extern crate criterion;
+
+use group::ff::Field;
+use halo2curves::pasta::Fp;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{BenchmarkId, Criterion};
+use rand_core::OsRng;
+
+/// The number of rows, those of an extended domain of 2^18 rows
+const ROWS: usize = 1 << 18;
+
+/// The squarings per row, about the work of the custom gates of a row
+const SQUARINGS: usize = 64;
+
+/// Splits the rows into `chunks_per_thread` chunks per thread, as the CPU loop
+/// of the custom gates does, each row computing the same number of squarings
+fn evaluate_rows(values: &mut [Fp], chunks_per_thread: usize) {
+    let num_chunks = rayon::current_num_threads() * chunks_per_thread;
+    let chunk_size = ((values.len() + num_chunks - 1) / num_chunks).max(1);
+    rayon::scope(|scope| {
+        for values in values.chunks_mut(chunk_size) {
+            scope.spawn(move |_| {
+                for value in values.iter_mut() {
+                    for _ in 0..SQUARINGS {
+                        *value = value.square();
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut values: Vec<_> = (0..ROWS).map(|_| Fp::random(OsRng)).collect();
+
+    // Threads spinning on half of the cores make some of the evaluating
+    // threads slower than the others, as on a loaded or NUMA machine, so
+    // that a single chunk per thread waits for the slowest one
+    let stop = Arc::new(AtomicBool::new(false));
+    let spinners: Vec<_> = (0..(rayon::current_num_threads() / 2).max(1))
+        .map(|_| {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    std::hint::spin_loop();
+                }
+            })
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("custom gate chunks");
+    group.sample_size(20);
+    for chunks_per_thread in [1, 4, 16] {
+        group.bench_function(BenchmarkId::new("skewed", chunks_per_thread), |b| {
+            b.iter(|| evaluate_rows(&mut values, chunks_per_thread));
+        });
+    }
+    group.finish();
+
+    stop.store(true, Ordering::Relaxed);
+    for spinner in spinners {
+        spinner.join().unwrap();
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
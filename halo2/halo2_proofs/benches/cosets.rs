@@ -0,0 +1,52 @@
+#[macro_use]
+extern crate criterion;
+
+use group::ff::Field;
+use halo2_proofs::poly::EvaluationDomain;
+use halo2curves::pasta::Fp;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use criterion::{BenchmarkId, Criterion};
+use rand_core::OsRng;
+
+/// The number of advice columns of the wide circuit
+const COLUMNS: usize = 128;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    // The extended cosets of the columns of a wide circuit, computed one
+    // column after the other and for several columns at once
+    let mut group = c.benchmark_group("cosets");
+    group.sample_size(10);
+    for k in [10, 14, 16] {
+        let domain = EvaluationDomain::<Fp>::new(4, k);
+        let polys: Vec<_> = (0..COLUMNS)
+            .map(|_| {
+                let mut poly = domain.empty_coeff();
+                for coeff in poly.iter_mut() {
+                    *coeff = Fp::random(OsRng);
+                }
+                poly
+            })
+            .collect();
+
+        group.bench_function(BenchmarkId::new("sequential", k), |b| {
+            b.iter(|| {
+                polys
+                    .iter()
+                    .map(|poly| domain.coeff_to_extended(poly.clone()))
+                    .collect::<Vec<_>>()
+            });
+        });
+        group.bench_function(BenchmarkId::new("parallel", k), |b| {
+            b.iter(|| {
+                polys
+                    .par_iter()
+                    .map(|poly| domain.coeff_to_extended(poly.clone()))
+                    .collect::<Vec<_>>()
+            });
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,143 @@
+#[macro_use]
+extern crate criterion;
+
+use halo2_proofs::plonk::{
+    Backend, ConstraintSystem, Evaluator, LookupLayout, SyntheticEvaluation,
+};
+use halo2_proofs::poly::Rotation;
+use halo2curves::pasta::{EqAffine, Fp};
+use std::time::Duration;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+
+/// The lookups of the synthetic circuits
+const LOOKUPS: usize = 4;
+
+/// The advice columns of the wide constraint system whose graphs are built
+const COLUMNS: usize = 256;
+
+/// A constraint system with a gate of `COLUMNS` products of consecutive
+/// columns at two rotations, and a lookup of every column
+fn wide_constraint_system() -> ConstraintSystem<Fp> {
+    let mut cs = ConstraintSystem::default();
+    let columns: Vec<_> = (0..COLUMNS).map(|_| cs.advice_column()).collect();
+    let table = cs.lookup_table_column();
+    cs.create_gate("wide", |meta| {
+        (0..COLUMNS)
+            .map(|i| {
+                let a = meta.query_advice(columns[i], Rotation::cur());
+                let b = meta.query_advice(columns[(i + 1) % COLUMNS], Rotation::next());
+                a.clone() * b - a
+            })
+            .collect::<Vec<_>>()
+    });
+    for column in columns.iter().take(LOOKUPS) {
+        cs.lookup("wide", |meta| {
+            vec![(meta.query_advice(*column, Rotation::cur()), table)]
+        });
+    }
+    cs
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    // The evaluation of h(X) for circuits of growing sizes, on the CPU and on
+    // the CUDA device when there is one, with the speedup of the device
+    let mut group = c.benchmark_group("evaluate_h");
+    group.sample_size(10);
+    for k in [12, 14, 16] {
+        let synthetic = SyntheticEvaluation::new(k, k + 2, LOOKUPS);
+        let time = |backend: Backend| -> Option<Duration> {
+            match synthetic.evaluate_h(backend) {
+                Ok(elapsed) => Some(elapsed),
+                Err(err) => {
+                    eprintln!("skipping {} for k = {}: {}", backend.name(), k, err);
+                    None
+                }
+            }
+        };
+        let cpu = time(Backend::Cpu);
+        let cuda = time(Backend::Cuda);
+        if let (Some(cpu), Some(cuda)) = (cpu, cuda) {
+            println!(
+                "k = {}: cpu {:?}, cuda {:?}, speedup {:.2}x",
+                k,
+                cpu,
+                cuda,
+                cpu.as_secs_f64() / cuda.as_secs_f64()
+            );
+        }
+
+        for (backend, available) in [
+            (Backend::Cpu, cpu.is_some()),
+            (Backend::Cuda, cuda.is_some()),
+        ] {
+            if !available {
+                continue;
+            }
+            group.bench_function(BenchmarkId::new(backend.name(), k), |b| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| synthetic.evaluate_h(backend).unwrap())
+                        .sum()
+                });
+            });
+        }
+    }
+    group.finish();
+
+    // The evaluation of h(X) on the CPU with all the advice columns but one
+    // zero, whose cosets are not computed, against a witness using all of them
+    let mut group = c.benchmark_group("evaluate_h_zero_columns");
+    group.sample_size(10);
+    let k = 14;
+    let synthetic = SyntheticEvaluation::new(k, k + 2, LOOKUPS);
+    let sparse = SyntheticEvaluation::new(k, k + 2, LOOKUPS)
+        .with_zero_columns(synthetic.num_advice_columns() - 1);
+    for (name, synthetic) in [("dense", &synthetic), ("sparse", &sparse)] {
+        group.bench_function(BenchmarkId::new(name, k), |b| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| synthetic.evaluate_h(Backend::Cpu).unwrap())
+                    .sum()
+            });
+        });
+    }
+    group.finish();
+
+    // The lookups on the CUDA device with their per-row values interleaved and
+    // split, the throughput being the bytes of these values. The split layout
+    // needs a kernel module built with its kernels.
+    let mut group = c.benchmark_group("evaluate_h_lookup_layout");
+    group.sample_size(10);
+    for k in [14, 16] {
+        let rows = LOOKUPS << (k + 2);
+        for (name, layout, row_bytes) in [
+            ("interleaved", LookupLayout::Interleaved, 80),
+            ("split", LookupLayout::Split, 64),
+        ] {
+            let synthetic = SyntheticEvaluation::new(k, k + 2, LOOKUPS).with_lookup_layout(layout);
+            if let Err(err) = synthetic.evaluate_h(Backend::Cuda) {
+                eprintln!("skipping the {} layout for k = {}: {}", name, k, err);
+                continue;
+            }
+            group.throughput(Throughput::Bytes((rows * row_bytes) as u64));
+            group.bench_function(BenchmarkId::new(name, k), |b| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| synthetic.evaluate_h(Backend::Cuda).unwrap())
+                        .sum()
+                });
+            });
+        }
+    }
+    group.finish();
+
+    // Building the evaluation graphs of a wide constraint system, as keygen does
+    let cs = wide_constraint_system();
+    c.bench_function("evaluator_new", |b| {
+        b.iter(|| Evaluator::<EqAffine>::new(&cs))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+use std::env;
+use std::path::Path;
+
+/// Checks that the CUDA kernels the `cuda-embedded-kernel` and
+/// `cuda-embedded-cubin` features compile in are built, as they are not
+/// checked in: `make cuda_kernels` builds them from `cuda-kernel-src` with
+/// `nvcc`, which the build of the crate otherwise does not need.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CU_KERNEL");
+    println!("cargo:rerun-if-env-changed=CU_KERNEL_CUBIN");
+    println!("cargo:rerun-if-env-changed=CU_KERNEL_ARCH");
+
+    if env::var_os("CARGO_FEATURE_CUDA_EMBEDDED_KERNEL").is_some() {
+        require_kernel("cuda-embedded-kernel", "CU_KERNEL", "the PTX kernel");
+    }
+    if env::var_os("CARGO_FEATURE_CUDA_EMBEDDED_CUBIN").is_some() {
+        require_kernel("cuda-embedded-cubin", "CU_KERNEL_CUBIN", "the cubin kernel");
+        if env::var_os("CU_KERNEL_ARCH").is_none() {
+            panic!(
+                "the `cuda-embedded-cubin` feature needs `CU_KERNEL_ARCH`, the `sm_XY` \
+                 architecture `CU_KERNEL_CUBIN` is compiled for"
+            );
+        }
+    }
+}
+
+/// Fails the build with how to build the kernel if the file `var` points at
+/// is missing
+fn require_kernel(feature: &str, var: &str, kernel: &str) {
+    let how = "build it with `make cuda_kernels` at the root of the repository, which needs \
+               `nvcc` from the CUDA toolkit, and point the variable at it";
+    let path = match env::var(var) {
+        Ok(path) => path,
+        Err(_) => panic!(
+            "the `{}` feature compiles in {} `{}` points at, which is not set: {}",
+            feature, kernel, var, how
+        ),
+    };
+    if !Path::new(&path).is_file() {
+        panic!(
+            "the `{}` feature compiles in {} `{}` points at, but `{}` does not exist: {}",
+            feature, kernel, var, path, how
+        );
+    }
+    println!("cargo:rerun-if-changed={}", path);
+}
@@ -366,15 +366,21 @@ where
     q
 }
 
-/// This simple utility function will parallelize an operation that is to be
-/// performed over a mutable slice.
-pub fn parallelize<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(v: &mut [T], f: F) {
-    let n = v.len();
+/// Returns the length of the chunks `parallelize` splits a slice of `n`
+/// elements into. Every chunk starts at a multiple of this length.
+pub(crate) fn parallelize_chunk_size(n: usize) -> usize {
     let num_threads = multicore::current_num_threads();
-    let mut chunk = (n as usize) / num_threads;
+    let mut chunk = n / num_threads;
     if chunk < num_threads {
-        chunk = n as usize;
+        chunk = n;
     }
+    chunk
+}
+
+/// This simple utility function will parallelize an operation that is to be
+/// performed over a mutable slice.
+pub fn parallelize<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(v: &mut [T], f: F) {
+    let chunk = parallelize_chunk_size(v.len());
 
     multicore::scope(|scope| {
         for (chunk_num, v) in v.chunks_mut(chunk).enumerate() {
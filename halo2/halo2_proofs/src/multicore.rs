@@ -2,4 +2,50 @@
 //! `halo2`. It's currently just a (very!) thin wrapper around [`rayon`] but may
 //! be extended in the future to allow for various parallelism strategies.
 
-pub use rayon::{current_num_threads, scope, Scope};
+pub use rayon::{current_num_threads, in_place_scope, scope, Scope};
+
+thread_local! {
+    /// The pool `sequential` runs on, started by its first call on this thread
+    /// and reused by the later ones
+    static SEQUENTIAL: rayon::ThreadPool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .expect("failed to start a single threaded pool");
+
+    /// The pool `pinned` runs on, started by its first call on this thread and
+    /// reused by the later ones, or `None` when the cores cannot be listed
+    #[cfg(feature = "numa")]
+    static PINNED: Option<std::rc::Rc<rayon::ThreadPool>> = core_affinity::get_core_ids()
+        .filter(|cores| !cores.is_empty())
+        .map(|cores| {
+            let num_cores = cores.len();
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_cores)
+                .start_handler(move |index| {
+                    core_affinity::set_for_current(cores[index % num_cores]);
+                })
+                .build()
+                .expect("failed to start a pinned pool");
+            std::rc::Rc::new(pool)
+        });
+}
+
+/// Runs `f` on a pool of a single thread, so that the parallel computations it
+/// starts run one after the other, in the order they are spawned. The pool is
+/// started once per calling thread and reused by the later calls.
+pub fn sequential<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    SEQUENTIAL.with(|pool| pool.install(f))
+}
+
+/// Runs `f` on a pool of a thread per core, each pinned to its core, so that
+/// the threads do not move across NUMA nodes and the memory they touch first
+/// stays local to them. Without the `numa` feature, or when the cores cannot
+/// be listed, `f` runs on the current pool. The pool is started, and its
+/// threads pinned, once per calling thread and reused by the later calls.
+pub fn pinned<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    #[cfg(feature = "numa")]
+    if let Some(pool) = PINNED.with(Clone::clone) {
+        return pool.install(f);
+    }
+    f()
+}
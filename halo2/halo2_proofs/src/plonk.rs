@@ -19,6 +19,9 @@ use crate::transcript::{ChallengeScalar, EncodedChallenge, Transcript};
 mod assigned;
 mod circuit;
 mod error;
+// The evaluation calls the device drivers and views the field elements as the
+// bytes the devices read, the rest of the crate being safe Rust
+#[allow(unsafe_code)]
 mod evaluation;
 mod keygen;
 mod lookup;
@@ -35,7 +38,14 @@ pub use keygen::*;
 pub use prover::*;
 pub use verifier::*;
 
-use evaluation::Evaluator;
+pub use evaluation::{
+    bench_evaluate_h, clear_cuda_module_cache, cuda_counters, cuda_device_memory,
+    cuda_unified_memory_available, find_violating_row, reduce_values_on_gpu, reset_cuda_counters,
+    Backend, CancellationToken, CudaCounters, CustomKernel, DeviceMemory, EvalError, Evaluator,
+    GraphEvaluator, LookupLayout, MemoryMode, Progress, ProgressPhase, SyntheticEvaluation,
+};
+#[cfg(feature = "eval-dumps")]
+pub use evaluation::{dump_extended_poly, load_extended_poly};
 use std::io;
 
 /// This is a verifying key which allows for the verification of proofs for a
@@ -159,6 +169,11 @@ impl<C: CurveAffine> ProvingKey<C> {
     pub fn get_vk(&self) -> &VerifyingKey<C> {
         &self.vk
     }
+
+    /// Get the evaluator of the h(X) polynomial, to inspect its graphs.
+    pub fn get_ev(&self) -> &Evaluator<C> {
+        &self.ev
+    }
 }
 
 impl<C: CurveAffine> VerifyingKey<C> {
@@ -3,7 +3,7 @@ use std::error;
 use std::fmt;
 use std::io;
 
-use super::{Any, Column};
+use super::{Any, Column, EvalError};
 
 /// This is an error that could occur during proving or circuit synthesis.
 // TODO: these errors need to be cleaned up
@@ -37,6 +37,10 @@ pub enum Error {
     /// The instance sets up a copy constraint involving a column that has not been
     /// included in the permutation.
     ColumnNotInPermutation(Column<Any>),
+    /// The h(X) polynomial or the expressions of a lookup could not be
+    /// evaluated, e.g. on a CUDA device without a kernel module and without
+    /// falling back to the CPU.
+    Evaluation(EvalError),
 }
 
 impl From<io::Error> for Error {
@@ -46,6 +50,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<EvalError> for Error {
+    fn from(error: EvalError) -> Self {
+        Error::Evaluation(error)
+    }
+}
+
 impl Error {
     /// Constructs an `Error::NotEnoughRowsAvailable`.
     pub(crate) fn not_enough_rows_available(current_k: u32) -> Self {
@@ -79,6 +89,7 @@ impl fmt::Display for Error {
                 "Column {:?} must be included in the permutation. Help: try applying `meta.enable_equalty` on the column",
                 column
             ),
+            Error::Evaluation(e) => write!(f, "Evaluation failed: {}", e),
         }
     }
 }
@@ -87,6 +98,7 @@ impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             Error::Transcript(e) => Some(e),
+            Error::Evaluation(e) => Some(e),
             _ => None,
         }
     }
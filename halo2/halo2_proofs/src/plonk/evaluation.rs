@@ -1,10 +1,10 @@
-use crate::multicore;
 use crate::plonk::lookup::prover::Committed;
 use crate::plonk::permutation::Argument;
-use crate::plonk::{lookup, permutation, AdviceQuery, Any, FixedQuery, InstanceQuery, ProvingKey};
+use crate::plonk::{lookup, permutation, FixedQuery, InstanceQuery, ProvingKey};
 use crate::poly::Basis;
 use crate::{
-    arithmetic::{eval_polynomial, parallelize, CurveAffine, FieldExt},
+    arithmetic::{best_fft, eval_polynomial, parallelize, CurveAffine, FieldExt},
+    multicore,
     poly::{
         commitment::Params, Coeff, EvaluationDomain, ExtendedLagrangeCoeff, LagrangeCoeff,
         Polynomial, ProverQuery, Rotation,
@@ -16,25 +16,117 @@ use group::{
     ff::{BatchInvert, Field},
     Curve,
 };
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{
     any::TypeId,
-    collections::BTreeMap,
-    convert::TryInto,
-    env,
+    collections::{BTreeMap, BTreeSet},
     ffi::{c_void, CString},
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
     iter, mem,
     num::ParseIntError,
-    ops::{Index, Mul, MulAssign},
+    ops::{Index, Mul, MulAssign, Range},
+    path::Path,
     slice,
+    time::{Duration, Instant},
 };
 
 use super::{ConstraintSystem, Expression};
 use ark_std::{end_timer, start_timer};
-use colored::Colorize;
-use cuda_driver_wrapper::*;
+use style::Colorize;
+
+#[cfg(all(test, feature = "count-allocations"))]
+mod allocations;
+mod backend;
+mod bn256;
+mod cache;
+mod cancel;
+mod config;
+mod context;
+mod control;
+mod cpu;
+mod cuda;
+mod deferred;
+mod device;
+#[cfg(feature = "eval-dumps")]
+mod dump;
+mod error;
+mod fingerprint;
+#[cfg(test)]
+mod fixtures;
+#[cfg(test)]
+mod mock;
+#[cfg(feature = "opencl")]
+mod opencl;
+mod pinned;
+mod sample;
+mod scratch;
+mod serialize;
+mod spill;
+mod stats;
+mod style;
+mod synthetic;
+mod timings;
+#[cfg(feature = "webgpu")]
+mod webgpu;
+
+pub use backend::Backend;
+pub(in crate::plonk) use backend::{CircuitData, HEvaluatorBackend};
+pub(in crate::plonk) use cache::{hash_inputs, HCache, HCacheKey};
+pub use cancel::CancellationToken;
+pub use config::{
+    EvalThreadPool, EvaluatorConfig, GpuPhases, LookupLayout, LookupStrategy, MemoryMode,
+    RetryPolicy, Verbosity,
+};
+pub(in crate::plonk) use context::ExtendedContext;
+pub(in crate::plonk) use control::EvalControl;
+pub use control::{Progress, ProgressPhase};
+pub(in crate::plonk) use cpu::CpuBackend;
+pub(in crate::plonk) use cuda::CudaBackend;
+pub use cuda::LaunchPlan;
+pub use cuda::{
+    clear_cuda_module_cache, cuda_counters, cuda_device_memory, cuda_unified_memory_available,
+    reset_cuda_counters, CudaCounters, DeviceMemory,
+};
+pub(in crate::plonk) use deferred::DeferredChallenges;
+pub(in crate::plonk) use device::{
+    accumulate_lookups, prepare_lookup, prepare_lookups, DeviceGraph, FieldLayout, LookupBuffers,
+    LOOKUP_CONSTRAINTS,
+};
+#[cfg(any(feature = "opencl", feature = "webgpu"))]
+pub(in crate::plonk) use device::{as_bytes, as_bytes_mut};
+#[cfg(feature = "eval-dumps")]
+pub use dump::{dump_extended_poly, load_extended_poly};
+pub use error::EvalError;
+pub(in crate::plonk) use fingerprint::{cs_fingerprint, fnv1a};
+#[cfg(feature = "opencl")]
+pub(in crate::plonk) use opencl::OpenClBackend;
+pub(in crate::plonk) use sample::sample_rows;
+pub(in crate::plonk) use scratch::{extended_cosets_into, is_zero_poly, EvalScratch};
+pub(in crate::plonk) use stats::{lookup_row_counts, permutation_row_counts};
+pub use stats::{EvalStats, EvaluatorStats, OpCounts};
+pub use synthetic::{bench_evaluate_h, SyntheticEvaluation};
+pub use timings::{EvalTimings, Transfers};
+#[cfg(feature = "webgpu")]
+pub(in crate::plonk) use webgpu::WgpuBackend;
 
 /// Return the index in the polynomial of size `isize` after rotation `rot`.
 fn get_rotation_idx(idx: usize, rot: i32, rot_scale: i32, isize: i32) -> usize {
+    // A micro-optimization of the expressions evaluated in the Lagrange basis,
+    // such as the compressed lookup expressions: without an extension the
+    // rotation needs no scaling and wraps around at most once, which saves
+    // the remainder. h(X) itself never takes it, the permutation argument
+    // having degree 3 the extended domain is at least twice the size of the
+    // domain, so there is no unextended case of `evaluate_h` to fast-path.
+    if rot_scale == 1 && rot.abs() < isize {
+        let rotated = idx as i32 + rot;
+        return match rotated {
+            rotated if rotated < 0 => rotated + isize,
+            rotated if rotated >= isize => rotated - isize,
+            rotated => rotated,
+        } as usize;
+    }
     (((idx as i32) + (rot * rot_scale)).rem_euclid(isize)) as usize
 }
 
@@ -51,7 +143,9 @@ pub enum ValueSource {
     Advice(usize, usize),
     /// This is an instance (external) column
     Instance(usize, usize),
-    /// This is a challenge
+    /// This is a challenge, by its index in the order the challenges were
+    /// requested, whatever their phase. The prover and the verifier order the
+    /// challenge values the same way.
     Challenge(usize),
     /// beta
     Beta(),
@@ -59,6 +153,9 @@ pub enum ValueSource {
     Gamma(),
     /// theta
     Theta(),
+    /// theta to the given power, computed once per evaluation rather than
+    /// on every row
+    ThetaPower(usize),
     /// y
     Y(),
     /// Previous value
@@ -85,6 +182,7 @@ impl ValueSource {
         beta: &F,
         gamma: &F,
         theta: &F,
+        theta_powers: &[F],
         y: &F,
         previous_value: &F,
     ) -> F {
@@ -104,12 +202,22 @@ impl ValueSource {
             ValueSource::Beta() => *beta,
             ValueSource::Gamma() => *gamma,
             ValueSource::Theta() => *theta,
+            ValueSource::ThetaPower(power) => theta_powers[*power],
             ValueSource::Y() => *y,
             ValueSource::PreviousValue() => *previous_value,
         }
     }
 }
 
+/// The fewest products a sum must have to be evaluated as one
+/// `Calculation::LinearCombination`, a sum of two products being a `MulAdd`
+const LINEAR_COMBINATION_TERMS: usize = 3;
+
+/// The rows whose values of a `Calculation::Inverse` are inverted in a single
+/// batch, a batch inversion costing one inversion and three multiplications
+/// per value
+const INVERSION_ROWS: usize = 1024;
+
 /// Calculation
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Calculation {
@@ -125,10 +233,17 @@ pub enum Calculation {
     Double(ValueSource),
     /// This is a negation
     Negate(ValueSource),
+    /// This is a fused multiply-add: `a * b + c`
+    MulAdd(ValueSource, ValueSource, ValueSource),
     /// This is Horner's rule: `val = a; val = val * c + b[]`
     Horner(ValueSource, Vec<ValueSource>, ValueSource),
+    /// This is a sum of products: `a[0] * b[0] + a[1] * b[1] + ...`
+    LinearCombination(Vec<(ValueSource, ValueSource)>),
     /// This is a simple assignment
     Store(ValueSource),
+    /// This is an inversion, zero for zero. `GraphEvaluator::evaluate_rows`
+    /// inverts its values on a block of rows at once.
+    Inverse(ValueSource),
 }
 
 impl Calculation {
@@ -145,6 +260,7 @@ impl Calculation {
         beta: &F,
         gamma: &F,
         theta: &F,
+        theta_powers: &[F],
         y: &F,
         previous_value: &F,
     ) -> F {
@@ -160,6 +276,7 @@ impl Calculation {
                 beta,
                 gamma,
                 theta,
+                theta_powers,
                 y,
                 previous_value,
             )
@@ -171,6 +288,7 @@ impl Calculation {
             Calculation::Square(v) => get_value(v).square(),
             Calculation::Double(v) => get_value(v).double(),
             Calculation::Negate(v) => -get_value(v),
+            Calculation::MulAdd(a, b, c) => get_value(a) * get_value(b) + get_value(c),
             Calculation::Horner(start_value, parts, factor) => {
                 let factor = get_value(factor);
                 let mut value = get_value(start_value);
@@ -179,18 +297,212 @@ impl Calculation {
                 }
                 value
             }
+            Calculation::LinearCombination(terms) => {
+                terms.iter().fold(F::zero(), |value, (a, b)| {
+                    value + get_value(a) * get_value(b)
+                })
+            }
             Calculation::Store(v) => get_value(v),
+            Calculation::Inverse(v) => get_value(v).invert().unwrap_or(F::zero()),
+        }
+    }
+}
+
+impl Calculation {
+    /// The values this calculation reads
+    fn sources(&self) -> Vec<&ValueSource> {
+        match self {
+            Calculation::Add(a, b) | Calculation::Sub(a, b) | Calculation::Mul(a, b) => {
+                vec![a, b]
+            }
+            Calculation::Square(v)
+            | Calculation::Double(v)
+            | Calculation::Negate(v)
+            | Calculation::Store(v)
+            | Calculation::Inverse(v) => vec![v],
+            Calculation::MulAdd(a, b, c) => vec![a, b, c],
+            Calculation::Horner(start_value, parts, factor) => iter::once(start_value)
+                .chain(parts.iter())
+                .chain(iter::once(factor))
+                .collect(),
+            Calculation::LinearCombination(terms) => {
+                terms.iter().flat_map(|(a, b)| vec![a, b]).collect()
+            }
+        }
+    }
+
+    /// The values this calculation reads, mutably
+    fn sources_mut(&mut self) -> Vec<&mut ValueSource> {
+        match self {
+            Calculation::Add(a, b) | Calculation::Sub(a, b) | Calculation::Mul(a, b) => {
+                vec![a, b]
+            }
+            Calculation::Square(v)
+            | Calculation::Double(v)
+            | Calculation::Negate(v)
+            | Calculation::Store(v)
+            | Calculation::Inverse(v) => vec![v],
+            Calculation::MulAdd(a, b, c) => vec![a, b, c],
+            Calculation::Horner(start_value, parts, factor) => iter::once(start_value)
+                .chain(parts.iter_mut())
+                .chain(iter::once(factor))
+                .collect(),
+            Calculation::LinearCombination(terms) => {
+                terms.iter_mut().flat_map(|(a, b)| vec![a, b]).collect()
+            }
+        }
+    }
+}
+
+impl ValueSource {
+    /// Writes this value, showing the actual rotation of column queries when
+    /// `rotations` is given and the rotation index otherwise
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, rotations: Option<&[i32]>) -> fmt::Result {
+        let rotation = |rotation: usize| match rotations.and_then(|r| r.get(rotation)) {
+            Some(rotation) => format!("@rot{}", rotation),
+            None => format!("r{}", rotation),
+        };
+        match self {
+            ValueSource::Constant(idx) => write!(f, "c{}", idx),
+            ValueSource::Intermediate(idx) => write!(f, "i{}", idx),
+            ValueSource::Fixed(column_index, rot) => {
+                write!(f, "Fixed({},{})", column_index, rotation(*rot))
+            }
+            ValueSource::Advice(column_index, rot) => {
+                write!(f, "Advice({},{})", column_index, rotation(*rot))
+            }
+            ValueSource::Instance(column_index, rot) => {
+                write!(f, "Instance({},{})", column_index, rotation(*rot))
+            }
+            ValueSource::Challenge(index) => write!(f, "Challenge({})", index),
+            ValueSource::Beta() => write!(f, "Beta"),
+            ValueSource::Gamma() => write!(f, "Gamma"),
+            ValueSource::Theta() => write!(f, "Theta"),
+            ValueSource::ThetaPower(power) => write!(f, "Theta^{}", power),
+            ValueSource::Y() => write!(f, "Y"),
+            ValueSource::PreviousValue() => write!(f, "prev"),
+        }
+    }
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, None)
+    }
+}
+
+impl Calculation {
+    /// Writes this calculation, see `ValueSource::fmt_with`
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, rotations: Option<&[i32]>) -> fmt::Result {
+        let binary = |f: &mut fmt::Formatter<'_>, name: &str, a: &ValueSource, b: &ValueSource| {
+            write!(f, "{}(", name)?;
+            a.fmt_with(f, rotations)?;
+            write!(f, ", ")?;
+            b.fmt_with(f, rotations)?;
+            write!(f, ")")
+        };
+        let unary = |f: &mut fmt::Formatter<'_>, name: &str, v: &ValueSource| {
+            write!(f, "{}(", name)?;
+            v.fmt_with(f, rotations)?;
+            write!(f, ")")
+        };
+        match self {
+            Calculation::Add(a, b) => binary(f, "Add", a, b),
+            Calculation::Sub(a, b) => binary(f, "Sub", a, b),
+            Calculation::Mul(a, b) => binary(f, "Mul", a, b),
+            Calculation::Square(v) => unary(f, "Square", v),
+            Calculation::Double(v) => unary(f, "Double", v),
+            Calculation::Negate(v) => unary(f, "Negate", v),
+            Calculation::MulAdd(a, b, c) => {
+                write!(f, "MulAdd(")?;
+                a.fmt_with(f, rotations)?;
+                write!(f, ", ")?;
+                b.fmt_with(f, rotations)?;
+                write!(f, ", ")?;
+                c.fmt_with(f, rotations)?;
+                write!(f, ")")
+            }
+            Calculation::Horner(start_value, parts, factor) => {
+                write!(f, "Horner(")?;
+                start_value.fmt_with(f, rotations)?;
+                write!(f, ", [")?;
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    part.fmt_with(f, rotations)?;
+                }
+                write!(f, "], ")?;
+                factor.fmt_with(f, rotations)?;
+                write!(f, ")")
+            }
+            Calculation::LinearCombination(terms) => {
+                write!(f, "LinearCombination([")?;
+                for (i, (a, b)) in terms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    binary(f, "", a, b)?;
+                }
+                write!(f, "])")
+            }
+            Calculation::Store(v) => unary(f, "Store", v),
+            Calculation::Inverse(v) => unary(f, "Inverse", v),
         }
     }
 }
 
+impl fmt::Display for Calculation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, None)
+    }
+}
+
+/// A calculation displayed with the actual rotations of its column queries
+struct ResolvedCalculation<'a> {
+    calculation: &'a Calculation,
+    rotations: &'a [i32],
+}
+
+impl fmt::Display for ResolvedCalculation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.calculation.fmt_with(f, Some(self.rotations))
+    }
+}
+
 /// Evaluator
+///
+/// The evaluator holds no device state, the CUDA contexts and modules being
+/// cached per thread by the backend, so it is `Send` and `Sync` and can be
+/// shared behind an `Arc` by provers running on several threads.
 #[derive(Clone, Default, Debug)]
 pub struct Evaluator<C: CurveAffine> {
     ///  Custom gates evalution
     pub custom_gates: GraphEvaluator<C>,
-    ///  Lookups evalution
+    ///  Lookups evalution, of the grand-product identity, as the prover has
+    ///  no log-derivative lookup argument
     pub lookups: Vec<GraphEvaluator<C>>,
+    /// Evaluation settings
+    pub config: EvaluatorConfig,
+    /// The hand-written kernels evaluating known gate sets
+    pub custom_kernels: Vec<CustomKernel>,
+    /// The polynomials h(X) evaluated last, when
+    /// [`EvaluatorConfig::h_cache_entries`] is not 0
+    pub(in crate::plonk) h_cache: HCache<C::ScalarExt>,
+    /// The fingerprint of the constraint system the graphs are built from,
+    /// see [`Evaluator::fingerprint`]
+    pub(in crate::plonk) cs_fingerprint: u64,
+}
+
+/// A CUDA kernel function, with a name of the loaded module, evaluating the
+/// custom gates of the evaluators with a given
+/// [`Evaluator::gates_fingerprint`] in place of their graph
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomKernel {
+    /// The fingerprint of the gate set
+    pub fingerprint: u64,
+    /// The name of the kernel function
+    pub symbol: &'static str,
 }
 
 /// GraphEvaluator
@@ -213,6 +525,10 @@ pub struct EvaluationData<C: CurveAffine> {
     pub intermediates: Vec<C::ScalarExt>,
     /// Rotations
     pub rotations: Vec<usize>,
+    /// The powers of theta the graph reads, from theta^0
+    pub theta_powers: Vec<C::ScalarExt>,
+    /// The theta of `theta_powers`, if they are computed
+    pub theta: Option<C::ScalarExt>,
 }
 
 /// CaluclationInfo
@@ -224,12 +540,72 @@ pub struct CalculationInfo {
     pub target: usize,
 }
 
+/// Where the values of a graph merged into another one are in the merged
+/// graph, see `GraphEvaluator::merge`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Remapping {
+    /// The index in the merged graph of each constant
+    pub constants: Vec<usize>,
+    /// The index in the merged graph of each rotation
+    pub rotations: Vec<usize>,
+    /// The value in the merged graph of each intermediate, a constant if its
+    /// calculation folded into one
+    pub intermediates: Vec<ValueSource>,
+}
+
+impl Remapping {
+    /// Translates a value of the graph that was merged into the merged graph
+    pub fn apply(&self, source: &ValueSource) -> ValueSource {
+        match *source {
+            ValueSource::Constant(idx) => ValueSource::Constant(self.constants[idx]),
+            ValueSource::Intermediate(idx) => self.intermediates[idx],
+            ValueSource::Fixed(column, rotation) => {
+                ValueSource::Fixed(column, self.rotations[rotation])
+            }
+            ValueSource::Advice(column, rotation) => {
+                ValueSource::Advice(column, self.rotations[rotation])
+            }
+            ValueSource::Instance(column, rotation) => {
+                ValueSource::Instance(column, self.rotations[rotation])
+            }
+            _ => *source,
+        }
+    }
+}
+
 impl<C: CurveAffine> Evaluator<C> {
     /// Creates a new evaluation structure
+    /// The configuration is read from the environment, see
+    /// [`EvaluatorConfig::from_env`].
     pub fn new(cs: &ConstraintSystem<C::ScalarExt>) -> Self {
-        let mut ev = Evaluator::default();
+        let mut ev = Evaluator {
+            config: EvaluatorConfig::from_env(),
+            cs_fingerprint: cs_fingerprint(cs),
+            ..Default::default()
+        };
+        tracing::debug!(
+            "Evaluator::new: constraint system {:016x}",
+            ev.cs_fingerprint
+        );
+
+        // Room for the graphs up front, for the calculations and constants of
+        // large circuits not to be reallocated as they are pushed. Expressions
+        // sharing subexpressions need less, which is released once pruned.
+        let num_rotations = cs
+            .advice_queries
+            .iter()
+            .map(|(_, rotation)| rotation.0)
+            .chain(cs.instance_queries.iter().map(|(_, rotation)| rotation.0))
+            .chain(cs.fixed_queries.iter().map(|(_, rotation)| rotation.0))
+            .collect::<BTreeSet<_>>()
+            .len();
 
         // Custom gates
+        ev.custom_gates.reserve(
+            cs.gates.iter().flat_map(|gate| gate.polynomials().iter()),
+            1,
+            num_rotations,
+        );
         let mut parts = Vec::new();
         for gate in cs.gates.iter() {
             parts.extend(
@@ -243,27 +619,40 @@ impl<C: CurveAffine> Evaluator<C> {
             parts,
             ValueSource::Y(),
         ));
+        ev.custom_gates.prune();
+        ev.custom_gates.shrink_to_fit();
 
-        // Lookups
+        // Lookups, their expressions compressed as
+        // `theta^{m-1} a_0(X) + ... + theta a_{m-2}(X) + a_{m-1}(X)` with the
+        // powers of theta computed once per evaluation
+        let evaluate_lc = |graph: &mut GraphEvaluator<C>, expressions: &Vec<Expression<_>>| {
+            let parts: Vec<_> = expressions
+                .iter()
+                .map(|expr| graph.add_expression(expr))
+                .collect();
+            let terms = parts
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(power, part)| (ValueSource::ThetaPower(power), *part))
+                .collect();
+            graph.add_calculation(Calculation::LinearCombination(terms))
+        };
         for lookup in cs.lookups.iter() {
             let mut graph = GraphEvaluator::default();
-
-            let mut evaluate_lc = |expressions: &Vec<Expression<_>>| {
-                let parts = expressions
+            graph.reserve(
+                lookup
+                    .input_expressions
                     .iter()
-                    .map(|expr| graph.add_expression(expr))
-                    .collect();
-                graph.add_calculation(Calculation::Horner(
-                    ValueSource::Constant(0),
-                    parts,
-                    ValueSource::Theta(),
-                ))
-            };
+                    .chain(lookup.table_expressions.iter()),
+                5,
+                num_rotations,
+            );
 
             // Input coset
-            let compressed_input_coset = evaluate_lc(&lookup.input_expressions);
+            let compressed_input_coset = evaluate_lc(&mut graph, &lookup.input_expressions);
             // table coset
-            let compressed_table_coset = evaluate_lc(&lookup.table_expressions);
+            let compressed_table_coset = evaluate_lc(&mut graph, &lookup.table_expressions);
             // z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
             let right_gamma = graph.add_calculation(Calculation::Add(
                 compressed_table_coset,
@@ -274,6 +663,8 @@ impl<C: CurveAffine> Evaluator<C> {
                 ValueSource::Beta(),
             ));
             graph.add_calculation(Calculation::Mul(lc, right_gamma));
+            graph.prune();
+            graph.shrink_to_fit();
 
             ev.lookups.push(graph);
         }
@@ -281,7 +672,258 @@ impl<C: CurveAffine> Evaluator<C> {
         ev
     }
 
-    /// Evaluate h poly
+    /// Evaluates the custom gates of a circuit on the rows of its domain,
+    /// from the Lagrange values of its columns rather than their cosets on the
+    /// extended domain, e.g. to check that the gates hold at every row before
+    /// the columns are extended. The gates are combined with the powers of
+    /// `y` as in h(X): the values are zero at the rows where all the gates
+    /// hold, and nonzero, but for a negligible chance, where one of them does
+    /// not. The blinding rows are evaluated too, and only vanish for the gates
+    /// turned off there.
+    pub fn evaluate_gates_on_rows(
+        &self,
+        pk: &ProvingKey<C>,
+        advice: &[Polynomial<C::ScalarExt, LagrangeCoeff>],
+        instance: &[Polynomial<C::ScalarExt, LagrangeCoeff>],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+    ) -> Vec<C::ScalarExt> {
+        let size = 1 << pk.vk.domain.k();
+        let zero = C::ScalarExt::zero();
+        let mut values = vec![zero; size];
+        parallelize(&mut values, |values, start| {
+            let mut data = self.custom_gates.instance();
+            self.custom_gates.evaluate_rows(
+                &mut data,
+                &pk.fixed_values,
+                advice,
+                instance,
+                challenges,
+                &zero,
+                &zero,
+                &zero,
+                &y,
+                values,
+                start,
+                1,
+                size as i32,
+            );
+        });
+        values
+    }
+
+    /// A fingerprint of the constraint system the evaluator is built from, of
+    /// its gates, lookups and permutation columns, which is the same across
+    /// runs and platforms for the same constraint system. A serialized
+    /// evaluator keeps it, and is only read back for a constraint system
+    /// with the same one. It is 0 for an evaluator built by `default`.
+    pub fn fingerprint(&self) -> u64 {
+        self.cs_fingerprint
+    }
+
+    /// The CUDA backend, as configured
+    fn cuda_backend(&self) -> CudaBackend {
+        CudaBackend {
+            threads: self.config.cuda_threads,
+            retry: self.config.retry,
+            verbosity: self.config.verbosity,
+            transfers: Transfers::default(),
+            pinned_pool_bytes: self.config.pinned_pool_bytes,
+            custom_kernel: self.custom_kernel(),
+            lookup_device_columns: self.config.lookup_device_columns,
+            lookup_strategy: self.config.lookup_strategy,
+            lookup_layout: self.config.lookup_layout,
+            devices: self.config.devices.clone(),
+            memory_mode: self.config.memory_mode,
+        }
+    }
+
+    /// Registers `symbol`, a kernel function of the CUDA module, to evaluate
+    /// the custom gates of the evaluators whose
+    /// [`Evaluator::gates_fingerprint`] is `fingerprint`, replacing the kernel
+    /// registered before for it. The CUDA backend then launches it instead of
+    /// evaluating the graph of the gates on the CPU; the other backends ignore
+    /// it. The proving key keeps the evaluator it is built with, so the
+    /// kernels are registered before [`keygen_pk_with_evaluator`].
+    ///
+    /// The kernel is launched once per circuit with the parameters `values`,
+    /// `fixed`, `advice`, `instance`, `scalars`, `rot_scale` and `size`, as
+    /// described in the README, and has to leave the same values as the graph.
+    ///
+    /// [`keygen_pk_with_evaluator`]: crate::plonk::keygen_pk_with_evaluator
+    pub fn register_custom_kernel(&mut self, fingerprint: u64, symbol: &'static str) {
+        self.custom_kernels
+            .retain(|kernel| kernel.fingerprint != fingerprint);
+        self.custom_kernels.push(CustomKernel {
+            fingerprint,
+            symbol,
+        });
+    }
+
+    /// The kernel registered for the custom gates of this evaluator, if any
+    pub fn custom_kernel(&self) -> Option<&'static str> {
+        if self.custom_kernels.is_empty() {
+            return None;
+        }
+        let fingerprint = self.gates_fingerprint();
+        self.custom_kernels
+            .iter()
+            .find(|kernel| kernel.fingerprint == fingerprint)
+            .map(|kernel| kernel.symbol)
+    }
+
+    /// Plans the CUDA evaluation of h(X) for `num_circuits` circuits without
+    /// running anything: the kernel launches, their device buffers and their
+    /// number of threads, sized as the evaluation would on a single device,
+    /// whatever `EvaluatorConfig::devices` lists.
+    pub fn cuda_launch_plan(&self, pk: &ProvingKey<C>, num_circuits: usize) -> LaunchPlan {
+        self.cuda_backend().plan::<C::ScalarExt>(
+            pk.vk.domain.extended_len(),
+            num_circuits,
+            self.lookups.len(),
+        )
+    }
+
+    /// Counts the field operations of an evaluation of h(X) for `num_circuits`
+    /// circuits, from the number of operations of each constraint per row.
+    /// Nothing is evaluated, so the counts can be compared to the timings of
+    /// an evaluation without slowing it down.
+    pub fn operation_counts(&self, pk: &ProvingKey<C>, num_circuits: usize) -> EvalStats {
+        let cs = &pk.vk.cs;
+        let rows = (pk.vk.domain.extended_len() * num_circuits) as u64;
+        let set_sizes: Vec<_> = cs
+            .permutation
+            .columns
+            .chunks(cs.degree() - 2)
+            .map(|columns| columns.len())
+            .collect();
+        let lookups = self
+            .lookups
+            .iter()
+            .map(|graph| graph.op_counts() + lookup_row_counts())
+            .fold(OpCounts::default(), |total, counts| total + counts);
+        EvalStats {
+            custom_gates: self.custom_gates.op_counts() * rows,
+            permutations: permutation_row_counts(&set_sizes) * rows,
+            lookups: lookups * rows,
+        }
+    }
+
+    /// Summarizes the sizes of the compiled graphs
+    pub fn stats(&self) -> EvaluatorStats {
+        EvaluatorStats {
+            gate_intermediates: self.custom_gates.num_intermediates,
+            gate_constants: self.custom_gates.constants.len(),
+            gate_rotations: self.custom_gates.rotations.len(),
+            per_lookup_intermediates: self
+                .lookups
+                .iter()
+                .map(|graph| graph.num_intermediates)
+                .collect(),
+        }
+    }
+
+    /// Creates the CUDA context of the current thread, loads and compiles the
+    /// kernel module and runs a kernel on a single row, so that the first
+    /// evaluation on this thread does not pay for them, returning how long it
+    /// took. The evaluations run on the first visible device, and the contexts
+    /// are per thread, so this is called on the thread that proves. Calling it
+    /// again only runs the kernel.
+    pub fn warmup_cuda(&self) -> Result<Duration, EvalError> {
+        let start = Instant::now();
+        self.cuda_backend().warmup::<C::ScalarExt>()?;
+        let elapsed = start.elapsed();
+        tracing::info!("CUDA warmup: {:?}", elapsed);
+        Ok(elapsed)
+    }
+
+    /// Uploads the constant buffers of `pk` to the CUDA context of the current
+    /// thread, creating it if needed, ahead of re-proving the circuit with
+    /// other witnesses: `l0`, `l_last` and `l_active_row`, and the fixed cosets
+    /// when a custom gates kernel is registered for the gates. They stay
+    /// resident, so that the following evaluations of `pk` on this thread
+    /// only upload the advice and instance cosets and the buffers of the
+    /// lookups depending on them. It returns how long the upload took.
+    ///
+    /// The evaluations upload them on their first launch otherwise, this only
+    /// moves the cost ahead of the first proof. Like the context, they are per
+    /// thread, so this is called on the thread that proves, and are torn down
+    /// by [`Evaluator::reset_cuda`].
+    pub fn upload_cuda_constants(&self, pk: &ProvingKey<C>) -> Result<Duration, EvalError> {
+        let start = Instant::now();
+        let mut cuda = self.cuda_backend();
+        cuda.upload_constants(pk, cuda.custom_kernel.is_some())?;
+        let elapsed = start.elapsed();
+        tracing::info!(
+            "CUDA constants: {} bytes uploaded in {:?}",
+            cuda.transfers.constant_bytes,
+            elapsed
+        );
+        Ok(elapsed)
+    }
+
+    /// Tears down the CUDA context of the current thread, with its kernel
+    /// modules and the constant buffers resident in it, as
+    /// [`clear_cuda_module_cache`] does, so that the next evaluation on this
+    /// thread creates them again. The context is per thread, and is otherwise
+    /// torn down when the thread exits; provers on other threads keep theirs.
+    pub fn reset_cuda(&self) {
+        clear_cuda_module_cache();
+    }
+
+    /// Evaluates `expression` on the `size` rows of the columns, as
+    /// [`evaluate`] does, on the configured backend. Only the CUDA backend
+    /// evaluates it on the device, the other ones evaluate it on the CPU.
+    ///
+    /// As with h(X), a failure of the device without fallback is returned,
+    /// and the values of the CPU are returned instead if it falls back.
+    pub(in crate::plonk) fn evaluate_expression<B: Basis>(
+        &self,
+        expression: &Expression<C::ScalarExt>,
+        size: usize,
+        rot_scale: i32,
+        fixed: &[Polynomial<C::ScalarExt, B>],
+        advice: &[Polynomial<C::ScalarExt, B>],
+        instance: &[Polynomial<C::ScalarExt, B>],
+        challenges: &[C::ScalarExt],
+    ) -> Result<Vec<C::ScalarExt>, EvalError> {
+        let evaluate_on_cpu = || {
+            evaluate(
+                expression, size, rot_scale, fixed, advice, instance, challenges,
+            )
+        };
+        if !self.resolve_backend(CudaBackend::is_available)?.is_cuda() {
+            return Ok(evaluate_on_cpu());
+        }
+
+        let graph = GraphEvaluator::<C>::from_expression(expression);
+        let result = DeviceGraph::lower(&graph, size, fixed, advice, instance, challenges)
+            .and_then(|graph| self.cuda_backend().evaluate_graph(&graph, size, rot_scale));
+        match result {
+            Ok(values) => Ok(values),
+            Err(err) if self.config.fallback_to_cpu => {
+                self.config
+                    .verbosity
+                    .warn(format!("{}, falling back to the CPU", err));
+                Ok(evaluate_on_cpu())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Evaluate h poly, returning `EvalError::Cancelled` once the token of
+    /// `control` is cancelled and reporting its progress to its callback, both
+    /// before each phase of each circuit. It runs on the pool of
+    /// [`EvaluatorConfig::thread_pool`], if any.
+    ///
+    /// The circuits of a batch are proven together, with a single vanishing
+    /// argument: h(X) is a single polynomial, combining the constraints of
+    /// all the circuits with the powers of y, in the order of the circuits,
+    /// as the verifier folds their evaluations. The values are therefore not
+    /// reset between the circuits, each constraint multiplying the values of
+    /// all the constraints before it, those of the previous circuits
+    /// included, by y. h(X) of a batch is not the sum of those of its
+    /// circuits, and circuits proven on their own are evaluated alone.
     pub(in crate::plonk) fn evaluate_h(
         &self,
         pk: &ProvingKey<C>,
@@ -294,19 +936,49 @@ impl<C: CurveAffine> Evaluator<C> {
         theta: C::ScalarExt,
         lookups: &[Vec<lookup::prover::Committed<C>>],
         permutations: &[permutation::prover::Committed<C>],
-    ) -> Polynomial<C::ScalarExt, ExtendedLagrangeCoeff> {
-        //
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        control: &mut EvalControl<'_>,
+    ) -> Result<Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>, EvalError> {
+        if let Some(EvalThreadPool(pool)) = &self.config.thread_pool {
+            if pool.current_thread_index().is_none() {
+                return pool.install(|| {
+                    self.evaluate_h(
+                        pk,
+                        advice_polys,
+                        instance_polys,
+                        challenges,
+                        y,
+                        beta,
+                        gamma,
+                        theta,
+                        lookups,
+                        permutations,
+                        scratch,
+                        control,
+                    )
+                });
+            }
+        }
 
-        let use_cuda = match env::var("CUDA") {
-            Ok(val) => val,
-            Err(_) => "no".to_string(),
-        };
+        let start = Instant::now();
+        let mut timings = EvalTimings::default();
 
-        let values = if use_cuda == "1" || use_cuda == "y" || use_cuda == "yes" {
-            let evaluate_h_start_timer =
-                start_timer!(|| format!("evaluate_h(...) using {} ", "CUDA".green().bold()));
+        // Checked before anything is evaluated: the circuits are zipped with
+        // their inputs, and a zip silently stops at the shortest of them
+        let no_instance: Vec<&[Polynomial<C::ScalarExt, Coeff>]> = vec![&[]; advice_polys.len()];
+        let checked_instance = match instance_polys {
+            [] if pk.vk.cs.num_instance_columns == 0 => &no_instance[..],
+            instance_polys => instance_polys,
+        };
+        if let Err(err) = check_input_lengths(advice_polys, checked_instance, lookups, permutations)
+        {
+            self.config.verbosity.error(&err);
+            return Err(err);
+        }
 
-            let return_values = self.evaluate_h_with_cuda(
+        let cache_key = match self.config.h_cache_entries {
+            0 => None,
+            _ => Some(self.h_cache_key(
                 pk,
                 advice_polys,
                 instance_polys,
@@ -317,19 +989,248 @@ impl<C: CurveAffine> Evaluator<C> {
                 theta,
                 lookups,
                 permutations,
+            )),
+        };
+        if let Some(values) = cache_key.and_then(|key| self.h_cache.get(key)) {
+            tracing::info!("evaluate_h: cached for these inputs");
+            control.finish();
+            return Ok(values);
+        }
+
+        if self.config.count_operations {
+            tracing::info!(
+                "evaluate_h operations: {}",
+                self.operation_counts(pk, advice_polys.len())
             );
+        }
+
+        let backend = match self.resolve_backend(CudaBackend::is_available) {
+            Ok(backend) => backend,
+            Err(err) => {
+                self.config.verbosity.error(&err);
+                return Err(err);
+            }
+        };
 
-            end_timer!(evaluate_h_start_timer);
+        let result = match backend {
+            Backend::Cuda | Backend::CudaSampledVerify { .. } => {
+                let evaluate_h_start_timer =
+                    start_timer!(|| format!("evaluate_h(...) using {} ", "CUDA".green().bold()));
 
-            return_values
-        } else {
-            let evaluate_h_start_timer =
-                start_timer!(|| format!("evaluate_h(...) using {} ", "CPU only".red().bold()));
+                let mut cuda = self.cuda_backend();
+                let result = self.evaluate_h_with_fallback(
+                    &mut cuda,
+                    pk,
+                    advice_polys,
+                    instance_polys,
+                    challenges,
+                    y,
+                    beta,
+                    gamma,
+                    theta,
+                    lookups,
+                    permutations,
+                    scratch,
+                    &mut timings,
+                    control,
+                );
+                let result = match (backend, result) {
+                    (Backend::CudaSampledVerify { rows, seed }, Ok(values))
+                        if !timings.fallback =>
+                    {
+                        self.verify_sampled_rows(
+                            pk,
+                            challenges,
+                            y,
+                            beta,
+                            gamma,
+                            theta,
+                            lookups,
+                            permutations,
+                            scratch,
+                            &values,
+                            rows,
+                            seed,
+                        )
+                        .map(|()| values)
+                        .or_else(|err| {
+                            if !self.config.fallback_to_cpu {
+                                return Err(err);
+                            }
+                            self.config
+                                .verbosity
+                                .warn(format!("{}, falling back to the CPU", err));
+                            let mut values = pk.vk.domain.empty_extended();
+                            timings.restart();
+                            timings.fallback = true;
+                            self.on_cpu(|| {
+                                self.evaluate_h_with_backend(
+                                    &mut CpuBackend,
+                                    pk,
+                                    advice_polys,
+                                    instance_polys,
+                                    challenges,
+                                    y,
+                                    beta,
+                                    gamma,
+                                    theta,
+                                    lookups,
+                                    permutations,
+                                    &mut values,
+                                    scratch,
+                                    &mut timings,
+                                    control,
+                                )
+                            })?;
+                            Ok(values)
+                        })
+                    }
+                    (_, result) => result,
+                };
+                if self.config.release_cuda_memory {
+                    clear_cuda_module_cache();
+                }
+                if !timings.fallback {
+                    timings.transfers = cuda.transfers;
+                    if let (Some(up), Some(down)) =
+                        (cuda.transfers.upload_gbps(), cuda.transfers.download_gbps())
+                    {
+                        tracing::info!(
+                            "CUDA transfers: {:.2} GB/s to the device, {:.2} GB/s back",
+                            up,
+                            down
+                        );
+                    }
+                }
 
-            let return_values = self.evaluate_h_cpu_only(
+                end_timer!(evaluate_h_start_timer);
+
+                result
+            }
+            #[cfg(feature = "opencl")]
+            Backend::OpenCl => {
+                let evaluate_h_start_timer =
+                    start_timer!(|| format!("evaluate_h(...) using {} ", "OpenCL".green().bold()));
+
+                let result = self.evaluate_h_with_fallback(
+                    &mut OpenClBackend,
+                    pk,
+                    advice_polys,
+                    instance_polys,
+                    challenges,
+                    y,
+                    beta,
+                    gamma,
+                    theta,
+                    lookups,
+                    permutations,
+                    scratch,
+                    &mut timings,
+                    control,
+                );
+
+                end_timer!(evaluate_h_start_timer);
+
+                result
+            }
+            #[cfg(feature = "webgpu")]
+            Backend::Wgpu => {
+                let evaluate_h_start_timer =
+                    start_timer!(|| format!("evaluate_h(...) using {} ", "wgpu".green().bold()));
+
+                let result = self.evaluate_h_with_fallback(
+                    &mut WgpuBackend,
+                    pk,
+                    advice_polys,
+                    instance_polys,
+                    challenges,
+                    y,
+                    beta,
+                    gamma,
+                    theta,
+                    lookups,
+                    permutations,
+                    scratch,
+                    &mut timings,
+                    control,
+                );
+
+                end_timer!(evaluate_h_start_timer);
+
+                result
+            }
+            Backend::Cpu => {
+                let evaluate_h_start_timer =
+                    start_timer!(|| format!("evaluate_h(...) using {} ", "CPU only".red().bold()));
+
+                let result = self.on_cpu(|| match self.spill_path(pk) {
+                    Some(path) => self.evaluate_h_spilled(
+                        pk,
+                        advice_polys,
+                        checked_instance,
+                        challenges,
+                        y,
+                        beta,
+                        gamma,
+                        theta,
+                        lookups,
+                        permutations,
+                        path,
+                        control,
+                    ),
+                    None if self.parallel_circuits(pk, advice_polys.len()) => self
+                        .evaluate_h_circuits_in_parallel(
+                            pk,
+                            advice_polys,
+                            instance_polys,
+                            challenges,
+                            y,
+                            beta,
+                            gamma,
+                            theta,
+                            lookups,
+                            permutations,
+                            scratch,
+                            &mut timings,
+                            control,
+                        ),
+                    None => self.evaluate_h_with_fallback(
+                        &mut CpuBackend,
+                        pk,
+                        advice_polys,
+                        instance_polys,
+                        challenges,
+                        y,
+                        beta,
+                        gamma,
+                        theta,
+                        lookups,
+                        permutations,
+                        scratch,
+                        &mut timings,
+                        control,
+                    ),
+                });
+
+                end_timer!(evaluate_h_start_timer);
+
+                result
+            }
+        };
+
+        let values = match result {
+            Ok(values) => values,
+            Err(err) => {
+                self.config.verbosity.error(&err);
+                return Err(err);
+            }
+        };
+
+        if self.config.assert_vanishing {
+            self.assert_vanishing(
                 pk,
                 advice_polys,
-                instance_polys,
+                checked_instance,
                 challenges,
                 y,
                 beta,
@@ -337,17 +1238,29 @@ impl<C: CurveAffine> Evaluator<C> {
                 theta,
                 lookups,
                 permutations,
+                &values,
             );
+        }
 
-            end_timer!(evaluate_h_start_timer);
+        if let Some(key) = cache_key {
+            self.h_cache
+                .insert(key, &values, self.config.h_cache_entries);
+        }
 
-            return_values
-        };
+        if let Some(path) = &self.config.timing_report {
+            timings.total = start.elapsed();
+            self.report_timings(pk, backend, advice_polys.len(), timings, path);
+        }
 
-        values
+        control.finish();
+        Ok(values)
     }
 
-    fn evaluate_h_cpu_only(
+    /// Panics if `values` of h(X) do not vanish on the domain, with the first
+    /// row at which they do not. The phases of each circuit are then evaluated
+    /// again on their own, on the CPU, for the panic to name the first circuit
+    /// and phase with a constraint not holding at some row, and that row.
+    fn assert_vanishing(
         &self,
         pk: &ProvingKey<C>,
         advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
@@ -359,251 +1272,77 @@ impl<C: CurveAffine> Evaluator<C> {
         theta: C::ScalarExt,
         lookups: &[Vec<lookup::prover::Committed<C>>],
         permutations: &[permutation::prover::Committed<C>],
-    ) -> Polynomial<C::ScalarExt, ExtendedLagrangeCoeff> {
+        values: &Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) {
         let domain = &pk.vk.domain;
-        let size = domain.extended_len();
-        let rot_scale = 1 << (domain.extended_k() - domain.k());
-        let fixed = &pk.fixed_cosets[..];
-        let extended_omega = domain.get_extended_omega();
-        let isize = size as i32;
-        let one = C::ScalarExt::one();
-        let l0 = &pk.l0;
-        let l_last = &pk.l_last;
-        let l_active_row = &pk.l_active_row;
-        let p = &pk.vk.cs.permutation;
-
-        // Calculate the advice and instance cosets
-        let advice: Vec<Vec<Polynomial<C::Scalar, ExtendedLagrangeCoeff>>> = advice_polys
-            .iter()
-            .map(|advice_polys| {
-                advice_polys
-                    .iter()
-                    .map(|poly| domain.coeff_to_extended(poly.clone()))
-                    .collect()
-            })
-            .collect();
-        let instance: Vec<Vec<Polynomial<C::Scalar, ExtendedLagrangeCoeff>>> = instance_polys
-            .iter()
-            .map(|instance_polys| {
-                instance_polys
-                    .iter()
-                    .map(|poly| domain.coeff_to_extended(poly.clone()))
-                    .collect()
-            })
-            .collect();
-
-        let mut values = domain.empty_extended();
+        let row = match find_violating_row(values, domain) {
+            Some(row) => row,
+            None => return,
+        };
 
-        // Core expression evaluations
-        let num_threads = multicore::current_num_threads();
-        for (((advice, instance), lookups), permutation) in advice
+        let advice = extended_cosets(domain, advice_polys);
+        let instance = extended_cosets(domain, instance_polys);
+        for (n, (((advice, instance), lookups), permutation)) in advice
             .iter()
             .zip(instance.iter())
             .zip(lookups.iter())
             .zip(permutations.iter())
+            .enumerate()
         {
-            // Custom gates
-            multicore::scope(|scope| {
-                let chunk_size = (size + num_threads - 1) / num_threads;
-                for (thread_idx, values) in values.chunks_mut(chunk_size).enumerate() {
-                    let start = thread_idx * chunk_size;
-                    scope.spawn(move |_| {
-                        let mut eval_data = self.custom_gates.instance();
-                        for (i, value) in values.iter_mut().enumerate() {
-                            let idx = start + i;
-                            *value = self.custom_gates.evaluate(
-                                &mut eval_data,
-                                fixed,
-                                advice,
-                                instance,
-                                challenges,
-                                &beta,
-                                &gamma,
-                                &theta,
-                                &y,
-                                value,
-                                idx,
-                                rot_scale,
-                                isize,
-                            );
-                        }
-                    });
+            let data = CircuitData {
+                pk,
+                advice,
+                instance,
+                challenges,
+                y,
+                beta,
+                gamma,
+                theta,
+            };
+            let phases: [(ProgressPhase, Box<dyn Fn(&mut [C::ScalarExt]) + '_>); 3] = [
+                (
+                    ProgressPhase::CustomGates,
+                    Box::new(|values| CpuBackend::custom_gates_rows(self, &data, values, 0)),
+                ),
+                (
+                    ProgressPhase::Permutations,
+                    Box::new(|values| CpuBackend::permutations_rows(&data, permutation, values, 0)),
+                ),
+                (
+                    ProgressPhase::Lookups,
+                    Box::new(|values| {
+                        let cosets = CpuBackend::all_lookup_cosets(pk, lookups);
+                        CpuBackend::lookups_rows(self, &data, &cosets, values, 0)
+                    }),
+                ),
+            ];
+            for (phase, evaluate) in phases.iter() {
+                let mut values = domain.empty_extended();
+                evaluate(&mut values);
+                if let Some(row) = find_violating_row(&values, domain) {
+                    panic!(
+                        "h(X) does not vanish at row {} of the domain: the {:?} of circuit {} do not hold there",
+                        row, phase, n
+                    );
                 }
-            });
-
-            // Permutations
-            let sets = &permutation.sets;
-            if !sets.is_empty() {
-                let blinding_factors = pk.vk.cs.blinding_factors();
-                let last_rotation = Rotation(-((blinding_factors + 1) as i32));
-                let chunk_len = pk.vk.cs.degree() - 2;
-                let delta_start = beta * &C::Scalar::ZETA;
-
-                let first_set = sets.first().unwrap();
-                let last_set = sets.last().unwrap();
-
-                // Permutation constraints
-                parallelize(&mut values, |values, start| {
-                    let mut beta_term = extended_omega.pow_vartime(&[start as u64, 0, 0, 0]);
-                    for (i, value) in values.iter_mut().enumerate() {
-                        let idx = start + i;
-                        let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-                        let r_last = get_rotation_idx(idx, last_rotation.0, rot_scale, isize);
-
-                        // Enforce only for the first set.
-                        // l_0(X) * (1 - z_0(X)) = 0
-                        *value = *value * y
-                            + ((one - first_set.permutation_product_coset[idx]) * l0[idx]);
-                        // Enforce only for the last set.
-                        // l_last(X) * (z_l(X)^2 - z_l(X)) = 0
-                        *value = *value * y
-                            + ((last_set.permutation_product_coset[idx]
-                                * last_set.permutation_product_coset[idx]
-                                - last_set.permutation_product_coset[idx])
-                                * l_last[idx]);
-                        // Except for the first set, enforce.
-                        // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) = 0
-                        for (set_idx, set) in sets.iter().enumerate() {
-                            if set_idx != 0 {
-                                *value = *value * y
-                                    + ((set.permutation_product_coset[idx]
-                                        - permutation.sets[set_idx - 1].permutation_product_coset
-                                            [r_last])
-                                        * l0[idx]);
-                            }
-                        }
-                        // And for all the sets we enforce:
-                        // (1 - (l_last(X) + l_blind(X))) * (
-                        //   z_i(\omega X) \prod_j (p(X) + \beta s_j(X) + \gamma)
-                        // - z_i(X) \prod_j (p(X) + \delta^j \beta X + \gamma)
-                        // )
-                        let mut current_delta = delta_start * beta_term;
-                        for ((set, columns), cosets) in sets
-                            .iter()
-                            .zip(p.columns.chunks(chunk_len))
-                            .zip(pk.permutation.cosets.chunks(chunk_len))
-                        {
-                            let mut left = set.permutation_product_coset[r_next];
-                            for (values, permutation) in columns
-                                .iter()
-                                .map(|&column| match column.column_type() {
-                                    Any::Advice(_) => &advice[column.index()],
-                                    Any::Fixed => &fixed[column.index()],
-                                    Any::Instance => &instance[column.index()],
-                                })
-                                .zip(cosets.iter())
-                            {
-                                left *= values[idx] + beta * permutation[idx] + gamma;
-                            }
-
-                            let mut right = set.permutation_product_coset[idx];
-                            for values in columns.iter().map(|&column| match column.column_type() {
-                                Any::Advice(_) => &advice[column.index()],
-                                Any::Fixed => &fixed[column.index()],
-                                Any::Instance => &instance[column.index()],
-                            }) {
-                                right *= values[idx] + current_delta + gamma;
-                                current_delta *= &C::Scalar::DELTA;
-                            }
-
-                            *value = *value * y + ((left - right) * l_active_row[idx]);
-                        }
-                        beta_term *= &extended_omega;
-                    }
-                });
-            }
-
-            // Lookups
-
-            let start_timer = start_timer!(|| format!(
-                "{}{}{}{}",
-                "Lookups : lookups.len()=".dimmed(),
-                lookups.len().to_string().dimmed().bold(),
-                ", values.len()=".dimmed(),
-                values.len().to_string().dimmed().bold(),
-            ));
-
-            for (n, lookup) in lookups.iter().enumerate() {
-                // Polynomials required for this lookup.
-                // Calculated here so these only have to be kept in memory for the short time
-                // they are actually needed.
-                let product_coset = pk.vk.domain.coeff_to_extended(lookup.product_poly.clone());
-                let permuted_input_coset = pk
-                    .vk
-                    .domain
-                    .coeff_to_extended(lookup.permuted_input_poly.clone());
-                let permuted_table_coset = pk
-                    .vk
-                    .domain
-                    .coeff_to_extended(lookup.permuted_table_poly.clone());
-
-                // Lookup constraints
-                parallelize(&mut values, |values, start| {
-                    let lookup_evaluator = &self.lookups[n];
-                    let mut eval_data = lookup_evaluator.instance();
-                    for (i, value) in values.iter_mut().enumerate() {
-                        let idx = start + i;
-
-                        let table_value = lookup_evaluator.evaluate(
-                            &mut eval_data,
-                            fixed,
-                            advice,
-                            instance,
-                            challenges,
-                            &beta,
-                            &gamma,
-                            &theta,
-                            &y,
-                            &C::ScalarExt::zero(),
-                            idx,
-                            rot_scale,
-                            isize,
-                        );
-
-                        let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-                        let r_prev = get_rotation_idx(idx, -1, rot_scale, isize);
-
-                        let a_minus_s = permuted_input_coset[idx] - permuted_table_coset[idx];
-                        // l_0(X) * (1 - z(X)) = 0
-                        *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
-                        // l_last(X) * (z(X)^2 - z(X)) = 0
-                        *value = *value * y
-                            + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
-                                * l_last[idx]);
-                        // (1 - (l_last(X) + l_blind(X))) * (
-                        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
-                        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
-                        //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
-                        // ) = 0
-                        *value = *value * y
-                            + ((product_coset[r_next]
-                                * (permuted_input_coset[idx] + beta)
-                                * (permuted_table_coset[idx] + gamma)
-                                - product_coset[idx] * table_value)
-                                * l_active_row[idx]);
-                        // Check that the first values in the permuted input expression and permuted
-                        // fixed expression are the same.
-                        // l_0(X) * (a'(X) - s'(X)) = 0
-                        *value = *value * y + (a_minus_s * l0[idx]);
-                        // Check that each value in the permuted lookup input expression is either
-                        // equal to the value above it, or the value at the same index in the
-                        // permuted table expression.
-                        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
-                        *value = *value * y
-                            + (a_minus_s
-                                * (permuted_input_coset[idx] - permuted_input_coset[r_prev])
-                                * l_active_row[idx]);
-                    }
-                });
             }
-
-            end_timer!(start_timer);
         }
-        values
+        panic!("h(X) does not vanish at row {} of the domain", row);
     }
 
-    fn evaluate_h_with_cuda(
+    /// `evaluate_h` into `out`, a buffer the caller owns, e.g. from a pool of
+    /// buffers recycled across proofs, instead of a new one. It must have a
+    /// value for each row of the extended domain, otherwise
+    /// `EvalError::OutputLengthMismatch` is returned. The backends evaluating
+    /// into a buffer of values evaluate into `out` itself, the values
+    /// evaluated otherwise, e.g. cached or spilled, are copied into it. On
+    /// an error, `out` keeps its length but not its values. The prover
+    /// evaluates into the buffer of its previous proof when it keeps its
+    /// scratch, see [`EvaluatorConfig::keep_scratch`]. Returns the parameters
+    /// of the extended domain of the values, as `evaluate_h_with_context`.
+    pub(in crate::plonk) fn evaluate_h_into(
         &self,
+        out: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
         pk: &ProvingKey<C>,
         advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
         instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
@@ -614,417 +1353,1154 @@ impl<C: CurveAffine> Evaluator<C> {
         theta: C::ScalarExt,
         lookups: &[Vec<lookup::prover::Committed<C>>],
         permutations: &[permutation::prover::Committed<C>],
-    ) -> Polynomial<C::ScalarExt, ExtendedLagrangeCoeff> {
-        //
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        control: &mut EvalControl<'_>,
+    ) -> Result<ExtendedContext<C::ScalarExt>, EvalError> {
+        let extended_len = pk.vk.domain.extended_len();
+        if out.len() != extended_len {
+            let err = EvalError::OutputLengthMismatch {
+                expected: extended_len,
+                actual: out.len(),
+            };
+            self.config.verbosity.error(&err);
+            return Err(err);
+        }
 
-        let domain = &pk.vk.domain;
-        let size = domain.extended_len();
-        let rot_scale = 1 << (domain.extended_k() - domain.k());
-        let fixed = &pk.fixed_cosets[..];
-        let extended_omega = domain.get_extended_omega();
-        let isize = size as i32;
-        let one = C::ScalarExt::one();
-        let l0 = &pk.l0;
-        let l_last = &pk.l_last;
-        let l_active_row = &pk.l_active_row;
-        let p = &pk.vk.cs.permutation;
-        let mut values = domain.empty_extended();
+        scratch.output = Some(mem::take(&mut out.values));
+        let result = self.evaluate_h_with_context(
+            pk,
+            advice_polys,
+            instance_polys,
+            challenges,
+            y,
+            beta,
+            gamma,
+            theta,
+            lookups,
+            permutations,
+            scratch,
+            control,
+        );
+        // Left in the scratch when the values were not evaluated into it
+        let buffer = scratch.output.take();
+        match (result, buffer) {
+            (Ok((values, context)), None) => {
+                out.values = values.values;
+                Ok(context)
+            }
+            (Ok((values, context)), Some(mut buffer)) => {
+                buffer.copy_from_slice(&values);
+                out.values = buffer;
+                Ok(context)
+            }
+            (Err(err), buffer) => {
+                out.values = buffer.unwrap_or_else(|| vec![C::ScalarExt::zero(); extended_len]);
+                Err(err)
+            }
+        }
+    }
 
-        // Calculate the advice and instance cosets
-        let advice: Vec<Vec<Polynomial<C::Scalar, ExtendedLagrangeCoeff>>> = advice_polys
-            .iter()
-            .map(|advice_polys| {
-                advice_polys
-                    .iter()
-                    .map(|poly| domain.coeff_to_extended(poly.clone()))
-                    .collect()
-            })
-            .collect();
-        let instance: Vec<Vec<Polynomial<C::Scalar, ExtendedLagrangeCoeff>>> = instance_polys
-            .iter()
-            .map(|instance_polys| {
-                instance_polys
-                    .iter()
-                    .map(|poly| domain.coeff_to_extended(poly.clone()))
-                    .collect()
-            })
-            .collect();
+    /// `evaluate_h_into` for a single circuit, with its inputs as they are
+    /// rather than in slices of one circuit each, as `create_proof` evaluates
+    /// a proof of one circuit. The batch `evaluate_h` remains the primitive,
+    /// which this forwards to.
+    // The batch method takes the lookups of each circuit in a `Vec`
+    #[allow(clippy::ptr_arg)]
+    pub(in crate::plonk) fn evaluate_h_single(
+        &self,
+        out: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        pk: &ProvingKey<C>,
+        advice_polys: &[Polynomial<C::ScalarExt, Coeff>],
+        instance_polys: &[Polynomial<C::ScalarExt, Coeff>],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &Vec<lookup::prover::Committed<C>>,
+        permutation: &permutation::prover::Committed<C>,
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        control: &mut EvalControl<'_>,
+    ) -> Result<ExtendedContext<C::ScalarExt>, EvalError> {
+        self.evaluate_h_into(
+            out,
+            pk,
+            &[advice_polys],
+            &[instance_polys],
+            challenges,
+            y,
+            beta,
+            gamma,
+            theta,
+            slice::from_ref(lookups),
+            slice::from_ref(permutation),
+            scratch,
+            control,
+        )
+    }
 
-        // Core expression evaluations
-        let num_threads = multicore::current_num_threads();
-        for (((advice, instance), lookups), permutation) in advice
+    /// `evaluate_h`, returning the parameters of the extended domain of the
+    /// values along with them
+    pub(in crate::plonk) fn evaluate_h_with_context(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        control: &mut EvalControl<'_>,
+    ) -> Result<
+        (
+            Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+            ExtendedContext<C::ScalarExt>,
+        ),
+        EvalError,
+    > {
+        let values = self.evaluate_h(
+            pk,
+            advice_polys,
+            instance_polys,
+            challenges,
+            y,
+            beta,
+            gamma,
+            theta,
+            lookups,
+            permutations,
+            scratch,
+            control,
+        )?;
+        Ok((values, ExtendedContext::new(&pk.vk.domain)))
+    }
+
+    /// Evaluates again on the CPU the `rows` rows of h(X) sampled from `seed`,
+    /// from the cosets left in `scratch` by the evaluation of `values`, and
+    /// compares them with those of `values`. Each lookup computes its cosets
+    /// again, which is the main cost of the check with few rows.
+    fn verify_sampled_rows(
+        &self,
+        pk: &ProvingKey<C>,
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        scratch: &EvalScratch<C::ScalarExt>,
+        values: &Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        rows: usize,
+        seed: u64,
+    ) -> Result<(), EvalError> {
+        let sampled = sample_rows(seed, rows, values.len());
+        let mut expected = vec![C::ScalarExt::zero(); sampled.len()];
+        for (n, (advice, instance)) in scratch
+            .advice
             .iter()
-            .zip(instance.iter())
-            .zip(lookups.iter())
-            .zip(permutations.iter())
+            .zip(scratch.instance.iter())
+            .enumerate()
         {
-            // Custom gates
-            multicore::scope(|scope| {
-                let chunk_size = (size + num_threads - 1) / num_threads;
-                for (thread_idx, values) in values.chunks_mut(chunk_size).enumerate() {
-                    let start = thread_idx * chunk_size;
-                    scope.spawn(move |_| {
-                        let mut eval_data = self.custom_gates.instance();
-                        for (i, value) in values.iter_mut().enumerate() {
-                            let idx = start + i;
-                            *value = self.custom_gates.evaluate(
-                                &mut eval_data,
-                                fixed,
-                                advice,
-                                instance,
-                                challenges,
-                                &beta,
-                                &gamma,
-                                &theta,
-                                &y,
-                                value,
-                                idx,
-                                rot_scale,
-                                isize,
-                            );
-                        }
-                    });
+            let data = CircuitData {
+                pk,
+                advice,
+                instance,
+                challenges,
+                y,
+                beta,
+                gamma,
+                theta,
+            };
+            for (value, &row) in expected.iter_mut().zip(sampled.iter()) {
+                CpuBackend::custom_gates_rows(self, &data, slice::from_mut(value), row);
+                CpuBackend::permutations_rows(&data, &permutations[n], slice::from_mut(value), row);
+            }
+            for (idx, lookup) in lookups[n].iter().enumerate() {
+                let cosets = CpuBackend::lookup_cosets(pk, lookup);
+                for (value, &row) in expected.iter_mut().zip(sampled.iter()) {
+                    CpuBackend::lookup_rows(self, &data, idx, &cosets, slice::from_mut(value), row);
                 }
-            });
+            }
+        }
 
-            // Permutations
-            let sets = &permutation.sets;
-            if !sets.is_empty() {
-                let blinding_factors = pk.vk.cs.blinding_factors();
-                let last_rotation = Rotation(-((blinding_factors + 1) as i32));
-                let chunk_len = pk.vk.cs.degree() - 2;
-                let delta_start = beta * &C::Scalar::ZETA;
-
-                let first_set = sets.first().unwrap();
-                let last_set = sets.last().unwrap();
-
-                // Permutation constraints
-                parallelize(&mut values, |values, start| {
-                    let mut beta_term = extended_omega.pow_vartime(&[start as u64, 0, 0, 0]);
-                    for (i, value) in values.iter_mut().enumerate() {
-                        let idx = start + i;
-                        let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-                        let r_last = get_rotation_idx(idx, last_rotation.0, rot_scale, isize);
-
-                        // Enforce only for the first set.
-                        // l_0(X) * (1 - z_0(X)) = 0
-                        *value = *value * y
-                            + ((one - first_set.permutation_product_coset[idx]) * l0[idx]);
-                        // Enforce only for the last set.
-                        // l_last(X) * (z_l(X)^2 - z_l(X)) = 0
-                        *value = *value * y
-                            + ((last_set.permutation_product_coset[idx]
-                                * last_set.permutation_product_coset[idx]
-                                - last_set.permutation_product_coset[idx])
-                                * l_last[idx]);
-                        // Except for the first set, enforce.
-                        // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) = 0
-                        for (set_idx, set) in sets.iter().enumerate() {
-                            if set_idx != 0 {
-                                *value = *value * y
-                                    + ((set.permutation_product_coset[idx]
-                                        - permutation.sets[set_idx - 1].permutation_product_coset
-                                            [r_last])
-                                        * l0[idx]);
-                            }
-                        }
-                        // And for all the sets we enforce:
-                        // (1 - (l_last(X) + l_blind(X))) * (
-                        //   z_i(\omega X) \prod_j (p(X) + \beta s_j(X) + \gamma)
-                        // - z_i(X) \prod_j (p(X) + \delta^j \beta X + \gamma)
-                        // )
-                        let mut current_delta = delta_start * beta_term;
-                        for ((set, columns), cosets) in sets
-                            .iter()
-                            .zip(p.columns.chunks(chunk_len))
-                            .zip(pk.permutation.cosets.chunks(chunk_len))
-                        {
-                            let mut left = set.permutation_product_coset[r_next];
-                            for (values, permutation) in columns
-                                .iter()
-                                .map(|&column| match column.column_type() {
-                                    Any::Advice(_) => &advice[column.index()],
-                                    Any::Fixed => &fixed[column.index()],
-                                    Any::Instance => &instance[column.index()],
-                                })
-                                .zip(cosets.iter())
-                            {
-                                left *= values[idx] + beta * permutation[idx] + gamma;
-                            }
+        match sampled
+            .iter()
+            .zip(expected.iter())
+            .find(|(row, expected)| values[**row] != **expected)
+        {
+            Some((&row, _)) => Err(EvalError::VerificationFailed { row }),
+            None => Ok(()),
+        }
+    }
 
-                            let mut right = set.permutation_product_coset[idx];
-                            for values in columns.iter().map(|&column| match column.column_type() {
-                                Any::Advice(_) => &advice[column.index()],
-                                Any::Fixed => &fixed[column.index()],
-                                Any::Instance => &instance[column.index()],
-                            }) {
-                                right *= values[idx] + current_delta + gamma;
-                                current_delta *= &C::Scalar::DELTA;
-                            }
+    /// The key of the evaluation of h(X) for these inputs in the cache: a
+    /// BLAKE2b digest of the circuit, through its verifying key, of the
+    /// challenges and of every polynomial, the lookups and permutations
+    /// included as they carry random blinding factors
+    fn h_cache_key(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+    ) -> HCacheKey {
+        let mut scalars = vec![pk.vk.transcript_repr, y, beta, gamma, theta];
+        scalars.extend_from_slice(challenges);
 
-                            *value = *value * y + ((left - right) * l_active_row[idx]);
-                        }
-                        beta_term *= &extended_omega;
-                    }
-                });
+        // The number of polynomials of each kind, for lists of polynomials
+        // split differently to hash differently
+        let mut shape = vec![challenges.len(), advice_polys.len()];
+        let mut polys = vec![];
+        for (idx, advice) in advice_polys.iter().enumerate() {
+            let instance = instance_polys.get(idx).copied().unwrap_or(&[]);
+            polys.extend(advice.iter().chain(instance.iter()));
+            shape.extend([advice.len(), instance.len()]);
+
+            let lookups = lookups.get(idx).map_or(&[][..], Vec::as_slice);
+            for lookup in lookups {
+                polys.extend([
+                    &lookup.permuted_input_poly,
+                    &lookup.permuted_table_poly,
+                    &lookup.product_poly,
+                ]);
             }
+            let sets = permutations
+                .get(idx)
+                .map_or(&[][..], |permutation| &permutation.sets[..]);
+            polys.extend(sets.iter().map(|set| &set.permutation_product_poly));
+            shape.extend([lookups.len(), sets.len()]);
+        }
+        scalars.extend(shape.iter().map(|len| C::ScalarExt::from(*len as u64)));
 
-            // Lookups
+        hash_inputs(&scalars, &polys)
+    }
 
-            let start_timer = start_timer!(|| format!(
-                "{}{}{}{}",
-                "Lookups : lookups.len()=".dimmed(),
-                lookups.len().to_string().dimmed().bold(),
-                ", values.len()=".dimmed(),
-                values.len().to_string().dimmed().bold(),
-            ));
+    /// Appends the `timings` of an evaluation with `backend` to the report at
+    /// `path`, warning if it cannot be written
+    fn report_timings(
+        &self,
+        pk: &ProvingKey<C>,
+        backend: Backend,
+        num_circuits: usize,
+        timings: EvalTimings,
+        path: &Path,
+    ) {
+        let timings = EvalTimings {
+            backend: backend.name(),
+            device: match (backend, timings.fallback) {
+                (backend, false) if backend.is_cuda() => Some(CudaBackend::device_name()),
+                _ => None,
+            },
+            k: pk.vk.domain.k(),
+            extended_k: pk.vk.domain.extended_k(),
+            num_circuits,
+            num_lookups: self.lookups.len(),
+            ..timings
+        };
+        if let Err(err) = timings.append_to(path) {
+            tracing::warn!("cannot write the timing report {}: {}", path.display(), err);
+        }
+    }
 
-            let lookup_count: i32 = (lookups.len()).try_into().unwrap();
-            let array_size: i32 = (values.values.len()).try_into().unwrap();
-            let chunk_size = (values.values.len() + num_threads - 1) / num_threads;
+    /// Evaluates the rows `rows` of the extended domain of h(X), before the
+    /// division by the vanishing polynomial, on the CPU.
+    ///
+    /// This is meant for bisecting a failing constraint without evaluating the
+    /// whole domain. The cosets are still computed in full, as the rotations
+    /// of the rows may read outside of `rows`, wrapping around the domain. As
+    /// in `evaluate_h`, a circuit without instance columns may come without
+    /// instance polynomials.
+    pub(in crate::plonk) fn evaluate_h_range(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        rows: Range<usize>,
+    ) -> Result<Vec<C::ScalarExt>, EvalError> {
+        let domain = &pk.vk.domain;
+        if rows.start > rows.end || rows.end > domain.extended_len() {
+            return Err(EvalError::RowsOutOfRange {
+                start: rows.start,
+                end: rows.end,
+                size: domain.extended_len(),
+            });
+        }
+        // As in `evaluate_h_with_backend`
+        let no_instance: Vec<&[Polynomial<C::ScalarExt, Coeff>]> = vec![&[]; advice_polys.len()];
+        let instance_polys = match instance_polys {
+            [] if pk.vk.cs.num_instance_columns == 0 => &no_instance[..],
+            instance_polys => instance_polys,
+        };
+        check_input_lengths(advice_polys, instance_polys, lookups, permutations)?;
 
-            assert_eq!(
-                std::mem::size_of::<(C::ScalarExt, C::ScalarExt, usize, usize)>(),
-                80
-            );
-            let mut combined_data_in: Vec<Vec<(C::ScalarExt, C::ScalarExt, usize, usize)>> = vec![
-                    vec![(C::ScalarExt::zero(), C::ScalarExt::zero(), 0, 0); values.len()];
-                    lookups.len()
-                ];
-            let mut product_coset_list: Vec<Vec<C::ScalarExt>> =
-                vec![vec![C::ScalarExt::zero(); values.len()]; lookups.len()];
-            let mut permuted_input_coset_list: Vec<Vec<C::ScalarExt>> =
-                vec![vec![C::ScalarExt::zero(); values.len()]; lookups.len()];
-            let mut permuted_table_coset_list: Vec<Vec<C::ScalarExt>> =
-                vec![vec![C::ScalarExt::zero(); values.len()]; lookups.len()];
-            let y_beta_gamma_one: Vec<C::ScalarExt> = vec![y, beta, gamma, one];
-
-            let block_1_start_timer = start_timer!(|| String::from("Lookups : Block 1"));
-
-            for (n, lookup) in lookups.iter().enumerate() {
-                // Polynomials required for this lookup.
-                // Calculated here so these only have to be kept in memory for the short time
-                // they are actually needed.
-                let product_coset = pk.vk.domain.coeff_to_extended(lookup.product_poly.clone());
-                let permuted_input_coset = pk
-                    .vk
-                    .domain
-                    .coeff_to_extended(lookup.permuted_input_poly.clone());
-                let permuted_table_coset = pk
-                    .vk
-                    .domain
-                    .coeff_to_extended(lookup.permuted_table_poly.clone());
+        let mut values = vec![C::ScalarExt::zero(); rows.len()];
+        if values.is_empty() {
+            return Ok(values);
+        }
 
-                multicore::scope(|scope| {
-                    for (thread_idx, combined_data_in) in
-                        combined_data_in[n].chunks_mut(chunk_size).enumerate()
-                    {
-                        let start = thread_idx * chunk_size;
-                        let permuted_input_coset_ref = &permuted_input_coset.values;
-                        let permuted_table_coset_ref = &permuted_table_coset.values;
-                        let lookup_evaluator = &self.lookups[n];
-                        let mut eval_data = lookup_evaluator.instance();
-
-                        scope.spawn(move |_| {
-                            for (i, combined) in combined_data_in.iter_mut().enumerate() {
-                                let idx = start + i;
-
-                                let table_value = lookup_evaluator.evaluate(
-                                    &mut eval_data,
-                                    fixed,
-                                    advice,
-                                    instance,
-                                    challenges,
-                                    &beta,
-                                    &gamma,
-                                    &theta,
-                                    &y,
-                                    &C::ScalarExt::zero(),
-                                    idx,
-                                    rot_scale,
-                                    isize,
-                                );
-
-                                let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-                                let r_prev = get_rotation_idx(idx, -1, rot_scale, isize);
-
-                                let a_minus_s =
-                                    permuted_input_coset_ref[idx] - permuted_table_coset_ref[idx];
-
-                                combined.0 = table_value;
-                                combined.1 = a_minus_s;
-                                combined.2 = r_next;
-                                combined.3 = r_prev;
-                            }
-                        });
-                    }
-                });
+        let advice = extended_cosets(domain, advice_polys);
+        let instance = extended_cosets(domain, instance_polys);
+        for (((advice, instance), lookups), permutation) in advice
+            .iter()
+            .zip(instance.iter())
+            .zip(lookups.iter())
+            .zip(permutations.iter())
+        {
+            let data = CircuitData {
+                pk,
+                advice,
+                instance,
+                challenges,
+                y,
+                beta,
+                gamma,
+                theta,
+            };
+            CpuBackend::custom_gates_rows(self, &data, &mut values, rows.start);
+            CpuBackend::permutations_rows(&data, permutation, &mut values, rows.start);
+            let cosets = CpuBackend::all_lookup_cosets(pk, lookups);
+            CpuBackend::lookups_rows(self, &data, &cosets, &mut values, rows.start);
+        }
 
-                product_coset_list[n] = product_coset.values;
-                permuted_input_coset_list[n] = permuted_input_coset.values;
-                permuted_table_coset_list[n] = permuted_table_coset.values;
-            }
+        Ok(values)
+    }
 
-            end_timer!(block_1_start_timer);
+    /// `evaluate_h_range` from the polynomials of the prover, in coefficient
+    /// form, to bisect a failing constraint from outside the crate: for each
+    /// circuit, the permuted input, permuted table and product polynomials of
+    /// each of its lookups, and the product polynomial of each set of its
+    /// permutation.
+    pub fn evaluate_h_rows(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookup_polys: &[&[[Polynomial<C::ScalarExt, Coeff>; 3]]],
+        permutation_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        rows: Range<usize>,
+    ) -> Result<Vec<C::ScalarExt>, EvalError> {
+        let domain = &pk.vk.domain;
+        let lookups: Vec<Vec<_>> = lookup_polys
+            .iter()
+            .map(|lookups| {
+                lookups
+                    .iter()
+                    .map(|[permuted_input, permuted_table, product]| {
+                        lookup::prover::Committed::from_polys(
+                            permuted_input.clone(),
+                            permuted_table.clone(),
+                            product.clone(),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        let permutations: Vec<_> = permutation_polys
+            .iter()
+            .map(|products| permutation::prover::Committed {
+                sets: products
+                    .iter()
+                    .map(|product| {
+                        permutation::prover::CommittedSet::from_poly(domain, product.clone())
+                    })
+                    .collect(),
+            })
+            .collect();
+        self.evaluate_h_range(
+            pk,
+            advice_polys,
+            instance_polys,
+            challenges,
+            y,
+            beta,
+            gamma,
+            theta,
+            &lookups,
+            &permutations,
+            rows,
+        )
+    }
 
-            /*
-                ---------------------------------------------------------------------------------------
-                --------------------- Computing this code block in gpu --------------------------------
-                ---------------------------------------------------------------------------------------
-                // l_0(X) * (1 - z(X)) = 0
-                *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
-                // l_last(X) * (z(X)^2 - z(X)) = 0
-                *value = *value * y
-                    + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
-                        * l_last[idx]);
-                // (1 - (l_last(X) + l_blind(X))) * (
-                //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
-                //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
-                //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
-                // ) = 0
-                *value = *value * y
-                    + ((product_coset[r_next]
-                        * (permuted_input_coset[idx] + beta)
-                        * (permuted_table_coset[idx] + gamma)
-                        - product_coset[idx] * table_value)
-                        * l_active_row[idx]);
-                // Check that the first values in the permuted input expression and permuted
-                // fixed expression are the same.
-                // l_0(X) * (a'(X) - s'(X)) = 0
-                *value = *value * y + (a_minus_s * l0[idx]);
-                // Check that each value in the permuted lookup input expression is either
-                // equal to the value above it, or the value at the same index in the
-                // permuted table expression.
-                // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
-                *value = *value * y
-                    + (a_minus_s
-                        * (permuted_input_coset[idx] - permuted_input_coset[r_prev])
-                        * l_active_row[idx]);
-                ---------------------------------------------------------------------------------------
-                ---------------------------------------------------------------------------------------
-            */
-
-            let cu_kernel_path = match env::var("CU_KERNEL") {
-                Ok(val) => val,
-                Err(_) => {
-                    println!(
-                        "\n{}\n",
-                        "*** Error : 'CU_KERNEL' env variable not found ***"
-                            .red()
-                            .bold()
-                    );
-                    return values;
-                }
-            };
+    /// Evaluates each custom gate polynomial on its own over the rows `rows` of
+    /// the domain of a circuit, on the CPU, to find out which gate is not
+    /// satisfied where: a polynomial is zero on the rows where its gate holds.
+    ///
+    /// The polynomials are returned in the order h(X) folds them in with y,
+    /// each one with its gate name, followed by its constraint name if it has
+    /// one. Their graphs are built from the constraint system for each call,
+    /// the evaluator only keeping the graph of all the gates together.
+    pub fn gate_contributions(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[Polynomial<C::ScalarExt, Coeff>],
+        instance_polys: &[Polynomial<C::ScalarExt, Coeff>],
+        challenges: &[C::ScalarExt],
+        rows: Range<usize>,
+    ) -> Vec<(String, Vec<C::ScalarExt>)> {
+        let domain = &pk.vk.domain;
+        let n = 1 << domain.k();
+        assert!(
+            rows.start <= rows.end && rows.end <= n,
+            "rows {:?} are not in the domain of size {}",
+            rows,
+            n
+        );
 
-            let mut drv_interface = DriverInterface::new(ModuleSource::FILE(cu_kernel_path));
+        // The values of the columns on the rows of the domain
+        let lagrange = |polys: &[Polynomial<C::ScalarExt, Coeff>]| -> Vec<_> {
+            polys
+                .par_iter()
+                .map(|poly| {
+                    let mut values = poly.values.clone();
+                    best_fft(&mut values, domain.get_omega(), domain.k());
+                    domain.lagrange_from_vec(values)
+                })
+                .collect()
+        };
+        let advice = lagrange(advice_polys);
+        let instance = lagrange(instance_polys);
+        let zero = C::ScalarExt::zero();
 
-            drv_interface.high_verbosity();
+        pk.vk
+            .cs
+            .gates
+            .iter()
+            .flat_map(|gate| {
+                gate.polynomials()
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, poly)| (gate, idx, poly))
+            })
+            .map(|(gate, idx, poly)| {
+                let name = match gate.constraint_name(idx) {
+                    "" => gate.name().to_string(),
+                    constraint => format!("{}: {}", gate.name(), constraint),
+                };
+                let graph = GraphEvaluator::<C>::from_expression(poly);
+                let mut data = graph.instance();
+                let values = rows
+                    .clone()
+                    .map(|row| {
+                        graph.evaluate(
+                            &mut data,
+                            &pk.fixed_values,
+                            &advice,
+                            &instance,
+                            challenges,
+                            &zero,
+                            &zero,
+                            &zero,
+                            &zero,
+                            &zero,
+                            row,
+                            1,
+                            n as i32,
+                        )
+                    })
+                    .collect();
+                (name, values)
+            })
+            .collect()
+    }
 
-            if drv_interface.error_occured() {
-                drv_interface.dump_error();
-                return values;
-            }
+    /// The file h(X) is spilled to, if one is configured and its values do
+    /// not fit in the budget
+    fn spill_path(&self, pk: &ProvingKey<C>) -> Option<&Path> {
+        let bytes = pk.vk.domain.extended_len() * mem::size_of::<C::ScalarExt>();
+        match &self.config.spill_path {
+            Some(path) if bytes > self.config.spill_ram_budget => Some(path),
+            _ => None,
+        }
+    }
 
-            let block_2_start_timer = start_timer!(|| String::from("Lookups : Block 2"));
+    /// Evaluates h(X) on the CPU in chunks of rows of at most
+    /// `spill_ram_budget` bytes, writing each one to the file at `path` once
+    /// it is evaluated, and reads them back into a polynomial once the advice
+    /// and instance cosets are freed, removing the file. The rows of a chunk
+    /// are evaluated as by `evaluate_h_range`, from the full cosets, so the
+    /// rotations reading outside of the chunk read the rows around it. The
+    /// lookup cosets are computed once, along with those of the advice and
+    /// instance, rather than for each chunk.
+    ///
+    /// Only the values are spilled: the division by the vanishing polynomial
+    /// and the FFTs that follow take the whole polynomial in memory.
+    fn evaluate_h_spilled(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        path: &Path,
+        control: &mut EvalControl<'_>,
+    ) -> Result<Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>, EvalError> {
+        let domain = &pk.vk.domain;
+        let size = domain.extended_len();
+        let chunk_rows = (self.config.spill_ram_budget / mem::size_of::<C::ScalarExt>()).max(1);
+        let spill_error = |err: io::Error| EvalError::Spill(format!("{}: {}", path.display(), err));
 
-            match drv_interface.add_allocations_2(
-                alloc_info_list![
-                    ("values", &values.values),
-                    ("l0", &l0.values),
-                    ("l_active_row", &l_active_row.values),
-                    ("l_last", &l_last.values),
-                    ("y_beta_gamma_one", &y_beta_gamma_one)
-                ],
-                alloc_info_list_2D![
-                    ("combined_data_in", &combined_data_in),
-                    ("product_coset", &product_coset_list),
-                    ("permuted_input_coset", &permuted_input_coset_list),
-                    ("permuted_table_coset", &permuted_table_coset_list)
-                ],
-            ) {
-                Err(_) => {
-                    drv_interface.dump_error();
-                    return values;
+        control.begin((size + chunk_rows - 1) / chunk_rows);
+        let spilled = (|| {
+            let mut writer = io::BufWriter::new(File::create(path)?);
+            let advice = extended_cosets(domain, advice_polys);
+            let instance = extended_cosets(domain, instance_polys);
+            let lookup_cosets: Vec<_> = lookups
+                .iter()
+                .map(|lookups| CpuBackend::all_lookup_cosets(pk, lookups))
+                .collect();
+            for start in (0..size).step_by(chunk_rows) {
+                if control.step(ProgressPhase::SpilledRows).is_err() {
+                    break;
                 }
-                Ok(_) => {}
-            }
-
-            match drv_interface.launch_kernel(
-                "compute_evaluate_h_lookups_codeblock",
-                kernel_param![
-                    "values",
-                    "combined_data_in",
-                    "product_coset",
-                    "permuted_input_coset",
-                    "permuted_table_coset",
-                    "l0",
-                    "l_active_row",
-                    "l_last",
-                    "y_beta_gamma_one",
-                    lookup_count,
-                    array_size
-                ],
-                values.len(),
-            ) {
-                Err(_) => {
-                    drv_interface.dump_error();
-                    return values;
+                let rows = start..(start + chunk_rows).min(size);
+                let mut values = vec![C::ScalarExt::zero(); rows.len()];
+                for (((advice, instance), lookup_cosets), permutation) in advice
+                    .iter()
+                    .zip(instance.iter())
+                    .zip(lookup_cosets.iter())
+                    .zip(permutations.iter())
+                {
+                    let data = CircuitData {
+                        pk,
+                        advice,
+                        instance,
+                        challenges,
+                        y,
+                        beta,
+                        gamma,
+                        theta,
+                    };
+                    CpuBackend::custom_gates_rows(self, &data, &mut values, rows.start);
+                    CpuBackend::permutations_rows(&data, permutation, &mut values, rows.start);
+                    CpuBackend::lookups_rows(self, &data, lookup_cosets, &mut values, rows.start);
                 }
-                Ok(_) => {}
+                spill::write_rows(&mut writer, &values)?;
             }
+            writer.flush()
+        })();
+        if control.is_cancelled() {
+            let _ = fs::remove_file(path);
+            return Err(EvalError::Cancelled);
+        }
+        let read = spilled.and_then(|_| {
+            let mut values = domain.empty_extended();
+            spill::read_rows(&mut io::BufReader::new(File::open(path)?), &mut values)?;
+            Ok(values)
+        });
+        let _ = fs::remove_file(path);
+
+        read.map_err(spill_error)
+    }
 
-            match drv_interface.copy_vec_to_host("values", &mut values.values) {
-                Err(_) => {
-                    drv_interface.dump_error();
-                    return values;
+    /// Returns the configured backend, or the CPU if the CUDA device it needs is
+    /// missing and the fallback is enabled. `cuda_available` is only called
+    /// when CUDA is configured.
+    fn resolve_backend(&self, cuda_available: impl FnOnce() -> bool) -> Result<Backend, EvalError> {
+        match self.config.backend {
+            backend if backend.is_cuda() && !cuda_available() => {
+                if !self.config.fallback_to_cpu {
+                    return Err(EvalError::NoCudaDevice);
                 }
-                Ok(_) => {}
+                self.config
+                    .verbosity
+                    .warn("CUDA requested but no device found, using the CPU");
+                Ok(Backend::Cpu)
             }
-
-            end_timer!(block_2_start_timer);
-
-            end_timer!(start_timer);
+            backend => Ok(backend),
         }
-        values
     }
-}
 
-impl<C: CurveAffine> Default for GraphEvaluator<C> {
-    fn default() -> Self {
-        Self {
-            // Fixed positions to allow easy access
-            constants: vec![
-                C::ScalarExt::zero(),
-                C::ScalarExt::one(),
-                C::ScalarExt::from(2u64),
-            ],
-            rotations: Vec::new(),
-            calculations: Vec::new(),
-            num_intermediates: 0,
+    /// Evaluates h(X) on `backend`, re-evaluating it from scratch on the CPU
+    /// if `backend` fails and the fallback is enabled. Otherwise the values
+    /// accumulated up to the failure, of some circuits or phases only, are
+    /// dropped with the error. A cancelled evaluation is not evaluated again.
+    fn evaluate_h_with_fallback<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        timings: &mut EvalTimings,
+        control: &mut EvalControl<'_>,
+    ) -> Result<Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>, EvalError> {
+        let mut values = scratch.take_output(&pk.vk.domain);
+        match self.evaluate_h_with_backend(
+            backend,
+            pk,
+            advice_polys,
+            instance_polys,
+            challenges,
+            y,
+            beta,
+            gamma,
+            theta,
+            lookups,
+            permutations,
+            &mut values,
+            scratch,
+            timings,
+            control,
+        ) {
+            Ok(()) => Ok(values),
+            Err(err) if self.config.fallback_to_cpu && err != EvalError::Cancelled => {
+                self.config
+                    .verbosity
+                    .warn(format!("{}, falling back to the CPU", err));
+                parallelize(&mut values, |values, _| {
+                    for value in values.iter_mut() {
+                        *value = C::ScalarExt::zero();
+                    }
+                });
+                timings.restart();
+                timings.fallback = true;
+                self.on_cpu(|| {
+                    self.evaluate_h_with_backend(
+                        &mut CpuBackend,
+                        pk,
+                        advice_polys,
+                        instance_polys,
+                        challenges,
+                        y,
+                        beta,
+                        gamma,
+                        theta,
+                        lookups,
+                        permutations,
+                        &mut values,
+                        scratch,
+                        timings,
+                        control,
+                    )
+                })?;
+                Ok(values)
+            }
+            Err(err) => Err(err),
         }
     }
-}
 
-impl<C: CurveAffine> GraphEvaluator<C> {
-    /// Adds a rotation
-    fn add_rotation(&mut self, rotation: &Rotation) -> usize {
-        let position = self.rotations.iter().position(|&c| c == rotation.0);
-        match position {
-            Some(pos) => pos,
-            None => {
-                self.rotations.push(rotation.0);
-                self.rotations.len() - 1
-            }
+    /// Runs an evaluation on the CPU backend, on a single thread or on
+    /// pinned threads if so configured
+    fn on_cpu<R: Send>(&self, evaluate: impl FnOnce() -> R + Send) -> R {
+        if self.config.single_threaded {
+            multicore::sequential(evaluate)
+        } else if self.config.pin_threads {
+            multicore::pinned(evaluate)
+        } else {
+            evaluate()
         }
     }
 
-    /// Adds a constant
-    fn add_constant(&mut self, constant: &C::ScalarExt) -> ValueSource {
-        let position = self.constants.iter().position(|&c| c == *constant);
-        ValueSource::Constant(match position {
-            Some(pos) => pos,
-            None => {
+    /// Evaluates all phases of every circuit on `backend`, accumulating into
+    /// `values` from one circuit to the next, see `evaluate_h`
+    fn evaluate_h_with_backend<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        timings: &mut EvalTimings,
+        control: &mut EvalControl<'_>,
+    ) -> Result<(), EvalError> {
+        let domain = &pk.vk.domain;
+
+        // A circuit without instance columns may come without instance
+        // polynomials, otherwise every circuit needs all of its polynomials
+        let no_instance: Vec<&[Polynomial<C::ScalarExt, Coeff>]> = vec![&[]; advice_polys.len()];
+        let instance_polys = match instance_polys {
+            [] if pk.vk.cs.num_instance_columns == 0 => &no_instance[..],
+            instance_polys => instance_polys,
+        };
+        check_input_lengths(advice_polys, instance_polys, lookups, permutations)?;
+        let num_circuits = advice_polys.len();
+
+        // The cosets, then the three phases of every circuit
+        control.begin(1 + 3 * num_circuits);
+
+        if self.config.pipeline_cosets && advice_polys.len() > 1 && !scratch.cosets_ready {
+            return self.evaluate_h_pipelined(
+                backend,
+                pk,
+                advice_polys,
+                instance_polys,
+                challenges,
+                y,
+                beta,
+                gamma,
+                theta,
+                lookups,
+                permutations,
+                values,
+                scratch,
+                timings,
+                control,
+            );
+        }
+
+        // Calculate the advice and instance cosets
+        control.step(ProgressPhase::Cosets)?;
+        let start = Instant::now();
+        scratch.compute_cosets(domain, advice_polys, instance_polys);
+        timings.cosets += start.elapsed();
+
+        let circuits: Vec<CircuitData<'_, C>> = scratch
+            .advice
+            .iter()
+            .zip(scratch.instance.iter())
+            .map(|(advice, instance)| CircuitData {
+                pk,
+                advice,
+                instance,
+                challenges,
+                y,
+                beta,
+                gamma,
+                theta,
+            })
+            .collect();
+
+        // Lookups of every circuit at once, if the backend batches them
+        let start = Instant::now();
+        let batched_lookups = match self.config.gpu_phases.lookups {
+            true => backend.evaluate_lookups_batched(self, &circuits, lookups)?,
+            false => None,
+        };
+        timings.lookups += start.elapsed();
+
+        // Core expression evaluations
+        for (n, ((data, lookups), permutation)) in circuits
+            .iter()
+            .zip(lookups.iter())
+            .zip(permutations.iter())
+            .enumerate()
+        {
+            let batched_lookups = batched_lookups
+                .as_ref()
+                .map(|contributions| &contributions[n][..]);
+            self.evaluate_circuit(
+                backend,
+                data,
+                lookups,
+                permutation,
+                batched_lookups,
+                values,
+                timings,
+                control,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// `evaluate_h_with_backend` computing the cosets of each circuit while
+    /// the previous one is evaluated, for the FFTs to run on the CPU threads
+    /// while the backend waits on its device. The lookups are evaluated
+    /// circuit by circuit.
+    fn evaluate_h_pipelined<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        timings: &mut EvalTimings,
+        control: &mut EvalControl<'_>,
+    ) -> Result<(), EvalError> {
+        let domain = &pk.vk.domain;
+        scratch.allocate_cosets(domain, advice_polys, instance_polys);
+        let EvalScratch {
+            advice, instance, ..
+        } = scratch;
+
+        control.step(ProgressPhase::Cosets)?;
+        let start = Instant::now();
+        extended_cosets_into(domain, advice_polys[0], &mut advice[0]);
+        extended_cosets_into(domain, instance_polys[0], &mut instance[0]);
+        timings.cosets += start.elapsed();
+
+        for n in 0..advice.len() {
+            let (advice, next_advice) = advice.split_at_mut(n + 1);
+            let (instance, next_instance) = instance.split_at_mut(n + 1);
+            let data = CircuitData {
+                pk,
+                advice: &advice[n],
+                instance: &instance[n],
+                challenges,
+                y,
+                beta,
+                gamma,
+                theta,
+            };
+
+            // Overlaps with the evaluation, so that it is not part of its total
+            let mut cosets = Duration::default();
+            let result = multicore::in_place_scope(|scope| {
+                if let (Some(advice), Some(instance)) =
+                    (next_advice.first_mut(), next_instance.first_mut())
+                {
+                    let (advice_polys, instance_polys) =
+                        (advice_polys[n + 1], instance_polys[n + 1]);
+                    let cosets = &mut cosets;
+                    scope.spawn(move |_| {
+                        let start = Instant::now();
+                        extended_cosets_into(domain, advice_polys, advice);
+                        extended_cosets_into(domain, instance_polys, instance);
+                        *cosets = start.elapsed();
+                    });
+                }
+                self.evaluate_circuit(
+                    backend,
+                    &data,
+                    &lookups[n],
+                    &permutations[n],
+                    None,
+                    values,
+                    timings,
+                    control,
+                )
+            });
+            timings.cosets += cosets;
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the `num_circuits` circuits of a batch are evaluated in parallel
+    /// on the CPU, see [`EvaluatorConfig::parallel_circuits_max_k`]
+    fn parallel_circuits(&self, pk: &ProvingKey<C>, num_circuits: usize) -> bool {
+        num_circuits > 1 && pk.vk.domain.k() <= self.config.parallel_circuits_max_k
+    }
+
+    /// `evaluate_h_with_backend` on the CPU, with the circuits evaluated in
+    /// parallel rather than one after the other. The values are not reset
+    /// between the circuits: each constraint multiplies the values of all the
+    /// constraints before it by y, those of the previous circuits included,
+    /// so that h(X) combines the constraints of every circuit as the verifier
+    /// does. Each circuit is then evaluated from zero into values of its own,
+    /// which are added in order to the values of the previous circuits times
+    /// y to the power of its number of constraints. Each circuit checks the
+    /// cancellation between its phases, and the evaluation stops at the first
+    /// circuit failing, but the steps of the circuits are only reported once
+    /// all of them are evaluated, and their timings are summed although they
+    /// overlap.
+    fn evaluate_h_circuits_in_parallel(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        timings: &mut EvalTimings,
+        control: &mut EvalControl<'_>,
+    ) -> Result<Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>, EvalError> {
+        let domain = &pk.vk.domain;
+
+        // As in `evaluate_h_with_backend`
+        let no_instance: Vec<&[Polynomial<C::ScalarExt, Coeff>]> = vec![&[]; advice_polys.len()];
+        let instance_polys = match instance_polys {
+            [] if pk.vk.cs.num_instance_columns == 0 => &no_instance[..],
+            instance_polys => instance_polys,
+        };
+        check_input_lengths(advice_polys, instance_polys, lookups, permutations)?;
+        let num_circuits = advice_polys.len();
+        control.begin(1 + 3 * num_circuits);
+
+        control.step(ProgressPhase::Cosets)?;
+        let start = Instant::now();
+        scratch.compute_cosets(domain, advice_polys, instance_polys);
+        timings.cosets += start.elapsed();
+
+        let circuits: Vec<_> = scratch
+            .advice
+            .iter()
+            .zip(scratch.instance.iter())
+            .zip(lookups.iter())
+            .zip(permutations.iter())
+            .collect();
+        let cancel = control.cancellation().clone();
+        let evaluated = circuits
+            .into_par_iter()
+            .map(|(((advice, instance), lookups), permutation)| {
+                let data = CircuitData {
+                    pk,
+                    advice,
+                    instance,
+                    challenges,
+                    y,
+                    beta,
+                    gamma,
+                    theta,
+                };
+                let mut values = domain.empty_extended();
+                let mut circuit_timings = EvalTimings::default();
+                self.evaluate_circuit(
+                    &mut CpuBackend,
+                    &data,
+                    lookups,
+                    permutation,
+                    None,
+                    &mut values,
+                    &mut circuit_timings,
+                    &mut EvalControl::new(&cancel, None),
+                )
+                .map(|()| (values, circuit_timings))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut values = scratch.take_output(domain);
+        for ((circuit_values, circuit_timings), (lookups, permutation)) in evaluated
+            .into_iter()
+            .zip(lookups.iter().zip(permutations.iter()))
+        {
+            for phase in [
+                ProgressPhase::CustomGates,
+                ProgressPhase::Permutations,
+                ProgressPhase::Lookups,
+            ] {
+                control.step(phase)?;
+            }
+            timings.custom_gates += circuit_timings.custom_gates;
+            timings.permutations += circuit_timings.permutations;
+            timings.lookups += circuit_timings.lookups;
+
+            let num_constraints = num_constraints(&pk.vk.cs, lookups, permutation);
+            let y_power = y.pow_vartime(&[num_constraints, 0, 0, 0]);
+            parallelize(&mut values, |values, start| {
+                for (i, value) in values.iter_mut().enumerate() {
+                    *value = *value * y_power + circuit_values[start + i];
+                }
+            });
+        }
+
+        Ok(values)
+    }
+
+    /// Evaluates the constraints of a circuit into `values`, accumulating the
+    /// contribution of its lookups when they were evaluated in a batch
+    fn evaluate_circuit<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        permutation: &permutation::prover::Committed<C>,
+        batched_lookups: Option<&[C::ScalarExt]>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        timings: &mut EvalTimings,
+        control: &mut EvalControl<'_>,
+    ) -> Result<(), EvalError> {
+        control.step(ProgressPhase::CustomGates)?;
+        self.evaluate_custom_gates(backend, data, values, timings)?;
+        control.step(ProgressPhase::Permutations)?;
+        self.evaluate_permutations(backend, data, permutation, values, timings)?;
+        control.step(ProgressPhase::Lookups)?;
+        self.evaluate_lookups(backend, data, lookups, batched_lookups, values, timings)
+    }
+
+    /// Accumulates the custom gates of a circuit into `values` on `backend`,
+    /// the first phase of `evaluate_h` for each circuit
+    pub(in crate::plonk) fn evaluate_custom_gates<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        data: &CircuitData<'_, C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        timings: &mut EvalTimings,
+    ) -> Result<(), EvalError> {
+        let start = Instant::now();
+        match self.config.gpu_phases.gates {
+            true => backend.evaluate_custom_gates(self, data, values)?,
+            false => CpuBackend.evaluate_custom_gates(self, data, values)?,
+        }
+        timings.custom_gates += start.elapsed();
+        Ok(())
+    }
+
+    /// Accumulates the permutation of a circuit into `values` on `backend`,
+    /// after its custom gates
+    pub(in crate::plonk) fn evaluate_permutations<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        timings: &mut EvalTimings,
+    ) -> Result<(), EvalError> {
+        let start = Instant::now();
+        match self.config.gpu_phases.permutations {
+            true => backend.evaluate_permutations(data, permutation, values)?,
+            false => CpuBackend.evaluate_permutations(data, permutation, values)?,
+        }
+        timings.permutations += start.elapsed();
+        Ok(())
+    }
+
+    /// Accumulates the lookups of a circuit into `values` on `backend`, after
+    /// its permutation. The lookups already evaluated in a batch are given by
+    /// their `batched_lookups` contribution instead.
+    pub(in crate::plonk) fn evaluate_lookups<B: HEvaluatorBackend<C>>(
+        &self,
+        backend: &mut B,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        batched_lookups: Option<&[C::ScalarExt]>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        timings: &mut EvalTimings,
+    ) -> Result<(), EvalError> {
+        let start_timer = start_timer!(|| format!(
+            "{}{}{}{}",
+            "Lookups : lookups.len()=".dimmed(),
+            lookups.len().to_string().dimmed().bold(),
+            ", values.len()=".dimmed(),
+            values.len().to_string().dimmed().bold(),
+        ));
+        let start = Instant::now();
+
+        match batched_lookups {
+            Some(contribution) => accumulate_lookups(values, data.y, lookups.len(), contribution),
+            None if self.config.gpu_phases.lookups => {
+                backend.evaluate_lookups(self, data, lookups, values)?
+            }
+            None => CpuBackend.evaluate_lookups(self, data, lookups, values)?,
+        }
+
+        timings.lookups += start.elapsed();
+        end_timer!(start_timer);
+
+        Ok(())
+    }
+}
+
+impl<C: CurveAffine> Default for GraphEvaluator<C> {
+    fn default() -> Self {
+        Self {
+            // Fixed positions to allow easy access
+            constants: vec![
+                C::ScalarExt::zero(),
+                C::ScalarExt::one(),
+                C::ScalarExt::from(2u64),
+            ],
+            rotations: Vec::new(),
+            calculations: Vec::new(),
+            num_intermediates: 0,
+        }
+    }
+}
+
+impl<C: CurveAffine> GraphEvaluator<C> {
+    /// Reserves room for the graph of `expressions` and `extra` calculations
+    /// on top of them: at most a calculation for each node of the expressions
+    /// that is not a constant, a constant for each constant or scaling factor
+    /// and `rotations` rotations
+    fn reserve<'e>(
+        &mut self,
+        expressions: impl Iterator<Item = &'e Expression<C::ScalarExt>>,
+        extra: usize,
+        rotations: usize,
+    ) {
+        let (calculations, constants) = expressions
+            .map(|expr| {
+                expr.evaluate(
+                    &|_| (0, 1),
+                    &|_| (1, 0),
+                    &|_| (1, 0),
+                    &|_| (1, 0),
+                    &|_| (1, 0),
+                    &|_| (1, 0),
+                    &|(calculations, constants)| (calculations + 1, constants),
+                    &|a, b| (a.0 + b.0 + 1, a.1 + b.1),
+                    &|a, b| (a.0 + b.0 + 1, a.1 + b.1),
+                    &|(calculations, constants), _| (calculations + 1, constants + 1),
+                )
+            })
+            .fold((extra, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+        self.calculations.reserve(calculations);
+        self.constants.reserve(constants);
+        self.rotations.reserve(rotations);
+    }
+
+    /// Releases the room reserved beyond what the graph needs
+    fn shrink_to_fit(&mut self) {
+        self.calculations.shrink_to_fit();
+        self.constants.shrink_to_fit();
+        self.rotations.shrink_to_fit();
+    }
+
+    /// Adds a rotation
+    fn add_rotation(&mut self, rotation: &Rotation) -> usize {
+        let position = self.rotations.iter().position(|&c| c == rotation.0);
+        match position {
+            Some(pos) => pos,
+            None => {
+                self.rotations.push(rotation.0);
+                self.rotations.len() - 1
+            }
+        }
+    }
+
+    /// Adds a constant
+    fn add_constant(&mut self, constant: &C::ScalarExt) -> ValueSource {
+        let position = self.constants.iter().position(|&c| c == *constant);
+        ValueSource::Constant(match position {
+            Some(pos) => pos,
+            None => {
                 self.constants.push(*constant);
                 self.constants.len() - 1
             }
@@ -1032,10 +2508,14 @@ impl<C: CurveAffine> GraphEvaluator<C> {
     }
 
     /// Adds a calculation.
-    /// Currently does the simplest thing possible: just stores the
-    /// resulting value so the result can be reused  when that calculation
-    /// is done multiple times.
+    /// A calculation on constants only is folded into a new constant.
+    /// Otherwise the resulting value is stored so the result can be reused
+    /// when that calculation is done multiple times.
     fn add_calculation(&mut self, calculation: Calculation) -> ValueSource {
+        if let Some(constant) = self.fold_constants(&calculation) {
+            return self.add_constant(&constant);
+        }
+
         let existing_calculation = self
             .calculations
             .iter()
@@ -1054,6 +2534,32 @@ impl<C: CurveAffine> GraphEvaluator<C> {
         }
     }
 
+    /// Returns the value of `calculation` if it only reads constants
+    fn fold_constants(&self, calculation: &Calculation) -> Option<C::ScalarExt> {
+        let is_constant = |source: &&ValueSource| matches!(source, ValueSource::Constant(_));
+        if !calculation.sources().iter().all(is_constant) {
+            return None;
+        }
+
+        let zero = C::ScalarExt::zero();
+        let no_values: &[Polynomial<C::ScalarExt, LagrangeCoeff>] = &[];
+        Some(calculation.evaluate(
+            &[],
+            &self.constants,
+            &[],
+            no_values,
+            no_values,
+            no_values,
+            &[],
+            &zero,
+            &zero,
+            &zero,
+            &[],
+            &zero,
+            &zero,
+        ))
+    }
+
     /// Generates an optimized evaluation for the expression
     fn add_expression(&mut self, expr: &Expression<C::ScalarExt>) -> ValueSource {
         match expr {
@@ -1108,6 +2614,12 @@ impl<C: CurveAffine> GraphEvaluator<C> {
                         }
                     }
                     _ => {
+                        if let Some(result) = self.add_linear_combination(expr) {
+                            return result;
+                        }
+                        if let Some(result) = self.add_mul_add(a, b) {
+                            return result;
+                        }
                         let result_a = self.add_expression(a);
                         let result_b = self.add_expression(b);
                         if result_a == ValueSource::Constant(0) {
@@ -1157,67 +2669,835 @@ impl<C: CurveAffine> GraphEvaluator<C> {
         }
     }
 
-    /// Creates a new evaluation structure
-    pub fn instance(&self) -> EvaluationData<C> {
-        EvaluationData {
-            intermediates: vec![C::ScalarExt::zero(); self.num_intermediates],
-            rotations: vec![0usize; self.rotations.len()],
+    /// Emits a sum of at least `LINEAR_COMBINATION_TERMS` products, such as
+    /// the dot product `c_0 * x_0 + ... + c_n * x_n`, as a single
+    /// `LinearCombination` instead of a chain of `MulAdd`s. The products are
+    /// the terms of the sum that are a `Product` or a `Scaled`; a sum with any
+    /// other term is left to `add_mul_add`.
+    fn add_linear_combination(&mut self, expr: &Expression<C::ScalarExt>) -> Option<ValueSource> {
+        fn collect_terms<'a, F: Field>(
+            expr: &'a Expression<F>,
+            terms: &mut Vec<&'a Expression<F>>,
+        ) {
+            match expr {
+                Expression::Sum(a, b) if !matches!(**b, Expression::Negated(_)) => {
+                    collect_terms(a, terms);
+                    collect_terms(b, terms);
+                }
+                _ => terms.push(expr),
+            }
+        }
+        let mut products = vec![];
+        collect_terms(expr, &mut products);
+        let is_product = |term: &&Expression<C::ScalarExt>| match term {
+            Expression::Product(_, _) => true,
+            Expression::Scaled(_, f) => *f != C::ScalarExt::zero() && *f != C::ScalarExt::one(),
+            _ => false,
+        };
+        if products.len() < LINEAR_COMBINATION_TERMS || !products.iter().all(is_product) {
+            return None;
+        }
+
+        let mut pairs = Vec::with_capacity(products.len());
+        for product in products {
+            let (a, b) = match product {
+                Expression::Product(a, b) => (self.add_expression(a), self.add_expression(b)),
+                Expression::Scaled(a, f) => (self.add_expression(a), self.add_constant(f)),
+                _ => unreachable!("only products are combined"),
+            };
+            if a == ValueSource::Constant(0) || b == ValueSource::Constant(0) {
+                continue;
+            }
+            pairs.push(if a <= b { (a, b) } else { (b, a) });
         }
+        Some(match pairs.len() {
+            0 => ValueSource::Constant(0),
+            1 => self.add_calculation(Calculation::Mul(pairs[0].0, pairs[0].1)),
+            _ => self.add_calculation(Calculation::LinearCombination(pairs)),
+        })
     }
 
-    pub fn evaluate<B: Basis>(
-        &self,
-        data: &mut EvaluationData<C>,
-        fixed: &[Polynomial<C::ScalarExt, B>],
-        advice: &[Polynomial<C::ScalarExt, B>],
-        instance: &[Polynomial<C::ScalarExt, B>],
-        challenges: &[C::ScalarExt],
-        beta: &C::ScalarExt,
-        gamma: &C::ScalarExt,
-        theta: &C::ScalarExt,
-        y: &C::ScalarExt,
-        previous_value: &C::ScalarExt,
-        idx: usize,
-        rot_scale: i32,
-        isize: i32,
-    ) -> C::ScalarExt {
-        // All rotation index values
-        for (rot_idx, rot) in self.rotations.iter().enumerate() {
-            data.rotations[rot_idx] = get_rotation_idx(idx, *rot, rot_scale, isize);
+    /// Emits the sum `a + b` as a single `MulAdd` when one of its terms is a
+    /// product that does not simplify and is not already computed, so that it
+    /// can still be shared.
+    fn add_mul_add(
+        &mut self,
+        a: &Expression<C::ScalarExt>,
+        b: &Expression<C::ScalarExt>,
+    ) -> Option<ValueSource> {
+        let ((factor_a, factor_b), addend) = match (a, b) {
+            (Expression::Product(factor_a, factor_b), addend)
+            | (addend, Expression::Product(factor_a, factor_b)) => ((factor_a, factor_b), addend),
+            _ => return None,
+        };
+
+        let result_a = self.add_expression(factor_a);
+        let result_b = self.add_expression(factor_b);
+        let (result_a, result_b) = if result_a <= result_b {
+            (result_a, result_b)
+        } else {
+            (result_b, result_a)
+        };
+        let simplifies = |value: ValueSource| {
+            matches!(
+                value,
+                ValueSource::Constant(0) | ValueSource::Constant(1) | ValueSource::Constant(2)
+            )
+        };
+        let both_constant = matches!(
+            (result_a, result_b),
+            (ValueSource::Constant(_), ValueSource::Constant(_))
+        );
+        let product = Calculation::Mul(result_a, result_b);
+        if simplifies(result_a)
+            || simplifies(result_b)
+            || result_a == result_b
+            || both_constant
+            || self.calculations.iter().any(|c| c.calculation == product)
+        {
+            return None;
         }
 
-        // All calculations, with cached intermediate results
-        for calc in self.calculations.iter() {
-            data.intermediates[calc.target] = calc.calculation.evaluate(
-                &data.rotations,
-                &self.constants,
-                &data.intermediates,
-                fixed,
-                advice,
-                instance,
-                challenges,
-                beta,
-                gamma,
-                theta,
-                y,
-                previous_value,
-            );
+        let result_c = self.add_expression(addend);
+        if result_c == ValueSource::Constant(0) {
+            return None;
         }
+        Some(self.add_calculation(Calculation::MulAdd(result_a, result_b, result_c)))
+    }
 
-        // Return the result of the last calculation (if any)
-        if let Some(calc) = self.calculations.last() {
-            data.intermediates[calc.target]
-        } else {
-            C::ScalarExt::zero()
+    /// Builds the graph of a single expression, its last calculation
+    /// computing the value of the expression
+    pub fn from_expression(expression: &Expression<C::ScalarExt>) -> Self {
+        let mut graph = GraphEvaluator::default();
+        let result = graph.add_expression(expression);
+        // Constants and queries are not calculations, and neither are the
+        // constant folded results
+        let computed = graph
+            .calculations
+            .last()
+            .map(|calc| ValueSource::Intermediate(calc.target));
+        if computed != Some(result) {
+            graph.calculations.push(CalculationInfo {
+                calculation: Calculation::Store(result),
+                target: graph.num_intermediates,
+            });
+            graph.num_intermediates += 1;
         }
+        graph.prune();
+        graph
     }
-}
 
-/// Simple evaluation of an expression
-pub fn evaluate<F: FieldExt, B: Basis>(
-    expression: &Expression<F>,
-    size: usize,
-    rot_scale: i32,
+    /// Evaluates the graph on the rows of the extended domain of `domain` on
+    /// the CUDA device, in a single kernel launch, returning the values of its
+    /// last calculation. The columns are extended cosets, and for a graph
+    /// built with `from_expression` the values are those [`evaluate`]
+    /// computes on the CPU. The graph cannot read beta, gamma, theta, y nor a
+    /// previous value.
+    pub fn run_on_gpu(
+        &self,
+        fixed: &[Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>],
+        advice: &[Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>],
+        instance: &[Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>],
+        challenges: &[C::ScalarExt],
+        domain: &EvaluationDomain<C::ScalarExt>,
+    ) -> Result<Vec<C::ScalarExt>, EvalError> {
+        if !CudaBackend::is_available() {
+            return Err(EvalError::NoCudaDevice);
+        }
+
+        let size = domain.extended_len();
+        let rot_scale = 1 << (domain.extended_k() - domain.k());
+        let graph = DeviceGraph::lower(self, size, fixed, advice, instance, challenges)?;
+        CudaBackend::default().evaluate_graph(&graph, size, rot_scale)
+    }
+
+    /// Removes the calculations the last calculation does not depend on, and
+    /// renumbers the remaining intermediates in order. `evaluate` returns the
+    /// result of the last calculation, so its semantics are preserved.
+    pub fn prune(&mut self) {
+        let last = match self.calculations.last() {
+            Some(calc) => calc.target,
+            None => return,
+        };
+
+        // Calculations only read intermediates computed before them, so a
+        // single backward pass finds everything the last one depends on.
+        let mut live = vec![false; self.num_intermediates];
+        live[last] = true;
+        for calc in self.calculations.iter().rev() {
+            if live[calc.target] {
+                for source in calc.calculation.sources() {
+                    if let ValueSource::Intermediate(idx) = source {
+                        live[*idx] = true;
+                    }
+                }
+            }
+        }
+
+        let mut new_targets = vec![0; self.num_intermediates];
+        let mut num_intermediates = 0;
+        for calc in self.calculations.iter() {
+            if live[calc.target] {
+                new_targets[calc.target] = num_intermediates;
+                num_intermediates += 1;
+            }
+        }
+
+        self.calculations.retain(|calc| live[calc.target]);
+        for calc in self.calculations.iter_mut() {
+            calc.target = new_targets[calc.target];
+            for source in calc.calculation.sources_mut() {
+                if let ValueSource::Intermediate(idx) = source {
+                    *idx = new_targets[*idx];
+                }
+            }
+        }
+        self.num_intermediates = num_intermediates;
+    }
+
+    /// Appends the calculations of `other`, reusing the constants, rotations
+    /// and calculations this graph already has, and returns where the values
+    /// of `other` are in this graph. The merged graph evaluates to its last
+    /// calculation, so the results of both graphs are read from their
+    /// intermediates, and pruning it would drop the ones it does not need.
+    pub fn merge(&mut self, other: &GraphEvaluator<C>) -> Remapping {
+        let mut remapping = Remapping {
+            constants: other
+                .constants
+                .iter()
+                .map(|constant| match self.add_constant(constant) {
+                    ValueSource::Constant(idx) => idx,
+                    _ => unreachable!("a constant is added as a constant"),
+                })
+                .collect(),
+            rotations: other
+                .rotations
+                .iter()
+                .map(|&rotation| self.add_rotation(&Rotation(rotation)))
+                .collect(),
+            intermediates: vec![ValueSource::default(); other.num_intermediates],
+        };
+        for calc in other.calculations.iter() {
+            let mut calculation = calc.calculation.clone();
+            for source in calculation.sources_mut() {
+                *source = remapping.apply(source);
+            }
+            remapping.intermediates[calc.target] = self.add_calculation(calculation);
+        }
+        remapping
+    }
+
+    /// Returns the degree in X of the result of the graph, counting every
+    /// column query as degree 1. Challenges, the other scalars and the
+    /// previous value are degree 0.
+    pub fn degree(&self) -> usize {
+        let mut degrees = vec![0; self.num_intermediates];
+        for calc in self.calculations.iter() {
+            let degree = |source: &ValueSource| match source {
+                ValueSource::Intermediate(idx) => degrees[*idx],
+                ValueSource::Fixed(_, _)
+                | ValueSource::Advice(_, _)
+                | ValueSource::Instance(_, _) => 1,
+                _ => 0,
+            };
+            let result = match &calc.calculation {
+                Calculation::Add(a, b) | Calculation::Sub(a, b) => degree(a).max(degree(b)),
+                Calculation::Mul(a, b) => degree(a) + degree(b),
+                Calculation::MulAdd(a, b, c) => (degree(a) + degree(b)).max(degree(c)),
+                Calculation::Square(v) => 2 * degree(v),
+                Calculation::Double(v) | Calculation::Negate(v) | Calculation::Store(v) => {
+                    degree(v)
+                }
+                // As for its input, which the constraints multiply it back by
+                Calculation::Inverse(v) => degree(v),
+                Calculation::Horner(start_value, parts, factor) => {
+                    let factor = degree(factor);
+                    parts.iter().fold(degree(start_value), |value, part| {
+                        (value + factor).max(degree(part))
+                    })
+                }
+                Calculation::LinearCombination(terms) => terms
+                    .iter()
+                    .map(|(a, b)| degree(a) + degree(b))
+                    .max()
+                    .unwrap_or(0),
+            };
+            degrees[calc.target] = result;
+        }
+        self.calculations
+            .last()
+            .map_or(0, |calc| degrees[calc.target])
+    }
+
+    /// Returns the number of field multiplications done per row
+    pub fn num_multiplications(&self) -> usize {
+        self.calculations
+            .iter()
+            .map(|calc| match &calc.calculation {
+                Calculation::Mul(_, _) | Calculation::Square(_) | Calculation::MulAdd(_, _, _) => 1,
+                Calculation::Horner(_, parts, _) => parts.len(),
+                Calculation::LinearCombination(terms) => terms.len(),
+                // Inverted in a batch, with three multiplications per value
+                Calculation::Inverse(_) => 3,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the number of field additions done per row, counting
+    /// subtractions, doublings and negations as additions
+    pub fn num_additions(&self) -> usize {
+        self.calculations
+            .iter()
+            .map(|calc| match &calc.calculation {
+                Calculation::Add(_, _)
+                | Calculation::Sub(_, _)
+                | Calculation::Double(_)
+                | Calculation::Negate(_)
+                | Calculation::MulAdd(_, _, _) => 1,
+                Calculation::Horner(_, parts, _) => parts.len(),
+                Calculation::LinearCombination(terms) => terms.len().saturating_sub(1),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the number of field operations done per row
+    pub(in crate::plonk) fn op_counts(&self) -> OpCounts {
+        OpCounts::new(self.num_multiplications(), self.num_additions())
+    }
+
+    /// Checks that every value the graph reads exists, given the number of
+    /// columns of each kind and of challenges it is evaluated with.
+    /// Calculations may only read the intermediates of earlier calculations,
+    /// and each calculation must store its result in a distinct intermediate.
+    pub fn validate(
+        &self,
+        num_fixed: usize,
+        num_advice: usize,
+        num_instance: usize,
+        num_challenges: usize,
+    ) -> Result<(), EvalError> {
+        let mut computed = vec![false; self.num_intermediates];
+        for (n, calc) in self.calculations.iter().enumerate() {
+            let invalid = |reason: String| {
+                EvalError::InvalidGraph(format!(
+                    "calculation {} ({}): {}",
+                    n, calc.calculation, reason
+                ))
+            };
+            let check = |kind: &str, index: usize, count: usize| {
+                if index < count {
+                    Ok(())
+                } else {
+                    Err(invalid(format!(
+                        "{} index {} out of range 0..{}",
+                        kind, index, count
+                    )))
+                }
+            };
+
+            for source in calc.calculation.sources() {
+                match *source {
+                    ValueSource::Constant(idx) => check("constant", idx, self.constants.len())?,
+                    ValueSource::Intermediate(idx) => {
+                        if !computed.get(idx).copied().unwrap_or(false) {
+                            return Err(invalid(format!(
+                                "intermediate {} is read before it is computed",
+                                idx
+                            )));
+                        }
+                    }
+                    ValueSource::Fixed(column, rotation) => {
+                        check("fixed column", column, num_fixed)?;
+                        check("rotation", rotation, self.rotations.len())?;
+                    }
+                    ValueSource::Advice(column, rotation) => {
+                        check("advice column", column, num_advice)?;
+                        check("rotation", rotation, self.rotations.len())?;
+                    }
+                    ValueSource::Instance(column, rotation) => {
+                        check("instance column", column, num_instance)?;
+                        check("rotation", rotation, self.rotations.len())?;
+                    }
+                    ValueSource::Challenge(idx) => check("challenge", idx, num_challenges)?,
+                    ValueSource::Beta()
+                    | ValueSource::Gamma()
+                    | ValueSource::Theta()
+                    | ValueSource::ThetaPower(_)
+                    | ValueSource::Y()
+                    | ValueSource::PreviousValue() => {}
+                }
+            }
+
+            check("target intermediate", calc.target, self.num_intermediates)?;
+            if computed[calc.target] {
+                return Err(invalid(format!(
+                    "intermediate {} is already computed",
+                    calc.target
+                )));
+            }
+            computed[calc.target] = true;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the calculations in evaluation order, one per line, such as
+    /// `i8 = Horner(prev, [i7, i4], Y)`. Column queries show their actual
+    /// rotation, as in `Fixed(2,@rot1)`.
+    pub fn trace(&self) -> String {
+        self.calculations
+            .iter()
+            .map(|calc| {
+                format!(
+                    "i{} = {}\n",
+                    calc.target,
+                    ResolvedCalculation {
+                        calculation: &calc.calculation,
+                        rotations: &self.rotations,
+                    }
+                )
+            })
+            .collect()
+    }
+
+    /// Creates a new evaluation structure
+    pub fn instance(&self) -> EvaluationData<C> {
+        let num_theta_powers = self
+            .calculations
+            .iter()
+            .flat_map(|calc| calc.calculation.sources())
+            .filter_map(|source| match source {
+                ValueSource::ThetaPower(power) => Some(power + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        EvaluationData {
+            intermediates: vec![C::ScalarExt::zero(); self.num_intermediates],
+            rotations: vec![0usize; self.rotations.len()],
+            theta_powers: vec![C::ScalarExt::zero(); num_theta_powers],
+            theta: None,
+        }
+    }
+
+    pub fn evaluate<B: Basis>(
+        &self,
+        data: &mut EvaluationData<C>,
+        fixed: &[Polynomial<C::ScalarExt, B>],
+        advice: &[Polynomial<C::ScalarExt, B>],
+        instance: &[Polynomial<C::ScalarExt, B>],
+        challenges: &[C::ScalarExt],
+        beta: &C::ScalarExt,
+        gamma: &C::ScalarExt,
+        theta: &C::ScalarExt,
+        y: &C::ScalarExt,
+        previous_value: &C::ScalarExt,
+        idx: usize,
+        rot_scale: i32,
+        isize: i32,
+    ) -> C::ScalarExt {
+        // All rotation index values
+        for (rot_idx, rot) in self.rotations.iter().enumerate() {
+            data.rotations[rot_idx] = get_rotation_idx(idx, *rot, rot_scale, isize);
+        }
+
+        data.set_theta(theta);
+
+        // All calculations, with cached intermediate results
+        for calc in self.calculations.iter() {
+            let value = self.evaluate_calculation(
+                &calc.calculation,
+                &data.rotations,
+                &data.intermediates,
+                fixed,
+                advice,
+                instance,
+                challenges,
+                beta,
+                gamma,
+                theta,
+                &data.theta_powers,
+                y,
+                previous_value,
+            );
+            data.intermediates[calc.target] = value;
+        }
+
+        // Return the result of the last calculation (if any)
+        if let Some(calc) = self.calculations.last() {
+            data.intermediates[calc.target]
+        } else {
+            C::ScalarExt::zero()
+        }
+    }
+
+    /// Evaluates the graph on row `idx` as `evaluate` does, returning the
+    /// value of every calculation in evaluation order instead of only the
+    /// last one, so that the value of the `n`th line of `trace` is the `n`th.
+    /// The values are left in the intermediates of `data` as well.
+    pub fn evaluate_with_trace<B: Basis>(
+        &self,
+        data: &mut EvaluationData<C>,
+        fixed: &[Polynomial<C::ScalarExt, B>],
+        advice: &[Polynomial<C::ScalarExt, B>],
+        instance: &[Polynomial<C::ScalarExt, B>],
+        challenges: &[C::ScalarExt],
+        beta: &C::ScalarExt,
+        gamma: &C::ScalarExt,
+        theta: &C::ScalarExt,
+        y: &C::ScalarExt,
+        previous_value: &C::ScalarExt,
+        idx: usize,
+        rot_scale: i32,
+        isize: i32,
+    ) -> Vec<C::ScalarExt> {
+        self.evaluate(
+            data,
+            fixed,
+            advice,
+            instance,
+            challenges,
+            beta,
+            gamma,
+            theta,
+            y,
+            previous_value,
+            idx,
+            rot_scale,
+            isize,
+        );
+        self.calculations
+            .iter()
+            .map(|calc| data.intermediates[calc.target])
+            .collect()
+    }
+
+    /// Evaluates the graph on the rows from `idx`, as `evaluate` does for
+    /// each of them with its value in `values` as the previous value, and
+    /// replaces it with the result.
+    ///
+    /// The inversions of a graph are done on blocks of rows at once: the
+    /// calculations up to an inversion are evaluated for every row of the
+    /// block, then a batch inversion inverts its values, before the
+    /// calculations up to the next one.
+    pub fn evaluate_rows<B: Basis>(
+        &self,
+        data: &mut EvaluationData<C>,
+        fixed: &[Polynomial<C::ScalarExt, B>],
+        advice: &[Polynomial<C::ScalarExt, B>],
+        instance: &[Polynomial<C::ScalarExt, B>],
+        challenges: &[C::ScalarExt],
+        beta: &C::ScalarExt,
+        gamma: &C::ScalarExt,
+        theta: &C::ScalarExt,
+        y: &C::ScalarExt,
+        values: &mut [C::ScalarExt],
+        idx: usize,
+        rot_scale: i32,
+        isize: i32,
+    ) {
+        let has_inversions = self
+            .calculations
+            .iter()
+            .any(|calc| matches!(calc.calculation, Calculation::Inverse(_)));
+        if !has_inversions {
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = self.evaluate(
+                    data,
+                    fixed,
+                    advice,
+                    instance,
+                    challenges,
+                    beta,
+                    gamma,
+                    theta,
+                    y,
+                    value,
+                    idx + i,
+                    rot_scale,
+                    isize,
+                );
+            }
+            return;
+        }
+
+        data.set_theta(theta);
+        let num_intermediates = self.num_intermediates;
+        let mut intermediates = vec![C::ScalarExt::zero(); INVERSION_ROWS * num_intermediates];
+        for (block, values) in values.chunks_mut(INVERSION_ROWS).enumerate() {
+            let start = idx + block * INVERSION_ROWS;
+            let intermediates = &mut intermediates[..values.len() * num_intermediates];
+
+            let mut calculations = &self.calculations[..];
+            while !calculations.is_empty() {
+                // The calculations up to the next inversion, included
+                let len = calculations
+                    .iter()
+                    .position(|calc| matches!(calc.calculation, Calculation::Inverse(_)))
+                    .map_or(calculations.len(), |pos| pos + 1);
+                let (stage, rest) = calculations.split_at(len);
+
+                for (row, (previous_value, intermediates)) in values
+                    .iter()
+                    .zip(intermediates.chunks_mut(num_intermediates))
+                    .enumerate()
+                {
+                    for (rot_idx, rot) in self.rotations.iter().enumerate() {
+                        data.rotations[rot_idx] =
+                            get_rotation_idx(start + row, *rot, rot_scale, isize);
+                    }
+                    for calc in stage.iter() {
+                        let value = match &calc.calculation {
+                            // Its input, inverted below for every row at once
+                            Calculation::Inverse(v) => v.get(
+                                &data.rotations,
+                                &self.constants,
+                                intermediates,
+                                fixed,
+                                advice,
+                                instance,
+                                challenges,
+                                beta,
+                                gamma,
+                                theta,
+                                &data.theta_powers,
+                                y,
+                                previous_value,
+                            ),
+                            calculation => self.evaluate_calculation(
+                                calculation,
+                                &data.rotations,
+                                intermediates,
+                                fixed,
+                                advice,
+                                instance,
+                                challenges,
+                                beta,
+                                gamma,
+                                theta,
+                                &data.theta_powers,
+                                y,
+                                previous_value,
+                            ),
+                        };
+                        intermediates[calc.target] = value;
+                    }
+                }
+
+                if let Some(calc) = stage.last() {
+                    if let Calculation::Inverse(_) = calc.calculation {
+                        intermediates
+                            .iter_mut()
+                            .skip(calc.target)
+                            .step_by(num_intermediates)
+                            .batch_invert();
+                    }
+                }
+                calculations = rest;
+            }
+
+            // The result of the last calculation, which there is
+            let target = self.calculations.last().unwrap().target;
+            for (value, intermediates) in values
+                .iter_mut()
+                .zip(intermediates.chunks(num_intermediates))
+            {
+                *value = intermediates[target];
+            }
+        }
+    }
+
+    /// Evaluates a calculation of the graph. The BN256 scalars have their own
+    /// multiplication, the check being resolved at compile time.
+    fn evaluate_calculation<B: Basis>(
+        &self,
+        calculation: &Calculation,
+        rotations: &[usize],
+        intermediates: &[C::ScalarExt],
+        fixed: &[Polynomial<C::ScalarExt, B>],
+        advice: &[Polynomial<C::ScalarExt, B>],
+        instance: &[Polynomial<C::ScalarExt, B>],
+        challenges: &[C::ScalarExt],
+        beta: &C::ScalarExt,
+        gamma: &C::ScalarExt,
+        theta: &C::ScalarExt,
+        theta_powers: &[C::ScalarExt],
+        y: &C::ScalarExt,
+        previous_value: &C::ScalarExt,
+    ) -> C::ScalarExt {
+        let is_bn256 = TypeId::of::<C::ScalarExt>() == TypeId::of::<bn256::Fr>();
+        match is_bn256 {
+            // Safety: the scalars are BN256 ones
+            true => unsafe {
+                bn256::evaluate(
+                    calculation,
+                    rotations,
+                    &self.constants,
+                    intermediates,
+                    fixed,
+                    advice,
+                    instance,
+                    challenges,
+                    beta,
+                    gamma,
+                    theta,
+                    theta_powers,
+                    y,
+                    previous_value,
+                )
+            },
+            false => calculation.evaluate(
+                rotations,
+                &self.constants,
+                intermediates,
+                fixed,
+                advice,
+                instance,
+                challenges,
+                beta,
+                gamma,
+                theta,
+                theta_powers,
+                y,
+                previous_value,
+            ),
+        }
+    }
+}
+
+impl<C: CurveAffine> EvaluationData<C> {
+    /// The powers of theta, once for all the rows evaluated with it
+    fn set_theta(&mut self, theta: &C::ScalarExt) {
+        if self.theta != Some(*theta) {
+            let mut power = C::ScalarExt::one();
+            for value in self.theta_powers.iter_mut() {
+                *value = power;
+                power *= theta;
+            }
+            self.theta = Some(*theta);
+        }
+    }
+}
+
+/// The number of constraints a circuit adds to h(X), each of which multiplies
+/// the values of the constraints before it by y
+fn num_constraints<C: CurveAffine>(
+    cs: &ConstraintSystem<C::ScalarExt>,
+    lookups: &[lookup::prover::Committed<C>],
+    permutation: &permutation::prover::Committed<C>,
+) -> u64 {
+    let gates: usize = cs.gates.iter().map(|gate| gate.polynomials().len()).sum();
+    // The first and the last set, the transitions between the sets and
+    // their products
+    let permutation = match permutation.sets.len() {
+        0 => 0,
+        sets => 2 * sets + 1,
+    };
+    (gates + permutation) as u64 + LOOKUP_CONSTRAINTS * lookups.len() as u64
+}
+
+/// Checks that the inputs of an evaluation of h(X) are given for as many
+/// circuits as the advice
+fn check_input_lengths<C: CurveAffine>(
+    advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+    instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+    lookups: &[Vec<lookup::prover::Committed<C>>],
+    permutations: &[permutation::prover::Committed<C>],
+) -> Result<(), EvalError> {
+    let advice = advice_polys.len();
+    match [instance_polys.len(), lookups.len(), permutations.len()]
+        .iter()
+        .all(|&len| len == advice)
+    {
+        true => Ok(()),
+        false => Err(EvalError::InputLengthMismatch {
+            advice,
+            instance: instance_polys.len(),
+            lookups: lookups.len(),
+            permutations: permutations.len(),
+        }),
+    }
+}
+
+/// Computes the extended cosets of the polynomials of every circuit, the
+/// columns of a circuit in parallel
+fn extended_cosets<F: FieldExt>(
+    domain: &EvaluationDomain<F>,
+    polys: &[&[Polynomial<F, Coeff>]],
+) -> Vec<Vec<Polynomial<F, ExtendedLagrangeCoeff>>> {
+    polys
+        .iter()
+        .map(|polys| {
+            polys
+                .par_iter()
+                .map(|poly| match is_zero_poly(poly) {
+                    true => domain.empty_extended(),
+                    false => domain.coeff_to_extended(poly.clone()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the first row of the domain at which the polynomial evaluated on
+/// the extended coset by `values` is nonzero, if any.
+///
+/// Applied to the evaluations of h(X) before the division by the vanishing
+/// polynomial, this is the first row at which a constraint is not satisfied.
+pub fn find_violating_row<F: FieldExt>(
+    values: &Polynomial<F, ExtendedLagrangeCoeff>,
+    domain: &EvaluationDomain<F>,
+) -> Option<usize> {
+    let n = 1 << domain.k();
+    let extended_len = domain.extended_len();
+    assert_eq!(values.len(), extended_len);
+
+    // The coefficients of p(zeta X), from its evaluations on the extended domain.
+    let mut coeffs = values.values.clone();
+    best_fft(
+        &mut coeffs,
+        domain.get_extended_omega().invert().unwrap(),
+        domain.extended_k(),
+    );
+    let divisor = F::from(extended_len as u64).invert().unwrap();
+
+    // The coefficients of p(X), as zeta^3 = 1, reduced modulo X^n - 1 since
+    // omega^n = 1 at the points of the domain.
+    let zeta_powers = [F::one(), F::ZETA.square(), F::ZETA];
+    let mut reduced = vec![F::zero(); n];
+    for (k, coeff) in coeffs.iter().enumerate() {
+        reduced[k % n] += *coeff * divisor * zeta_powers[k % 3];
+    }
+
+    best_fft(&mut reduced, domain.get_omega(), domain.k());
+    reduced
+        .iter()
+        .position(|value| !bool::from(value.is_zero()))
+}
+
+/// Returns the sum of `values[i] * r^i` over the values, e.g. of h(X) on the
+/// extended domain, computed on the CUDA device: with `r = 1` their sum, and
+/// with a random `r` a digest of them. Only a partial sum per chunk of rows
+/// is copied back, instead of every value.
+///
+/// The partial sums are of chunks of 256 rows whatever the device, each summed
+/// by Horner's rule from its last row, then folded in order on the host from
+/// the last chunk, so that the result and every intermediate value are the
+/// same for any launch and equal to those on the CPU.
+pub fn reduce_values_on_gpu<F: FieldExt>(values: &[F], r: F) -> Result<F, EvalError> {
+    if !CudaBackend::is_available() {
+        return Err(EvalError::NoCudaDevice);
+    }
+    CudaBackend::default().reduce_values(values, r)
+}
+
+/// Simple evaluation of an expression
+pub fn evaluate<F: FieldExt, B: Basis>(
+    expression: &Expression<F>,
+    size: usize,
+    rot_scale: i32,
     fixed: &[Polynomial<F, B>],
     advice: &[Polynomial<F, B>],
     instance: &[Polynomial<F, B>],
@@ -1253,3 +3533,4249 @@ pub fn evaluate<F: FieldExt, B: Basis>(
     });
     values
 }
+
+#[cfg(test)]
+mod tests {
+    use super::cuda::{
+        check_ptx_declarations, fold_chunks, is_transient, ptx_entry_points, reduce_chunks,
+        DeviceProperties, REDUCE_CHUNK_ROWS,
+    };
+    use super::device::{
+        extend_kernel_repr, from_kernel_repr, row_slices, to_kernel_repr, window_rows,
+    };
+    use super::fixtures::Fixture;
+    use super::mock::{MockBackend, Phase};
+    use super::pinned::{HostAllocator, PinnedPool};
+    use super::{
+        accumulate_lookups, bn256, cs_fingerprint, cuda_counters, cuda_device_memory,
+        cuda_unified_memory_available, evaluate, extended_cosets, extended_cosets_into,
+        find_violating_row, get_rotation_idx, is_zero_poly, num_constraints,
+        permutation_row_counts, prepare_lookup, prepare_lookups, reduce_values_on_gpu,
+        reset_cuda_counters, sample_rows, Backend, Calculation, CancellationToken, CircuitData,
+        CpuBackend, CudaBackend, CudaCounters, DeferredChallenges, DeviceGraph, EvalControl,
+        EvalError, EvalScratch, EvalTimings, EvaluationData, Evaluator, EvaluatorConfig,
+        EvaluatorStats, ExtendedContext, FieldLayout, GpuPhases, GraphEvaluator, HEvaluatorBackend,
+        LookupBuffers, LookupLayout, LookupStrategy, MemoryMode, OpCounts, Progress, ProgressPhase,
+        RetryPolicy, SyntheticEvaluation, Transfers, ValueSource, Verbosity,
+    };
+    use crate::arithmetic::{best_fft, FieldExt};
+    use crate::circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value};
+    use crate::plonk::{
+        create_proof, keygen_pk, keygen_pk_with_evaluator, keygen_vk, lookup, permutation,
+        verify_proof, Advice, AdviceQuery, Challenge, Circuit, Column, ConstraintSystem, Error,
+        Expression, FirstPhase, FixedQuery, ProvingKey, SecondPhase, Selector, TableColumn,
+    };
+    use crate::poly::{
+        commitment::ParamsProver,
+        ipa::{
+            commitment::{IPACommitmentScheme, ParamsIPA},
+            multiopen::{ProverIPA, VerifierIPA},
+            strategy::SingleStrategy,
+        },
+        Coeff, EvaluationDomain, ExtendedLagrangeCoeff, LagrangeCoeff, Polynomial, Rotation,
+        VerificationStrategy,
+    };
+    use crate::transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+    };
+    use group::ff::Field;
+    use halo2curves::pasta::{EqAffine, Fp};
+    use rand_core::OsRng;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const K: u32 = 5;
+    const ROWS: usize = 4;
+
+    #[derive(Clone)]
+    struct TestConfig {
+        a: Column<Advice>,
+        b: Column<Advice>,
+        c: Column<Advice>,
+        q_mul: Selector,
+        table: TableColumn,
+    }
+
+    /// Doubles `a` on every row: a gate, a lookup and a permutation
+    #[derive(Clone)]
+    struct TestCircuit {
+        a: Value<Fp>,
+    }
+
+    impl Circuit<Fp> for TestCircuit {
+        type Config = TestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                a: Value::unknown(),
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> TestConfig {
+            let a = meta.advice_column();
+            let b = meta.advice_column();
+            let c = meta.advice_column();
+            let q_mul = meta.complex_selector();
+            let table = meta.lookup_table_column();
+            meta.enable_equality(a);
+            meta.enable_equality(c);
+
+            meta.create_gate("mul", |meta| {
+                let q_mul = meta.query_selector(q_mul);
+                let a = meta.query_advice(a, Rotation::cur());
+                let b = meta.query_advice(b, Rotation::cur());
+                let c = meta.query_advice(c, Rotation::cur());
+                vec![q_mul * (a * b - c)]
+            });
+
+            meta.lookup("range", |meta| {
+                let q_mul = meta.query_selector(q_mul);
+                let a = meta.query_advice(a, Rotation::cur());
+                vec![(q_mul * a, table)]
+            });
+
+            TestConfig {
+                a,
+                b,
+                c,
+                q_mul,
+                table,
+            }
+        }
+
+        fn synthesize(
+            &self,
+            config: TestConfig,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            layouter.assign_table(
+                || "table",
+                |mut table| {
+                    for i in 0..16 {
+                        table.assign_cell(
+                            || "table",
+                            config.table,
+                            i,
+                            || Value::known(Fp::from(i as u64)),
+                        )?;
+                    }
+                    Ok(())
+                },
+            )?;
+
+            layouter.assign_region(
+                || "mul",
+                |mut region| {
+                    let mut a = self.a;
+                    let mut prev_c: Option<AssignedCell<Fp, Fp>> = None;
+                    for row in 0..ROWS {
+                        config.q_mul.enable(&mut region, row)?;
+                        let a_cell = region.assign_advice(|| "a", config.a, row, || a)?;
+                        if let Some(prev_c) = &prev_c {
+                            region.constrain_equal(prev_c.cell(), a_cell.cell())?;
+                        }
+                        region.assign_advice(
+                            || "b",
+                            config.b,
+                            row,
+                            || Value::known(Fp::from(2)),
+                        )?;
+                        let c = a.map(|a| a.double());
+                        prev_c = Some(region.assign_advice(|| "c", config.c, row, || c)?);
+                        a = c;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    fn keygen_test_circuit() -> (ParamsIPA<EqAffine>, ProvingKey<EqAffine>) {
+        let params = ParamsIPA::<EqAffine>::new(K);
+        let empty_circuit = TestCircuit {
+            a: Value::unknown(),
+        };
+        let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+        let pk = keygen_pk(&params, vk, &empty_circuit).expect("keygen_pk should not fail");
+        (params, pk)
+    }
+
+    fn random_poly(domain: &EvaluationDomain<Fp>) -> Polynomial<Fp, Coeff> {
+        let mut poly = domain.empty_coeff();
+        for coeff in poly.iter_mut() {
+            *coeff = Fp::random(OsRng);
+        }
+        poly
+    }
+
+    fn random_coset(domain: &EvaluationDomain<Fp>) -> Polynomial<Fp, ExtendedLagrangeCoeff> {
+        domain.coeff_to_extended(random_poly(domain))
+    }
+
+    fn fixed_query(column_index: usize, rotation: i32) -> Expression<Fp> {
+        Expression::Fixed(FixedQuery {
+            index: 0,
+            column_index,
+            rotation: Rotation(rotation),
+        })
+    }
+
+    /// Evaluates `graph` on every row of `fixed`
+    fn evaluate_graph(
+        graph: &GraphEvaluator<EqAffine>,
+        fixed: &[Polynomial<Fp, LagrangeCoeff>],
+    ) -> Vec<Fp> {
+        let size = fixed[0].len();
+        let mut data = graph.instance();
+        let (beta, gamma, theta, y) = (Fp::from(2), Fp::from(3), Fp::from(5), Fp::from(7));
+        (0..size)
+            .map(|idx| {
+                graph.evaluate(
+                    &mut data,
+                    fixed,
+                    &[],
+                    &[],
+                    &[],
+                    &beta,
+                    &gamma,
+                    &theta,
+                    &y,
+                    &Fp::from(11),
+                    idx,
+                    1,
+                    size as i32,
+                )
+            })
+            .collect()
+    }
+
+    fn random_fixed(num_columns: usize) -> Vec<Polynomial<Fp, LagrangeCoeff>> {
+        let domain = EvaluationDomain::<Fp>::new(1, 3);
+        (0..num_columns)
+            .map(|_| {
+                let mut poly = domain.empty_lagrange();
+                for value in poly.iter_mut() {
+                    *value = Fp::random(OsRng);
+                }
+                poly
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reserved_graphs_are_not_reallocated() {
+        let expressions = [
+            (fixed_query(0, 0) - fixed_query(1, 1)) * Expression::Constant(Fp::from(3)),
+            fixed_query(0, 0) * fixed_query(2, -1) + fixed_query(1, 0).square(),
+        ];
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        graph.reserve(expressions.iter(), 1, 3);
+        let capacities = (
+            graph.calculations.capacity(),
+            graph.constants.capacity(),
+            graph.rotations.capacity(),
+        );
+        let parts = expressions
+            .iter()
+            .map(|expr| graph.add_expression(expr))
+            .collect();
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            parts,
+            ValueSource::Y(),
+        ));
+        assert_eq!(
+            (
+                graph.calculations.capacity(),
+                graph.constants.capacity(),
+                graph.rotations.capacity(),
+            ),
+            capacities
+        );
+
+        graph.prune();
+        graph.shrink_to_fit();
+        assert_eq!(graph.calculations.capacity(), graph.calculations.len());
+    }
+
+    #[test]
+    fn prune_drops_dangling_intermediates() {
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        // The product with zero leaves the square of column 1 dangling.
+        let dangling = fixed_query(1, 0) * fixed_query(1, 0);
+        let zero = graph.add_expression(&(dangling * Expression::Constant(Fp::zero())));
+        let sum = graph.add_expression(&(fixed_query(0, 1) + fixed_query(2, -1)));
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            vec![sum, zero],
+            ValueSource::Y(),
+        ));
+
+        let fixed = random_fixed(3);
+        let expected = evaluate_graph(&graph, &fixed);
+        let num_intermediates = graph.num_intermediates;
+
+        graph.prune();
+        assert_eq!(graph.num_intermediates, num_intermediates - 2);
+        assert_eq!(graph.calculations.len(), graph.num_intermediates);
+        assert_eq!(evaluate_graph(&graph, &fixed), expected);
+        assert_eq!(
+            graph.trace(),
+            "i0 = Store(Fixed(0,@rot1))\n\
+             i1 = Store(Fixed(2,@rot-1))\n\
+             i2 = Add(i0, i1)\n\
+             i3 = Horner(prev, [i2, c0], Y)\n"
+        );
+    }
+
+    #[test]
+    fn traced_evaluation_returns_every_calculation() {
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        let sum = graph.add_expression(&(fixed_query(0, 1) + fixed_query(2, -1)));
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            vec![sum],
+            ValueSource::Y(),
+        ));
+        assert_eq!(
+            graph.trace(),
+            "i0 = Store(Fixed(0,@rot1))\n\
+             i1 = Store(Fixed(2,@rot-1))\n\
+             i2 = Add(i0, i1)\n\
+             i3 = Horner(prev, [i2], Y)\n"
+        );
+
+        let fixed = random_fixed(3);
+        let size = fixed[0].len();
+        let (y, previous_value) = (Fp::from(7), Fp::from(11));
+        let mut data = graph.instance();
+        let values = graph.evaluate_with_trace(
+            &mut data,
+            &fixed,
+            &[],
+            &[],
+            &[],
+            &Fp::from(2),
+            &Fp::from(3),
+            &Fp::from(5),
+            &y,
+            &previous_value,
+            2,
+            1,
+            size as i32,
+        );
+        let (a, b) = (fixed[0][3], fixed[2][1]);
+        assert_eq!(values, vec![a, b, a + b, previous_value * y + a + b]);
+        assert_eq!(data.intermediates, values);
+        assert_eq!(values[3], evaluate_graph(&graph, &fixed)[2]);
+    }
+
+    #[test]
+    fn constant_gate_folds_to_constant() {
+        let constant = |value: u64| Expression::Constant(Fp::from(value));
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        // (3 + 4) * 5 - (-(6 * 2)) * 7
+        let gate = (constant(3) + constant(4))
+            * Expression::Scaled(Box::new(constant(5)), Fp::one())
+            - Expression::Negated(Box::new(constant(6) * constant(2))) * constant(7);
+        let folded = graph.add_expression(&gate);
+        let horner = graph.add_calculation(Calculation::Horner(
+            folded,
+            vec![ValueSource::Constant(1), folded],
+            ValueSource::Constant(2),
+        ));
+
+        assert!(graph.calculations.is_empty());
+        assert_eq!(graph.num_intermediates, 0);
+        assert_eq!(folded, ValueSource::Constant(graph.constants.len() - 2));
+        assert_eq!(horner, ValueSource::Constant(graph.constants.len() - 1));
+        let value = |source: &ValueSource| match source {
+            ValueSource::Constant(idx) => graph.constants[*idx],
+            _ => unreachable!(),
+        };
+        assert_eq!(value(&folded), Fp::from(119));
+        assert_eq!(value(&horner), Fp::from(119 * 4 + 2 + 119));
+    }
+
+    #[test]
+    fn sum_of_product_is_fused() {
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        let fused =
+            graph.add_expression(&(fixed_query(1, 0) * fixed_query(0, 0) + fixed_query(2, 0)));
+        let mul_adds = graph
+            .calculations
+            .iter()
+            .filter(|calc| matches!(calc.calculation, Calculation::MulAdd(..)));
+        assert_eq!(mul_adds.count(), 1);
+        assert_eq!(graph.num_intermediates, 4);
+        assert_eq!(
+            graph.calculations.last().unwrap().calculation.to_string(),
+            "MulAdd(i0, i1, i2)"
+        );
+
+        // The same sum with the operands swapped is shared.
+        let swapped =
+            graph.add_expression(&(fixed_query(2, 0) + fixed_query(0, 0) * fixed_query(1, 0)));
+        assert_eq!(swapped, fused);
+        assert_eq!(graph.num_intermediates, 4);
+
+        let fixed = random_fixed(3);
+        let expected: Vec<_> = (0..fixed[0].len())
+            .map(|idx| fixed[0][idx] * fixed[1][idx] + fixed[2][idx])
+            .collect();
+        assert_eq!(evaluate_graph(&graph, &fixed), expected);
+    }
+
+    #[test]
+    fn dot_product_is_a_linear_combination() {
+        let dot_product = fixed_query(0, 0) * Expression::Constant(Fp::from(3))
+            + fixed_query(1, 0) * Expression::Constant(Fp::from(5))
+            + fixed_query(2, 0) * fixed_query(0, 0)
+            + Expression::Scaled(Box::new(fixed_query(3, 0)), Fp::from(7));
+        let graph = GraphEvaluator::<EqAffine>::from_expression(&dot_product);
+
+        // The four queries, then a single node for the sum
+        assert_eq!(graph.num_intermediates, 5);
+        let combinations: Vec<_> = graph
+            .calculations
+            .iter()
+            .filter_map(|calc| match &calc.calculation {
+                Calculation::LinearCombination(terms) => Some(terms.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(combinations, vec![4]);
+        assert!(matches!(
+            graph.calculations.last().unwrap().calculation,
+            Calculation::LinearCombination(_)
+        ));
+        assert_eq!(graph.degree(), 2);
+        assert_eq!(graph.num_multiplications(), 4);
+        assert_eq!(graph.num_additions(), 3);
+
+        let fixed = random_fixed(4);
+        let expected: Vec<_> = (0..fixed[0].len())
+            .map(|idx| {
+                fixed[0][idx] * Fp::from(3)
+                    + fixed[1][idx] * Fp::from(5)
+                    + fixed[2][idx] * fixed[0][idx]
+                    + fixed[3][idx] * Fp::from(7)
+            })
+            .collect();
+        assert_eq!(evaluate_graph(&graph, &fixed), expected);
+
+        // Lowered to a product and a multiply-add per other term
+        let none: &[Polynomial<Fp, LagrangeCoeff>] = &[];
+        let device_graph =
+            DeviceGraph::lower(&graph, fixed[0].len(), &fixed, none, none, &[]).unwrap();
+        assert_eq!(device_graph.calculations.len(), 4 + 4);
+        assert_eq!(device_graph.evaluate(fixed[0].len(), 1), expected);
+    }
+
+    #[test]
+    fn theta_powers_match_horner_compression() {
+        let add_parts = |graph: &mut GraphEvaluator<EqAffine>| -> Vec<_> {
+            (0..3)
+                .map(|column| graph.add_expression(&fixed_query(column, 0)))
+                .collect()
+        };
+        let mut horner = GraphEvaluator::<EqAffine>::default();
+        let parts = add_parts(&mut horner);
+        horner.add_calculation(Calculation::Horner(
+            ValueSource::Constant(0),
+            parts,
+            ValueSource::Theta(),
+        ));
+        let mut powers = GraphEvaluator::<EqAffine>::default();
+        let parts = add_parts(&mut powers);
+        powers.add_calculation(Calculation::LinearCombination(
+            parts
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(power, part)| (ValueSource::ThetaPower(power), *part))
+                .collect(),
+        ));
+        assert_eq!(powers.instance().theta_powers.len(), 3);
+
+        let fixed = random_fixed(3);
+        let size = fixed[0].len();
+        let evaluate =
+            |graph: &GraphEvaluator<EqAffine>, data: &mut EvaluationData<EqAffine>, theta| {
+                (0..size)
+                    .map(|idx| {
+                        graph.evaluate(
+                            data,
+                            &fixed,
+                            &[],
+                            &[],
+                            &[],
+                            &Fp::zero(),
+                            &Fp::zero(),
+                            &theta,
+                            &Fp::zero(),
+                            &Fp::zero(),
+                            idx,
+                            1,
+                            size as i32,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+        // The powers are computed again when the same data is evaluated with
+        // another theta
+        let mut horner_data = horner.instance();
+        let mut powers_data = powers.instance();
+        for theta in [Fp::from(5), Fp::random(OsRng)] {
+            assert_eq!(
+                evaluate(&powers, &mut powers_data, theta),
+                evaluate(&horner, &mut horner_data, theta)
+            );
+        }
+    }
+
+    #[test]
+    fn lookup_graphs_read_precomputed_theta_powers() {
+        let fixture = Fixture::new(K, 1, 2);
+        for graph in fixture.pk.ev.lookups.iter() {
+            let sources: Vec<_> = graph
+                .calculations
+                .iter()
+                .flat_map(|calc| calc.calculation.sources())
+                .collect();
+            assert!(!sources.contains(&&ValueSource::Theta()));
+            assert!(sources.contains(&&ValueSource::ThetaPower(0)));
+            assert!(!graph
+                .calculations
+                .iter()
+                .any(|calc| matches!(calc.calculation, Calculation::Horner(..))));
+        }
+        let ev = evaluator_with(&fixture.pk, false);
+        let values = fixture.evaluate_h(&ev).unwrap();
+        assert_eq!(find_violating_row(&values, &fixture.pk.vk.domain), None);
+    }
+
+    #[test]
+    fn unscaled_rotations_match_the_remainder() {
+        for isize in [1, 2, 8] {
+            for idx in 0..isize as usize {
+                for rot in -2 * isize..=2 * isize {
+                    assert_eq!(
+                        get_rotation_idx(idx, rot, 1, isize),
+                        (idx as i32 + rot).rem_euclid(isize) as usize
+                    );
+                }
+            }
+        }
+
+        // A degree 2 expression on a domain that is not extended, as the one
+        // of a degree 2 circuit would be without the permutation argument
+        let domain = EvaluationDomain::<Fp>::new(2, K);
+        assert_eq!(domain.extended_k(), domain.k());
+        let expression =
+            fixed_query(0, -1) * fixed_query(1, 1) + fixed_query(2, 0) * fixed_query(0, 7);
+        let fixed = random_fixed(3);
+        let size = fixed[0].len();
+        let at = |column: usize, idx: usize, rot: i32| {
+            fixed[column][(idx as i32 + rot).rem_euclid(size as i32) as usize]
+        };
+        let expected: Vec<_> = (0..size)
+            .map(|idx| at(0, idx, -1) * at(1, idx, 1) + at(2, idx, 0) * at(0, idx, 7))
+            .collect();
+        let none: &[Polynomial<Fp, LagrangeCoeff>] = &[];
+        assert_eq!(
+            evaluate(&expression, size, 1, &fixed, none, none, &[]),
+            expected
+        );
+        assert_eq!(
+            evaluate_graph(&GraphEvaluator::from_expression(&expression), &fixed),
+            expected
+        );
+
+        // With it, the domain of h(X) of a circuit whose gates have degree 2
+        // is extended, whether or not it has columns to permute
+        for equality in [false, true] {
+            let mut cs = ConstraintSystem::<Fp>::default();
+            let a = cs.advice_column();
+            if equality {
+                cs.enable_equality(a);
+            }
+            cs.create_gate("square", |meta| {
+                let cur = meta.query_advice(a, Rotation::cur());
+                let next = meta.query_advice(a, Rotation::next());
+                vec![next - cur.clone() * cur]
+            });
+            assert_eq!(cs.degree(), 3);
+            let domain = EvaluationDomain::<Fp>::new(cs.degree() as u32, K);
+            assert!(domain.extended_k() > domain.k());
+        }
+        let (_, pk) = keygen_test_circuit();
+        assert!(pk.vk.domain.extended_k() > pk.vk.domain.k());
+    }
+
+    #[test]
+    fn graph_degree() {
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        assert_eq!(graph.degree(), 0);
+
+        let gates = [
+            fixed_query(0, 0) * fixed_query(1, 0) * fixed_query(2, 1) + fixed_query(3, 0),
+            fixed_query(0, 0) * fixed_query(0, 0) - Expression::Constant(Fp::from(3)),
+            fixed_query(1, -1).square().square() * Expression::Constant(Fp::from(2)),
+        ];
+        let parts: Vec<_> = gates
+            .iter()
+            .map(|gate| graph.add_expression(gate))
+            .collect();
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            parts,
+            ValueSource::Y(),
+        ));
+
+        let expected = gates.iter().map(|gate| gate.degree()).max().unwrap();
+        assert_eq!(expected, 4);
+        assert_eq!(graph.degree(), expected);
+        // Two products, one square, two squares and the Horner steps
+        assert_eq!(graph.num_multiplications(), 2 + 1 + 2 + 3);
+    }
+
+    #[test]
+    fn graph_validation() {
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        let sum = graph.add_expression(&(fixed_query(0, 1) + fixed_query(2, -1)));
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            vec![sum, ValueSource::Constant(2)],
+            ValueSource::Y(),
+        ));
+        assert_eq!(graph.validate(3, 0, 0, 0), Ok(()));
+        assert_eq!(
+            graph.validate(2, 0, 0, 0),
+            Err(EvalError::InvalidGraph(
+                "calculation 1 (Store(Fixed(2,r1))): fixed column index 2 out of range 0..2"
+                    .to_string()
+            ))
+        );
+
+        let mut malformed = graph.clone();
+        malformed.calculations[3].calculation = Calculation::Horner(
+            ValueSource::PreviousValue(),
+            vec![sum, ValueSource::Constant(3)],
+            ValueSource::Y(),
+        );
+        assert!(matches!(
+            malformed.validate(3, 0, 0, 0),
+            Err(EvalError::InvalidGraph(_))
+        ));
+
+        let mut malformed = graph.clone();
+        malformed.calculations[0].calculation = Calculation::Store(ValueSource::Advice(0, 3));
+        assert!(matches!(
+            malformed.validate(3, 1, 0, 0),
+            Err(EvalError::InvalidGraph(_))
+        ));
+
+        let mut malformed = graph.clone();
+        malformed.calculations.swap(0, 2);
+        assert_eq!(
+            malformed.validate(3, 0, 0, 0),
+            Err(EvalError::InvalidGraph(
+                "calculation 0 (Add(i0, i1)): intermediate 0 is read before it is computed"
+                    .to_string()
+            ))
+        );
+
+        let mut malformed = graph;
+        malformed.calculations[1].target = 0;
+        assert!(matches!(
+            malformed.validate(3, 0, 0, 0),
+            Err(EvalError::InvalidGraph(_))
+        ));
+    }
+
+    #[test]
+    fn merge_reuses_shared_calculations() {
+        let fixed = fixed_query;
+        let mut gates = GraphEvaluator::<EqAffine>::default();
+        gates.add_expression(&(fixed(0, 0) * fixed(1, 1) + fixed(2, 0)));
+        let mut lookup = GraphEvaluator::<EqAffine>::default();
+        lookup.add_expression(
+            &(fixed(2, -1) - fixed(0, 0) * fixed(1, 1) * Expression::Constant(Fp::from(5))),
+        );
+
+        // Merging a graph with itself does not grow it
+        let mut merged = gates.clone();
+        let remapping = merged.merge(&gates);
+        assert_eq!(merged.num_intermediates, gates.num_intermediates);
+        assert_eq!(merged.trace(), gates.trace());
+        assert_eq!(
+            remapping.intermediates,
+            (0..gates.num_intermediates)
+                .map(ValueSource::Intermediate)
+                .collect::<Vec<_>>()
+        );
+
+        // The product and the queries of the current rows are shared
+        let mut merged = gates.clone();
+        let remapping = merged.merge(&lookup);
+        assert!(merged.num_intermediates < gates.num_intermediates + lookup.num_intermediates);
+        assert_eq!(merged.validate(3, 0, 0, 0), Ok(()));
+
+        // The result of `lookup` is read from the merged graph
+        let fixed_values = random_fixed(3);
+        let size = fixed_values[0].len();
+        let expected = evaluate_graph(&lookup, &fixed_values);
+        let result = match remapping.apply(&ValueSource::Intermediate(
+            lookup.calculations.last().unwrap().target,
+        )) {
+            ValueSource::Intermediate(idx) => idx,
+            source => panic!("the result of the lookup is {:?}", source),
+        };
+        let mut data = merged.instance();
+        let (beta, gamma, theta, y) = (Fp::from(2), Fp::from(3), Fp::from(5), Fp::from(7));
+        for (idx, expected) in expected.iter().enumerate() {
+            merged.evaluate(
+                &mut data,
+                &fixed_values,
+                &[],
+                &[],
+                &[],
+                &beta,
+                &gamma,
+                &theta,
+                &y,
+                &Fp::from(11),
+                idx,
+                1,
+                size as i32,
+            );
+            assert_eq!(data.intermediates[result], *expected);
+        }
+    }
+
+    #[test]
+    fn graph_trace() {
+        let fixed = fixed_query;
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        let product = graph.add_expression(&(fixed(2, 1) * fixed(0, -1)));
+        let sum =
+            graph.add_expression(&(fixed(2, 1) * fixed(0, -1) + Expression::Constant(Fp::from(5))));
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            vec![sum, product],
+            ValueSource::Y(),
+        ));
+
+        assert_eq!(
+            graph.trace(),
+            "i0 = Store(Fixed(2,@rot1))\n\
+             i1 = Store(Fixed(0,@rot-1))\n\
+             i2 = Mul(i0, i1)\n\
+             i3 = Add(c3, i2)\n\
+             i4 = Horner(prev, [i3, i2], Y)\n"
+        );
+        assert_eq!(
+            Calculation::Mul(ValueSource::Fixed(2, 0), ValueSource::Beta()).to_string(),
+            "Mul(Fixed(2,r0), Beta)"
+        );
+    }
+
+    #[test]
+    fn cpu_backend_proof_verifies() {
+        let (params, pk) = keygen_test_circuit();
+        let circuit = TestCircuit {
+            a: Value::known(Fp::one()),
+        };
+
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<_>, _, _, _, _>(
+            &params,
+            &pk,
+            &[circuit],
+            &[&[]],
+            OsRng,
+            &mut transcript,
+        )
+        .expect("proof generation should not fail");
+        let proof = transcript.finalize();
+
+        let verifier_params = params.verifier_params();
+        let strategy = SingleStrategy::new(verifier_params);
+        let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(&proof[..]);
+        assert!(
+            verify_proof::<IPACommitmentScheme<EqAffine>, VerifierIPA<_>, _, _, _>(
+                verifier_params,
+                pk.get_vk(),
+                strategy,
+                &[&[]],
+                &mut transcript,
+            )
+            .is_ok()
+        );
+    }
+
+    #[derive(Clone, Debug)]
+    struct ChallengeConfig {
+        a: Column<Advice>,
+        b: Column<Advice>,
+        q: Selector,
+        early: Challenge,
+    }
+
+    /// Scales `a` by a challenge of the first phase into the second phase
+    /// column `b`. The challenge usable after the second phase is requested
+    /// first, so that the indices of the challenges are not in phase order.
+    #[derive(Clone, Default)]
+    struct ChallengeCircuit;
+
+    impl Circuit<Fp> for ChallengeCircuit {
+        type Config = ChallengeConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> ChallengeConfig {
+            let a = meta.advice_column_in(FirstPhase);
+            let b = meta.advice_column_in(SecondPhase);
+            let q = meta.selector();
+            let late = meta.challenge_usable_after(SecondPhase);
+            let early = meta.challenge_usable_after(FirstPhase);
+            assert_eq!((late.index(), early.index()), (0, 1));
+
+            // late * (b - a * early) = 0
+            meta.create_gate("scale", |meta| {
+                let q = meta.query_selector(q);
+                let a = meta.query_advice(a, Rotation::cur());
+                let b = meta.query_advice(b, Rotation::cur());
+                let late = meta.query_challenge(late);
+                let early = meta.query_challenge(early);
+                vec![q * late * (b - a * early)]
+            });
+
+            ChallengeConfig { a, b, q, early }
+        }
+
+        fn synthesize(
+            &self,
+            config: ChallengeConfig,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let early = layouter.get_challenge(config.early);
+            layouter.assign_region(
+                || "scale",
+                |mut region| {
+                    for row in 0..ROWS {
+                        config.q.enable(&mut region, row)?;
+                        let a = Value::known(Fp::from(row as u64 + 1));
+                        region.assign_advice(|| "a", config.a, row, || a)?;
+                        region.assign_advice(|| "b", config.b, row, || a * early)?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn challenges_of_later_phases_are_indexed_by_challenge() {
+        let params = ParamsIPA::<EqAffine>::new(K);
+        let vk = keygen_vk(&params, &ChallengeCircuit).expect("keygen_vk should not fail");
+        let pk = keygen_pk(&params, vk, &ChallengeCircuit).expect("keygen_pk should not fail");
+
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<_>, _, _, _, _>(
+            &params,
+            &pk,
+            &[ChallengeCircuit],
+            &[&[]],
+            OsRng,
+            &mut transcript,
+        )
+        .expect("proof generation should not fail");
+        let proof = transcript.finalize();
+
+        let verifier_params = params.verifier_params();
+        let strategy = SingleStrategy::new(verifier_params);
+        let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(&proof[..]);
+        assert!(
+            verify_proof::<IPACommitmentScheme<EqAffine>, VerifierIPA<_>, _, _, _>(
+                verifier_params,
+                pk.get_vk(),
+                strategy,
+                &[&[]],
+                &mut transcript,
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn cpu_custom_gates_match_expression_evaluation() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..pk.vk.cs.num_advice_columns)
+            .map(|_| random_coset(domain))
+            .collect();
+        let y = Fp::random(OsRng);
+
+        let data = CircuitData {
+            pk: &pk,
+            advice: &advice,
+            instance: &[],
+            challenges: &[],
+            y,
+            beta: Fp::random(OsRng),
+            gamma: Fp::random(OsRng),
+            theta: Fp::random(OsRng),
+        };
+        let mut values = domain.empty_extended();
+        CpuBackend
+            .evaluate_custom_gates(&pk.ev, &data, &mut values)
+            .unwrap();
+
+        let mut expected = vec![Fp::zero(); values.len()];
+        for gate in pk.vk.cs.gates.iter() {
+            for poly in gate.polynomials() {
+                let evaluated = evaluate(
+                    poly,
+                    values.len(),
+                    data.rot_scale(),
+                    &pk.fixed_cosets,
+                    &advice,
+                    &[],
+                    &[],
+                );
+                for (expected, evaluated) in expected.iter_mut().zip(evaluated) {
+                    *expected = *expected * y + evaluated;
+                }
+            }
+        }
+        assert_eq!(values.values, expected);
+    }
+
+    fn random_advice(pk: &ProvingKey<EqAffine>) -> Vec<Polynomial<Fp, Coeff>> {
+        (0..pk.vk.cs.num_advice_columns)
+            .map(|_| random_poly(&pk.vk.domain))
+            .collect()
+    }
+
+    /// Evaluates h(X) for a single circuit without lookups or permutation sets
+    fn evaluate_with<B: HEvaluatorBackend<EqAffine>>(
+        ev: &Evaluator<EqAffine>,
+        backend: &mut B,
+        pk: &ProvingKey<EqAffine>,
+        advice: &[Polynomial<Fp, Coeff>],
+    ) -> Result<Polynomial<Fp, ExtendedLagrangeCoeff>, EvalError> {
+        ev.evaluate_h_with_fallback(
+            backend,
+            pk,
+            &[advice],
+            &[&[]],
+            &[],
+            Fp::from(2),
+            Fp::from(3),
+            Fp::from(5),
+            Fp::from(7),
+            &[vec![]],
+            &[permutation::prover::Committed { sets: vec![] }],
+            &mut EvalScratch::default(),
+            &mut EvalTimings::default(),
+            &mut EvalControl::default(),
+        )
+    }
+
+    fn evaluator_with(pk: &ProvingKey<EqAffine>, fallback_to_cpu: bool) -> Evaluator<EqAffine> {
+        let mut ev = pk.ev.clone();
+        ev.config = EvaluatorConfig {
+            fallback_to_cpu,
+            ..Default::default()
+        };
+        ev
+    }
+
+    #[test]
+    fn lookup_phase_is_affine_in_values() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..pk.vk.cs.num_advice_columns)
+            .map(|_| random_coset(domain))
+            .collect();
+        let lookups: Vec<_> = (0..pk.vk.cs.lookups.len())
+            .map(|_| {
+                lookup::prover::Committed::from_polys(
+                    random_poly(domain),
+                    random_poly(domain),
+                    random_poly(domain),
+                )
+            })
+            .collect();
+        let y = Fp::random(OsRng);
+
+        let data = CircuitData {
+            pk: &pk,
+            advice: &advice,
+            instance: &[],
+            challenges: &[],
+            y,
+            beta: Fp::random(OsRng),
+            gamma: Fp::random(OsRng),
+            theta: Fp::random(OsRng),
+        };
+
+        let mut contribution = domain.empty_extended();
+        CpuBackend
+            .evaluate_lookups(&pk.ev, &data, &lookups, &mut contribution)
+            .unwrap();
+
+        let mut values = random_coset(domain);
+        let mut expected = values.clone();
+        CpuBackend
+            .evaluate_lookups(&pk.ev, &data, &lookups, &mut expected)
+            .unwrap();
+
+        accumulate_lookups(&mut values, y, lookups.len(), &contribution.values);
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn violating_row_is_found() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let random_lagrange = || {
+            let mut poly = domain.empty_lagrange();
+            for value in poly.iter_mut() {
+                *value = Fp::random(OsRng);
+            }
+            poly
+        };
+        // The gate a * b - c is enabled on the first rows only.
+        let a = random_lagrange();
+        let b = random_lagrange();
+        let mut c = domain.empty_lagrange();
+        for ((c, a), b) in c.iter_mut().zip(a.iter()).zip(b.iter()) {
+            *c = *a * *b;
+        }
+
+        let violating_row = |c: &Polynomial<Fp, LagrangeCoeff>| {
+            let advice: Vec<_> = [&a, &b, c]
+                .iter()
+                .map(|poly| domain.coeff_to_extended(domain.lagrange_to_coeff((*poly).clone())))
+                .collect();
+            let data = CircuitData {
+                pk: &pk,
+                advice: &advice,
+                instance: &[],
+                challenges: &[],
+                y: Fp::random(OsRng),
+                beta: Fp::random(OsRng),
+                gamma: Fp::random(OsRng),
+                theta: Fp::random(OsRng),
+            };
+            let mut values = domain.empty_extended();
+            CpuBackend
+                .evaluate_custom_gates(&pk.ev, &data, &mut values)
+                .unwrap();
+            find_violating_row(&values, domain)
+        };
+        assert_eq!(violating_row(&c), None);
+
+        let mut corrupted = c.clone();
+        corrupted[2] += Fp::one();
+        assert_eq!(violating_row(&corrupted), Some(2));
+
+        // The gate is disabled on this row.
+        let mut corrupted = c;
+        corrupted[ROWS + 1] += Fp::one();
+        assert_eq!(violating_row(&corrupted), None);
+    }
+
+    #[test]
+    fn row_range_matches_full_evaluation() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice = random_advice(&pk);
+        let lookups = vec![(0..pk.vk.cs.lookups.len())
+            .map(|_| {
+                lookup::prover::Committed::from_polys(
+                    random_poly(domain),
+                    random_poly(domain),
+                    random_poly(domain),
+                )
+            })
+            .collect::<Vec<_>>()];
+        let chunk_len = pk.vk.cs.degree() - 2;
+        let sets = pk
+            .vk
+            .cs
+            .permutation
+            .columns
+            .chunks(chunk_len)
+            .map(|_| permutation::prover::CommittedSet::from_poly(domain, random_poly(domain)))
+            .collect();
+        let permutations = [permutation::prover::Committed { sets }];
+        let ev = evaluator_with(&pk, false);
+        let (y, beta, gamma, theta) = (
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+        );
+
+        let mut full = domain.empty_extended();
+        ev.evaluate_h_with_backend(
+            &mut CpuBackend,
+            &pk,
+            &[&advice],
+            &[&[]],
+            &[],
+            y,
+            beta,
+            gamma,
+            theta,
+            &lookups,
+            &permutations,
+            &mut full,
+            &mut EvalScratch::default(),
+            &mut EvalTimings::default(),
+            &mut EvalControl::default(),
+        )
+        .unwrap();
+
+        let size = full.len();
+        // The last rows rotate past the end of the domain.
+        for rows in [0..5, 37..101, size - 3..size, 17..17, 0..size] {
+            let values = ev.evaluate_h_range(
+                &pk,
+                &[&advice],
+                &[],
+                &[],
+                y,
+                beta,
+                gamma,
+                theta,
+                &lookups,
+                &permutations,
+                rows.clone(),
+            );
+            assert_eq!(values, Ok(full.values[rows].to_vec()));
+        }
+
+        // From the polynomials of the prover
+        let lookup_polys: Vec<_> = lookups[0]
+            .iter()
+            .map(|lookup| {
+                [
+                    lookup.permuted_input_poly.clone(),
+                    lookup.permuted_table_poly.clone(),
+                    lookup.product_poly.clone(),
+                ]
+            })
+            .collect();
+        let permutation_polys: Vec<_> = permutations[0]
+            .sets
+            .iter()
+            .map(|set| set.permutation_product_poly.clone())
+            .collect();
+        let evaluate_rows = |rows: Range<usize>| {
+            ev.evaluate_h_rows(
+                &pk,
+                &[&advice],
+                &[],
+                &[],
+                y,
+                beta,
+                gamma,
+                theta,
+                &[&lookup_polys],
+                &[&permutation_polys],
+                rows,
+            )
+        };
+        assert_eq!(evaluate_rows(37..101), Ok(full.values[37..101].to_vec()));
+        assert_eq!(
+            evaluate_rows(size - 3..size + 1),
+            Err(EvalError::RowsOutOfRange {
+                start: size - 3,
+                end: size + 1,
+                size,
+            })
+        );
+    }
+
+    #[test]
+    fn stats_count_the_graph_sizes() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let q = cs.fixed_column();
+        let t = cs.fixed_column();
+        let a = cs.advice_column();
+        let b = cs.advice_column();
+        cs.create_gate("next", |meta| {
+            let q = meta.query_fixed(q, Rotation::cur());
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::next());
+            vec![q * (a - b)]
+        });
+        for _ in 0..2 {
+            cs.lookup_any("table", |meta| {
+                let a = meta.query_advice(a, Rotation::cur());
+                let t = meta.query_fixed(t, Rotation::cur());
+                vec![(a, t)]
+            });
+        }
+
+        // The gate stores its three queries, subtracts, multiplies and folds
+        // with y. Each lookup stores and compresses each side, adds beta and
+        // gamma and multiplies.
+        assert_eq!(
+            Evaluator::<EqAffine>::new(&cs).stats(),
+            EvaluatorStats {
+                gate_intermediates: 6,
+                gate_constants: 3,
+                gate_rotations: 2,
+                per_lookup_intermediates: vec![7, 7],
+            }
+        );
+    }
+
+    #[test]
+    fn mock_backend_records_phases() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let ev = evaluator_with(&pk, false);
+        let size = pk.vk.domain.extended_len();
+
+        let expected = evaluate_with(&ev, &mut CpuBackend, &pk, &advice).unwrap();
+
+        let mut backend = MockBackend::default();
+        let values = evaluate_with(&ev, &mut backend, &pk, &advice).unwrap();
+        assert_eq!(
+            backend.calls,
+            vec![
+                (Phase::CustomGates, size),
+                (Phase::Permutations, size),
+                (Phase::Lookups, size)
+            ]
+        );
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn circuits_without_lookups_skip_the_cuda_lookup_kernel() {
+        let (_, pk) = keygen_test_circuit();
+        let mut cs = pk.vk.cs.clone();
+        cs.lookups.clear();
+        let mut ev = Evaluator::<EqAffine>::new(&cs);
+        ev.config = evaluator_with(&pk, false).config;
+        assert!(ev.lookups.is_empty());
+        assert_eq!(ev.cuda_launch_plan(&pk, 2).kernel_launches, 0);
+
+        let advice = random_advice(&pk);
+        let expected = evaluate_with(&ev, &mut CpuBackend, &pk, &advice).unwrap();
+
+        // Without a device or a kernel module, as in CI, the CUDA backend only
+        // succeeds if neither the batched nor the per circuit lookups load it
+        let mut backend = ev.cuda_backend();
+        let values = evaluate_with(&ev, &mut backend, &pk, &advice).unwrap();
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn custom_gate_chunks_do_not_change_the_values() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let ev = evaluator_with(&pk, false);
+        assert_eq!(ev.config.chunks_per_thread, 1);
+        let expected = evaluate_with(&ev, &mut CpuBackend, &pk, &advice).unwrap();
+
+        // More chunks than rows, of a row each
+        for chunks_per_thread in [7, pk.vk.domain.extended_len() + 1] {
+            let mut ev = ev.clone();
+            ev.config.chunks_per_thread = chunks_per_thread;
+            let values = evaluate_with(&ev, &mut CpuBackend, &pk, &advice).unwrap();
+            assert_eq!(values.values, expected.values);
+        }
+    }
+
+    #[test]
+    fn backend_failure_is_reported() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let ev = evaluator_with(&pk, false);
+
+        let mut backend =
+            MockBackend::failing_at(Phase::Lookups, EvalError::Driver("kernel launch"));
+        let result = evaluate_with(&ev, &mut backend, &pk, &advice);
+        assert_eq!(result.err(), Some(EvalError::Driver("kernel launch")));
+        assert_eq!(backend.calls.len(), 3);
+    }
+
+    #[test]
+    fn failure_after_the_first_circuit_returns_no_polynomial() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let evaluate = |ev: &Evaluator<EqAffine>, backend: &mut MockBackend| {
+            ev.evaluate_h_with_fallback(
+                backend,
+                &pk,
+                &[&advice, &advice, &advice],
+                &[&[], &[], &[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![], vec![], vec![]],
+                &[
+                    permutation::prover::Committed { sets: vec![] },
+                    permutation::prover::Committed { sets: vec![] },
+                    permutation::prover::Committed { sets: vec![] },
+                ],
+                &mut EvalScratch::default(),
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+        };
+        let failing = || {
+            MockBackend::failing_at(Phase::CustomGates, EvalError::Driver("kernel launch")).after(1)
+        };
+
+        // The first circuit is accumulated, but only the error comes out
+        let mut backend = failing();
+        let result = evaluate(&evaluator_with(&pk, false), &mut backend);
+        assert_eq!(result.err(), Some(EvalError::Driver("kernel launch")));
+        assert_eq!(backend.calls.len(), 5);
+        assert_eq!(backend.calls[4].0, Phase::CustomGates);
+
+        // Falling back, all the circuits are evaluated again from scratch
+        let ev = evaluator_with(&pk, true);
+        let expected = evaluate(&ev, &mut MockBackend::default()).unwrap();
+        let values = evaluate(&ev, &mut failing()).unwrap();
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn cancellation_stops_before_the_next_phase() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let cancel = CancellationToken::new();
+        let mut backend = MockBackend::cancelling_at(Phase::Permutations, &cancel);
+        let result = evaluator_with(&pk, true).evaluate_h_with_fallback(
+            &mut backend,
+            &pk,
+            &[&advice, &advice],
+            &[&[], &[]],
+            &[],
+            Fp::from(2),
+            Fp::from(3),
+            Fp::from(5),
+            Fp::from(7),
+            &[vec![], vec![]],
+            &[
+                permutation::prover::Committed { sets: vec![] },
+                permutation::prover::Committed { sets: vec![] },
+            ],
+            &mut EvalScratch::default(),
+            &mut EvalTimings::default(),
+            &mut EvalControl::new(&cancel, None),
+        );
+
+        // Neither the lookups of the first circuit nor the fallback are run
+        assert_eq!(result.err(), Some(EvalError::Cancelled));
+        let phases: Vec<_> = backend.calls.iter().map(|call| call.0).collect();
+        assert_eq!(phases, vec![Phase::CustomGates, Phase::Permutations]);
+        assert!(cancel.is_cancelled());
+    }
+
+    #[test]
+    fn progress_is_reported_before_each_phase() {
+        let fixture = Fixture::new(K, 1, 1);
+        let ev = evaluator_with(&fixture.pk, false);
+        let mut reports = vec![];
+        let mut progress = |progress: Progress| reports.push(progress);
+        ev.evaluate_h(
+            &fixture.pk,
+            &[&fixture.advice],
+            &[&fixture.instance],
+            &[],
+            fixture.y,
+            fixture.beta,
+            fixture.gamma,
+            fixture.theta,
+            &fixture.lookups,
+            &fixture.permutations,
+            &mut EvalScratch::default(),
+            &mut EvalControl::new(&CancellationToken::default(), Some(&mut progress)),
+        )
+        .unwrap();
+
+        let phases: Vec<_> = reports.iter().map(|report| report.phase).collect();
+        assert_eq!(
+            phases,
+            vec![
+                ProgressPhase::Cosets,
+                ProgressPhase::CustomGates,
+                ProgressPhase::Permutations,
+                ProgressPhase::Lookups,
+                ProgressPhase::Done,
+            ]
+        );
+        let fractions: Vec<_> = reports.iter().map(|report| report.fraction).collect();
+        assert_eq!(fractions, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn inversions_are_batched_over_the_rows() {
+        let domain = EvaluationDomain::<Fp>::new(1, 11);
+        let mut advice = domain.empty_lagrange();
+        for value in advice.iter_mut() {
+            *value = Fp::random(OsRng);
+        }
+        // Zero inversions on the rows 4 and 7
+        advice[5] = Fp::zero();
+        advice[7] = -Fp::from(2);
+
+        // 1 / (1 / (a + beta) * a(X * omega)), folded into the previous value
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        let rotation = graph.add_rotation(&Rotation::cur());
+        let next = graph.add_rotation(&Rotation::next());
+        let a = graph.add_calculation(Calculation::Store(ValueSource::Advice(0, rotation)));
+        let shifted = graph.add_calculation(Calculation::Add(a, ValueSource::Beta()));
+        let inverse = graph.add_calculation(Calculation::Inverse(shifted));
+        let a_next = graph.add_calculation(Calculation::Store(ValueSource::Advice(0, next)));
+        let product = graph.add_calculation(Calculation::Mul(inverse, a_next));
+        let inverse = graph.add_calculation(Calculation::Inverse(product));
+        graph.add_calculation(Calculation::Horner(
+            ValueSource::PreviousValue(),
+            vec![inverse],
+            ValueSource::Y(),
+        ));
+
+        let size = advice.len();
+        let previous: Vec<_> = (0..size).map(|_| Fp::random(OsRng)).collect();
+        let (beta, gamma, theta, y) = (Fp::from(2), Fp::from(3), Fp::from(5), Fp::from(7));
+        let advice = [advice];
+        let mut data = graph.instance();
+        let expected: Vec<_> = previous
+            .iter()
+            .enumerate()
+            .map(|(idx, previous)| {
+                graph.evaluate(
+                    &mut data,
+                    &[],
+                    &advice,
+                    &[],
+                    &[],
+                    &beta,
+                    &gamma,
+                    &theta,
+                    &y,
+                    previous,
+                    idx,
+                    1,
+                    size as i32,
+                )
+            })
+            .collect();
+        assert_eq!(expected[4], previous[4] * y);
+        assert_eq!(expected[7], previous[7] * y);
+
+        // Two blocks of rows, the last one partial, from an offset
+        let offset = 3;
+        let mut values = previous[offset..].to_vec();
+        graph.evaluate_rows(
+            &mut data,
+            &[],
+            &advice,
+            &[],
+            &[],
+            &beta,
+            &gamma,
+            &theta,
+            &y,
+            &mut values,
+            offset,
+            1,
+            size as i32,
+        );
+        assert_eq!(values, expected[offset..]);
+    }
+
+    #[test]
+    fn every_split_of_the_phases_gives_the_same_polynomial() {
+        let fixture = Fixture::new(K, 2, 1);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        let evaluate = |ev: &Evaluator<EqAffine>, backend: &mut MockBackend| {
+            ev.evaluate_h_with_fallback(
+                backend,
+                &fixture.pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &mut EvalScratch::default(),
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+        let expected = evaluate(&ev, &mut MockBackend::default());
+
+        for split in 0..8 {
+            let phases = GpuPhases {
+                gates: split & 1 != 0,
+                permutations: split & 2 != 0,
+                lookups: split & 4 != 0,
+            };
+            ev.config.gpu_phases = phases;
+            let mut backend = MockBackend::default();
+            assert_eq!(evaluate(&ev, &mut backend).values, expected.values);
+
+            // Only the phases on the device reach the backend
+            let on_device: Vec<_> = backend.calls.iter().map(|call| call.0).collect();
+            let enabled = [
+                (phases.gates, Phase::CustomGates),
+                (phases.permutations, Phase::Permutations),
+                (phases.lookups, Phase::Lookups),
+            ];
+            let expected_calls: Vec<_> = enabled
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, phase)| *phase)
+                .collect();
+            assert_eq!(on_device, expected_calls);
+        }
+
+        assert!(!GpuPhases::parse("Gates, lookups").unwrap().permutations);
+        assert!(!GpuPhases::parse("").unwrap().gates);
+        assert_eq!(GpuPhases::parse("fft"), None);
+    }
+
+    #[test]
+    fn sampled_rows_catch_a_wrong_value() {
+        assert_eq!(sample_rows(7, 16, 1 << 8), sample_rows(7, 16, 1 << 8));
+        assert_ne!(sample_rows(7, 16, 1 << 8), sample_rows(8, 16, 1 << 8));
+        assert_eq!(sample_rows(7, 16, 8), (0..8).collect::<Vec<_>>());
+        let sampled = sample_rows(7, 16, 1 << 8);
+        assert_eq!(sampled.len(), 16);
+        assert!(sampled.windows(2).all(|rows| rows[0] < rows[1]));
+
+        let fixture = Fixture::new(K, 2, 1);
+        let ev = evaluator_with(&fixture.pk, false);
+        let mut scratch = EvalScratch::default();
+        let mut values = ev
+            .evaluate_h_with_fallback(
+                &mut CpuBackend,
+                &fixture.pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &mut scratch,
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+        let verify = |values: &Polynomial<Fp, ExtendedLagrangeCoeff>, rows: usize| {
+            ev.verify_sampled_rows(
+                &fixture.pk,
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &scratch,
+                values,
+                rows,
+                7,
+            )
+        };
+        assert_eq!(verify(&values, 16), Ok(()));
+
+        // Only a sampled row is checked
+        let size = values.len();
+        let sampled = sample_rows(7, 16, size);
+        let skipped = (0..size).find(|row| !sampled.contains(row)).unwrap();
+        values[skipped] += Fp::one();
+        assert_eq!(verify(&values, 16), Ok(()));
+        values[sampled[3]] += Fp::one();
+        assert_eq!(
+            verify(&values, 16),
+            Err(EvalError::VerificationFailed { row: sampled[3] })
+        );
+        assert_eq!(
+            verify(&values, size),
+            Err(EvalError::VerificationFailed {
+                row: skipped.min(sampled[3])
+            })
+        );
+    }
+
+    #[test]
+    fn missing_cuda_device() {
+        let (_, pk) = keygen_test_circuit();
+        let mut ev = evaluator_with(&pk, true);
+        ev.config.backend = Backend::Cuda;
+        assert_eq!(ev.resolve_backend(|| true), Ok(Backend::Cuda));
+        assert_eq!(ev.resolve_backend(|| false), Ok(Backend::Cpu));
+
+        ev.config.fallback_to_cpu = false;
+        assert_eq!(ev.resolve_backend(|| false), Err(EvalError::NoCudaDevice));
+        ev.config.backend = Backend::CudaSampledVerify { rows: 16, seed: 7 };
+        assert_eq!(ev.resolve_backend(|| false), Err(EvalError::NoCudaDevice));
+
+        ev.config.backend = Backend::Cpu;
+        assert_eq!(
+            ev.resolve_backend(|| panic!("the CPU backend needs no probe")),
+            Ok(Backend::Cpu)
+        );
+    }
+
+    #[test]
+    fn cuda_launch_saturates_device() {
+        let device = |multiprocessor_count| DeviceProperties {
+            multiprocessor_count,
+            max_threads_per_block: 1024,
+            warp_size: 32,
+            compute_capability: (8, 0),
+        };
+        let (small, large) = (device(30), device(108));
+
+        // Large kernels fill every multiprocessor, whatever their count
+        assert_eq!(small.launch_threads(1 << 20), 30 * 1024 * 2);
+        assert_eq!(large.launch_threads(1 << 20), 108 * 1024 * 2);
+        // Small kernels only launch the warps they need
+        assert_eq!(small.launch_threads(1000), 1024);
+        assert_eq!(large.launch_threads(1000), 1024);
+        assert_eq!(large.launch_threads(0), 32);
+    }
+
+    #[test]
+    fn cubin_architecture_matches_device() {
+        let device = |compute_capability| DeviceProperties {
+            multiprocessor_count: 108,
+            max_threads_per_block: 1024,
+            warp_size: 32,
+            compute_capability,
+        };
+
+        assert!(device((8, 0)).runs_cubin("sm_80"));
+        assert!(device((8, 6)).runs_cubin("80"));
+        assert!(!device((7, 5)).runs_cubin("sm_80"));
+        assert!(!device((9, 0)).runs_cubin("sm_80"));
+        assert!(!device((8, 6)).runs_cubin("sm_89"));
+        assert!(!device((8, 0)).runs_cubin("compute_80"));
+        assert!(!device((8, 0)).runs_cubin(""));
+    }
+
+    #[test]
+    fn ptx_entry_points_are_listed() {
+        let ptx = "
+            .version 7.4
+            .target sm_52
+            .address_size 64
+
+            .visible .entry compute_evaluate_h_lookups_codeblock(
+                .param .u64 compute_evaluate_h_lookups_codeblock_param_0
+            )
+            {
+                ret;
+            }
+            .entry helper (.param .u32 helper_param_0)
+            {
+                ret;
+            }
+        ";
+
+        assert_eq!(
+            ptx_entry_points(ptx),
+            vec!["compute_evaluate_h_lookups_codeblock", "helper"]
+        );
+        assert!(ptx_entry_points(".version 7.4").is_empty());
+        assert_eq!(
+            EvalError::MissingKernelSymbol {
+                name: "compute_evaluate_h_lookups_codeblock",
+                available: vec![String::from("helper")],
+            }
+            .to_string(),
+            "kernel function 'compute_evaluate_h_lookups_codeblock' not found in the CUDA module, which defines: helper"
+        );
+    }
+
+    #[test]
+    fn linked_ptx_declarations_are_resolved() {
+        let lookups = "
+            .version 7.4
+            .target sm_52
+            .extern .func (.param .b32 func_retval0) reduce_row (.param .b64 reduce_row_param_0);
+            .visible .entry compute_evaluate_h_lookups_codeblock()
+            {
+                ret;
+            }
+        ";
+        let reduce = "
+            .version 7.4
+            .target sm_52
+            .visible .func (.param .b32 func_retval0) reduce_row(.param .b64 reduce_row_param_0)
+            {
+                ret;
+            }
+        ";
+
+        assert_eq!(
+            check_ptx_declarations(&[("lookups.ptx", lookups), ("reduce.ptx", reduce)]),
+            Ok(())
+        );
+        let err = check_ptx_declarations(&[("lookups.ptx", lookups)]).unwrap_err();
+        assert_eq!(
+            err,
+            EvalError::UnresolvedKernelSymbol {
+                name: String::from("reduce_row"),
+                file: String::from("lookups.ptx"),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "function 'reduce_row' declared by lookups.ptx is not defined by any of the linked CUDA modules"
+        );
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        use tracing::level_filters::LevelFilter;
+
+        assert_eq!(Verbosity::parse("silent"), Some(Verbosity::Silent));
+        assert_eq!(Verbosity::parse(" Debug\n"), Some(Verbosity::Debug));
+        assert_eq!(Verbosity::parse("error"), Some(Verbosity::Errors));
+        assert_eq!(Verbosity::parse("loud"), None);
+        assert_eq!(EvaluatorConfig::default().verbosity, Verbosity::Errors);
+
+        assert_eq!(Verbosity::Silent.level_filter(), LevelFilter::OFF);
+        assert_eq!(Verbosity::Info.level_filter(), LevelFilter::INFO);
+        assert!(Verbosity::Errors < Verbosity::Info && Verbosity::Info < Verbosity::Debug);
+    }
+
+    #[test]
+    fn only_transient_cuda_errors_are_retried() {
+        let retry = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(10),
+        };
+        assert_eq!(retry.backoff(0), Duration::from_millis(10));
+        assert_eq!(retry.backoff(2), Duration::from_millis(40));
+        assert_eq!(EvaluatorConfig::default().retry.max_retries, 0);
+
+        let cuda = |code| EvalError::Cuda {
+            operation: "kernel launch",
+            code,
+            message: String::new(),
+        };
+        // CUDA_ERROR_LAUNCH_TIMEOUT and CUDA_ERROR_ECC_UNCORRECTABLE
+        assert!(is_transient(&cuda(702)));
+        assert!(is_transient(&cuda(214)));
+        // CUDA_ERROR_OUT_OF_MEMORY and CUDA_ERROR_ILLEGAL_ADDRESS
+        assert!(!is_transient(&cuda(2)));
+        assert!(!is_transient(&cuda(700)));
+        assert!(!is_transient(&EvalError::MissingKernelSymbol {
+            name: "compute_evaluate_h_lookups_codeblock",
+            available: vec![],
+        }));
+        assert!(!is_transient(&EvalError::Driver("kernel launch")));
+    }
+
+    #[test]
+    fn shared_evaluator_evaluates_on_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Evaluator<EqAffine>>();
+
+        let (_, pk) = keygen_test_circuit();
+        let advice = Arc::new(random_advice(&pk));
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.backend = Backend::Cpu;
+        let (ev, pk) = (Arc::new(ev), Arc::new(pk));
+
+        let evaluate = |ev: &Evaluator<EqAffine>,
+                        pk: &ProvingKey<EqAffine>,
+                        advice: &[Polynomial<Fp, Coeff>]| {
+            ev.evaluate_h(
+                pk,
+                &[advice],
+                &[&[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![]],
+                &[permutation::prover::Committed { sets: vec![] }],
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+        let expected = evaluate(&ev, &pk, &advice);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let (ev, pk, advice) = (ev.clone(), pk.clone(), advice.clone());
+                std::thread::spawn(move || evaluate(&ev, &pk, &advice))
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().values, expected.values);
+        }
+    }
+
+    #[test]
+    fn single_threaded_evaluation_matches() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.backend = Backend::Cpu;
+
+        let evaluate = |ev: &Evaluator<EqAffine>| {
+            ev.evaluate_h(
+                &pk,
+                &[&advice],
+                &[&[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![]],
+                &[permutation::prover::Committed { sets: vec![] }],
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+        let expected = evaluate(&ev);
+
+        ev.config.single_threaded = true;
+        assert_eq!(evaluate(&ev).values, expected.values);
+        assert_eq!(ev.on_cpu(crate::multicore::current_num_threads), 1);
+    }
+
+    #[test]
+    fn pinned_evaluation_matches() {
+        let fixture = Fixture::new(K, 2, 1);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cpu;
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        ev.config.pin_threads = true;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        assert!(ev.on_cpu(crate::multicore::current_num_threads) >= 1);
+    }
+
+    #[test]
+    fn scratch_is_reused_across_evaluations() {
+        let (_, pk) = keygen_test_circuit();
+        let ev = evaluator_with(&pk, false);
+        let evaluate = |advice: &[Polynomial<Fp, Coeff>], scratch: &mut EvalScratch<Fp>| {
+            ev.evaluate_h_with_fallback(
+                &mut CpuBackend,
+                &pk,
+                &[advice],
+                &[&[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![]],
+                &[permutation::prover::Committed { sets: vec![] }],
+                scratch,
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+        let cosets = |scratch: &EvalScratch<Fp>| -> Vec<_> {
+            scratch.advice[0]
+                .iter()
+                .map(|coset| coset.values.as_ptr())
+                .collect()
+        };
+
+        let mut scratch = EvalScratch::default();
+        evaluate(&random_advice(&pk), &mut scratch);
+        let allocations = cosets(&scratch);
+
+        // The second evaluation writes its cosets over those of the first
+        let advice = random_advice(&pk);
+        assert_eq!(
+            evaluate(&advice, &mut scratch).values,
+            evaluate(&advice, &mut EvalScratch::default()).values
+        );
+        assert_eq!(cosets(&scratch), allocations);
+
+        // As does the one of a thread keeping its scratch
+        let allocations = EvalScratch::with_thread_scratch(|scratch| {
+            evaluate(&advice, scratch);
+            cosets(scratch)
+        });
+        EvalScratch::with_thread_scratch(|scratch| {
+            evaluate(&random_advice(&pk), scratch);
+            assert_eq!(cosets(scratch), allocations);
+        });
+    }
+
+    #[cfg(feature = "count-allocations")]
+    #[test]
+    fn reused_scratch_does_not_allocate_the_cosets_again() {
+        use super::allocations::count_allocations;
+
+        let (_, pk) = keygen_test_circuit();
+        let ev = evaluator_with(&pk, false);
+        let advice = random_advice(&pk);
+        let coset_bytes = pk.vk.domain.extended_len() * std::mem::size_of::<Fp>();
+        let mut scratch = EvalScratch::default();
+        let mut evaluate = || {
+            count_allocations(coset_bytes, || {
+                ev.evaluate_h_with_fallback(
+                    &mut CpuBackend,
+                    &pk,
+                    &[&advice],
+                    &[&[]],
+                    &[],
+                    Fp::from(2),
+                    Fp::from(3),
+                    Fp::from(5),
+                    Fp::from(7),
+                    &[vec![]],
+                    &[permutation::prover::Committed { sets: vec![] }],
+                    &mut scratch,
+                    &mut EvalTimings::default(),
+                    &mut EvalControl::default(),
+                )
+                .unwrap()
+            })
+        };
+
+        let (first, first_count) = evaluate();
+        let (second, second_count) = evaluate();
+        assert_eq!(first.values, second.values);
+        // The values are allocated by every evaluation, the cosets of the
+        // advice only by the first
+        assert!(second_count >= 1);
+        assert!(
+            second_count + advice.len() <= first_count,
+            "{} large allocations with a reused scratch, {} without",
+            second_count,
+            first_count
+        );
+    }
+
+    #[test]
+    fn cuda_launch_plan() {
+        let (_, pk) = keygen_test_circuit();
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.cuda_threads = Some(256);
+        let size = pk.vk.domain.extended_len();
+        let lookups = ev.lookups.len();
+        assert!(lookups > 0);
+
+        let plan = ev.cuda_launch_plan(&pk, 2);
+        assert_eq!(plan.kernel_launches, 1);
+        assert_eq!(plan.threads, 256);
+        assert_eq!(
+            plan.buffers
+                .iter()
+                .find(|(name, _)| *name == "combined_data_in"),
+            Some(&("combined_data_in", 2 * lookups * size * 80))
+        );
+        assert_eq!(
+            plan.device_bytes(),
+            (2 + 3 + 3 * 2 * lookups) * size * 32
+                + 4 * 32
+                + 3 * 8
+                + 2 * lookups * size * 80
+                + 2 * 32
+        );
+
+        // The split layout uploads 64 bytes per row instead of 80
+        ev.config.lookup_layout = LookupLayout::Split;
+        let plan = ev.cuda_launch_plan(&pk, 2);
+        assert_eq!(
+            plan.buffers.iter().find(|(name, _)| *name == "a_minus_s"),
+            Some(&("a_minus_s", 2 * lookups * size * 32))
+        );
+        assert_eq!(
+            plan.device_bytes(),
+            (2 + 3 + 5 * 2 * lookups) * size * 32 + 4 * 32 + 3 * 8 + 80
+        );
+        ev.config.lookup_layout = LookupLayout::Interleaved;
+
+        // A launch per lookup, with the buffers of a single one
+        ev.config.lookup_strategy = LookupStrategy::PerLookup;
+        let plan = ev.cuda_launch_plan(&pk, 2);
+        assert_eq!(plan.kernel_launches, 2 * lookups);
+        assert_eq!(
+            plan.device_bytes(),
+            (1 + 3 + 3) * size * 32 + 4 * 32 + 2 * 8 + size * 80 + 2 * 32
+        );
+
+        ev.lookups.clear();
+        assert_eq!(ev.cuda_launch_plan(&pk, 2).kernel_launches, 0);
+    }
+
+    #[test]
+    fn lookup_strategies() {
+        assert_eq!(LookupStrategy::default(), LookupStrategy::Batched);
+        assert_eq!(
+            LookupStrategy::parse(" Per-Lookup "),
+            Some(LookupStrategy::PerLookup)
+        );
+        assert_eq!(
+            LookupStrategy::parse("batched"),
+            Some(LookupStrategy::Batched)
+        );
+        assert_eq!(LookupStrategy::parse("streams"), None);
+    }
+
+    #[test]
+    fn lookup_layouts() {
+        assert_eq!(LookupLayout::default(), LookupLayout::Interleaved);
+        assert_eq!(LookupLayout::parse(" Split "), Some(LookupLayout::Split));
+        assert_eq!(
+            LookupLayout::parse("interleaved"),
+            Some(LookupLayout::Interleaved)
+        );
+        assert_eq!(LookupLayout::parse("soa"), None);
+    }
+
+    #[test]
+    fn split_lookup_buffers_match_the_interleaved_ones() {
+        let fixture = Fixture::new(K, 1, 2);
+        let ev = evaluator_with(&fixture.pk, false);
+        let advice = extended_cosets(&fixture.pk.vk.domain, &[&fixture.advice]);
+        let instance = extended_cosets(&fixture.pk.vk.domain, &[&fixture.instance]);
+        let data = CircuitData {
+            pk: &fixture.pk,
+            advice: &advice[0],
+            instance: &instance[0],
+            challenges: &[],
+            y: fixture.y,
+            beta: fixture.beta,
+            gamma: fixture.gamma,
+            theta: fixture.theta,
+        };
+        let lookups = &fixture.lookups[0];
+        let mut interleaved = LookupBuffers::new();
+        prepare_lookups(&ev, &data, lookups, 0, &mut interleaved);
+        let mut split = LookupBuffers::with_layout(LookupLayout::Split);
+        prepare_lookups(&ev, &data, lookups, 0, &mut split);
+
+        assert!(split.combined_data_in.is_empty());
+        assert!(interleaved.table_values.is_empty() && interleaved.a_minus_s.is_empty());
+        assert_eq!(split.product_coset, interleaved.product_coset);
+        let (table_values, a_minus_s): (Vec<_>, Vec<_>) = interleaved
+            .combined_data_in
+            .iter()
+            .map(|combined| (combined.table_value, combined.a_minus_s))
+            .unzip();
+        assert_eq!(split.table_values, table_values);
+        assert_eq!(split.a_minus_s, a_minus_s);
+
+        // The rows the kernel of the split layout computes instead
+        let (size, rot_scale) = (data.size(), data.rot_scale() as usize);
+        for (i, combined) in interleaved.combined_data_in.iter().enumerate() {
+            let row = i % size;
+            assert_eq!(combined.r_next, (row + rot_scale) % size);
+            assert_eq!(combined.r_prev, (row + size - rot_scale) % size);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn split_lookup_layout_matches_the_interleaved_one() {
+        let fixture = Fixture::new(K, 2, 2);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cuda;
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        ev.config.lookup_layout = LookupLayout::Split;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        // With the lookups compressed on the device too
+        ev.config.lookup_device_columns = 1;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+    }
+
+    /// Accumulates the lookups of `buffers` into `values`, lists of
+    /// `array_size` rows, on the CPU as `compute_evaluate_h_lookups_codeblock`
+    /// does, with `l0`, `l_active_row` and `l_last` given in `constants`
+    fn emulate_lookups_kernel(
+        data: &CircuitData<'_, EqAffine>,
+        buffers: &LookupBuffers<Fp>,
+        constants: [&[Fp]; 3],
+        lookup_offsets: &[u64],
+        array_size: usize,
+        values: &mut [Fp],
+    ) {
+        let [l0, l_active_row, l_last] = constants;
+        let (y, beta, gamma) = (data.y, data.beta, data.gamma);
+        for (i, value) in values.iter_mut().enumerate() {
+            let (circuit, row) = (i / array_size, i % array_size);
+            for n in lookup_offsets[circuit]..lookup_offsets[circuit + 1] {
+                let offset = n as usize * array_size;
+                let combined = &buffers.combined_data_in[offset + row];
+                let product = &buffers.product_coset[offset..offset + array_size];
+                let input = &buffers.permuted_input_coset[offset..offset + array_size];
+                let table = &buffers.permuted_table_coset[offset..offset + array_size];
+                *value = *value * y + (Fp::one() - product[row]) * l0[row];
+                *value = *value * y + (product[row] * product[row] - product[row]) * l_last[row];
+                *value = *value * y
+                    + (product[combined.r_next] * (input[row] + beta) * (table[row] + gamma)
+                        - product[row] * combined.table_value)
+                        * l_active_row[row];
+                *value = *value * y + combined.a_minus_s * l0[row];
+                *value = *value * y
+                    + combined.a_minus_s
+                        * (input[row] - input[combined.r_prev])
+                        * l_active_row[row];
+            }
+        }
+    }
+
+    #[test]
+    fn lookup_windows_stitch_back_into_the_rows() {
+        let fixture = Fixture::new(K, 1, 2);
+        let ev = evaluator_with(&fixture.pk, false);
+        let advice = extended_cosets(&fixture.pk.vk.domain, &[&fixture.advice]);
+        let instance = extended_cosets(&fixture.pk.vk.domain, &[&fixture.instance]);
+        let data = CircuitData {
+            pk: &fixture.pk,
+            advice: &advice[0],
+            instance: &instance[0],
+            challenges: &[],
+            y: fixture.y,
+            beta: fixture.beta,
+            gamma: fixture.gamma,
+            theta: fixture.theta,
+        };
+        let lookups = &fixture.lookups[0];
+        let mut buffers = LookupBuffers::new();
+        prepare_lookups(&ev, &data, lookups, 0, &mut buffers);
+        let pk = &fixture.pk;
+        let (size, halo) = (data.size(), data.rot_scale() as usize);
+        let lookup_offsets = [0, lookups.len() as u64];
+        // Accumulated over values of their own, for their rows to be checked
+        let initial: Vec<Fp> = (0..size as u64).map(Fp::from).collect();
+
+        // The emulated kernel is the CPU backend, on the whole domain
+        let mut expected = initial.clone();
+        let constants = [
+            &pk.l0.values[..],
+            &pk.l_active_row.values[..],
+            &pk.l_last.values[..],
+        ];
+        emulate_lookups_kernel(
+            &data,
+            &buffers,
+            constants,
+            &lookup_offsets,
+            size,
+            &mut expected,
+        );
+        let mut cpu = pk.vk.domain.empty_extended();
+        cpu.values.copy_from_slice(&initial);
+        CpuBackend
+            .evaluate_lookups(&ev, &data, lookups, &mut cpu)
+            .unwrap();
+        assert_eq!(cpu.values, expected);
+
+        // Uneven slices included, and slices shorter than the rotations reach
+        for parts in [1, 2, 3, 7, size / halo + 1] {
+            let slices = row_slices(size, parts);
+            assert_eq!(slices.first().unwrap().start, 0);
+            assert_eq!(slices.last().unwrap().end, size);
+            assert!(slices.windows(2).all(|pair| pair[0].end == pair[1].start));
+
+            let mut values = vec![Fp::zero(); size];
+            for rows in slices {
+                let len = rows.len() + 2 * halo;
+                let window = buffers.window(size, rows.clone(), halo);
+                let l0 = window_rows(&pk.l0.values, size, rows.clone(), halo);
+                let l_active_row = window_rows(&pk.l_active_row.values, size, rows.clone(), halo);
+                let l_last = window_rows(&pk.l_last.values, size, rows.clone(), halo);
+                let mut window_values = window_rows(&initial, size, rows.clone(), halo);
+                assert_eq!(window_values.len(), len);
+                emulate_lookups_kernel(
+                    &data,
+                    &window,
+                    [&l0, &l_active_row, &l_last],
+                    &lookup_offsets,
+                    len,
+                    &mut window_values,
+                );
+                values[rows.clone()].copy_from_slice(&window_values[halo..halo + rows.len()]);
+            }
+            assert_eq!(values, expected, "{} slices", parts);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn lookups_split_across_devices_match_a_single_device() {
+        let fixture = Fixture::new(K, 2, 2);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cuda;
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        // The slices of a device listed several times run one after the other
+        for devices in [vec![0], vec![0, 0], vec![0, 0, 0]] {
+            ev.config.devices = devices;
+            assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        }
+        ev.config.lookup_layout = LookupLayout::Split;
+        ev.config.lookup_device_columns = 1;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+    }
+
+    #[test]
+    fn memory_modes() {
+        assert_eq!(MemoryMode::parse("explicit"), Some(MemoryMode::Explicit));
+        assert_eq!(MemoryMode::parse(" Unified\n"), Some(MemoryMode::Unified));
+        assert_eq!(MemoryMode::parse("managed"), Some(MemoryMode::Unified));
+        assert_eq!(MemoryMode::parse("paged"), None);
+        assert_eq!(EvaluatorConfig::default().memory_mode, MemoryMode::Explicit);
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device with unified memory and the evaluation kernel"]
+    fn unified_memory_matches_explicit_copies() {
+        if !CudaBackend::is_available() || !cuda_unified_memory_available().unwrap_or(false) {
+            return;
+        }
+        let fixture = Fixture::new(K, 2, 2);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cuda;
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        ev.config.memory_mode = MemoryMode::Unified;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        // The windows of a device written while it runs the previous ones
+        ev.config.devices = vec![0, 0];
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        ev.config.lookup_layout = LookupLayout::Split;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+    }
+
+    #[test]
+    fn lookups_prepared_one_by_one_match_the_batch() {
+        let fixture = Fixture::new(K, 1, 2);
+        let ev = evaluator_with(&fixture.pk, false);
+        let advice = extended_cosets(&fixture.pk.vk.domain, &[&fixture.advice]);
+        let instance = extended_cosets(&fixture.pk.vk.domain, &[&fixture.instance]);
+        let data = CircuitData {
+            pk: &fixture.pk,
+            advice: &advice[0],
+            instance: &instance[0],
+            challenges: &[],
+            y: fixture.y,
+            beta: fixture.beta,
+            gamma: fixture.gamma,
+            theta: fixture.theta,
+        };
+        let lookups = &fixture.lookups[0];
+        let mut batch = LookupBuffers::new();
+        prepare_lookups(&ev, &data, lookups, 0, &mut batch);
+        let mut one_by_one = LookupBuffers::new();
+        for (n, lookup) in lookups.iter().enumerate() {
+            prepare_lookup(&ev, &data, n, lookup, 0, &mut one_by_one);
+        }
+        assert_eq!(one_by_one.product_coset, batch.product_coset);
+        assert_eq!(one_by_one.column_offsets, batch.column_offsets);
+        assert_eq!(
+            format!("{:?}", one_by_one.combined_data_in),
+            format!("{:?}", batch.combined_data_in)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn per_lookup_launches_match_the_batch() {
+        let fixture = Fixture::new(K, 1, 2);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cuda;
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        ev.config.lookup_strategy = LookupStrategy::PerLookup;
+        reset_cuda_counters();
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        assert_eq!(cuda_counters().kernel_launches, 2);
+    }
+
+    #[test]
+    fn bn256_multiplication_matches_the_field() {
+        use halo2curves::bn256::Fr;
+
+        let mut values = vec![Fr::zero(), Fr::one(), -Fr::one(), Fr::from(2)];
+        values.extend((0..32).map(|_| Fr::random(OsRng)));
+        for &a in values.iter() {
+            for &b in values.iter() {
+                assert_eq!(bn256::mul(a, b), a * b);
+            }
+        }
+    }
+
+    #[test]
+    fn bn256_calculations_match_the_generic_ones() {
+        use halo2curves::bn256::Fr;
+
+        let constants: Vec<Fr> = (0..3).map(|_| Fr::random(OsRng)).collect();
+        let c = ValueSource::Constant;
+        let calculations = vec![
+            Calculation::Mul(c(0), c(1)),
+            Calculation::Square(c(2)),
+            Calculation::MulAdd(c(0), c(1), c(2)),
+            Calculation::Horner(c(0), vec![c(1), c(2)], c(1)),
+            Calculation::LinearCombination(vec![(c(0), c(1)), (c(2), c(2))]),
+            Calculation::Sub(c(0), c(1)),
+        ];
+        let no_values: &[Polynomial<Fr, LagrangeCoeff>] = &[];
+        let zero = Fr::zero();
+        for calculation in calculations.iter() {
+            let expected = calculation.evaluate(
+                &[],
+                &constants,
+                &[],
+                no_values,
+                no_values,
+                no_values,
+                &[],
+                &zero,
+                &zero,
+                &zero,
+                &[],
+                &zero,
+                &zero,
+            );
+            let value = unsafe {
+                bn256::evaluate(
+                    calculation,
+                    &[],
+                    &constants,
+                    &[],
+                    no_values,
+                    no_values,
+                    no_values,
+                    &[],
+                    &zero,
+                    &zero,
+                    &zero,
+                    &[],
+                    &zero,
+                    &zero,
+                )
+            };
+            assert_eq!(value, expected, "{}", calculation);
+        }
+    }
+
+    /// The sum of `values[i] * r^i`, one row after the other
+    fn reduce_values(values: &[Fp], r: Fp) -> Fp {
+        values
+            .iter()
+            .rev()
+            .fold(Fp::zero(), |value, row| value * r + row)
+    }
+
+    #[test]
+    fn chunked_reduction_matches_the_sequential_one() {
+        let values: Vec<_> = (0..1000).map(|_| Fp::random(OsRng)).collect();
+        let r = Fp::random(OsRng);
+        for chunk_size in [1, 7, 64, 1000] {
+            // The partial sums the kernel computes, the last chunk being shorter
+            let partials: Vec<_> = values
+                .chunks(chunk_size)
+                .map(|chunk| reduce_values(chunk, r))
+                .collect();
+            assert_eq!(
+                fold_chunks(&partials, r, chunk_size),
+                reduce_values(&values, r)
+            );
+        }
+        assert_eq!(fold_chunks(&[], r, 64), Fp::zero());
+    }
+
+    #[test]
+    fn reduction_chunks_are_fixed() {
+        let values: Vec<_> = (0..2 * REDUCE_CHUNK_ROWS + 5)
+            .map(|_| Fp::random(OsRng))
+            .collect();
+        let r = Fp::random(OsRng);
+        let partials = reduce_chunks(&values, r);
+        assert_eq!(partials.len(), 3);
+        assert_eq!(
+            partials[1],
+            reduce_values(&values[REDUCE_CHUNK_ROWS..2 * REDUCE_CHUNK_ROWS], r)
+        );
+        assert_eq!(
+            partials[2],
+            reduce_values(&values[2 * REDUCE_CHUNK_ROWS..], r)
+        );
+        assert_eq!(
+            fold_chunks(&partials, r, REDUCE_CHUNK_ROWS),
+            reduce_values(&values, r)
+        );
+        assert!(reduce_chunks(&[], r).is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn gpu_reduction_matches_the_cpu() {
+        let values: Vec<_> = (0..(1 << 12) + 3).map(|_| Fp::random(OsRng)).collect();
+        let r = Fp::random(OsRng);
+        assert_eq!(
+            reduce_values_on_gpu(&values, r),
+            Ok(reduce_values(&values, r))
+        );
+        assert_eq!(
+            reduce_values_on_gpu(&values, Fp::one()),
+            Ok(values.iter().fold(Fp::zero(), |sum, value| sum + value))
+        );
+        assert_eq!(reduce_values_on_gpu(&[], r), Ok(Fp::zero()));
+
+        // The partial sums of the device, whatever its number of threads
+        for threads in [None, Some(1), Some(3)] {
+            let backend = CudaBackend {
+                threads,
+                ..CudaBackend::default()
+            };
+            assert_eq!(
+                backend.reduce_chunks(&values, r),
+                Ok(reduce_chunks(&values, r))
+            );
+        }
+    }
+
+    #[test]
+    fn kernel_repr_is_the_memory_layout() {
+        fn check<F: FieldExt>(r: Vec<u64>) {
+            // R mod p is the representation of one
+            assert_eq!(to_kernel_repr(&F::one()), r);
+            for _ in 0..16 {
+                let value = F::random(OsRng);
+                assert_eq!(from_kernel_repr::<F>(&to_kernel_repr(&value)), Some(value));
+            }
+            assert_eq!(from_kernel_repr::<F>(&FieldLayout::of::<F>().modulus), None);
+            assert_eq!(FieldLayout::checked::<F>(), Ok(FieldLayout::of::<F>()));
+
+            // Copied in bulk for BN256 and converted otherwise, both giving
+            // back the same elements on the host for these fields
+            let values: Vec<F> = (0..16).map(|_| F::random(OsRng)).collect();
+            let mut buffer = vec![F::one()];
+            extend_kernel_repr(&mut buffer, &values);
+            assert_eq!(buffer[0], F::one());
+            assert_eq!(buffer[1..], values[..]);
+        }
+
+        check::<Fp>(vec![
+            0x34786d38fffffffd,
+            0x992c350be41914ad,
+            0xffffffffffffffff,
+            0x3fffffffffffffff,
+        ]);
+        check::<halo2curves::bn256::Fr>(vec![
+            0xac96341c4ffffffb,
+            0x36fc76959f60cd29,
+            0x666ea36f7879462e,
+            0x0e0a77c19a07df2f,
+        ]);
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn field_elements_round_trip_through_the_device() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let column = random_coset(domain);
+        // The kernel stores the column it reads, as it is
+        let graph = GraphEvaluator::<EqAffine>::from_expression(&fixed_query(0, 0));
+        assert_eq!(
+            graph.run_on_gpu(&[column.clone()], &[], &[], &[], domain),
+            Ok(column.values)
+        );
+    }
+
+    #[test]
+    fn field_layout_matches_the_kernel_fields() {
+        let field = FieldLayout::of::<Fp>();
+        assert_eq!(field.bytes, 32);
+        assert_eq!(
+            field.modulus,
+            vec![
+                0x992d30ed00000001,
+                0x224698fc094cf91b,
+                0,
+                0x4000000000000000
+            ]
+        );
+        assert_eq!(
+            FieldLayout::of::<halo2curves::bn256::Fr>().modulus,
+            vec![
+                0x43e1f593f0000001,
+                0x2833e84879b97091,
+                0xb85045b68181585d,
+                0x30644e72e131a029
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn gpu_expression_matches_cpu_evaluation() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..pk.vk.cs.num_advice_columns)
+            .map(|_| random_coset(domain))
+            .collect();
+        let size = domain.extended_len();
+        let rot_scale = 1 << (domain.extended_k() - domain.k());
+
+        let expressions = pk
+            .vk
+            .cs
+            .gates
+            .iter()
+            .flat_map(|gate| gate.polynomials().iter().cloned())
+            .chain(std::iter::once(
+                fixed_query(0, -1) * Expression::Constant(Fp::from(3)),
+            ));
+        for expression in expressions {
+            let graph = GraphEvaluator::<EqAffine>::from_expression(&expression);
+            assert_eq!(
+                graph.run_on_gpu(&pk.fixed_cosets, &advice, &[], &[], domain),
+                Ok(evaluate(
+                    &expression,
+                    size,
+                    rot_scale,
+                    &pk.fixed_cosets,
+                    &advice,
+                    &[],
+                    &[]
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn device_graph_matches_expression_evaluation() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..pk.vk.cs.num_advice_columns)
+            .map(|_| random_coset(domain))
+            .collect();
+        let size = domain.extended_len();
+        let rot_scale = 1 << (domain.extended_k() - domain.k());
+
+        let mut expressions: Vec<_> = pk
+            .vk
+            .cs
+            .gates
+            .iter()
+            .flat_map(|gate| gate.polynomials().iter().cloned())
+            .collect();
+        for lookup in pk.vk.cs.lookups.iter() {
+            expressions.extend(lookup.input_expressions.iter().cloned());
+            expressions.extend(lookup.table_expressions.iter().cloned());
+        }
+        expressions.push(Expression::Constant(Fp::from(3)));
+        expressions.push(fixed_query(0, -1));
+        for expression in expressions.iter() {
+            let graph = GraphEvaluator::<EqAffine>::from_expression(expression);
+            let device_graph =
+                DeviceGraph::lower(&graph, size, &pk.fixed_cosets, &advice, &[], &[]).unwrap();
+            assert_eq!(
+                device_graph.evaluate(size, rot_scale),
+                evaluate(
+                    expression,
+                    size,
+                    rot_scale,
+                    &pk.fixed_cosets,
+                    &advice,
+                    &[],
+                    &[]
+                )
+            );
+        }
+
+        let mut graph = GraphEvaluator::<EqAffine>::default();
+        graph.add_calculation(Calculation::Add(ValueSource::Y(), ValueSource::Constant(1)));
+        assert!(matches!(
+            DeviceGraph::lower(&graph, size, &pk.fixed_cosets, &advice, &[], &[]),
+            Err(EvalError::InvalidGraph(_))
+        ));
+    }
+
+    #[cfg(feature = "eval-reports")]
+    #[test]
+    fn timing_reports_are_appended() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let path =
+            std::env::temp_dir().join(format!("halo2-timing-report-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.backend = Backend::Cpu;
+        ev.config.timing_report = Some(path.clone());
+        for _ in 0..2 {
+            ev.evaluate_h(
+                &pk,
+                &[&advice],
+                &[&[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![]],
+                &[permutation::prover::Committed { sets: vec![] }],
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+        }
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = report
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines.iter() {
+            assert_eq!(line["backend"], "cpu");
+            assert_eq!(line["fallback"], false);
+            assert_eq!(line["device"], serde_json::Value::Null);
+            assert_eq!(line["k"], K);
+            assert_eq!(line["num_lookups"], ev.lookups.len());
+            assert!(line["total"].as_f64().unwrap() >= line["custom_gates"].as_f64().unwrap());
+        }
+    }
+
+    #[cfg(feature = "eval-dumps")]
+    #[test]
+    fn dumped_polynomials_are_loaded_back() {
+        let fixture = Fixture::new(K, 2, 1);
+        let ev = evaluator_with(&fixture.pk, false);
+        let values = fixture.evaluate_h(&ev).unwrap();
+        let path = std::env::temp_dir().join(format!("halo2-h-dump-{}.bin", std::process::id()));
+
+        super::dump_extended_poly(&values, &path).unwrap();
+        let loaded: Vec<Fp> = super::load_extended_poly(&path).unwrap();
+        assert_eq!(&loaded[..], &values[..]);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[4] += 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let err = super::load_extended_poly::<Fp, _>(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn cuda_warmup_needs_a_device() {
+        let (_, pk) = keygen_test_circuit();
+        if !CudaBackend::is_available() {
+            assert_eq!(pk.ev.warmup_cuda(), Err(EvalError::NoCudaDevice));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn cuda_memory_is_released_after_each_proof() {
+        let (params, mut pk) = keygen_test_circuit();
+        pk.ev.config = EvaluatorConfig {
+            backend: Backend::Cuda,
+            fallback_to_cpu: false,
+            release_cuda_memory: true,
+            ..Default::default()
+        };
+
+        let baseline = cuda_device_memory().unwrap().used();
+        for _ in 0..8 {
+            let circuit = TestCircuit {
+                a: Value::known(Fp::one()),
+            };
+            let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+            create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<_>, _, _, _, _>(
+                &params,
+                &pk,
+                &[circuit],
+                &[&[]],
+                OsRng,
+                &mut transcript,
+            )
+            .expect("proof generation should not fail");
+            assert!(cuda_device_memory().unwrap().used() <= baseline);
+        }
+    }
+
+    #[test]
+    fn cuda_counters_are_per_thread() {
+        reset_cuda_counters();
+        assert_eq!(cuda_counters(), CudaCounters::default());
+        let (_, pk) = keygen_test_circuit();
+        if !CudaBackend::is_available() {
+            let mut ev = evaluator_with(&pk, true);
+            ev.config.backend = Backend::Cuda;
+            ev.evaluate_h(
+                &pk,
+                &[&random_advice(&pk)],
+                &[&[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![]],
+                &[permutation::prover::Committed { sets: vec![] }],
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+            assert_eq!(cuda_counters(), CudaCounters::default());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn cuda_batches_load_their_module_and_launch_their_lookups_once() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..4).map(|_| random_advice(&pk)).collect();
+        let advice: Vec<&[_]> = advice.iter().map(|advice| &advice[..]).collect();
+        let lookups: Vec<_> = (0..4)
+            .map(|_| {
+                (0..pk.vk.cs.lookups.len())
+                    .map(|_| {
+                        lookup::prover::Committed::from_polys(
+                            random_poly(domain),
+                            random_poly(domain),
+                            random_poly(domain),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let permutations: Vec<_> = (0..4)
+            .map(|_| permutation::prover::Committed { sets: vec![] })
+            .collect();
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.backend = Backend::Cuda;
+        ev.config.gpu_phases = GpuPhases {
+            gates: false,
+            permutations: false,
+            lookups: true,
+        };
+        let evaluate = || {
+            ev.evaluate_h(
+                &pk,
+                &advice,
+                &[&[], &[], &[], &[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &lookups,
+                &permutations,
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+
+        // The first batch loads the module, checking its field with a launch
+        clear_cuda_module_cache();
+        reset_cuda_counters();
+        evaluate();
+        assert_eq!(
+            cuda_counters(),
+            CudaCounters {
+                module_loads: 1,
+                constant_uploads: 1,
+                kernel_launches: 2,
+            }
+        );
+
+        // The next one reuses the module and its resident constants
+        reset_cuda_counters();
+        let values = evaluate();
+        assert_eq!(
+            cuda_counters(),
+            CudaCounters {
+                module_loads: 0,
+                constant_uploads: 0,
+                kernel_launches: 1,
+            }
+        );
+
+        // A reset tears them down, and they are loaded and uploaded again
+        ev.reset_cuda();
+        reset_cuda_counters();
+        assert_eq!(evaluate().values, values.values);
+        assert_eq!(
+            cuda_counters(),
+            CudaCounters {
+                module_loads: 1,
+                constant_uploads: 1,
+                kernel_launches: 2,
+            }
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device and the evaluation kernel"]
+    fn cuda_uploaded_constants_are_reused_by_the_next_witnesses() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.backend = Backend::Cuda;
+        let evaluate = || {
+            let advice = random_advice(&pk);
+            let polys: Vec<_> = (0..pk.vk.cs.lookups.len())
+                .map(|_| {
+                    (
+                        random_poly(domain),
+                        random_poly(domain),
+                        random_poly(domain),
+                    )
+                })
+                .collect();
+            let lookups = || -> Vec<_> {
+                polys
+                    .iter()
+                    .map(|(input, table, product)| {
+                        lookup::prover::Committed::from_polys(
+                            input.clone(),
+                            table.clone(),
+                            product.clone(),
+                        )
+                    })
+                    .collect()
+            };
+            let on_cuda = ev
+                .evaluate_h(
+                    &pk,
+                    &[&advice],
+                    &[&[]],
+                    &[],
+                    Fp::from(2),
+                    Fp::from(3),
+                    Fp::from(5),
+                    Fp::from(7),
+                    &[lookups()],
+                    &[permutation::prover::Committed { sets: vec![] }],
+                    &mut EvalScratch::default(),
+                    &mut EvalControl::default(),
+                )
+                .unwrap();
+            let on_cpu = evaluator_with(&pk, false)
+                .evaluate_h(
+                    &pk,
+                    &[&advice],
+                    &[&[]],
+                    &[],
+                    Fp::from(2),
+                    Fp::from(3),
+                    Fp::from(5),
+                    Fp::from(7),
+                    &[lookups()],
+                    &[permutation::prover::Committed { sets: vec![] }],
+                    &mut EvalScratch::default(),
+                    &mut EvalControl::default(),
+                )
+                .unwrap();
+            assert_eq!(on_cuda.values, on_cpu.values);
+        };
+
+        // Uploaded ahead of the first witness, which only uploads its cosets
+        ev.reset_cuda();
+        reset_cuda_counters();
+        ev.upload_cuda_constants(&pk).unwrap();
+        assert_eq!(cuda_counters().constant_uploads, 1);
+        reset_cuda_counters();
+        evaluate();
+        evaluate();
+        assert_eq!(cuda_counters().constant_uploads, 0);
+        assert_eq!(cuda_counters().module_loads, 0);
+
+        // The fixed cosets stay resident too, for the custom gates kernels
+        let mut cuda = ev.cuda_backend();
+        reset_cuda_counters();
+        cuda.upload_constants(&pk, true).unwrap();
+        cuda.upload_constants(&pk, true).unwrap();
+        assert_eq!(cuda_counters().constant_uploads, 1);
+        let fixed_bytes: usize = pk
+            .fixed_cosets
+            .iter()
+            .map(|coset| std::mem::size_of_val(&coset.values[..]))
+            .sum();
+        assert_eq!(
+            cuda.transfers.constant_bytes,
+            fixed_bytes.max(std::mem::size_of::<Fp>())
+        );
+    }
+
+    #[test]
+    fn pipelined_cosets_match_serial_evaluation() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..3).map(|_| random_advice(&pk)).collect();
+        let advice: Vec<&[_]> = advice.iter().map(|advice| &advice[..]).collect();
+        let lookups: Vec<_> = (0..3)
+            .map(|_| {
+                (0..pk.vk.cs.lookups.len())
+                    .map(|_| {
+                        lookup::prover::Committed::from_polys(
+                            random_poly(domain),
+                            random_poly(domain),
+                            random_poly(domain),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let chunk_len = pk.vk.cs.degree() - 2;
+        let permutations: Vec<_> = (0..3)
+            .map(|_| {
+                let sets = pk
+                    .vk
+                    .cs
+                    .permutation
+                    .columns
+                    .chunks(chunk_len)
+                    .map(|_| {
+                        permutation::prover::CommittedSet::from_poly(domain, random_poly(domain))
+                    })
+                    .collect();
+                permutation::prover::Committed { sets }
+            })
+            .collect();
+        let (y, beta, gamma, theta) = (
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+        );
+        let evaluate = |pipeline_cosets: bool| {
+            let mut ev = evaluator_with(&pk, false);
+            ev.config.pipeline_cosets = pipeline_cosets;
+            let mut values = domain.empty_extended();
+            ev.evaluate_h_with_backend(
+                &mut CpuBackend,
+                &pk,
+                &advice,
+                &[&[], &[], &[]],
+                &[],
+                y,
+                beta,
+                gamma,
+                theta,
+                &lookups,
+                &permutations,
+                &mut values,
+                &mut EvalScratch::default(),
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+            values
+        };
+
+        assert_eq!(evaluate(true).values, evaluate(false).values);
+    }
+
+    #[test]
+    fn parallel_circuits_match_sequential_evaluation() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let advice: Vec<_> = (0..3).map(|_| random_advice(&pk)).collect();
+        let advice: Vec<&[_]> = advice.iter().map(|advice| &advice[..]).collect();
+        let lookups: Vec<_> = (0..3)
+            .map(|_| {
+                (0..pk.vk.cs.lookups.len())
+                    .map(|_| {
+                        lookup::prover::Committed::from_polys(
+                            random_poly(domain),
+                            random_poly(domain),
+                            random_poly(domain),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let chunk_len = pk.vk.cs.degree() - 2;
+        let permutations: Vec<_> = (0..3)
+            .map(|_| {
+                let sets = pk
+                    .vk
+                    .cs
+                    .permutation
+                    .columns
+                    .chunks(chunk_len)
+                    .map(|_| {
+                        permutation::prover::CommittedSet::from_poly(domain, random_poly(domain))
+                    })
+                    .collect();
+                permutation::prover::Committed { sets }
+            })
+            .collect();
+        let (y, beta, gamma, theta) = (
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+        );
+
+        let mut ev = evaluator_with(&pk, false);
+        let mut expected = domain.empty_extended();
+        ev.evaluate_h_with_backend(
+            &mut CpuBackend,
+            &pk,
+            &advice,
+            &[&[], &[], &[]],
+            &[],
+            y,
+            beta,
+            gamma,
+            theta,
+            &lookups,
+            &permutations,
+            &mut expected,
+            &mut EvalScratch::default(),
+            &mut EvalTimings::default(),
+            &mut EvalControl::default(),
+        )
+        .unwrap();
+
+        assert!(!ev.parallel_circuits(&pk, 3));
+        ev.config.parallel_circuits_max_k = domain.k();
+        assert!(ev.parallel_circuits(&pk, 3));
+        assert!(!ev.parallel_circuits(&pk, 1));
+        let values = ev
+            .evaluate_h_circuits_in_parallel(
+                &pk,
+                &advice,
+                &[&[], &[], &[]],
+                &[],
+                y,
+                beta,
+                gamma,
+                theta,
+                &lookups,
+                &permutations,
+                &mut EvalScratch::default(),
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn cancellation_stops_the_circuits_evaluated_in_parallel() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let mut ev = evaluator_with(&pk, false);
+        ev.config.parallel_circuits_max_k = pk.vk.domain.k();
+
+        // Cancelled once the cosets are computed, before any circuit starts
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        let mut phases = vec![];
+        let mut progress = |progress: Progress| {
+            phases.push(progress.phase);
+            token.cancel();
+        };
+        let result = ev.evaluate_h_circuits_in_parallel(
+            &pk,
+            &[&advice, &advice],
+            &[&[], &[]],
+            &[],
+            Fp::from(2),
+            Fp::from(3),
+            Fp::from(5),
+            Fp::from(7),
+            &[vec![], vec![]],
+            &[
+                permutation::prover::Committed { sets: vec![] },
+                permutation::prover::Committed { sets: vec![] },
+            ],
+            &mut EvalScratch::default(),
+            &mut EvalTimings::default(),
+            &mut EvalControl::new(&cancel, Some(&mut progress)),
+        );
+        assert_eq!(result.err(), Some(EvalError::Cancelled));
+        assert_eq!(phases, vec![ProgressPhase::Cosets]);
+    }
+
+    #[test]
+    fn evaluator_round_trips_through_serialization() {
+        let (params, pk) = keygen_test_circuit();
+        let mut bytes = vec![];
+        pk.ev.write(&mut bytes).expect("writing should not fail");
+        let ev = Evaluator::<EqAffine>::read(&mut &bytes[..], pk.vk.cs())
+            .expect("reading should not fail");
+        assert_eq!(
+            format!("{:?}", ev.custom_gates),
+            format!("{:?}", pk.ev.custom_gates)
+        );
+        assert_eq!(format!("{:?}", ev.lookups), format!("{:?}", pk.ev.lookups));
+
+        // A proving key built with the evaluator that was read evaluates the same
+        let empty_circuit = TestCircuit {
+            a: Value::unknown(),
+        };
+        let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+        let read_pk = keygen_pk_with_evaluator(&params, vk, ev, &empty_circuit)
+            .expect("keygen_pk_with_evaluator should not fail");
+        let advice = random_advice(&pk);
+        let values = evaluate_with(&read_pk.ev, &mut CpuBackend, &read_pk, &advice).unwrap();
+        let expected = evaluate_with(&pk.ev, &mut CpuBackend, &pk, &advice).unwrap();
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn serialized_evaluator_is_checked_against_the_constraint_system() {
+        let (params, pk) = keygen_test_circuit();
+        let mut bytes = vec![];
+        pk.ev.write(&mut bytes).expect("writing should not fail");
+
+        // The evaluator of another circuit
+        let vk = keygen_vk(&params, &ChallengeCircuit).expect("keygen_vk should not fail");
+        let err = Evaluator::<EqAffine>::read(&mut &bytes[..], vk.cs()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(pk.ev.validate(vk.cs()).is_err());
+
+        // Another format version
+        let mut other_version = bytes.clone();
+        other_version[4] = 2;
+        let err = Evaluator::<EqAffine>::read(&mut &other_version[..], pk.vk.cs()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Another fingerprint
+        let mut other_fingerprint = bytes.clone();
+        other_fingerprint[8] ^= 1;
+        let err = Evaluator::<EqAffine>::read(&mut &other_fingerprint[..], pk.vk.cs()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // A truncated file
+        let err =
+            Evaluator::<EqAffine>::read(&mut &bytes[..bytes.len() - 1], pk.vk.cs()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn evaluators_keep_the_fingerprint_of_their_constraint_system() {
+        let (params, pk) = keygen_test_circuit();
+        let fingerprint = pk.ev.fingerprint();
+        assert_ne!(fingerprint, 0);
+        assert_eq!(
+            Evaluator::<EqAffine>::new(pk.vk.cs()).fingerprint(),
+            fingerprint
+        );
+        assert_eq!(cs_fingerprint(pk.vk.cs()), fingerprint);
+
+        let vk = keygen_vk(&params, &ChallengeCircuit).expect("keygen_vk should not fail");
+        assert_ne!(
+            Evaluator::<EqAffine>::new(vk.cs()).fingerprint(),
+            fingerprint
+        );
+
+        let mut bytes = vec![];
+        pk.ev.write(&mut bytes).expect("writing should not fail");
+        let ev = Evaluator::<EqAffine>::read(&mut &bytes[..], pk.vk.cs())
+            .expect("reading should not fail");
+        assert_eq!(ev.fingerprint(), fingerprint);
+    }
+
+    #[derive(Clone)]
+    struct SquareConfig {
+        a: Column<Advice>,
+        b: Column<Advice>,
+        q: Selector,
+    }
+
+    /// Squares `a` into `b` on every row: a single gate, without instance
+    /// columns nor permutation
+    #[derive(Clone)]
+    struct SquareCircuit {
+        a: Value<Fp>,
+    }
+
+    impl Circuit<Fp> for SquareCircuit {
+        type Config = SquareConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                a: Value::unknown(),
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> SquareConfig {
+            let a = meta.advice_column();
+            let b = meta.advice_column();
+            let q = meta.selector();
+
+            meta.create_gate("square", |meta| {
+                let q = meta.query_selector(q);
+                let a = meta.query_advice(a, Rotation::cur());
+                let b = meta.query_advice(b, Rotation::cur());
+                vec![q * (a.clone() * a - b)]
+            });
+
+            SquareConfig { a, b, q }
+        }
+
+        fn synthesize(
+            &self,
+            config: SquareConfig,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            layouter.assign_region(
+                || "square",
+                |mut region| {
+                    for row in 0..ROWS {
+                        config.q.enable(&mut region, row)?;
+                        let a = self.a.map(|a| a + Fp::from(row as u64));
+                        region.assign_advice(|| "a", config.a, row, || a)?;
+                        region.assign_advice(|| "b", config.b, row, || a.map(|a| a.square()))?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn gates_only_circuit_proof_verifies() {
+        let params = ParamsIPA::<EqAffine>::new(K);
+        let empty_circuit = SquareCircuit {
+            a: Value::unknown(),
+        };
+        let vk = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+        let pk = keygen_pk(&params, vk, &empty_circuit).expect("keygen_pk should not fail");
+        assert_eq!(pk.vk.cs.num_instance_columns, 0);
+        assert!(pk.vk.cs.permutation.columns.is_empty());
+        assert!(pk.vk.cs.lookups.is_empty());
+
+        // h(X) only verifies if the gate is evaluated
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<_>, _, _, _, _>(
+            &params,
+            &pk,
+            &[SquareCircuit {
+                a: Value::known(Fp::from(3)),
+            }],
+            &[&[]],
+            OsRng,
+            &mut transcript,
+        )
+        .expect("proof generation should not fail");
+        let proof = transcript.finalize();
+
+        let verifier_params = params.verifier_params();
+        let strategy = SingleStrategy::new(verifier_params);
+        let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(&proof[..]);
+        assert!(
+            verify_proof::<IPACommitmentScheme<EqAffine>, VerifierIPA<_>, _, _, _>(
+                verifier_params,
+                pk.get_vk(),
+                strategy,
+                &[&[]],
+                &mut transcript,
+            )
+            .is_ok()
+        );
+
+        // Without instance columns, the instance polynomials may be left out
+        let ev = evaluator_with(&pk, false);
+        let advice = random_advice(&pk);
+        let evaluate = |instance_polys: &[&[Polynomial<Fp, Coeff>]]| {
+            ev.evaluate_h(
+                &pk,
+                &[&advice],
+                instance_polys,
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![]],
+                &[permutation::prover::Committed { sets: vec![] }],
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+        let values = evaluate(&[&[]]);
+        assert!(values.values.iter().any(|value| *value != Fp::zero()));
+        assert_eq!(evaluate(&[]).values, values.values);
+    }
+
+    #[test]
+    fn missing_circuit_data_is_rejected() {
+        let (_, pk) = keygen_test_circuit();
+        let ev = evaluator_with(&pk, false);
+        let advice = random_advice(&pk);
+        let result = ev.evaluate_h(
+            &pk,
+            &[&advice, &advice],
+            &[&[], &[]],
+            &[],
+            Fp::from(2),
+            Fp::from(3),
+            Fp::from(5),
+            Fp::from(7),
+            &[vec![], vec![]],
+            &[permutation::prover::Committed { sets: vec![] }],
+            &mut EvalScratch::default(),
+            &mut EvalControl::default(),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            EvalError::InputLengthMismatch {
+                advice: 2,
+                instance: 2,
+                lookups: 2,
+                permutations: 1,
+            }
+        );
+
+        // Also with lookups, which the evaluation of the first circuit reads
+        let fixture = Fixture::new(K, 1, 1);
+        let ev = evaluator_with(&fixture.pk, false);
+        let lookups = vec![vec![], vec![]];
+        let result = ev.evaluate_h(
+            &fixture.pk,
+            &[&fixture.advice],
+            &[&fixture.instance],
+            &[],
+            fixture.y,
+            fixture.beta,
+            fixture.gamma,
+            fixture.theta,
+            &lookups,
+            &fixture.permutations,
+            &mut EvalScratch::default(),
+            &mut EvalControl::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(EvalError::InputLengthMismatch {
+                advice: 1,
+                lookups: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn fixture_satisfies_its_constraints() {
+        let mut fixture = Fixture::new(K, 3, 2);
+        assert_eq!(fixture.pk.vk.cs.gates.len(), 4);
+        assert_eq!(fixture.lookups[0].len(), 2);
+        assert_eq!(fixture.permutations[0].sets.len(), 2);
+        let ev = evaluator_with(&fixture.pk, false);
+        let values = fixture.evaluate_h(&ev).unwrap();
+        assert_eq!(find_violating_row(&values, &fixture.pk.vk.domain), None);
+
+        // The first gate column off by one on every row
+        fixture.advice[1][0] += Fp::one();
+        let values = fixture.evaluate_h(&ev).unwrap();
+        assert_eq!(find_violating_row(&values, &fixture.pk.vk.domain), Some(0));
+    }
+
+    #[test]
+    fn custom_kernels_are_registered_by_gate_set() {
+        let (_, pk) = keygen_test_circuit();
+        let fixture = Fixture::new(K, 2, 1);
+        let fingerprint = pk.ev.gates_fingerprint();
+        assert_eq!(
+            Evaluator::<EqAffine>::new(&pk.vk.cs).gates_fingerprint(),
+            fingerprint
+        );
+        assert_ne!(fixture.pk.ev.gates_fingerprint(), fingerprint);
+
+        let mut ev = pk.ev.clone();
+        assert_eq!(ev.custom_kernel(), None);
+        ev.register_custom_kernel(fixture.pk.ev.gates_fingerprint(), "fixture_gates");
+        assert_eq!(ev.custom_kernel(), None);
+        ev.register_custom_kernel(fingerprint, "test_gates");
+        ev.register_custom_kernel(fingerprint, "fused_test_gates");
+        assert_eq!(ev.custom_kernel(), Some("fused_test_gates"));
+        assert_eq!(ev.custom_kernels.len(), 2);
+        assert_eq!(ev.cuda_backend().custom_kernel, Some("fused_test_gates"));
+    }
+
+    #[test]
+    fn h_cache_returns_the_polynomial_of_the_same_inputs() {
+        let mut fixture = Fixture::new(K, 1, 1);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        let expected = fixture.evaluate_h(&ev).unwrap();
+        ev.config.h_cache_entries = 2;
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        assert_eq!(ev.h_cache.len(), 1);
+
+        // A hit is returned without evaluating
+        let key = |fixture: &Fixture| {
+            ev.h_cache_key(
+                &fixture.pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+            )
+        };
+        let mut cached = expected.clone();
+        cached[0] += Fp::one();
+        ev.h_cache.insert(key(&fixture), &cached, 2);
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, cached.values);
+
+        // Any other input misses, and the least recently used is dropped
+        fixture.y += Fp::one();
+        let other_key = key(&fixture);
+        assert!(ev.h_cache.get(other_key).is_none());
+        fixture.evaluate_h(&ev).unwrap();
+        fixture.advice[0][0] += Fp::one();
+        assert_ne!(key(&fixture), other_key);
+        fixture.evaluate_h(&ev).unwrap();
+        assert_eq!(ev.h_cache.len(), 2);
+        assert!(ev.h_cache.get(other_key).is_some());
+        fixture.y -= Fp::one();
+        fixture.advice[0][0] -= Fp::one();
+        assert!(ev.h_cache.get(key(&fixture)).is_none());
+
+        // Clones start empty
+        assert_eq!(ev.clone().h_cache.len(), 0);
+    }
+
+    #[test]
+    fn spilled_evaluation_matches_the_one_in_memory() {
+        let fixture = Fixture::new(K, 2, 2);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("halo2-spill-test-{}.bin", std::process::id()));
+        ev.config.spill_path = Some(path.clone());
+        // The budget fits the values, nothing is spilled
+        ev.config.spill_ram_budget = usize::MAX;
+        assert!(ev.spill_path(&fixture.pk).is_none());
+
+        // Chunks of 7 rows, the last one shorter, whose rotations read the
+        // rows of the chunks around them
+        ev.config.spill_ram_budget = 7 * std::mem::size_of::<Fp>();
+        assert_eq!(ev.spill_path(&fixture.pk), Some(path.as_path()));
+        assert_eq!(fixture.evaluate_h(&ev).unwrap().values, expected.values);
+        assert!(!path.exists());
+
+        // A file that cannot be created is reported
+        ev.config.spill_path = Some(path.join("missing").join("h.bin"));
+        assert!(matches!(fixture.evaluate_h(&ev), Err(EvalError::Spill(_))));
+    }
+
+    #[test]
+    fn spilled_evaluation_without_instance_columns_matches_the_one_in_memory() {
+        let (_, pk) = keygen_test_circuit();
+        assert_eq!(pk.vk.cs.num_instance_columns, 0);
+        let domain = &pk.vk.domain;
+        let advice = random_advice(&pk);
+        let lookups = vec![(0..pk.vk.cs.lookups.len())
+            .map(|_| {
+                lookup::prover::Committed::from_polys(
+                    random_poly(domain),
+                    random_poly(domain),
+                    random_poly(domain),
+                )
+            })
+            .collect::<Vec<_>>()];
+        let chunk_len = pk.vk.cs.degree() - 2;
+        let sets = pk
+            .vk
+            .cs
+            .permutation
+            .columns
+            .chunks(chunk_len)
+            .map(|_| permutation::prover::CommittedSet::from_poly(domain, random_poly(domain)))
+            .collect();
+        let permutations = [permutation::prover::Committed { sets }];
+        let (y, beta, gamma, theta) = (
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+            Fp::random(OsRng),
+        );
+        // Without instance columns, the circuits may pass no instances at all
+        let evaluate_h = |ev: &Evaluator<EqAffine>| {
+            ev.evaluate_h(
+                &pk,
+                &[&advice],
+                &[],
+                &[],
+                y,
+                beta,
+                gamma,
+                theta,
+                &lookups,
+                &permutations,
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap()
+        };
+
+        let mut ev = evaluator_with(&pk, false);
+        let expected = evaluate_h(&ev);
+        assert!(expected.iter().any(|value| !bool::from(value.is_zero())));
+
+        let path = std::env::temp_dir().join(format!(
+            "halo2-spill-no-instance-test-{}.bin",
+            std::process::id()
+        ));
+        ev.config.spill_path = Some(path.clone());
+        ev.config.spill_ram_budget = 7 * std::mem::size_of::<Fp>();
+        assert_eq!(ev.spill_path(&pk), Some(path.as_path()));
+        assert_eq!(evaluate_h(&ev).values, expected.values);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn gate_contributions_show_the_failing_gate() {
+        let mut fixture = Fixture::new(K, 2, 0);
+        let ev = evaluator_with(&fixture.pk, false);
+        let rows = 0..8;
+        let contributions = |fixture: &Fixture| {
+            ev.gate_contributions(
+                &fixture.pk,
+                &fixture.advice,
+                &fixture.instance,
+                &[],
+                rows.clone(),
+            )
+        };
+
+        let names: Vec<_> = contributions(&fixture)
+            .into_iter()
+            .map(|(name, values)| {
+                assert!(values.iter().all(|value| bool::from(value.is_zero())));
+                name
+            })
+            .collect();
+        assert_eq!(names, vec!["product", "product", "count"]);
+
+        // The second gate column off by one on every row
+        fixture.advice[2][0] += Fp::one();
+        let nonzero: Vec<_> = contributions(&fixture)
+            .into_iter()
+            .map(|(_, values)| values.iter().all(|value| !bool::from(value.is_zero())))
+            .collect();
+        assert_eq!(nonzero, vec![false, true, false]);
+    }
+
+    #[test]
+    fn lookups_compressed_on_the_device_get_the_same_table_values() {
+        let fixture = Fixture::new(K, 1, 2);
+        let pk = &fixture.pk;
+        let ev = evaluator_with(pk, false);
+        let mut scratch = EvalScratch::default();
+        scratch.compute_cosets(&pk.vk.domain, &[&fixture.advice], &[&fixture.instance]);
+        let data = CircuitData {
+            pk,
+            advice: &scratch.advice[0],
+            instance: &scratch.instance[0],
+            challenges: &[],
+            y: fixture.y,
+            beta: fixture.beta,
+            gamma: fixture.gamma,
+            theta: fixture.theta,
+        };
+        let size = data.size();
+
+        let mut on_cpu = LookupBuffers::new();
+        prepare_lookups(&ev, &data, &fixture.lookups[0], 0, &mut on_cpu);
+        assert_eq!(on_cpu.max_device_columns(), 0);
+        assert!(on_cpu.input_columns.is_empty());
+        let mut on_device = LookupBuffers::new();
+        prepare_lookups(&ev, &data, &fixture.lookups[0], 1, &mut on_device);
+        assert_eq!(on_device.column_offsets, vec![0, 1, 2]);
+        assert_eq!(on_device.input_columns.len(), 2 * size);
+
+        // The compression of `compute_lookup_table_values`, from the columns
+        let theta_powers = [Fp::one(), fixture.theta];
+        for (n, offsets) in on_device.column_offsets.windows(2).enumerate() {
+            let m = (offsets[1] - offsets[0]) as usize;
+            for row in 0..size {
+                let compress = |columns: &[Fp], start: Fp| {
+                    (0..m).fold(start, |value, j| {
+                        let column = (offsets[0] as usize + j) * size + row;
+                        value + theta_powers[m - 1 - j] * columns[column]
+                    })
+                };
+                let input = compress(&on_device.input_columns, fixture.beta);
+                let table = compress(&on_device.table_columns, fixture.gamma);
+                let (cpu, device) = (
+                    &on_cpu.combined_data_in[n * size + row],
+                    &on_device.combined_data_in[n * size + row],
+                );
+                assert_eq!(input * table, cpu.table_value);
+                assert_eq!(device.table_value, Fp::zero());
+                assert_eq!(device.a_minus_s, cpu.a_minus_s);
+            }
+        }
+    }
+
+    #[test]
+    fn single_circuits_evaluate_as_batches_of_one() {
+        let fixture = Fixture::new(K, 2, 2);
+        let ev = evaluator_with(&fixture.pk, false);
+        let mut values = fixture.pk.vk.domain.empty_extended();
+        ev.evaluate_h_single(
+            &mut values,
+            &fixture.pk,
+            &fixture.advice,
+            &fixture.instance,
+            &[],
+            fixture.y,
+            fixture.beta,
+            fixture.gamma,
+            fixture.theta,
+            &fixture.lookups[0],
+            &fixture.permutations[0],
+            &mut EvalScratch::default(),
+            &mut EvalControl::default(),
+        )
+        .unwrap();
+        assert_eq!(values.values, fixture.evaluate_h(&ev).unwrap().values);
+    }
+
+    #[test]
+    fn h_is_evaluated_into_the_buffer_of_the_caller() {
+        let fixture = Fixture::new(K, 2, 1);
+        let ev = evaluator_with(&fixture.pk, false);
+        let expected = fixture.evaluate_h(&ev).unwrap();
+        let evaluate_into = |out: &mut Polynomial<Fp, ExtendedLagrangeCoeff>| {
+            ev.evaluate_h_into(
+                out,
+                &fixture.pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+        };
+
+        // A recycled buffer, with the values of another evaluation
+        let mut out = random_coset(&fixture.pk.vk.domain);
+        let buffer = out.values.as_ptr();
+        let context = evaluate_into(&mut out).unwrap();
+        assert_eq!(out.values, expected.values);
+        assert_eq!(out.values.as_ptr(), buffer);
+        assert!(context.matches(&fixture.pk.vk.domain));
+
+        let mut short = random_coset(&fixture.pk.vk.domain);
+        short.values.pop();
+        assert_eq!(
+            evaluate_into(&mut short),
+            Err(EvalError::OutputLengthMismatch {
+                expected: expected.len(),
+                actual: expected.len() - 1,
+            })
+        );
+    }
+
+    #[test]
+    fn gates_vanish_on_the_rows_of_a_satisfying_witness() {
+        let fixture = Fixture::new(K, 2, 1);
+        let domain = &fixture.pk.vk.domain;
+        let to_lagrange = |polys: &[Polynomial<Fp, Coeff>]| -> Vec<_> {
+            polys
+                .iter()
+                .map(|poly| {
+                    let mut values = poly.values.clone();
+                    best_fft(&mut values, domain.get_omega(), domain.k());
+                    domain.lagrange_from_vec(values)
+                })
+                .collect()
+        };
+        let mut advice = to_lagrange(&fixture.advice);
+        let instance = to_lagrange(&fixture.instance);
+        let evaluate = |advice: &[_]| {
+            fixture
+                .pk
+                .ev
+                .evaluate_gates_on_rows(&fixture.pk, advice, &instance, &[], fixture.y)
+        };
+        assert!(evaluate(&advice)
+            .iter()
+            .all(|value| bool::from(value.is_zero())));
+
+        // The first product gate no longer holds at row 3
+        advice[1][3] += Fp::one();
+        let values = evaluate(&advice);
+        let violated: Vec<_> = (0..values.len())
+            .filter(|&row| !bool::from(values[row].is_zero()))
+            .collect();
+        assert_eq!(violated, vec![3]);
+    }
+
+    #[test]
+    fn evaluations_run_on_the_pool_of_the_config() {
+        let fixture = Fixture::new(K, 2, 1);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        let pool = Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(2)
+                .build()
+                .unwrap(),
+        );
+        ev.config.thread_pool = Some(pool.clone().into());
+        let mut on_pool = vec![];
+        let mut progress = |_: Progress| on_pool.push(pool.current_thread_index().is_some());
+        let values = ev
+            .evaluate_h(
+                &fixture.pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &mut EvalScratch::default(),
+                &mut EvalControl::new(&CancellationToken::default(), Some(&mut progress)),
+            )
+            .unwrap();
+        assert_eq!(values.values, expected.values);
+        assert!(!on_pool.is_empty());
+        assert!(on_pool.iter().all(|&on_pool| on_pool));
+    }
+
+    #[test]
+    fn vanishing_h_passes_the_assertion() {
+        let fixture = Fixture::new(K, 2, 1);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.assert_vanishing = true;
+        fixture.evaluate_h(&ev).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "row 3 of the domain: the CustomGates of circuit 0")]
+    fn bad_witness_fails_the_assertion_at_its_row() {
+        let mut fixture = Fixture::new(K, 2, 1);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.assert_vanishing = true;
+
+        // The second product column changed at row 3 only
+        let domain = &fixture.pk.vk.domain;
+        let mut row = domain.empty_lagrange();
+        row[3] = Fp::one();
+        let row = domain.lagrange_to_coeff(row);
+        for (coeff, delta) in fixture.advice[2].iter_mut().zip(row.iter()) {
+            *coeff += delta;
+        }
+        let _ = fixture.evaluate_h(&ev);
+    }
+
+    #[test]
+    fn zero_columns_have_zero_cosets() {
+        let (_, pk) = keygen_test_circuit();
+        let domain = &pk.vk.domain;
+        let polys = vec![domain.empty_coeff(), random_poly(domain)];
+        assert!(is_zero_poly(&polys[0]));
+        assert!(!is_zero_poly(&polys[1]));
+
+        // Cosets reused from other columns
+        let mut cosets = vec![random_coset(domain), random_coset(domain)];
+        extended_cosets_into(domain, &polys, &mut cosets);
+        assert_eq!(cosets[0].values, domain.empty_extended().values);
+        assert_eq!(
+            cosets[1].values,
+            domain.coeff_to_extended(polys[1].clone()).values
+        );
+    }
+
+    #[test]
+    fn batches_accumulate_the_constraints_of_their_circuits() {
+        let (_, pk) = keygen_test_circuit();
+        let ev = evaluator_with(&pk, false);
+        let advice: Vec<_> = (0..2).map(|_| random_advice(&pk)).collect();
+        let alone: Vec<_> = advice
+            .iter()
+            .map(|advice| evaluate_with(&ev, &mut CpuBackend, &pk, advice).unwrap())
+            .collect();
+        let batch = ev
+            .evaluate_h_with_fallback(
+                &mut CpuBackend,
+                &pk,
+                &[&advice[0], &advice[1]],
+                &[&[], &[]],
+                &[],
+                Fp::from(2),
+                Fp::from(3),
+                Fp::from(5),
+                Fp::from(7),
+                &[vec![], vec![]],
+                &[
+                    permutation::prover::Committed { sets: vec![] },
+                    permutation::prover::Committed { sets: vec![] },
+                ],
+                &mut EvalScratch::default(),
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+
+        // The second circuit carries on from the values of the first
+        let num_constraints = num_constraints(
+            &pk.vk.cs,
+            &[],
+            &permutation::prover::Committed { sets: vec![] },
+        );
+        assert!(num_constraints > 0);
+        let y_power = Fp::from(2).pow_vartime(&[num_constraints, 0, 0, 0]);
+        let expected: Vec<_> = alone[0]
+            .iter()
+            .zip(alone[1].iter())
+            .map(|(first, second)| *first * y_power + second)
+            .collect();
+        assert_eq!(batch.values, expected);
+        assert_ne!(batch.values, alone[1].values);
+    }
+
+    #[test]
+    fn the_extended_context_is_the_one_of_the_values() {
+        let fixture = Fixture::new(K, 1, 1);
+        let pk = &fixture.pk;
+        let ev = evaluator_with(pk, false);
+        let (values, context) = ev
+            .evaluate_h_with_context(
+                pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &mut EvalScratch::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+        assert_eq!(values.values, fixture.evaluate_h(&ev).unwrap().values);
+
+        let domain = &pk.vk.domain;
+        assert_eq!(values.len(), 1 << context.extended_k);
+        assert_eq!(context.rot_scale as usize, values.len() >> domain.k());
+        assert_eq!(
+            context
+                .extended_omega
+                .pow_vartime(&[values.len() as u64 / 2, 0, 0, 0]),
+            -Fp::one()
+        );
+        assert!(context.matches(domain));
+        let other = EvaluationDomain::<Fp>::new(pk.vk.cs.degree() as u32, K + 1);
+        assert!(!context.matches(&other));
+        assert_eq!(ExtendedContext::new(&other).rot_scale, context.rot_scale);
+    }
+
+    #[test]
+    fn deferred_challenges_evaluate_the_same_h() {
+        let fixture = Fixture::new(K, 2, 1);
+        let pk = &fixture.pk;
+        let ev = evaluator_with(pk, false);
+        let expected = fixture.evaluate_h(&ev).unwrap();
+
+        let mut scratch = EvalScratch::default();
+        let evaluate_deferred = |scratch: &mut EvalScratch<Fp>, fail: bool| {
+            ev.evaluate_h_deferred(
+                pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                || match fail {
+                    true => Err(EvalError::Cancelled),
+                    false => Ok(DeferredChallenges {
+                        challenges: vec![],
+                        y: fixture.y,
+                        beta: fixture.beta,
+                        gamma: fixture.gamma,
+                        theta: fixture.theta,
+                        lookups: &fixture.lookups,
+                        permutations: &fixture.permutations,
+                    }),
+                },
+                scratch,
+                &mut EvalControl::default(),
+            )
+        };
+        let values = evaluate_deferred(&mut scratch, false).unwrap();
+        assert_eq!(values.values, expected.values);
+        assert!(!scratch.cosets_ready);
+        assert_eq!(scratch.advice[0].len(), fixture.advice.len());
+
+        assert!(matches!(
+            evaluate_deferred(&mut scratch, true),
+            Err(EvalError::Cancelled)
+        ));
+        assert!(!scratch.cosets_ready);
+    }
+
+    #[test]
+    fn phases_compose_into_evaluate_h() {
+        let fixture = Fixture::new(K, 2, 2);
+        let pk = &fixture.pk;
+        let ev = evaluator_with(pk, false);
+        let expected = ev
+            .evaluate_h_with_fallback(
+                &mut CpuBackend,
+                pk,
+                &[&fixture.advice],
+                &[&fixture.instance],
+                &[],
+                fixture.y,
+                fixture.beta,
+                fixture.gamma,
+                fixture.theta,
+                &fixture.lookups,
+                &fixture.permutations,
+                &mut EvalScratch::default(),
+                &mut EvalTimings::default(),
+                &mut EvalControl::default(),
+            )
+            .unwrap();
+
+        let mut scratch = EvalScratch::default();
+        scratch.compute_cosets(&pk.vk.domain, &[&fixture.advice], &[&fixture.instance]);
+        let data = CircuitData {
+            pk,
+            advice: &scratch.advice[0],
+            instance: &scratch.instance[0],
+            challenges: &[],
+            y: fixture.y,
+            beta: fixture.beta,
+            gamma: fixture.gamma,
+            theta: fixture.theta,
+        };
+        let mut values = pk.vk.domain.empty_extended();
+        let mut timings = EvalTimings::default();
+        ev.evaluate_custom_gates(&mut CpuBackend, &data, &mut values, &mut timings)
+            .unwrap();
+        ev.evaluate_permutations(
+            &mut CpuBackend,
+            &data,
+            &fixture.permutations[0],
+            &mut values,
+            &mut timings,
+        )
+        .unwrap();
+        ev.evaluate_lookups(
+            &mut CpuBackend,
+            &data,
+            &fixture.lookups[0],
+            None,
+            &mut values,
+            &mut timings,
+        )
+        .unwrap();
+        assert_eq!(values.values, expected.values);
+    }
+
+    #[test]
+    fn missing_cuda_kernel_is_an_error() {
+        // Only without a kernel module, as in CI
+        if std::env::var_os("CU_KERNEL").is_some() || cfg!(feature = "cuda-embedded-kernel") {
+            return;
+        }
+        let fixture = Fixture::new(K, 1, 1);
+        let domain = &fixture.pk.vk.domain;
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cuda;
+
+        // Without a device or without a kernel, no polynomial comes out
+        assert!(fixture.evaluate_h(&ev).is_err());
+
+        // On a device, the lookups cannot load the kernel
+        let result = ev.evaluate_h_with_fallback(
+            &mut ev.cuda_backend(),
+            &fixture.pk,
+            &[&fixture.advice],
+            &[&fixture.instance],
+            &[],
+            fixture.y,
+            fixture.beta,
+            fixture.gamma,
+            fixture.theta,
+            &fixture.lookups,
+            &fixture.permutations,
+            &mut EvalScratch::default(),
+            &mut EvalTimings::default(),
+            &mut EvalControl::default(),
+        );
+        assert_eq!(
+            result.err(),
+            Some(EvalError::MissingKernelPath("CU_KERNEL"))
+        );
+
+        // Falling back, the CPU evaluates it
+        ev.config.fallback_to_cpu = true;
+        let values = fixture.evaluate_h(&ev).unwrap();
+        assert_eq!(find_violating_row(&values, domain), None);
+    }
+
+    #[test]
+    fn missing_cuda_kernel_fails_the_proof() {
+        // Only without a kernel module, as in CI
+        if std::env::var_os("CU_KERNEL").is_some() || cfg!(feature = "cuda-embedded-kernel") {
+            return;
+        }
+        let (params, mut pk) = keygen_test_circuit();
+        pk.ev.config.backend = Backend::Cuda;
+        pk.ev.config.fallback_to_cpu = false;
+        let circuit = TestCircuit {
+            a: Value::known(Fp::one()),
+        };
+
+        // The lookups fail to compress their expressions before h(X) is
+        // evaluated, instead of committing to zeros
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        let result = create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<_>, _, _, _, _>(
+            &params,
+            &pk,
+            &[circuit],
+            &[&[]],
+            OsRng,
+            &mut transcript,
+        );
+        assert!(matches!(result, Err(Error::Evaluation(_))));
+    }
+
+    #[test]
+    fn proofs_keeping_the_scratch_evaluate_h_into_the_same_buffer() {
+        let (params, mut pk) = keygen_test_circuit();
+        pk.ev.config.backend = Backend::Cpu;
+        pk.ev.config.keep_scratch = true;
+        let prove = || {
+            let circuit = TestCircuit {
+                a: Value::known(Fp::one()),
+            };
+            let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+            create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<_>, _, _, _, _>(
+                &params,
+                &pk,
+                &[circuit],
+                &[&[]],
+                OsRng,
+                &mut transcript,
+            )
+            .expect("proof generation should not fail");
+            EvalScratch::<Fp>::with_thread_scratch(|scratch| {
+                scratch.h_buffer.as_ref().map(|buffer| buffer.as_ptr())
+            })
+        };
+
+        // The buffer given back by the first proof is the one of the second
+        let buffer = prove();
+        assert!(buffer.is_some());
+        assert_eq!(prove(), buffer);
+    }
+
+    #[test]
+    fn transfer_bandwidths() {
+        let transfers = Transfers {
+            constant_bytes: 500_000_000,
+            coset_bytes: 1_500_000_000,
+            upload: Duration::from_secs(1),
+            values_bytes: 250_000_000,
+            download: Duration::from_millis(125),
+        };
+        assert_eq!(transfers.upload_gbps(), Some(2.0));
+        assert_eq!(transfers.download_gbps(), Some(2.0));
+
+        // Nothing was copied, as on the CPU
+        assert_eq!(Transfers::default().upload_gbps(), None);
+        assert_eq!(Transfers::default().download_gbps(), None);
+    }
+
+    /// Host memory standing in for the page-locked memory of a context
+    #[derive(Debug, Default)]
+    struct HeapAllocator {
+        allocations: usize,
+    }
+
+    impl HostAllocator for HeapAllocator {
+        fn allocate(&mut self, bytes: usize) -> Option<*mut u8> {
+            self.allocations += 1;
+            let layout = std::alloc::Layout::from_size_align(bytes, 64).unwrap();
+            Some(unsafe { std::alloc::alloc(layout) })
+        }
+
+        unsafe fn free(&mut self, ptr: *mut u8, bytes: usize) {
+            let layout = std::alloc::Layout::from_size_align(bytes, 64).unwrap();
+            std::alloc::dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    fn pinned_pool_overflows_to_pageable_memory() {
+        // 384 bytes each, the third one past the capacity
+        let data: Vec<Vec<Fp>> = (0..3)
+            .map(|_| (0..12).map(|_| Fp::random(OsRng)).collect())
+            .collect();
+        let mut pool = PinnedPool::new(HeapAllocator::default(), 1000);
+
+        let staged: Vec<_> = data.iter().map(|data| pool.stage(data)).collect();
+        assert_eq!(
+            staged
+                .iter()
+                .map(|staged| staged.is_pinned())
+                .collect::<Vec<_>>(),
+            vec![true, true, false]
+        );
+        for (staged, data) in staged.iter().zip(data.iter()) {
+            assert_eq!(staged.as_slice(), &data[..]);
+        }
+        assert_eq!(pool.allocated(), 768);
+        for staged in staged {
+            pool.release(staged);
+        }
+
+        // The released buffers are reused
+        let staged = pool.stage(&data[2]);
+        assert!(staged.is_pinned());
+        assert_eq!(staged.as_slice(), &data[2][..]);
+        pool.release(staged);
+        assert_eq!(pool.allocated(), 768);
+        assert_eq!(pool.allocator.allocations, 2);
+
+        // Buffers larger than the capacity are never pinned, and a smaller
+        // capacity frees the released buffers
+        let large: Vec<Fp> = data.concat();
+        let staged = pool.stage(&large);
+        assert!(!staged.is_pinned());
+        assert_eq!(staged.as_slice(), &large[..]);
+        pool.set_capacity(500);
+        assert_eq!(pool.allocated(), 0);
+    }
+
+    #[test]
+    fn synthetic_evaluation_has_the_requested_shape() {
+        let synthetic = SyntheticEvaluation::new(5, 7, 3);
+        assert_eq!(synthetic.pk.vk.domain.extended_k(), 7);
+        assert_eq!(synthetic.pk.vk.cs.lookups.len(), 3);
+        assert!(!synthetic.pk.vk.cs.permutation.columns.is_empty());
+        assert!(synthetic.evaluate_h(Backend::Cpu).is_ok());
+
+        let synthetic = SyntheticEvaluation::new(5, 6, 0);
+        assert_eq!(synthetic.pk.vk.domain.extended_k(), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "no extended domain")]
+    fn synthetic_lookups_need_a_larger_extended_domain() {
+        SyntheticEvaluation::new(5, 6, 1);
+    }
+
+    #[test]
+    fn operation_counts() {
+        let (_, pk) = keygen_test_circuit();
+        let ev = evaluator_with(&pk, false);
+        let rows = pk.vk.domain.extended_len() as u64;
+
+        let stats = ev.operation_counts(&pk, 1);
+        assert_eq!(stats.custom_gates, ev.custom_gates.op_counts() * rows);
+        assert!(stats.permutations.multiplications > 0);
+        assert!(stats.lookups.multiplications > 0);
+        assert_eq!(
+            stats.total(),
+            stats.custom_gates + stats.permutations + stats.lookups
+        );
+
+        let two_circuits = ev.operation_counts(&pk, 2);
+        assert_eq!(two_circuits.total(), stats.total() * 2);
+
+        assert_eq!(permutation_row_counts(&[]), OpCounts::default());
+        assert_eq!(permutation_row_counts(&[1]), OpCounts::new(12, 10));
+        assert_eq!(
+            permutation_row_counts(&[2, 1]),
+            permutation_row_counts(&[1]) + OpCounts::new(12, 12)
+        );
+    }
+
+    #[test]
+    fn delta_powers_are_the_identity_terms_of_the_first_row() {
+        let beta = Fp::from(3);
+        let powers = CpuBackend::delta_powers(beta, 5);
+        assert_eq!(powers.len(), 5);
+        for (j, power) in powers.iter().enumerate() {
+            assert_eq!(
+                *power,
+                beta * Fp::ZETA * Fp::DELTA.pow_vartime(&[j as u64, 0, 0, 0])
+            );
+        }
+
+        // The permutation of the fixture spans several sets of columns
+        let fixture = Fixture::new(K, 3, 1);
+        assert!(fixture.pk.vk.cs.permutation.columns.len() > fixture.pk.vk.cs.degree() - 2);
+        let mut ev = evaluator_with(&fixture.pk, false);
+        ev.config.backend = Backend::Cpu;
+        let values = fixture.evaluate_h(&ev).unwrap();
+        assert_eq!(find_violating_row(&values, &fixture.pk.vk.domain), None);
+    }
+
+    #[test]
+    fn backend_failure_falls_back_to_cpu() {
+        let (_, pk) = keygen_test_circuit();
+        let advice = random_advice(&pk);
+        let ev = evaluator_with(&pk, true);
+
+        let expected = evaluate_with(&ev, &mut CpuBackend, &pk, &advice).unwrap();
+
+        for phase in [Phase::CustomGates, Phase::Permutations, Phase::Lookups] {
+            let mut backend = MockBackend::failing_at(phase, EvalError::Driver("kernel launch"));
+            let values = evaluate_with(&ev, &mut backend, &pk, &advice).unwrap();
+            assert_eq!(backend.calls.last().map(|call| call.0), Some(phase));
+            assert_eq!(values.values, expected.values);
+        }
+    }
+}
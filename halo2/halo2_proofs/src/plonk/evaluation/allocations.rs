@@ -0,0 +1,68 @@
+use crate::multicore;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// The allocator of the tests, which counts the allocations of the threads
+/// measuring theirs with [`count_allocations`]
+struct CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// The smallest allocation counted by this thread and the number of those
+    /// counted so far, while it counts them
+    static COUNTED: Cell<Option<(usize, usize)>> = Cell::new(None);
+}
+
+fn count(size: usize) {
+    // The thread local is gone while the thread exits
+    let _ = COUNTED.try_with(|counted| {
+        if let Some((min_bytes, count)) = counted.get() {
+            if size >= min_bytes {
+                counted.set(Some((min_bytes, count + 1)));
+            }
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            count(new_size);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Runs `f` on a pool of a single thread, so that all of its allocations are
+/// made by that thread and none by the other tests, and returns its result
+/// with the number of allocations, or growths of an allocation, of at least
+/// `min_bytes` it made
+pub(in crate::plonk) fn count_allocations<R: Send>(
+    min_bytes: usize,
+    f: impl FnOnce() -> R + Send,
+) -> (R, usize) {
+    multicore::sequential(|| {
+        COUNTED.with(|counted| counted.set(Some((min_bytes, 0))));
+        let result = f();
+        let count = COUNTED
+            .with(|counted| counted.replace(None))
+            .map_or(0, |(_, count)| count);
+        (result, count)
+    })
+}
@@ -0,0 +1,172 @@
+use super::{EvalError, Evaluator};
+use crate::arithmetic::CurveAffine;
+use crate::plonk::{lookup, permutation, ProvingKey};
+use crate::poly::{ExtendedLagrangeCoeff, Polynomial};
+use std::env;
+
+/// The implementation used to evaluate the h(X) polynomial
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Every phase is evaluated on the CPU
+    Cpu,
+    /// The lookup phase is evaluated on a CUDA device
+    Cuda,
+    /// As `Cuda`, then `rows` rows of the extended domain, sampled from
+    /// `seed`, are evaluated again on the CPU and compared with those of the
+    /// device. This catches a kernel getting h(X) wrong on many rows at a
+    /// fraction of the cost of a full comparison, but is probabilistic: a bug
+    /// on rows that were not sampled goes unnoticed.
+    CudaSampledVerify {
+        /// The number of rows compared
+        rows: usize,
+        /// The seed the rows are sampled from, the same rows for the same seed
+        seed: u64,
+    },
+    /// The lookup phase is evaluated on an OpenCL device
+    #[cfg(feature = "opencl")]
+    OpenCl,
+    /// The lookup phase is evaluated through wgpu, on Vulkan, Metal or DX12
+    #[cfg(feature = "webgpu")]
+    Wgpu,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Cpu
+    }
+}
+
+impl Backend {
+    /// The name of the backend, as in the timing reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Cpu => "cpu",
+            Backend::Cuda => "cuda",
+            Backend::CudaSampledVerify { .. } => "cuda-sampled-verify",
+            #[cfg(feature = "opencl")]
+            Backend::OpenCl => "opencl",
+            #[cfg(feature = "webgpu")]
+            Backend::Wgpu => "wgpu",
+        }
+    }
+
+    /// Whether the backend evaluates h(X) on a CUDA device
+    pub fn is_cuda(&self) -> bool {
+        matches!(self, Backend::Cuda | Backend::CudaSampledVerify { .. })
+    }
+
+    /// Selects the backend from the `CUDA`, `OPENCL` and `WGPU` environment
+    /// variables, in that order of precedence. With `CUDA`, setting
+    /// `CUDA_VERIFY_ROWS` to a number of rows selects
+    /// `Backend::CudaSampledVerify`, with the seed in `CUDA_VERIFY_SEED` (0 by
+    /// default).
+    pub fn from_env() -> Self {
+        let enabled = |var: &str| matches!(env::var(var).as_deref(), Ok("1") | Ok("y") | Ok("yes"));
+        let number = |var: &str| {
+            env::var(var)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        if enabled("CUDA") {
+            return match number("CUDA_VERIFY_ROWS") {
+                Some(rows) => Backend::CudaSampledVerify {
+                    rows: rows as usize,
+                    seed: number("CUDA_VERIFY_SEED").unwrap_or(0),
+                },
+                None => Backend::Cuda,
+            };
+        }
+        #[cfg(feature = "opencl")]
+        if enabled("OPENCL") {
+            return Backend::OpenCl;
+        }
+        #[cfg(feature = "webgpu")]
+        if enabled("WGPU") {
+            return Backend::Wgpu;
+        }
+        Backend::Cpu
+    }
+}
+
+/// The inputs shared by all phases of the evaluation of a single circuit
+pub(in crate::plonk) struct CircuitData<'a, C: CurveAffine> {
+    pub(in crate::plonk) pk: &'a ProvingKey<C>,
+    /// Advice cosets of the circuit
+    pub(in crate::plonk) advice: &'a [Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>],
+    /// Instance cosets of the circuit
+    pub(in crate::plonk) instance: &'a [Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>],
+    pub(in crate::plonk) challenges: &'a [C::ScalarExt],
+    pub(in crate::plonk) y: C::ScalarExt,
+    pub(in crate::plonk) beta: C::ScalarExt,
+    pub(in crate::plonk) gamma: C::ScalarExt,
+    pub(in crate::plonk) theta: C::ScalarExt,
+}
+
+impl<'a, C: CurveAffine> CircuitData<'a, C> {
+    /// Size of the extended domain
+    pub(in crate::plonk) fn size(&self) -> usize {
+        self.pk.vk.domain.extended_len()
+    }
+
+    /// Distance between two consecutive rows in the extended domain
+    pub(in crate::plonk) fn rot_scale(&self) -> i32 {
+        1 << (self.pk.vk.domain.extended_k() - self.pk.vk.domain.k())
+    }
+
+    /// Fixed cosets of the circuit
+    pub(in crate::plonk) fn fixed(&self) -> &'a [Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>] {
+        &self.pk.fixed_cosets[..]
+    }
+}
+
+/// A backend evaluating the three phases of h(X) for a single circuit.
+///
+/// Every phase accumulates into `values` as `value = value * y + constraint`,
+/// in the order custom gates, permutations, lookups. The orchestration (coset
+/// computation and the loop over the circuits) is shared in
+/// `Evaluator::evaluate_h`.
+pub(in crate::plonk) trait HEvaluatorBackend<C: CurveAffine> {
+    /// Accumulates the custom gate constraints
+    fn evaluate_custom_gates(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError>;
+
+    /// Accumulates the permutation constraints
+    fn evaluate_permutations(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError>;
+
+    /// Accumulates the lookup constraints. It is also called for circuits
+    /// without lookups, with no `lookups`, for which a device backend returns
+    /// before touching the device.
+    fn evaluate_lookups(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError>;
+
+    /// Evaluates the lookup constraints of every circuit at once, before any
+    /// other phase.
+    ///
+    /// The lookup phase maps `value` to `value * y^(5 * lookups.len()) + s`,
+    /// so a backend may return the contribution `s` of each circuit, which is
+    /// then accumulated in place of calling `evaluate_lookups`. Returns `None`
+    /// when the lookups are evaluated circuit by circuit, which a device
+    /// backend also does when no circuit has lookups.
+    fn evaluate_lookups_batched(
+        &mut self,
+        _ev: &Evaluator<C>,
+        _circuits: &[CircuitData<'_, C>],
+        _lookups: &[Vec<lookup::prover::Committed<C>>],
+    ) -> Result<Option<Vec<Vec<C::ScalarExt>>>, EvalError> {
+        Ok(None)
+    }
+}
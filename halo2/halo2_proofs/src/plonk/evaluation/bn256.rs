@@ -0,0 +1,147 @@
+use super::{Calculation, ValueSource};
+use crate::halo2curves::bn256::Fr;
+use crate::poly::{Basis, Polynomial};
+use group::ff::Field;
+use std::mem;
+
+/// The modulus of the BN256 scalar field, in little-endian limbs
+const MODULUS: [u64; 4] = [
+    0x43e1f593f0000001,
+    0x2833e84879b97091,
+    0xb85045b68181585d,
+    0x30644e72e131a029,
+];
+
+/// -MODULUS^{-1} mod 2^64
+const INV: u64 = 0xc2e1f593efffffff;
+
+/// Computes a + b * c + carry, returning the result and the carry over
+#[inline(always)]
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let ret = (a as u128) + ((b as u128) * (c as u128)) + (carry as u128);
+    (ret as u64, (ret >> 64) as u64)
+}
+
+/// Multiplies two BN256 scalars, in their Montgomery limbs.
+///
+/// This is the CIOS Montgomery multiplication without the carries of the
+/// reduction, which cannot overflow as the top limb of the modulus is below
+/// `2^63 - 1`, followed by a single conditional subtraction. The generic field
+/// multiplication computes the full 512-bit product before reducing it.
+#[inline(always)]
+#[allow(clippy::needless_range_loop)]
+pub(in crate::plonk) fn mul(a: Fr, b: Fr) -> Fr {
+    // Safety: the scalars are their 4 Montgomery limbs
+    let a: [u64; 4] = unsafe { mem::transmute(a) };
+    let b: [u64; 4] = unsafe { mem::transmute(b) };
+
+    let mut t = [0u64; 4];
+    for &b in b.iter() {
+        let (t0, mut carry) = mac(t[0], a[0], b, 0);
+        let m = t0.wrapping_mul(INV);
+        let (_, mut reduction) = mac(t0, m, MODULUS[0], 0);
+        for j in 1..4 {
+            let (tj, c) = mac(t[j], a[j], b, carry);
+            carry = c;
+            let (tj, c) = mac(tj, m, MODULUS[j], reduction);
+            reduction = c;
+            t[j - 1] = tj;
+        }
+        t[3] = carry + reduction;
+    }
+
+    // The result is below 2 * MODULUS
+    let mut borrow = false;
+    let mut reduced = [0u64; 4];
+    for ((limb, &t), &p) in reduced.iter_mut().zip(t.iter()).zip(MODULUS.iter()) {
+        let (d, b0) = t.overflowing_sub(p);
+        let (d, b1) = d.overflowing_sub(borrow as u64);
+        *limb = d;
+        borrow = b0 || b1;
+    }
+    let result = match borrow {
+        true => t,
+        false => reduced,
+    };
+    // Safety: as above
+    unsafe { mem::transmute(result) }
+}
+
+/// `Calculation::evaluate` for BN256 scalars, with the multiplications done
+/// by `mul`.
+///
+/// # Safety
+///
+/// `F` must be `Fr`.
+pub(in crate::plonk) unsafe fn evaluate<F: Field, B: Basis>(
+    calculation: &Calculation,
+    rotations: &[usize],
+    constants: &[F],
+    intermediates: &[F],
+    fixed_values: &[Polynomial<F, B>],
+    advice_values: &[Polynomial<F, B>],
+    instance_values: &[Polynomial<F, B>],
+    challenges: &[F],
+    beta: &F,
+    gamma: &F,
+    theta: &F,
+    theta_powers: &[F],
+    y: &F,
+    previous_value: &F,
+) -> F {
+    let get_value = |value: &ValueSource| -> Fr {
+        mem::transmute_copy(&value.get(
+            rotations,
+            constants,
+            intermediates,
+            fixed_values,
+            advice_values,
+            instance_values,
+            challenges,
+            beta,
+            gamma,
+            theta,
+            theta_powers,
+            y,
+            previous_value,
+        ))
+    };
+    let value = match calculation {
+        Calculation::Mul(a, b) => mul(get_value(a), get_value(b)),
+        Calculation::Square(v) => {
+            let v = get_value(v);
+            mul(v, v)
+        }
+        Calculation::MulAdd(a, b, c) => mul(get_value(a), get_value(b)) + get_value(c),
+        Calculation::Horner(start_value, parts, factor) => {
+            let factor = get_value(factor);
+            let mut value = get_value(start_value);
+            for part in parts.iter() {
+                value = mul(value, factor) + get_value(part);
+            }
+            value
+        }
+        Calculation::LinearCombination(terms) => terms.iter().fold(Fr::zero(), |value, (a, b)| {
+            value + mul(get_value(a), get_value(b))
+        }),
+        // No multiplication to speed up
+        _ => {
+            return calculation.evaluate(
+                rotations,
+                constants,
+                intermediates,
+                fixed_values,
+                advice_values,
+                instance_values,
+                challenges,
+                beta,
+                gamma,
+                theta,
+                theta_powers,
+                y,
+                previous_value,
+            )
+        }
+    };
+    mem::transmute_copy(&value)
+}
@@ -0,0 +1,119 @@
+use crate::arithmetic::FieldExt;
+use crate::poly::{Coeff, ExtendedLagrangeCoeff, Polynomial};
+use blake2b_simd::Params as Blake2bParams;
+use group::ff::PrimeField;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The key of a polynomial h(X) in the cache, a 256-bit BLAKE2b digest of the
+/// inputs of its evaluation, so that two inputs sharing a key, which would
+/// return the h(X) of the other, take a collision of the hash to find
+pub(in crate::plonk) type HCacheKey = [u8; 32];
+
+/// The polynomials h(X) evaluated last, by the digest of the inputs of their
+/// evaluation, the most recently used last. It is shared by the threads of an
+/// evaluator, and a clone of the evaluator starts with an empty one.
+pub(in crate::plonk) struct HCache<F: FieldExt> {
+    entries: Mutex<VecDeque<(HCacheKey, Polynomial<F, ExtendedLagrangeCoeff>)>>,
+}
+
+impl<F: FieldExt> Default for HCache<F> {
+    fn default() -> Self {
+        HCache {
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl<F: FieldExt> Clone for HCache<F> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<F: FieldExt> fmt::Debug for HCache<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HCache")
+            .field("entries", &self.len())
+            .finish()
+    }
+}
+
+impl<F: FieldExt> HCache<F> {
+    /// The number of cached polynomials
+    pub(in crate::plonk) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// A copy of the polynomial cached for `key`, which becomes the most
+    /// recently used
+    pub(in crate::plonk) fn get(
+        &self,
+        key: HCacheKey,
+    ) -> Option<Polynomial<F, ExtendedLagrangeCoeff>> {
+        let mut entries = self.entries.lock().unwrap();
+        let idx = entries.iter().position(|(entry, _)| *entry == key)?;
+        let entry = entries.remove(idx).unwrap();
+        let values = entry.1.clone();
+        entries.push_back(entry);
+        Some(values)
+    }
+
+    /// Caches a copy of `values` for `key`, dropping the least recently used
+    /// polynomials beyond `max_entries`
+    pub(in crate::plonk) fn insert(
+        &self,
+        key: HCacheKey,
+        values: &Polynomial<F, ExtendedLagrangeCoeff>,
+        max_entries: usize,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(entry, _)| *entry != key);
+        entries.push_back((key, values.clone()));
+        while entries.len() > max_entries {
+            entries.pop_front();
+        }
+    }
+}
+
+/// Hashes the inputs of an evaluation of h(X) with BLAKE2b: the scalars, in
+/// order, then the digests of the polynomials, each one hashed on its own
+/// thread. The elements are hashed in their canonical form, a single pass over
+/// polynomials of the size of the domain, which is small next to the
+/// evaluation over the extended domain.
+pub(in crate::plonk) fn hash_inputs<F: FieldExt>(
+    scalars: &[F],
+    polys: &[&Polynomial<F, Coeff>],
+) -> HCacheKey {
+    let poly_digests: Vec<_> = polys
+        .into_par_iter()
+        .map(|poly| {
+            let mut hasher = Blake2bParams::new()
+                .hash_length(32)
+                .personal(b"Halo2-H-Cache-Po")
+                .to_state();
+            hasher.update(&(poly.len() as u64).to_le_bytes());
+            for value in poly.iter() {
+                hasher.update(value.to_repr().as_ref());
+            }
+            hasher.finalize()
+        })
+        .collect();
+
+    let mut hasher = Blake2bParams::new()
+        .hash_length(32)
+        .personal(b"Halo2-H-Cache-Ky")
+        .to_state();
+    hasher.update(&(scalars.len() as u64).to_le_bytes());
+    for scalar in scalars {
+        hasher.update(scalar.to_repr().as_ref());
+    }
+    hasher.update(&(poly_digests.len() as u64).to_le_bytes());
+    for digest in poly_digests.iter() {
+        hasher.update(digest.as_bytes());
+    }
+    hasher.finalize().as_bytes().try_into().unwrap()
+}
@@ -0,0 +1,37 @@
+use super::EvalError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag to abort an evaluation of h(X) from another thread, e.g. when the
+/// request it was started for timed out.
+///
+/// The evaluation checks it between circuits and between their phases, and on
+/// the GPU between the launches of its kernels, so a launch or a phase on the
+/// CPU still runs to its end. Its clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the evaluations checking this token or one of its clones
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns `EvalError::Cancelled` if the token was cancelled
+    pub(in crate::plonk) fn check(&self) -> Result<(), EvalError> {
+        match self.is_cancelled() {
+            true => Err(EvalError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use super::Backend;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a CUDA evaluation that failed with a transient error is retried
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled before each of the next ones
+    pub initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The policy that never retries
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(100),
+    };
+
+    /// The delay before the retry numbered `retry`, starting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff * 2u32.saturating_pow(retry)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// The phases of h(X) a device backend evaluates, the others being evaluated
+/// on the CPU, e.g. to keep on the CPU a phase it evaluates faster for the
+/// shape of a circuit. The CPU backend evaluates all of them either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuPhases {
+    /// The custom gates
+    pub gates: bool,
+    /// The permutation argument
+    pub permutations: bool,
+    /// The lookups
+    pub lookups: bool,
+}
+
+impl GpuPhases {
+    /// All the phases on the device
+    pub const ALL: GpuPhases = GpuPhases {
+        gates: true,
+        permutations: true,
+        lookups: true,
+    };
+
+    /// Parses a comma-separated list of phases as `EVAL_GPU_PHASES` names
+    /// them, among "gates", "permutations" and "lookups", ignoring the case.
+    /// An empty list keeps every phase on the CPU.
+    pub fn parse(phases: &str) -> Option<Self> {
+        let mut parsed = GpuPhases {
+            gates: false,
+            permutations: false,
+            lookups: false,
+        };
+        for phase in phases
+            .split(',')
+            .map(str::trim)
+            .filter(|phase| !phase.is_empty())
+        {
+            match phase.to_ascii_lowercase().as_str() {
+                "gates" => parsed.gates = true,
+                "permutations" => parsed.permutations = true,
+                "lookups" => parsed.lookups = true,
+                _ => return None,
+            }
+        }
+        Some(parsed)
+    }
+}
+
+impl Default for GpuPhases {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// How much the evaluation of h(X) reports, from nothing to the logs of the
+/// CUDA driver wrapper
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing is reported
+    Silent,
+    /// The errors and the fallbacks to the CPU are reported, as `tracing`
+    /// error and warning events
+    Errors,
+    /// As `Errors`, the `tracing` events up to the info level being expected
+    Info,
+    /// As `Info`, with the verbose mode of the CUDA driver wrapper and the
+    /// `tracing` events up to the debug level
+    Debug,
+}
+
+impl Verbosity {
+    /// Parses a level as `EVAL_VERBOSITY` names it, ignoring the case
+    pub fn parse(level: &str) -> Option<Self> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "silent" => Some(Verbosity::Silent),
+            "errors" | "error" => Some(Verbosity::Errors),
+            "info" => Some(Verbosity::Info),
+            "debug" => Some(Verbosity::Debug),
+            _ => None,
+        }
+    }
+
+    /// The most verbose `tracing` level a subscriber should record at this
+    /// verbosity, e.g. for `tracing_subscriber::fmt().with_max_level(..)`.
+    /// Apart from the errors and the fallbacks, which are not emitted when
+    /// silent, the evaluation emits its events whatever the verbosity,
+    /// filtering them is up to the subscriber.
+    pub fn level_filter(self) -> tracing::level_filters::LevelFilter {
+        use tracing::level_filters::LevelFilter;
+
+        match self {
+            Verbosity::Silent => LevelFilter::OFF,
+            Verbosity::Errors => LevelFilter::WARN,
+            Verbosity::Info => LevelFilter::INFO,
+            Verbosity::Debug => LevelFilter::DEBUG,
+        }
+    }
+
+    /// Emits an error of the evaluation as a `tracing` error, unless silent
+    pub(in crate::plonk) fn error(self, message: impl fmt::Display) {
+        if self >= Verbosity::Errors {
+            tracing::error!("{}", message);
+        }
+    }
+
+    /// Emits a fallback to the CPU as a `tracing` warning, unless silent
+    pub(in crate::plonk) fn warn(self, message: impl fmt::Display) {
+        if self >= Verbosity::Errors {
+            tracing::warn!("{}", message);
+        }
+    }
+}
+
+impl Default for Verbosity {
+    fn default() -> Self {
+        Verbosity::Errors
+    }
+}
+
+/// How the CUDA backend launches the lookups
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupStrategy {
+    /// The buffers of the lookups of all the circuits are prepared, then
+    /// uploaded to a single launch evaluating all of them
+    Batched,
+    /// The lookups of each circuit are evaluated one after the other, by a
+    /// launch each, the buffers of the next lookup being prepared on the CPU
+    /// threads while the device evaluates the current one. The device holds
+    /// the buffers of a single lookup at once, for circuits with many lookups
+    /// which do not fit the device memory together. The lookups compressed
+    /// on the device, see [`EvaluatorConfig::lookup_device_columns`], are
+    /// still compressed there, by their own launch. The driver wrapper runs
+    /// every launch on its default stream and returns once its values are
+    /// copied back, so the uploads of a lookup do not overlap the kernel of
+    /// the previous one.
+    PerLookup,
+}
+
+impl LookupStrategy {
+    /// Parses a strategy as `CUDA_LOOKUP_STRATEGY` names it, ignoring the case
+    pub fn parse(strategy: &str) -> Option<Self> {
+        match strategy.trim().to_ascii_lowercase().as_str() {
+            "batched" => Some(LookupStrategy::Batched),
+            "per-lookup" | "per_lookup" => Some(LookupStrategy::PerLookup),
+            _ => None,
+        }
+    }
+}
+
+impl Default for LookupStrategy {
+    fn default() -> Self {
+        LookupStrategy::Batched
+    }
+}
+
+/// How the CUDA backend lays out the per-row values of the lookups it uploads,
+/// the rows of each lookup being contiguous in both
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupLayout {
+    /// A `combined_t` per row, holding the compressed table value, a'(X) -
+    /// s'(X) and the indices of the next and previous rows, so that the
+    /// threads of a warp reading the table values of consecutive rows read
+    /// them 80 bytes apart
+    Interleaved,
+    /// The table values and a'(X) - s'(X) of the rows in arrays of their own,
+    /// which the threads of a warp read contiguously, the kernel computing
+    /// the next and previous rows from the rotation scale instead of reading
+    /// them. A fifth fewer bytes are uploaded for them.
+    Split,
+}
+
+impl LookupLayout {
+    /// Parses a layout as `CUDA_LOOKUP_LAYOUT` names it, ignoring the case
+    pub fn parse(layout: &str) -> Option<Self> {
+        match layout.trim().to_ascii_lowercase().as_str() {
+            "interleaved" => Some(LookupLayout::Interleaved),
+            "split" => Some(LookupLayout::Split),
+            _ => None,
+        }
+    }
+}
+
+impl Default for LookupLayout {
+    fn default() -> Self {
+        LookupLayout::Interleaved
+    }
+}
+
+/// How the buffers of the CUDA lookups reach the device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryMode {
+    /// The buffers are allocated on the device, and copied to it and back
+    Explicit,
+    /// The buffers are allocated as managed memory, which the host writes and
+    /// reads where it is and the device pages in on demand, without copies
+    /// to the device and back, on the devices where
+    /// [`cuda_unified_memory_available`] is true
+    ///
+    /// [`cuda_unified_memory_available`]: super::cuda_unified_memory_available
+    Unified,
+}
+
+impl MemoryMode {
+    /// Parses a mode as `CUDA_MEMORY_MODE` names it, ignoring the case
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "explicit" => Some(MemoryMode::Explicit),
+            "unified" | "managed" => Some(MemoryMode::Unified),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MemoryMode {
+    fn default() -> Self {
+        MemoryMode::Explicit
+    }
+}
+
+/// A thread pool of the caller the evaluation of h(X) runs on, e.g. the one
+/// of an application embedding the prover, instead of the global pool.
+/// Configurations are equal when they share the same pool.
+#[derive(Clone, Debug)]
+pub struct EvalThreadPool(pub Arc<rayon::ThreadPool>);
+
+impl From<Arc<rayon::ThreadPool>> for EvalThreadPool {
+    fn from(pool: Arc<rayon::ThreadPool>) -> Self {
+        Self(pool)
+    }
+}
+
+impl From<rayon::ThreadPool> for EvalThreadPool {
+    fn from(pool: rayon::ThreadPool) -> Self {
+        Self(Arc::new(pool))
+    }
+}
+
+impl PartialEq for EvalThreadPool {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for EvalThreadPool {}
+
+/// Settings controlling how the h(X) polynomial is evaluated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvaluatorConfig {
+    /// The backend used to evaluate h(X)
+    pub backend: Backend,
+    /// Re-evaluate h(X) on the CPU when the selected backend fails
+    pub fallback_to_cpu: bool,
+    /// The number of threads the CUDA lookup kernel is launched with, which
+    /// loops over the rows with a stride of that many threads. The driver
+    /// wrapper only takes a thread count and splits it into blocks itself.
+    /// `None` sizes the launch from the properties of the device.
+    pub cuda_threads: Option<usize>,
+    /// How each CUDA launch, of the lookups, of a custom gate kernel, of an
+    /// expression or of a reduction, is retried when it fails with a transient
+    /// error, like a launch timeout or an ECC error. No retries by default.
+    pub retry: RetryPolicy,
+    /// Keep the cosets computed by an evaluation of h(X) and the buffer of its
+    /// values in the prover thread, for the next proofs on it to reuse their
+    /// allocations. They are only released when the thread exits.
+    pub keep_scratch: bool,
+    /// Log the number of field operations of each evaluation of h(X), see
+    /// [`Evaluator::operation_counts`](super::Evaluator::operation_counts)
+    pub count_operations: bool,
+    /// Evaluate h(X) on a single thread when it is evaluated on the CPU,
+    /// including when falling back to it, for timings and operation orders that
+    /// do not depend on the scheduling of the threads. The device backends are
+    /// unaffected.
+    pub single_threaded: bool,
+    /// Evaluate h(X) on the CPU on a thread per core, each pinned to its
+    /// core, with the values and the cosets first written by the threads
+    /// evaluating their rows, so that on a machine with several NUMA nodes
+    /// each thread mostly works on the memory of its own node. Needs the
+    /// `numa` feature, and is overridden by `single_threaded`.
+    pub pin_threads: bool,
+    /// Append the [`EvalTimings`](super::EvalTimings) of each evaluation of
+    /// h(X) as a line of JSON to this file. Needs the `eval-reports` feature.
+    pub timing_report: Option<PathBuf>,
+    /// Free the device memory of the current thread after each CUDA
+    /// evaluation of h(X), by dropping its context, at the cost of creating
+    /// the context and loading the kernel module again for the next one.
+    /// The driver wrapper frees its buffers only with the context.
+    pub release_cuda_memory: bool,
+    /// Compute the cosets of each circuit of a batch while the previous one is
+    /// evaluated, instead of computing those of all the circuits first, so
+    /// that the FFTs run on the CPU threads while the backend waits on its
+    /// device. The lookups are then launched circuit by circuit rather than
+    /// in a single batch.
+    pub pipeline_cosets: bool,
+    /// Evaluate the circuits of a batch in parallel on the CPU, each into
+    /// values of its own, when the domain of the circuits has at most
+    /// 2^`parallel_circuits_max_k` rows, so that a batch of many small
+    /// circuits keeps all the threads busy rather than only those the rows of
+    /// a single circuit are split across. It takes the memory of the values
+    /// of h(X) once per circuit. 0, the default, evaluates the circuits one
+    /// after the other.
+    pub parallel_circuits_max_k: u32,
+    /// How much the evaluation reports. The verbose mode of the CUDA driver
+    /// wrapper is set when a thread loads the kernel module, so it only
+    /// changes for the modules loaded afterwards.
+    pub verbosity: Verbosity,
+    /// The number of chunks the rows of the custom gates are split into per
+    /// thread on the CPU, so that the threads that finish first take over the
+    /// chunks left when some run slower, e.g. on another NUMA node or sharing
+    /// a core. 1, the default, gives each thread a single chunk.
+    pub chunks_per_thread: usize,
+    /// The most page-locked host memory, in bytes, each thread keeps to
+    /// stage the cosets uploaded to the CUDA device, which the device copies
+    /// from without the driver staging them itself. The buffers are kept for
+    /// the next launches, and the cosets that do not fit are uploaded from
+    /// pageable memory. 0, the default, stages none.
+    pub pinned_pool_bytes: usize,
+    /// The number of polynomials h(X) the evaluator keeps, by a BLAKE2b digest
+    /// of all the inputs of their evaluation, to return them again when it is
+    /// called with the same inputs, e.g. by a test harness proving the same
+    /// witness several times. The least recently used is dropped first. 0,
+    /// the default, caches none, as the witness usually changes.
+    pub h_cache_entries: usize,
+    /// Evaluate h(X) on the CPU in chunks of rows of at most
+    /// `spill_ram_budget` bytes, written to this file as they are evaluated
+    /// and read back once the cosets of the advice and instance are freed,
+    /// when the values of h(X) are larger than the budget. The lookup cosets
+    /// are computed once and held with the others. The device backends are
+    /// unaffected.
+    pub spill_path: Option<PathBuf>,
+    /// The most memory, in bytes, the values of h(X) take while they are
+    /// evaluated, when `spill_path` is set. 1 GiB by default.
+    pub spill_ram_budget: usize,
+    /// The phases the device backends evaluate, all of them by default
+    pub gpu_phases: GpuPhases,
+    /// The CUDA backend uploads the value of each input and table expression
+    /// of the lookups with at least this many of them, and compresses them
+    /// with the powers of theta on the device, instead of uploading the
+    /// compressed values computed on the CPU. 0, the default, compresses all
+    /// of them on the CPU, which the kernel modules without the
+    /// `compute_lookup_table_values` kernel need.
+    pub lookup_device_columns: usize,
+    /// The pool the evaluation of h(X) runs on, the CPU backend and the work
+    /// of the device backends on the CPU, such as the cosets, included. The
+    /// global pool by default. `single_threaded` and `pin_threads` still run
+    /// the CPU backend on pools of their own. It is not read from the
+    /// environment.
+    pub thread_pool: Option<EvalThreadPool>,
+    /// Check that each h(X) evaluated vanishes on the domain, before it is
+    /// divided by the vanishing polynomial, and panic otherwise with the
+    /// first row at which a constraint does not hold, along with its circuit
+    /// and its phase. This catches a bad witness as h(X) is evaluated rather
+    /// than when the proof fails to verify, at the cost of an FFT on the
+    /// extended domain per evaluation, and of evaluating the phases again on
+    /// the CPU when it fails. Off by default, for CI rather than production.
+    pub assert_vanishing: bool,
+    /// How the CUDA backend launches the lookups, in a single batch by
+    /// default
+    pub lookup_strategy: LookupStrategy,
+    /// How the CUDA backend lays out the per-row values of the lookups,
+    /// interleaved by default. The split layout needs a kernel module built
+    /// with its kernels, the evaluation failing on the others.
+    pub lookup_layout: LookupLayout,
+    /// The ordinals of the CUDA devices the rows of the lookups are split
+    /// across, as the driver numbers the devices `CUDA_VISIBLE_DEVICES`
+    /// leaves visible, each evaluating its slice of rows in a context of its
+    /// own. An ordinal listed twice runs two slices. The lookups run on the
+    /// first device, through the driver wrapper, with none, the default. The
+    /// custom gates and the permutations stay where they run without it.
+    pub devices: Vec<usize>,
+    /// How the buffers of the CUDA lookups reach the device, with explicit
+    /// copies by default. The unified mode launches them through the driver
+    /// API, as `devices` does, on the first device if none are listed, and
+    /// fails on the devices without unified memory. The custom gates keep
+    /// copying their buffers explicitly.
+    pub memory_mode: MemoryMode,
+}
+
+impl Default for EvaluatorConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            fallback_to_cpu: true,
+            cuda_threads: None,
+            retry: RetryPolicy::default(),
+            keep_scratch: false,
+            count_operations: false,
+            single_threaded: false,
+            pin_threads: false,
+            timing_report: None,
+            release_cuda_memory: false,
+            pipeline_cosets: false,
+            parallel_circuits_max_k: 0,
+            verbosity: Verbosity::default(),
+            chunks_per_thread: 1,
+            pinned_pool_bytes: 0,
+            h_cache_entries: 0,
+            spill_path: None,
+            spill_ram_budget: 1 << 30,
+            gpu_phases: GpuPhases::default(),
+            lookup_device_columns: 0,
+            thread_pool: None,
+            assert_vanishing: false,
+            lookup_strategy: LookupStrategy::Batched,
+            lookup_layout: LookupLayout::Interleaved,
+            devices: vec![],
+            memory_mode: MemoryMode::Explicit,
+        }
+    }
+}
+
+impl EvaluatorConfig {
+    /// Reads the configuration from the environment, a variable that is unset
+    /// or does not parse leaving its setting at the default. Unless noted, a
+    /// flag is on when its variable is set, whatever its value.
+    ///
+    /// - `CUDA`, `OPENCL`, `WGPU`, `CUDA_VERIFY_ROWS`, `CUDA_VERIFY_SEED`: the
+    ///   [`Backend`], see [`Backend::from_env`]; the CPU by default.
+    /// - `CUDA_FALLBACK`: a flag, on unless set to "0", "n" or "no"; falls
+    ///   back to the CPU when the device fails.
+    /// - `CUDA_THREADS`: a positive number, the threads the kernels are
+    ///   launched with; sized from the device properties by default.
+    /// - `CUDA_RETRIES`: a number, the retries of a launch failing on a
+    ///   transient error; 0 by default.
+    /// - `CUDA_RELEASE_MEMORY`: a flag, off by default; releases the device
+    ///   memory after each evaluation.
+    /// - `CUDA_PINNED_POOL_BYTES`: a number of bytes, the page-locked memory
+    ///   staging the uploads; 0, none, by default.
+    /// - `CUDA_LOOKUP_STRATEGY`: "batched" or "per-lookup", see
+    ///   [`LookupStrategy`]; "batched" by default.
+    /// - `CUDA_LOOKUP_LAYOUT`: "interleaved" or "split", see
+    ///   [`LookupLayout`]; "interleaved" by default.
+    /// - `CUDA_DEVICES`: a comma-separated list of device ordinals, the
+    ///   devices the lookups are split across; none by default.
+    /// - `CUDA_MEMORY_MODE`: "explicit" or "unified", see [`MemoryMode`];
+    ///   "explicit" by default.
+    /// - `EVAL_COUNT_OPS`: a flag, off by default; counts the operations.
+    /// - `EVAL_SINGLE_THREADED`: a flag, off by default; evaluates on a single
+    ///   CPU thread.
+    /// - `EVAL_PIN_THREADS`: a flag, off by default; evaluates on CPU threads
+    ///   pinned to their cores.
+    /// - `EVAL_TIMINGS_REPORT`: a path, the file the timings are reported to;
+    ///   none by default.
+    /// - `EVAL_PIPELINE_COSETS`: a flag, off by default; pipelines the cosets
+    ///   with the evaluation of the circuits.
+    /// - `EVAL_PARALLEL_CIRCUITS_MAX_K`: a number, the circuits of at most
+    ///   2^k rows are evaluated in parallel; 0 by default.
+    /// - `EVAL_VERBOSITY`: "silent", "errors", "info" or "debug", see
+    ///   [`Verbosity`]; "errors" by default.
+    /// - `EVAL_CHUNKS_PER_THREAD`: a positive number, the chunks per thread
+    ///   the rows of the custom gates are split into; 1 by default.
+    /// - `EVAL_H_CACHE_ENTRIES`: a number, the polynomials h(X) cached; 0 by
+    ///   default.
+    /// - `EVAL_SPILL_PATH`: a path, the file the values of h(X) are spilled
+    ///   to; none by default.
+    /// - `EVAL_SPILL_RAM_BUDGET`: a positive number of bytes, the values of
+    ///   h(X) kept in memory before spilling; 1 GiB by default.
+    /// - `EVAL_GPU_PHASES`: a list of phases, see [`GpuPhases::parse`], the
+    ///   phases run on the device; all of them by default.
+    /// - `EVAL_LOOKUP_DEVICE_COLUMNS`: a number, the lookups with at least as
+    ///   many expressions are compressed on the device; 0, none of them, by
+    ///   default.
+    /// - `EVAL_ASSERT_VANISHING`: a flag, off by default; checks that h(X)
+    ///   vanishes on the domain.
+    pub fn from_env() -> Self {
+        let fallback_to_cpu = !matches!(
+            env::var("CUDA_FALLBACK").as_deref(),
+            Ok("0") | Ok("n") | Ok("no")
+        );
+        let cuda_threads = env::var("CUDA_THREADS")
+            .ok()
+            .and_then(|threads| threads.trim().parse().ok())
+            .filter(|&threads| threads > 0);
+        let retry = RetryPolicy {
+            max_retries: env::var("CUDA_RETRIES")
+                .ok()
+                .and_then(|retries| retries.trim().parse().ok())
+                .unwrap_or(0),
+            ..RetryPolicy::default()
+        };
+        Self {
+            backend: Backend::from_env(),
+            fallback_to_cpu,
+            cuda_threads,
+            retry,
+            keep_scratch: false,
+            count_operations: env::var("EVAL_COUNT_OPS").is_ok(),
+            single_threaded: env::var("EVAL_SINGLE_THREADED").is_ok(),
+            pin_threads: env::var("EVAL_PIN_THREADS").is_ok(),
+            timing_report: env::var_os("EVAL_TIMINGS_REPORT").map(PathBuf::from),
+            release_cuda_memory: env::var("CUDA_RELEASE_MEMORY").is_ok(),
+            pipeline_cosets: env::var("EVAL_PIPELINE_COSETS").is_ok(),
+            parallel_circuits_max_k: env::var("EVAL_PARALLEL_CIRCUITS_MAX_K")
+                .ok()
+                .and_then(|k| k.trim().parse().ok())
+                .unwrap_or(0),
+            verbosity: env::var("EVAL_VERBOSITY")
+                .ok()
+                .and_then(|level| Verbosity::parse(&level))
+                .unwrap_or_default(),
+            chunks_per_thread: env::var("EVAL_CHUNKS_PER_THREAD")
+                .ok()
+                .and_then(|chunks| chunks.trim().parse().ok())
+                .filter(|&chunks| chunks > 0)
+                .unwrap_or(1),
+            pinned_pool_bytes: env::var("CUDA_PINNED_POOL_BYTES")
+                .ok()
+                .and_then(|bytes| bytes.trim().parse().ok())
+                .unwrap_or(0),
+            h_cache_entries: env::var("EVAL_H_CACHE_ENTRIES")
+                .ok()
+                .and_then(|entries| entries.trim().parse().ok())
+                .unwrap_or(0),
+            spill_path: env::var_os("EVAL_SPILL_PATH").map(PathBuf::from),
+            spill_ram_budget: env::var("EVAL_SPILL_RAM_BUDGET")
+                .ok()
+                .and_then(|bytes| bytes.trim().parse().ok())
+                .filter(|&bytes| bytes > 0)
+                .unwrap_or(1 << 30),
+            gpu_phases: env::var("EVAL_GPU_PHASES")
+                .ok()
+                .and_then(|phases| GpuPhases::parse(&phases))
+                .unwrap_or_default(),
+            lookup_device_columns: env::var("EVAL_LOOKUP_DEVICE_COLUMNS")
+                .ok()
+                .and_then(|columns| columns.trim().parse().ok())
+                .unwrap_or(0),
+            thread_pool: None,
+            assert_vanishing: env::var("EVAL_ASSERT_VANISHING").is_ok(),
+            lookup_strategy: env::var("CUDA_LOOKUP_STRATEGY")
+                .ok()
+                .and_then(|strategy| LookupStrategy::parse(&strategy))
+                .unwrap_or_default(),
+            lookup_layout: env::var("CUDA_LOOKUP_LAYOUT")
+                .ok()
+                .and_then(|layout| LookupLayout::parse(&layout))
+                .unwrap_or_default(),
+            devices: env::var("CUDA_DEVICES")
+                .ok()
+                .and_then(|devices| {
+                    devices
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|device| !device.is_empty())
+                        .map(|device| device.parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            memory_mode: env::var("CUDA_MEMORY_MODE")
+                .ok()
+                .and_then(|mode| MemoryMode::parse(&mode))
+                .unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::arithmetic::FieldExt;
+use crate::poly::EvaluationDomain;
+
+/// The parameters of the extended domain the values of h(X) are evaluated
+/// on, returned along with them so that the division by the vanishing
+/// polynomial and the FFTs after it use the same ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(in crate::plonk) struct ExtendedContext<F> {
+    /// The log2 of the size of the extended domain
+    pub(in crate::plonk) extended_k: u32,
+    /// The distance between two consecutive rows of the domain in the
+    /// extended domain
+    pub(in crate::plonk) rot_scale: i32,
+    /// The generator of the extended domain
+    pub(in crate::plonk) extended_omega: F,
+}
+
+impl<F: FieldExt> ExtendedContext<F> {
+    /// The parameters of the extended domain of `domain`
+    pub(in crate::plonk) fn new(domain: &EvaluationDomain<F>) -> Self {
+        ExtendedContext {
+            extended_k: domain.extended_k(),
+            rot_scale: 1 << (domain.extended_k() - domain.k()),
+            extended_omega: domain.get_extended_omega(),
+        }
+    }
+
+    /// Whether these are the parameters of the extended domain of `domain`
+    pub(in crate::plonk) fn matches(&self, domain: &EvaluationDomain<F>) -> bool {
+        *self == Self::new(domain)
+    }
+}
@@ -0,0 +1,94 @@
+use super::{CancellationToken, EvalError};
+
+/// A step of an evaluation of h(X), as reported to a progress callback
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// Computing the advice and instance cosets of the circuits
+    Cosets,
+    /// The custom gates of a circuit
+    CustomGates,
+    /// The permutation of a circuit
+    Permutations,
+    /// The lookups of a circuit
+    Lookups,
+    /// A chunk of rows of h(X) spilled to a file
+    SpilledRows,
+    /// The evaluation is complete
+    Done,
+}
+
+/// The progress of an evaluation of h(X), reported as each of its steps starts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// The step starting
+    pub phase: ProgressPhase,
+    /// The fraction of the steps done so far, from 0 to 1
+    pub fraction: f64,
+}
+
+/// The controls of an evaluation of h(X) from its caller: the token cancelling
+/// it and the callback its progress is reported to, both only looked at
+/// between the steps of the evaluation, never from its parallel loops.
+pub(in crate::plonk) struct EvalControl<'a> {
+    cancel: CancellationToken,
+    progress: Option<&'a mut (dyn FnMut(Progress) + Send)>,
+    steps: usize,
+    done: usize,
+}
+
+impl<'a> Default for EvalControl<'a> {
+    fn default() -> Self {
+        Self::new(&CancellationToken::default(), None)
+    }
+}
+
+impl<'a> EvalControl<'a> {
+    pub(in crate::plonk) fn new(
+        cancel: &CancellationToken,
+        progress: Option<&'a mut (dyn FnMut(Progress) + Send)>,
+    ) -> Self {
+        Self {
+            cancel: cancel.clone(),
+            progress,
+            steps: 0,
+            done: 0,
+        }
+    }
+
+    /// Counts the progress over `steps` steps from now on, e.g. again from
+    /// the start when an evaluation falls back to the CPU
+    pub(in crate::plonk) fn begin(&mut self, steps: usize) {
+        self.steps = steps;
+        self.done = 0;
+    }
+
+    /// Starts a step of `phase`, unless the evaluation was cancelled
+    pub(in crate::plonk) fn step(&mut self, phase: ProgressPhase) -> Result<(), EvalError> {
+        self.cancel.check()?;
+        let fraction = self.done as f64 / self.steps.max(1) as f64;
+        self.report(phase, fraction.min(1.0));
+        self.done += 1;
+        Ok(())
+    }
+
+    /// Reports the end of the evaluation
+    pub(in crate::plonk) fn finish(&mut self) {
+        self.report(ProgressPhase::Done, 1.0);
+    }
+
+    pub(in crate::plonk) fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// The token cancelling the evaluation, for the controls of the circuits
+    /// evaluated in parallel, which check it without reporting any progress
+    pub(in crate::plonk) fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    fn report(&mut self, phase: ProgressPhase, fraction: f64) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress(Progress { phase, fraction });
+        }
+    }
+}
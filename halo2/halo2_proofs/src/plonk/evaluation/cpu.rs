@@ -0,0 +1,351 @@
+use super::{get_rotation_idx, CircuitData, EvalError, Evaluator, HEvaluatorBackend};
+use crate::multicore;
+use crate::plonk::{lookup, permutation, Any, ProvingKey};
+use crate::{
+    arithmetic::{parallelize, parallelize_chunk_size, CurveAffine, FieldExt},
+    poly::{ExtendedLagrangeCoeff, Polynomial, Rotation},
+};
+use group::ff::Field;
+use std::iter;
+
+/// Evaluates every phase of h(X) on the CPU
+#[derive(Clone, Copy, Debug, Default)]
+pub(in crate::plonk) struct CpuBackend;
+
+impl<C: CurveAffine> HEvaluatorBackend<C> for CpuBackend {
+    fn evaluate_custom_gates(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        Self::custom_gates_rows(ev, data, values, 0);
+        Ok(())
+    }
+
+    fn evaluate_permutations(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        Self::permutations_rows(data, permutation, values, 0);
+        Ok(())
+    }
+
+    fn evaluate_lookups(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        for (n, lookup) in lookups.iter().enumerate() {
+            // Calculated here so these only have to be kept in memory for the short time
+            // they are actually needed.
+            let cosets = Self::lookup_cosets(data.pk, lookup);
+            Self::lookup_rows(ev, data, n, &cosets, values, 0);
+        }
+        Ok(())
+    }
+}
+
+/// The phases evaluated on the rows `offset..offset + values.len()` of the
+/// extended domain only. Rotations still read the whole cosets, wrapping
+/// around the end of the domain.
+impl CpuBackend {
+    pub(in crate::plonk) fn custom_gates_rows<C: CurveAffine>(
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        values: &mut [C::ScalarExt],
+        offset: usize,
+    ) {
+        let size = data.size();
+        let rot_scale = data.rot_scale();
+        let isize = size as i32;
+        let fixed = data.fixed();
+        let advice = data.advice;
+        let instance = data.instance;
+        let challenges = data.challenges;
+        let (y, beta, gamma, theta) = (data.y, data.beta, data.gamma, data.theta);
+
+        let num_chunks = multicore::current_num_threads() * ev.config.chunks_per_thread.max(1);
+        multicore::scope(|scope| {
+            let chunk_size = ((values.len() + num_chunks - 1) / num_chunks).max(1);
+            for (chunk_idx, values) in values.chunks_mut(chunk_size).enumerate() {
+                let start = chunk_idx * chunk_size;
+                scope.spawn(move |_| {
+                    let mut eval_data = ev.custom_gates.instance();
+                    ev.custom_gates.evaluate_rows(
+                        &mut eval_data,
+                        fixed,
+                        advice,
+                        instance,
+                        challenges,
+                        &beta,
+                        &gamma,
+                        &theta,
+                        &y,
+                        values,
+                        offset + start,
+                        rot_scale,
+                        isize,
+                    );
+                });
+            }
+        });
+    }
+
+    pub(in crate::plonk) fn permutations_rows<C: CurveAffine>(
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut [C::ScalarExt],
+        offset: usize,
+    ) {
+        let sets = &permutation.sets;
+        if sets.is_empty() {
+            return;
+        }
+
+        let pk = data.pk;
+        let domain = &pk.vk.domain;
+        let size = data.size();
+        let rot_scale = data.rot_scale();
+        let isize = size as i32;
+        let fixed = data.fixed();
+        let advice = data.advice;
+        let instance = data.instance;
+        let (y, beta, gamma) = (data.y, data.beta, data.gamma);
+        let extended_omega = domain.get_extended_omega();
+        let one = C::ScalarExt::one();
+        let l0 = &pk.l0;
+        let l_last = &pk.l_last;
+        let l_active_row = &pk.l_active_row;
+        let p = &pk.vk.cs.permutation;
+
+        let blinding_factors = pk.vk.cs.blinding_factors();
+        let last_rotation = Rotation(-((blinding_factors + 1) as i32));
+        let chunk_len = pk.vk.cs.degree() - 2;
+        let delta_powers = Self::delta_powers(beta, p.columns.len());
+        let delta_powers = &delta_powers;
+
+        let first_set = sets.first().unwrap();
+        let last_set = sets.last().unwrap();
+
+        // The powers of the extended omega at the start of each chunk, so that
+        // no chunk has to raise it to its start offset.
+        let chunk = parallelize_chunk_size(values.len());
+        let chunk_step = extended_omega.pow_vartime(&[chunk as u64, 0, 0, 0]);
+        let first_power = extended_omega.pow_vartime(&[offset as u64, 0, 0, 0]);
+        let start_powers: Vec<_> =
+            iter::successors(Some(first_power), |power| Some(*power * chunk_step))
+                .take((values.len() + chunk - 1) / chunk)
+                .collect();
+        let start_powers = &start_powers;
+
+        // Permutation constraints
+        parallelize(values, |values, start| {
+            let mut beta_term = start_powers[start / chunk];
+            for (i, value) in values.iter_mut().enumerate() {
+                let idx = offset + start + i;
+                let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
+                let r_last = get_rotation_idx(idx, last_rotation.0, rot_scale, isize);
+
+                // Enforce only for the first set.
+                // l_0(X) * (1 - z_0(X)) = 0
+                *value = *value * y + ((one - first_set.permutation_product_coset[idx]) * l0[idx]);
+                // Enforce only for the last set.
+                // l_last(X) * (z_l(X)^2 - z_l(X)) = 0
+                *value = *value * y
+                    + ((last_set.permutation_product_coset[idx]
+                        * last_set.permutation_product_coset[idx]
+                        - last_set.permutation_product_coset[idx])
+                        * l_last[idx]);
+                // Except for the first set, enforce.
+                // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) = 0
+                for (set_idx, set) in sets.iter().enumerate() {
+                    if set_idx != 0 {
+                        *value = *value * y
+                            + ((set.permutation_product_coset[idx]
+                                - permutation.sets[set_idx - 1].permutation_product_coset[r_last])
+                                * l0[idx]);
+                    }
+                }
+                // And for all the sets we enforce:
+                // (1 - (l_last(X) + l_blind(X))) * (
+                //   z_i(\omega X) \prod_j (p(X) + \beta s_j(X) + \gamma)
+                // - z_i(X) \prod_j (p(X) + \delta^j \beta X + \gamma)
+                // )
+                for (((set, columns), cosets), delta_powers) in sets
+                    .iter()
+                    .zip(p.columns.chunks(chunk_len))
+                    .zip(pk.permutation.cosets.chunks(chunk_len))
+                    .zip(delta_powers.chunks(chunk_len))
+                {
+                    let mut left = set.permutation_product_coset[r_next];
+                    for (values, permutation) in columns
+                        .iter()
+                        .map(|&column| match column.column_type() {
+                            Any::Advice(_) => &advice[column.index()],
+                            Any::Fixed => &fixed[column.index()],
+                            Any::Instance => &instance[column.index()],
+                        })
+                        .zip(cosets.iter())
+                    {
+                        left *= values[idx] + beta * permutation[idx] + gamma;
+                    }
+
+                    let mut right = set.permutation_product_coset[idx];
+                    for (values, delta_power) in columns
+                        .iter()
+                        .map(|&column| match column.column_type() {
+                            Any::Advice(_) => &advice[column.index()],
+                            Any::Fixed => &fixed[column.index()],
+                            Any::Instance => &instance[column.index()],
+                        })
+                        .zip(delta_powers.iter())
+                    {
+                        right *= values[idx] + *delta_power * beta_term + gamma;
+                    }
+
+                    *value = *value * y + ((left - right) * l_active_row[idx]);
+                }
+                beta_term *= &extended_omega;
+            }
+        });
+    }
+
+    /// `beta * zeta * delta^j` for each of the `num_columns` columns `j` of
+    /// the permutation, the terms of its identity columns at the first row of
+    /// the extended domain, which the other rows scale by their power of the
+    /// extended omega. They are the same for every row, computed once.
+    pub(in crate::plonk) fn delta_powers<F: FieldExt>(beta: F, num_columns: usize) -> Vec<F> {
+        iter::successors(Some(beta * F::ZETA), |power| Some(*power * F::DELTA))
+            .take(num_columns)
+            .collect()
+    }
+
+    /// The constraints of every lookup of a circuit, from the `lookup_cosets`
+    /// of each, so that the callers evaluating several ranges of rows compute
+    /// them once
+    pub(in crate::plonk) fn lookups_rows<C: CurveAffine>(
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        cosets: &[[Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>; 3]],
+        values: &mut [C::ScalarExt],
+        offset: usize,
+    ) {
+        for (n, cosets) in cosets.iter().enumerate() {
+            Self::lookup_rows(ev, data, n, cosets, values, offset);
+        }
+    }
+
+    /// The `lookup_cosets` of each of `lookups`
+    pub(in crate::plonk) fn all_lookup_cosets<C: CurveAffine>(
+        pk: &ProvingKey<C>,
+        lookups: &[lookup::prover::Committed<C>],
+    ) -> Vec<[Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>; 3]> {
+        lookups
+            .iter()
+            .map(|lookup| Self::lookup_cosets(pk, lookup))
+            .collect()
+    }
+
+    /// The polynomials required for a lookup, on the extended domain: its
+    /// product and its permuted input and table
+    pub(in crate::plonk) fn lookup_cosets<C: CurveAffine>(
+        pk: &ProvingKey<C>,
+        lookup: &lookup::prover::Committed<C>,
+    ) -> [Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>; 3] {
+        let domain = &pk.vk.domain;
+        [
+            domain.coeff_to_extended(lookup.product_poly.clone()),
+            domain.coeff_to_extended(lookup.permuted_input_poly.clone()),
+            domain.coeff_to_extended(lookup.permuted_table_poly.clone()),
+        ]
+    }
+
+    /// The constraints of the `n`th lookup, from its `lookup_cosets`
+    pub(in crate::plonk) fn lookup_rows<C: CurveAffine>(
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        n: usize,
+        cosets: &[Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>; 3],
+        values: &mut [C::ScalarExt],
+        offset: usize,
+    ) {
+        let pk = data.pk;
+        let size = data.size();
+        let rot_scale = data.rot_scale();
+        let isize = size as i32;
+        let fixed = data.fixed();
+        let advice = data.advice;
+        let instance = data.instance;
+        let challenges = data.challenges;
+        let (y, beta, gamma, theta) = (data.y, data.beta, data.gamma, data.theta);
+        let one = C::ScalarExt::one();
+        let l0 = &pk.l0;
+        let l_last = &pk.l_last;
+        let l_active_row = &pk.l_active_row;
+        let [product_coset, permuted_input_coset, permuted_table_coset] = cosets;
+
+        // Lookup constraints
+        parallelize(values, |values, start| {
+            let lookup_evaluator = &ev.lookups[n];
+            let mut eval_data = lookup_evaluator.instance();
+            for (i, value) in values.iter_mut().enumerate() {
+                let idx = offset + start + i;
+
+                let table_value = lookup_evaluator.evaluate(
+                    &mut eval_data,
+                    fixed,
+                    advice,
+                    instance,
+                    challenges,
+                    &beta,
+                    &gamma,
+                    &theta,
+                    &y,
+                    &C::ScalarExt::zero(),
+                    idx,
+                    rot_scale,
+                    isize,
+                );
+
+                let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
+                let r_prev = get_rotation_idx(idx, -1, rot_scale, isize);
+
+                let a_minus_s = permuted_input_coset[idx] - permuted_table_coset[idx];
+                // l_0(X) * (1 - z(X)) = 0
+                *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
+                // l_last(X) * (z(X)^2 - z(X)) = 0
+                *value = *value * y
+                    + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
+                        * l_last[idx]);
+                // (1 - (l_last(X) + l_blind(X))) * (
+                //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+                //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
+                //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
+                // ) = 0
+                *value = *value * y
+                    + ((product_coset[r_next]
+                        * (permuted_input_coset[idx] + beta)
+                        * (permuted_table_coset[idx] + gamma)
+                        - product_coset[idx] * table_value)
+                        * l_active_row[idx]);
+                // Check that the first values in the permuted input expression and permuted
+                // fixed expression are the same.
+                // l_0(X) * (a'(X) - s'(X)) = 0
+                *value = *value * y + (a_minus_s * l0[idx]);
+                // Check that each value in the permuted lookup input expression is either
+                // equal to the value above it, or the value at the same index in the
+                // permuted table expression.
+                // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
+                *value = *value * y
+                    + (a_minus_s
+                        * (permuted_input_coset[idx] - permuted_input_coset[r_prev])
+                        * l_active_row[idx]);
+            }
+        });
+    }
+}
@@ -0,0 +1,1976 @@
+use super::device::{
+    extend_kernel_repr, CombinedData, DeviceCalculation, DeviceGraph, DeviceSource, FieldLayout,
+    OP_ADD,
+};
+use super::pinned::PinnedPool;
+use super::{
+    prepare_lookup, prepare_lookups, CircuitData, CpuBackend, EvalError, Evaluator,
+    HEvaluatorBackend, LookupBuffers, LookupLayout, LookupStrategy, MemoryMode, RetryPolicy,
+    Transfers, Verbosity,
+};
+use crate::plonk::{lookup, permutation, ProvingKey};
+use crate::{
+    arithmetic::{CurveAffine, FieldExt},
+    multicore,
+    poly::{ExtendedLagrangeCoeff, Polynomial},
+};
+use ark_std::{end_timer, start_timer};
+use cuda_driver_wrapper::*;
+use group::ff::{Field, PrimeField};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryInto,
+    env,
+    ffi::{CStr, CString},
+    fs,
+    hash::{Hash, Hasher},
+    iter, mem,
+    os::raw::{c_char, c_int, c_uint, c_void},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Instant,
+};
+
+mod direct;
+
+/// The PTX of the kernel, compiled in from the file `CU_KERNEL` points at when
+/// the crate is built
+#[cfg(feature = "cuda-embedded-kernel")]
+const EMBEDDED_KERNEL: Option<&str> = Some(include_str!(env!("CU_KERNEL")));
+#[cfg(not(feature = "cuda-embedded-kernel"))]
+const EMBEDDED_KERNEL: Option<&str> = None;
+
+/// The cubin of the kernel compiled in from the file `CU_KERNEL_CUBIN` points
+/// at when the crate is built, with the `sm_XY` architecture `CU_KERNEL_ARCH`
+/// it was compiled for. It is loaded instead of the PTX on the devices that can
+/// run it, saving the JIT compilation.
+#[cfg(feature = "cuda-embedded-cubin")]
+const EMBEDDED_CUBIN: Option<(&[u8], &str)> = Some((
+    include_bytes!(env!("CU_KERNEL_CUBIN")),
+    env!("CU_KERNEL_ARCH"),
+));
+#[cfg(not(feature = "cuda-embedded-cubin"))]
+const EMBEDDED_CUBIN: Option<(&[u8], &str)> = None;
+
+/// Number of times the embedded kernel has been extracted, to give each
+/// extraction its own file
+static EXTRACTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The kernel evaluating the lookups
+const LOOKUPS_KERNEL: &str = "compute_evaluate_h_lookups_codeblock";
+
+/// The kernel compressing the expressions of the lookups with the powers of
+/// theta, before `LOOKUPS_KERNEL`
+const LOOKUP_TABLE_VALUES_KERNEL: &str = "compute_lookup_table_values";
+
+/// `LOOKUPS_KERNEL` reading the split layout of `LookupLayout::Split`
+const LOOKUPS_SPLIT_KERNEL: &str = "compute_evaluate_h_lookups_split";
+
+/// `LOOKUP_TABLE_VALUES_KERNEL` writing the split layout, before
+/// `LOOKUPS_SPLIT_KERNEL`
+const LOOKUP_TABLE_VALUES_SPLIT_KERNEL: &str = "compute_lookup_table_values_split";
+
+/// The kernel evaluating a single expression
+const EXPRESSION_KERNEL: &str = "compute_evaluate_expression";
+
+/// The kernel folding values with the powers of a scalar
+const REDUCE_KERNEL: &str = "reduce_values";
+
+/// The rows of each chunk `REDUCE_KERNEL` sums, the last one being shorter, so
+/// that its partial sums do not depend on the device or the launch
+pub(in crate::plonk) const REDUCE_CHUNK_ROWS: usize = 256;
+
+/// The kernel checking the field the module is compiled for
+const FIELD_KERNEL: &str = "check_field";
+
+thread_local! {
+    /// The kernel modules loaded by this thread, keyed by a hash of their
+    /// contents and of the field they are checked against, so that each one is
+    /// only loaded and JIT compiled once.
+    ///
+    /// Each driver interface owns the CUDA context its module is loaded in, so
+    /// the context is created on the first evaluation of a thread and reused by
+    /// the following proofs on it. The interfaces are never shared between
+    /// threads: provers running in parallel each get their own context, and
+    /// those of a thread are torn down when it exits or by
+    /// `clear_cuda_module_cache`. The constant polynomials of the lookups stay
+    /// resident in the context, see [`ModuleState::constants`], the other
+    /// buffers of a launch are uploaded with each launch.
+    static MODULES: RefCell<HashMap<u64, LoadedModule>> = RefCell::new(HashMap::new());
+
+    /// What this thread did on the device since its counters were reset
+    static COUNTERS: Cell<CudaCounters> = Cell::new(CudaCounters::default());
+
+    /// The properties of the device, queried by the first launch of this
+    /// thread and reused by the next ones
+    static PROPERTIES: Cell<Option<DeviceProperties>> = Cell::new(None);
+}
+
+/// The kernel modules loaded and the kernels launched by the current thread,
+/// since it started or since `reset_cuda_counters`, for tests to check that a
+/// batch of circuits reuses its module and launches each kernel once
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CudaCounters {
+    /// The kernel modules loaded, each in its own context
+    pub module_loads: usize,
+    /// The uploads of the constant polynomials of the lookups, once per
+    /// module and domain
+    pub constant_uploads: usize,
+    /// The kernels launched, the `check_field` kernel launched once by each
+    /// module load included
+    pub kernel_launches: usize,
+}
+
+/// The counters of the current thread, see [`CudaCounters`]
+pub fn cuda_counters() -> CudaCounters {
+    COUNTERS.with(Cell::get)
+}
+
+/// Resets the counters of the current thread, see [`CudaCounters`]
+pub fn reset_cuda_counters() {
+    COUNTERS.with(|counters| counters.set(CudaCounters::default()));
+}
+
+fn count(f: impl FnOnce(&mut CudaCounters)) {
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        f(&mut value);
+        counters.set(value);
+    });
+}
+
+/// A loaded kernel module, with the state its launches reuse
+struct LoadedModule {
+    /// Freed before the context the buffers are allocated in is torn down
+    state: ModuleState,
+    drv_interface: DriverInterface,
+}
+
+/// What the launches of a loaded kernel module reuse from one another
+struct ModuleState {
+    /// The page-locked buffers the uploads are staged in
+    pool: PinnedPool,
+    /// The domain whose `l0`, `l_last` and `l_active_row` are resident in the
+    /// context, uploaded by the first lookup launch on it and kept by the
+    /// following ones, the driver wrapper keeping the buffers of an interface
+    /// by name until another upload replaces them
+    constants: Option<ConstantsKey>,
+    /// The verifying key, as it is represented in transcripts, whose fixed
+    /// cosets are resident in the context, uploaded by the first custom gates
+    /// launch of its proving key or by `Evaluator::upload_cuda_constants`
+    fixed: Option<Vec<u8>>,
+}
+
+/// What `l0`, `l_last` and `l_active_row` are determined by: the sizes of the
+/// domain and of its extension, and the blinding rows of the circuit, so that
+/// the proving keys of circuits of the same shape share them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ConstantsKey {
+    k: u32,
+    extended_k: u32,
+    blinding_factors: usize,
+}
+
+impl ConstantsKey {
+    fn of<C: CurveAffine>(pk: &ProvingKey<C>) -> Self {
+        Self {
+            k: pk.vk.domain.k(),
+            extended_k: pk.vk.domain.extended_k(),
+            blinding_factors: pk.vk.cs.blinding_factors(),
+        }
+    }
+}
+
+/// Uploads `l0`, `l_last` and `l_active_row` of `pk`, unless those of its
+/// domain are resident in the context already
+fn upload_lookup_constants<C: CurveAffine>(
+    drv_interface: &mut DriverInterface,
+    state: &mut ModuleState,
+    pk: &ProvingKey<C>,
+    transfers: &mut Transfers,
+) -> Result<(), EvalError> {
+    let constants = ConstantsKey::of(pk);
+    if state.constants == Some(constants) {
+        return Ok(());
+    }
+
+    let upload_start = Instant::now();
+    // Forgotten first, in case the upload replaces part of them
+    state.constants = None;
+    match drv_interface.add_allocations_2(
+        alloc_info_list![
+            ("l0", &pk.l0.values),
+            ("l_active_row", &pk.l_active_row.values),
+            ("l_last", &pk.l_last.values)
+        ],
+        alloc_info_list_2D![],
+    ) {
+        Err(_) => return Err(driver_error(drv_interface, "allocation")),
+        Ok(_) => {}
+    }
+    state.constants = Some(constants);
+    count(|counters| counters.constant_uploads += 1);
+    transfers.upload += upload_start.elapsed();
+    transfers.constant_bytes += mem::size_of_val(&pk.l0.values[..])
+        + mem::size_of_val(&pk.l_active_row.values[..])
+        + mem::size_of_val(&pk.l_last.values[..]);
+    Ok(())
+}
+
+/// Uploads the fixed cosets of `pk` one after the other, as the custom gates
+/// kernels read them, unless they are resident in the context already. The
+/// verifying key commits to the fixed columns, so its representation in
+/// transcripts tells the proving keys with the same cosets apart.
+fn upload_fixed<C: CurveAffine>(
+    drv_interface: &mut DriverInterface,
+    state: &mut ModuleState,
+    pk: &ProvingKey<C>,
+    transfers: &mut Transfers,
+) -> Result<(), EvalError> {
+    let key = pk.vk.transcript_repr.to_repr().as_ref().to_vec();
+    if state.fixed.as_ref() == Some(&key) {
+        return Ok(());
+    }
+
+    let fixed = flatten_columns(&pk.fixed_cosets);
+    let upload_start = Instant::now();
+    state.fixed = None;
+    match drv_interface
+        .add_allocations_2(alloc_info_list![("fixed", &fixed)], alloc_info_list_2D![])
+    {
+        Err(_) => return Err(driver_error(drv_interface, "allocation")),
+        Ok(_) => {}
+    }
+    state.fixed = Some(key);
+    count(|counters| counters.constant_uploads += 1);
+    transfers.upload += upload_start.elapsed();
+    transfers.constant_bytes += mem::size_of_val(&fixed[..]);
+    Ok(())
+}
+
+/// The values of `columns` one after the other, in the representation of the
+/// kernels, or a single zero, which the kernels do not read, without columns,
+/// as the driver wrapper allocates a buffer for each name
+fn flatten_columns<F: FieldExt>(columns: &[Polynomial<F, ExtendedLagrangeCoeff>]) -> Vec<F> {
+    let mut flat = Vec::with_capacity(columns.iter().map(|column| column.len()).sum());
+    for column in columns {
+        extend_kernel_repr(&mut flat, &column.values);
+    }
+    if flat.is_empty() {
+        flat.push(F::zero());
+    }
+    flat
+}
+
+/// Number of blocks of `max_threads_per_block` threads launched per
+/// multiprocessor, so that each one has a block to switch to when another stalls
+const BLOCKS_PER_MULTIPROCESSOR: usize = 2;
+
+// `CUresult` codes, from `cuda.h`
+const CUDA_ERROR_NOT_INITIALIZED: c_int = 3;
+const CUDA_ERROR_NO_DEVICE: c_int = 100;
+const CUDA_ERROR_DEVICE_UNAVAILABLE: c_int = 46;
+const CUDA_ERROR_INVALID_CONTEXT: c_int = 201;
+const CUDA_ERROR_ECC_UNCORRECTABLE: c_int = 214;
+const CUDA_ERROR_LAUNCH_TIMEOUT: c_int = 702;
+
+// `cuDeviceGetAttribute` attributes, from `cuda.h`
+const CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK: c_int = 1;
+const CU_DEVICE_ATTRIBUTE_WARP_SIZE: c_int = 10;
+const CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT: c_int = 16;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR: c_int = 75;
+const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR: c_int = 76;
+const CU_DEVICE_ATTRIBUTE_MANAGED_MEMORY: c_int = 83;
+const CU_DEVICE_ATTRIBUTE_CONCURRENT_MANAGED_ACCESS: c_int = 89;
+
+// `CUjitInputType` and `CUjit_option` values, from `cuda.h`
+const CU_JIT_INPUT_CUBIN: c_uint = 0;
+const CU_JIT_INPUT_PTX: c_uint = 1;
+const CU_JIT_INPUT_FATBINARY: c_uint = 2;
+const CU_JIT_INPUT_OBJECT: c_uint = 3;
+const CU_JIT_INPUT_LIBRARY: c_uint = 4;
+const CU_JIT_ERROR_LOG_BUFFER: c_uint = 5;
+const CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES: c_uint = 6;
+
+/// The size of the buffer the linker writes its errors to
+const LINK_LOG_BYTES: usize = 8192;
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cuInit(flags: c_uint) -> c_int;
+    fn cuDeviceGet(device: *mut c_int, ordinal: c_int) -> c_int;
+    fn cuDeviceGetAttribute(value: *mut c_int, attribute: c_int, device: c_int) -> c_int;
+    fn cuCtxSynchronize() -> c_int;
+    fn cuGetErrorName(error: c_int, name: *mut *const c_char) -> c_int;
+    fn cuGetErrorString(error: c_int, description: *mut *const c_char) -> c_int;
+    fn cuDevicePrimaryCtxRetain(context: *mut *mut c_void, device: c_int) -> c_int;
+    #[link_name = "cuDevicePrimaryCtxRelease_v2"]
+    fn cuDevicePrimaryCtxRelease(device: c_int) -> c_int;
+    #[link_name = "cuCtxPushCurrent_v2"]
+    fn cuCtxPushCurrent(context: *mut c_void) -> c_int;
+    #[link_name = "cuCtxPopCurrent_v2"]
+    fn cuCtxPopCurrent(context: *mut *mut c_void) -> c_int;
+    #[link_name = "cuMemGetInfo_v2"]
+    fn cuMemGetInfo(free: *mut usize, total: *mut usize) -> c_int;
+    #[link_name = "cuLinkCreate_v2"]
+    fn cuLinkCreate(
+        num_options: c_uint,
+        options: *mut c_uint,
+        option_values: *mut *mut c_void,
+        state: *mut *mut c_void,
+    ) -> c_int;
+    #[link_name = "cuLinkAddData_v2"]
+    fn cuLinkAddData(
+        state: *mut c_void,
+        input_type: c_uint,
+        data: *mut c_void,
+        size: usize,
+        name: *const c_char,
+        num_options: c_uint,
+        options: *mut c_uint,
+        option_values: *mut *mut c_void,
+    ) -> c_int;
+    fn cuLinkComplete(state: *mut c_void, cubin: *mut *mut c_void, size: *mut usize) -> c_int;
+    fn cuLinkDestroy(state: *mut c_void) -> c_int;
+}
+
+/// The error for the `CUresult` `code` returned during `operation`
+fn cuda_error(operation: &'static str, code: c_int) -> EvalError {
+    let describe = |query: unsafe extern "C" fn(c_int, *mut *const c_char) -> c_int| {
+        let mut description = std::ptr::null();
+        match unsafe { query(code, &mut description) } {
+            0 if !description.is_null() => unsafe { CStr::from_ptr(description) }
+                .to_string_lossy()
+                .into_owned(),
+            _ => String::from("unknown error"),
+        }
+    };
+
+    EvalError::Cuda {
+        operation,
+        code,
+        message: format!(
+            "{}: {}",
+            describe(cuGetErrorName),
+            describe(cuGetErrorString)
+        ),
+    }
+}
+
+/// Whether `err` may not happen again on a retry: an exceeded launch time on a
+/// device shared with a display, an ECC error or a device busy with another
+/// process. The other errors, like a lack of memory or a missing kernel
+/// function, fail again.
+pub(in crate::plonk) fn is_transient(err: &EvalError) -> bool {
+    matches!(
+        err,
+        EvalError::Cuda {
+            code: CUDA_ERROR_LAUNCH_TIMEOUT
+                | CUDA_ERROR_ECC_UNCORRECTABLE
+                | CUDA_ERROR_DEVICE_UNAVAILABLE,
+            ..
+        }
+    )
+}
+
+/// Checks the `CUresult` `code` returned during `operation`
+fn check(operation: &'static str, code: c_int) -> Result<(), EvalError> {
+    match code {
+        0 => Ok(()),
+        CUDA_ERROR_NO_DEVICE => Err(EvalError::NoCudaDevice),
+        code => Err(cuda_error(operation, code)),
+    }
+}
+
+/// The error for a failure of the driver wrapper during `operation`. The
+/// wrapper does not return the `CUresult` of the failed call, but the errors
+/// that leave the context unusable, like a faulting kernel, are reported again
+/// by the next call on the context.
+fn driver_error(drv_interface: &mut DriverInterface, operation: &'static str) -> EvalError {
+    drv_interface.dump_error();
+    match unsafe { cuCtxSynchronize() } {
+        // No context is left to report an error, when the module failed to load
+        0 | CUDA_ERROR_NOT_INITIALIZED | CUDA_ERROR_INVALID_CONTEXT => EvalError::Driver(operation),
+        code => cuda_error(operation, code),
+    }
+}
+
+/// Evaluates the lookup phase of h(X) on a CUDA device, or across several.
+///
+/// The custom gates and the permutations are evaluated on the CPU.
+#[derive(Clone, Debug, Default)]
+pub(in crate::plonk) struct CudaBackend {
+    /// The number of threads to launch the kernel with, sized from the device
+    /// if `None`
+    pub(in crate::plonk) threads: Option<usize>,
+    /// How the launches failing with a transient error are retried
+    pub(in crate::plonk) retry: RetryPolicy,
+    /// How much the driver wrapper and the module loading report
+    pub(in crate::plonk) verbosity: Verbosity,
+    /// The copies of the launches and of the constant uploads so far
+    pub(in crate::plonk) transfers: Transfers,
+    /// The most page-locked memory staging the uploads of the thread
+    pub(in crate::plonk) pinned_pool_bytes: usize,
+    /// The kernel function registered for the custom gates, which are then
+    /// evaluated on the device
+    pub(in crate::plonk) custom_kernel: Option<&'static str>,
+    /// The lookups with at least this many input expressions are compressed
+    /// on the device, none if 0
+    pub(in crate::plonk) lookup_device_columns: usize,
+    /// Whether the lookups are launched in a batch or one by one
+    pub(in crate::plonk) lookup_strategy: LookupStrategy,
+    /// How the per-row values of the lookups are laid out for the kernels
+    pub(in crate::plonk) lookup_layout: LookupLayout,
+    /// The ordinals of the devices the rows of the lookups are split across,
+    /// through the driver API, see the `direct` module. The lookups run on
+    /// the first device through the driver wrapper if none are listed.
+    pub(in crate::plonk) devices: Vec<usize>,
+    /// How the buffers of the lookups reach the devices, the unified mode
+    /// launching them through the driver API too
+    pub(in crate::plonk) memory_mode: MemoryMode,
+}
+
+impl CudaBackend {
+    /// The device the evaluations run on, which is the first one visible to
+    /// the process, as `CUDA_VISIBLE_DEVICES` names it if it is set
+    pub(in crate::plonk) fn device_name() -> String {
+        env::var("CUDA_VISIBLE_DEVICES")
+            .ok()
+            .and_then(|devices| {
+                let first = devices.split(',').next()?.trim();
+                (!first.is_empty()).then(|| first.to_string())
+            })
+            .unwrap_or_else(|| String::from("0"))
+    }
+
+    /// Cheaply checks whether a CUDA device can be used, without loading the
+    /// driver. This is false when `CUDA_VISIBLE_DEVICES` hides every device and,
+    /// on Linux, when the NVIDIA kernel driver is not loaded.
+    pub(in crate::plonk) fn is_available() -> bool {
+        if let Ok(devices) = env::var("CUDA_VISIBLE_DEVICES") {
+            let devices = devices.trim();
+            if devices.is_empty() || devices == "-1" {
+                return false;
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if !std::path::Path::new("/proc/driver/nvidia/version").exists() {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// The properties of a CUDA device that a kernel launch is sized from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(in crate::plonk) struct DeviceProperties {
+    pub(in crate::plonk) multiprocessor_count: usize,
+    pub(in crate::plonk) max_threads_per_block: usize,
+    pub(in crate::plonk) warp_size: usize,
+    /// The major and minor revision numbers of the device architecture
+    pub(in crate::plonk) compute_capability: (usize, usize),
+}
+
+impl DeviceProperties {
+    /// Queries the properties of the first CUDA device, the one the driver
+    /// wrapper loads the kernel on.
+    pub(in crate::plonk) fn query() -> Result<Self, EvalError> {
+        let attribute = |device: c_int, attribute: c_int| {
+            let mut value = 0;
+            check("device query", unsafe {
+                cuDeviceGetAttribute(&mut value, attribute, device)
+            })?;
+            Ok(value as usize)
+        };
+
+        let mut device = 0;
+        check("driver initialization", unsafe { cuInit(0) })?;
+        check("device query", unsafe { cuDeviceGet(&mut device, 0) })?;
+
+        Ok(DeviceProperties {
+            multiprocessor_count: attribute(device, CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)?,
+            max_threads_per_block: attribute(device, CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK)?,
+            warp_size: attribute(device, CU_DEVICE_ATTRIBUTE_WARP_SIZE)?,
+            compute_capability: (
+                attribute(device, CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?,
+                attribute(device, CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?,
+            ),
+        })
+    }
+
+    /// The properties of the device as `query` returns them, queried once by
+    /// each thread, as the device does not change while the process runs. A
+    /// failed query is not kept, and is made again by the next call.
+    pub(in crate::plonk) fn cached() -> Result<Self, EvalError> {
+        if let Some(properties) = PROPERTIES.with(Cell::get) {
+            return Ok(properties);
+        }
+        let properties = Self::query()?;
+        tracing::debug!(
+            "CUDA device: {} multiprocessors, {} threads per block, warp size {}, compute capability {}.{}",
+            properties.multiprocessor_count,
+            properties.max_threads_per_block,
+            properties.warp_size,
+            properties.compute_capability.0,
+            properties.compute_capability.1
+        );
+        PROPERTIES.with(|cached| cached.set(Some(properties)));
+        Ok(properties)
+    }
+
+    /// The number of threads that saturates the device for a kernel over
+    /// `len` values, in whole warps. The kernel strides over the values, so
+    /// any count is correct.
+    pub(in crate::plonk) fn launch_threads(&self, len: usize) -> usize {
+        let warp_size = self.warp_size.max(1);
+        let saturating =
+            self.multiprocessor_count * self.max_threads_per_block * BLOCKS_PER_MULTIPROCESSOR;
+        let needed = (len + warp_size - 1) / warp_size * warp_size;
+
+        saturating.min(needed).max(warp_size)
+    }
+
+    /// Whether the device can run a cubin compiled for the `sm_XY` (or `XY`)
+    /// architecture `arch`. A cubin runs on the devices of the same major
+    /// revision and a minor revision at least as high.
+    pub(in crate::plonk) fn runs_cubin(&self, arch: &str) -> bool {
+        let arch = arch.trim().trim_start_matches("sm_");
+        let revision = |digits: &str| digits.parse::<usize>().ok();
+        match arch.len().checked_sub(1).map(|split| arch.split_at(split)) {
+            Some((major, minor)) => match (revision(major), revision(minor)) {
+                (Some(major), Some(minor)) => {
+                    self.compute_capability.0 == major && self.compute_capability.1 >= minor
+                }
+                _ => false,
+            },
+            None => false,
+        }
+    }
+}
+
+impl CudaBackend {
+    /// The number of threads to launch a kernel over `len` values with: the
+    /// configured count if any, else enough to saturate the device, else one
+    /// per value if the device cannot be queried. The device is only queried
+    /// by the first launch of the thread, see [`DeviceProperties::cached`].
+    fn launch_threads(&self, len: usize) -> usize {
+        if let Some(threads) = self.threads {
+            tracing::debug!("CUDA launch: {} threads (configured)", threads);
+            return threads;
+        }
+
+        match DeviceProperties::cached() {
+            Ok(properties) => {
+                let threads = properties.launch_threads(len);
+                tracing::debug!("CUDA launch: {} threads for {} values", threads, len);
+                threads
+            }
+            Err(err) => {
+                tracing::warn!("CUDA launch: {}, one thread per value", err);
+                len
+            }
+        }
+    }
+}
+
+/// The work the CUDA backend plans for an evaluation of h(X), without running
+/// anything, as returned by `Evaluator::cuda_launch_plan`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LaunchPlan {
+    /// The number of kernel launches
+    pub kernel_launches: usize,
+    /// The device buffers of each launch, by name, with their sizes in bytes
+    pub buffers: Vec<(&'static str, usize)>,
+    /// The number of threads of each launch
+    pub threads: usize,
+}
+
+impl LaunchPlan {
+    /// The device memory each launch allocates, in bytes
+    pub fn device_bytes(&self) -> usize {
+        self.buffers.iter().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+impl CudaBackend {
+    /// Plans the launch of `evaluate_lookups_batched` for `num_circuits`
+    /// circuits of `num_lookups` lookups each, over an extended domain of
+    /// `size` rows
+    pub(in crate::plonk) fn plan<F>(
+        &self,
+        size: usize,
+        num_circuits: usize,
+        num_lookups: usize,
+    ) -> LaunchPlan {
+        let field = mem::size_of::<F>();
+        let lookups = num_circuits * num_lookups;
+        if lookups == 0 {
+            return LaunchPlan {
+                kernel_launches: 0,
+                buffers: vec![],
+                threads: 0,
+            };
+        }
+        // The launches, and the circuits and lookups of each of them
+        let (kernel_launches, num_circuits, lookups) = match self.lookup_strategy {
+            LookupStrategy::Batched => (1, num_circuits, lookups),
+            LookupStrategy::PerLookup => (lookups, 1, 1),
+        };
+        // The per-row values, the buffers of the other layout being a single
+        // zero
+        let row_buffers = match self.lookup_layout {
+            LookupLayout::Interleaved => [
+                (
+                    "combined_data_in",
+                    size * lookups * mem::size_of::<CombinedData<F>>(),
+                ),
+                ("table_values", field),
+                ("a_minus_s", field),
+            ],
+            LookupLayout::Split => [
+                ("combined_data_in", mem::size_of::<CombinedData<F>>()),
+                ("table_values", size * lookups * field),
+                ("a_minus_s", size * lookups * field),
+            ],
+        };
+
+        let mut buffers = vec![
+            ("values", size * num_circuits * field),
+            ("l0", size * field),
+            ("l_active_row", size * field),
+            ("l_last", size * field),
+            ("y_beta_gamma_one", 4 * field),
+            ("lookup_offsets", (num_circuits + 1) * mem::size_of::<u64>()),
+        ];
+        buffers.extend(row_buffers);
+        buffers.extend([
+            ("product_coset", size * lookups * field),
+            ("permuted_input_coset", size * lookups * field),
+            ("permuted_table_coset", size * lookups * field),
+        ]);
+
+        LaunchPlan {
+            kernel_launches,
+            buffers,
+            threads: self.launch_threads(size * num_circuits),
+        }
+    }
+
+    /// Evaluates the lookups of a circuit by a launch each, the buffers of
+    /// the next lookup being prepared on the other threads while the current
+    /// one is launched
+    fn evaluate_lookups_one_by_one<C: CurveAffine>(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        let device_columns = self.lookup_device_columns;
+        let layout = self.lookup_layout;
+        let threads = self.launch_threads(values.values.len());
+        let prepare = |n: usize| {
+            let mut buffers = LookupBuffers::with_layout(layout);
+            prepare_lookup(ev, data, n, &lookups[n], device_columns, &mut buffers);
+            buffers
+        };
+
+        let mut buffers = prepare(0);
+        for n in 0.. {
+            let mut next = None;
+            let launched = multicore::in_place_scope(|scope| {
+                if n + 1 < lookups.len() {
+                    let (prepare, next) = (&prepare, &mut next);
+                    scope.spawn(move |_| *next = Some(prepare(n + 1)));
+                }
+                self.launch_lookups(data, &buffers, vec![0, 1], threads, &mut values.values)
+            });
+            launched?;
+            match next {
+                Some(next) => buffers = next,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: CurveAffine> HEvaluatorBackend<C> for CudaBackend {
+    fn evaluate_custom_gates(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        match self.custom_kernel {
+            Some(symbol) => launch_custom_gates(
+                data,
+                symbol,
+                self.launch_threads(values.values.len()),
+                self.retry,
+                self.verbosity,
+                &mut self.transfers,
+                &mut values.values,
+            ),
+            None => CpuBackend.evaluate_custom_gates(ev, data, values),
+        }
+    }
+
+    fn evaluate_permutations(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        CpuBackend.evaluate_permutations(data, permutation, values)
+    }
+
+    fn evaluate_lookups(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        // Neither the module nor the device are needed without lookups
+        if lookups.is_empty() {
+            return Ok(());
+        }
+        if self.lookup_strategy == LookupStrategy::PerLookup {
+            return self.evaluate_lookups_one_by_one(ev, data, lookups, values);
+        }
+
+        let block_1_start_timer = start_timer!(|| String::from("Lookups : Block 1"));
+
+        let mut buffers = LookupBuffers::with_layout(self.lookup_layout);
+        prepare_lookups(ev, data, lookups, self.lookup_device_columns, &mut buffers);
+
+        end_timer!(block_1_start_timer);
+
+        let threads = self.launch_threads(values.values.len());
+        self.launch_lookups(
+            data,
+            &buffers,
+            vec![0, lookups.len() as u64],
+            threads,
+            &mut values.values,
+        )
+    }
+
+    fn evaluate_lookups_batched(
+        &mut self,
+        ev: &Evaluator<C>,
+        circuits: &[CircuitData<'_, C>],
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+    ) -> Result<Option<Vec<Vec<C::ScalarExt>>>, EvalError> {
+        // Evaluated circuit by circuit, and lookup by lookup
+        if self.lookup_strategy == LookupStrategy::PerLookup {
+            return Ok(None);
+        }
+        let data = match circuits.first() {
+            Some(data) if lookups.iter().any(|lookups| !lookups.is_empty()) => data,
+            _ => return Ok(None),
+        };
+        let size = data.size();
+
+        let block_1_start_timer = start_timer!(|| String::from("Lookups : Block 1"));
+
+        // The lists of all the circuits one after the other, the lookups of
+        // circuit `n` being `lookup_offsets[n]..lookup_offsets[n + 1]`
+        let mut buffers = LookupBuffers::with_layout(self.lookup_layout);
+        let mut lookup_offsets = vec![0];
+        for (data, lookups) in circuits.iter().zip(lookups.iter()) {
+            prepare_lookups(ev, data, lookups, self.lookup_device_columns, &mut buffers);
+            lookup_offsets.push((buffers.product_coset.len() / size) as u64);
+        }
+
+        end_timer!(block_1_start_timer);
+
+        // Starting from zero, the kernel computes the contribution of each circuit.
+        let mut values = vec![C::ScalarExt::zero(); size * (lookup_offsets.len() - 1)];
+        let threads = self.launch_threads(values.len());
+        self.launch_lookups(data, &buffers, lookup_offsets, threads, &mut values)?;
+
+        Ok(Some(
+            values.chunks(size).map(|chunk| chunk.to_vec()).collect(),
+        ))
+    }
+}
+
+impl CudaBackend {
+    /// Accumulates the lookups described by `buffers` into `values` with
+    /// `launch_lookups`, on the first device, or through the driver API when
+    /// the `devices` to split them across are listed or the memory is unified,
+    /// each window then being launched with as many threads as its rows need
+    /// rather than with `threads`
+    fn launch_lookups<C: CurveAffine>(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        buffers: &LookupBuffers<C::ScalarExt>,
+        lookup_offsets: Vec<u64>,
+        threads: usize,
+        values: &mut Vec<C::ScalarExt>,
+    ) -> Result<(), EvalError> {
+        if !self.devices.is_empty() || self.memory_mode == MemoryMode::Unified {
+            return direct::launch_lookups_across_devices(
+                self,
+                data,
+                buffers,
+                &lookup_offsets,
+                values,
+            );
+        }
+        launch_lookups(
+            data,
+            buffers,
+            lookup_offsets,
+            threads,
+            self.retry,
+            self.verbosity,
+            self.pinned_pool_bytes,
+            &mut self.transfers,
+            values,
+        )
+    }
+}
+
+/// Accumulates the lookups described by `buffers` into `values`, which holds
+/// one row per extended domain row for each group of lookups in
+/// `lookup_offsets`, in a single kernel launch of `threads` threads, retried
+/// following `retry` on transient errors.
+///
+/// The cosets are staged in up to `pinned_pool_bytes` bytes of page-locked
+/// memory, those that do not fit being uploaded from where they are.
+///
+/// The lookups left to compress on the device are compressed by a launch of
+/// `LOOKUP_TABLE_VALUES_KERNEL` first, given the powers of theta up to the
+/// most expressions of a lookup. Their columns are always uploaded, as a
+/// single zero when there are none, as the driver wrapper allocates a buffer
+/// for each name; so are the per-row buffers of the layout `buffers` does not
+/// use, and the kernels of its layout are launched.
+fn launch_lookups<C: CurveAffine>(
+    data: &CircuitData<'_, C>,
+    buffers: &LookupBuffers<C::ScalarExt>,
+    lookup_offsets: Vec<u64>,
+    threads: usize,
+    retry: RetryPolicy,
+    verbosity: Verbosity,
+    pinned_pool_bytes: usize,
+    transfers: &mut Transfers,
+    values: &mut Vec<C::ScalarExt>,
+) -> Result<(), EvalError> {
+    let pk = data.pk;
+    let (y, beta, gamma) = (data.y, data.beta, data.gamma);
+    let one = C::ScalarExt::one();
+
+    let circuit_count: i32 = (lookup_offsets.len() - 1).try_into().unwrap();
+    let array_size: i32 = data.size().try_into().unwrap();
+    let y_beta_gamma_one: Vec<C::ScalarExt> = vec![y, beta, gamma, one];
+    let field = FieldLayout::checked::<C::ScalarExt>()?;
+
+    let max_columns = buffers.max_device_columns();
+    let or_zero = |columns: &[C::ScalarExt]| match columns.is_empty() {
+        true => vec![C::ScalarExt::zero()],
+        false => columns.to_vec(),
+    };
+    let input_columns = or_zero(&buffers.input_columns);
+    let table_columns = or_zero(&buffers.table_columns);
+    let table_values = or_zero(&buffers.table_values);
+    let a_minus_s = or_zero(&buffers.a_minus_s);
+    let zero_combined = [CombinedData {
+        table_value: C::ScalarExt::zero(),
+        a_minus_s: C::ScalarExt::zero(),
+        r_next: 0,
+        r_prev: 0,
+    }];
+    let combined_data_in = match buffers.combined_data_in.is_empty() {
+        true => &zero_combined[..],
+        false => &buffers.combined_data_in[..],
+    };
+    let rot_scale = data.rot_scale();
+    let theta_powers: Vec<C::ScalarExt> =
+        iter::successors(Some(one), |power| Some(*power * data.theta))
+            .take(max_columns.max(1))
+            .collect();
+    let lookup_count: i32 = (buffers.column_offsets.len() - 1).try_into().unwrap();
+    let max_column_count: i32 = max_columns.try_into().unwrap();
+    let kernel = match (buffers.layout, max_columns) {
+        (LookupLayout::Interleaved, 0) => LOOKUPS_KERNEL,
+        (LookupLayout::Interleaved, _) => LOOKUP_TABLE_VALUES_KERNEL,
+        (LookupLayout::Split, 0) => LOOKUPS_SPLIT_KERNEL,
+        (LookupLayout::Split, _) => LOOKUP_TABLE_VALUES_SPLIT_KERNEL,
+    };
+
+    let constant_bytes = mem::size_of_val(&y_beta_gamma_one[..]);
+    let coset_bytes = mem::size_of_val(&values[..])
+        + mem::size_of_val(&lookup_offsets[..])
+        + mem::size_of_val(combined_data_in)
+        + mem::size_of_val(&table_values[..])
+        + mem::size_of_val(&a_minus_s[..])
+        + mem::size_of_val(&buffers.product_coset[..])
+        + mem::size_of_val(&buffers.permuted_input_coset[..])
+        + mem::size_of_val(&buffers.permuted_table_coset[..])
+        + mem::size_of_val(&input_columns[..])
+        + mem::size_of_val(&table_columns[..])
+        + mem::size_of_val(&buffers.column_offsets[..])
+        + mem::size_of_val(&theta_powers[..]);
+
+    /*
+        ---------------------------------------------------------------------------------------
+        --------------------- Computing this code block in gpu --------------------------------
+        ---------------------------------------------------------------------------------------
+        // l_0(X) * (1 - z(X)) = 0
+        *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
+        // l_last(X) * (z(X)^2 - z(X)) = 0
+        *value = *value * y
+            + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
+                * l_last[idx]);
+        // (1 - (l_last(X) + l_blind(X))) * (
+        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
+        //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
+        // ) = 0
+        *value = *value * y
+            + ((product_coset[r_next]
+                * (permuted_input_coset[idx] + beta)
+                * (permuted_table_coset[idx] + gamma)
+                - product_coset[idx] * table_value)
+                * l_active_row[idx]);
+        // Check that the first values in the permuted input expression and permuted
+        // fixed expression are the same.
+        // l_0(X) * (a'(X) - s'(X)) = 0
+        *value = *value * y + (a_minus_s * l0[idx]);
+        // Check that each value in the permuted lookup input expression is either
+        // equal to the value above it, or the value at the same index in the
+        // permuted table expression.
+        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
+        *value = *value * y
+            + (a_minus_s
+                * (permuted_input_coset[idx] - permuted_input_coset[r_prev])
+                * l_active_row[idx]);
+        ---------------------------------------------------------------------------------------
+        ---------------------------------------------------------------------------------------
+    */
+
+    with_module_retried(
+        kernel,
+        &field,
+        verbosity,
+        retry,
+        values,
+        |drv_interface, state, values| {
+            let block_2_start_timer = start_timer!(|| String::from("Lookups : Block 2"));
+
+            upload_lookup_constants(drv_interface, state, pk, transfers)?;
+
+            let pool = &mut state.pool;
+            pool.set_capacity(pinned_pool_bytes);
+            let combined_data_in = pool.stage(combined_data_in);
+            let table_values = pool.stage(&table_values);
+            let a_minus_s = pool.stage(&a_minus_s);
+            let product_coset = pool.stage(&buffers.product_coset);
+            let permuted_input_coset = pool.stage(&buffers.permuted_input_coset);
+            let permuted_table_coset = pool.stage(&buffers.permuted_table_coset);
+
+            let upload_start = Instant::now();
+            let uploaded = drv_interface.add_allocations_2(
+                alloc_info_list![
+                    ("values", &*values),
+                    ("y_beta_gamma_one", &y_beta_gamma_one),
+                    ("lookup_offsets", &lookup_offsets),
+                    ("combined_data_in", combined_data_in.as_slice()),
+                    ("table_values", table_values.as_slice()),
+                    ("a_minus_s", a_minus_s.as_slice()),
+                    ("product_coset", product_coset.as_slice()),
+                    ("permuted_input_coset", permuted_input_coset.as_slice()),
+                    ("permuted_table_coset", permuted_table_coset.as_slice()),
+                    ("input_columns", &input_columns),
+                    ("table_columns", &table_columns),
+                    ("column_offsets", &buffers.column_offsets),
+                    ("theta_powers", &theta_powers)
+                ],
+                alloc_info_list_2D![],
+            );
+            // The device has its copies, the buffers can stage the next launch
+            pool.release(combined_data_in);
+            pool.release(table_values);
+            pool.release(a_minus_s);
+            pool.release(product_coset);
+            pool.release(permuted_input_coset);
+            pool.release(permuted_table_coset);
+            if uploaded.is_err() {
+                return Err(driver_error(drv_interface, "allocation"));
+            }
+            transfers.upload += upload_start.elapsed();
+            transfers.constant_bytes += constant_bytes;
+            transfers.coset_bytes += coset_bytes;
+
+            if max_columns > 0 {
+                count(|counters| counters.kernel_launches += 1);
+                let launched = match buffers.layout {
+                    LookupLayout::Interleaved => drv_interface.launch_kernel(
+                        LOOKUP_TABLE_VALUES_KERNEL,
+                        kernel_param![
+                            "combined_data_in",
+                            "input_columns",
+                            "table_columns",
+                            "column_offsets",
+                            "theta_powers",
+                            "y_beta_gamma_one",
+                            lookup_count,
+                            max_column_count,
+                            array_size
+                        ],
+                        threads,
+                    ),
+                    LookupLayout::Split => drv_interface.launch_kernel(
+                        LOOKUP_TABLE_VALUES_SPLIT_KERNEL,
+                        kernel_param![
+                            "table_values",
+                            "input_columns",
+                            "table_columns",
+                            "column_offsets",
+                            "theta_powers",
+                            "y_beta_gamma_one",
+                            lookup_count,
+                            max_column_count,
+                            array_size
+                        ],
+                        threads,
+                    ),
+                };
+                match launched {
+                    Err(_) => return Err(driver_error(drv_interface, "kernel launch")),
+                    Ok(_) => {}
+                }
+            }
+
+            count(|counters| counters.kernel_launches += 1);
+            let launched = match buffers.layout {
+                LookupLayout::Interleaved => drv_interface.launch_kernel(
+                    LOOKUPS_KERNEL,
+                    kernel_param![
+                        "values",
+                        "combined_data_in",
+                        "product_coset",
+                        "permuted_input_coset",
+                        "permuted_table_coset",
+                        "l0",
+                        "l_active_row",
+                        "l_last",
+                        "y_beta_gamma_one",
+                        "lookup_offsets",
+                        circuit_count,
+                        array_size
+                    ],
+                    threads,
+                ),
+                LookupLayout::Split => drv_interface.launch_kernel(
+                    LOOKUPS_SPLIT_KERNEL,
+                    kernel_param![
+                        "values",
+                        "table_values",
+                        "a_minus_s",
+                        "product_coset",
+                        "permuted_input_coset",
+                        "permuted_table_coset",
+                        "l0",
+                        "l_active_row",
+                        "l_last",
+                        "y_beta_gamma_one",
+                        "lookup_offsets",
+                        circuit_count,
+                        rot_scale,
+                        array_size
+                    ],
+                    threads,
+                ),
+            };
+            match launched {
+                Err(_) => return Err(driver_error(drv_interface, "kernel launch")),
+                Ok(_) => {}
+            }
+
+            // Waits for the kernel first, for the copy to be timed alone
+            check("kernel synchronization", unsafe { cuCtxSynchronize() })?;
+            let download_start = Instant::now();
+            match drv_interface.copy_vec_to_host("values", values) {
+                Err(_) => return Err(driver_error(drv_interface, "copy to host")),
+                Ok(_) => {}
+            }
+            transfers.download += download_start.elapsed();
+            transfers.values_bytes += mem::size_of_val(&values[..]);
+
+            end_timer!(block_2_start_timer);
+
+            Ok(())
+        },
+    )
+}
+
+/// Accumulates the custom gates of a circuit into `values` with `symbol`, a
+/// kernel function registered for them, in a single launch of `threads`
+/// threads, retried following `retry` on transient errors.
+///
+/// The columns of each kind are uploaded one after the other, see
+/// `flatten_columns`, and the challenges followed by y, beta, gamma and theta.
+/// The fixed cosets stay resident in the context, so that the following
+/// proofs of the same proving key on this thread only upload the advice and
+/// instance cosets, see `upload_fixed`.
+fn launch_custom_gates<C: CurveAffine>(
+    data: &CircuitData<'_, C>,
+    symbol: &'static str,
+    threads: usize,
+    retry: RetryPolicy,
+    verbosity: Verbosity,
+    transfers: &mut Transfers,
+    values: &mut Vec<C::ScalarExt>,
+) -> Result<(), EvalError> {
+    let advice = flatten_columns(data.advice);
+    let instance = flatten_columns(data.instance);
+    let mut scalars = data.challenges.to_vec();
+    scalars.extend_from_slice(&[data.y, data.beta, data.gamma, data.theta]);
+
+    let rot_scale = data.rot_scale();
+    let array_size: i32 = data.size().try_into().unwrap();
+    let field = FieldLayout::checked::<C::ScalarExt>()?;
+
+    let coset_bytes = mem::size_of_val(&values[..])
+        + mem::size_of_val(&advice[..])
+        + mem::size_of_val(&instance[..])
+        + mem::size_of_val(&scalars[..]);
+
+    with_module_retried(
+        symbol,
+        &field,
+        verbosity,
+        retry,
+        values,
+        |drv_interface, state, values| {
+            let custom_gates_start_timer = start_timer!(|| format!("Custom gates : {}", symbol));
+
+            upload_fixed(drv_interface, state, data.pk, transfers)?;
+
+            let upload_start = Instant::now();
+            match drv_interface.add_allocations_2(
+                alloc_info_list![
+                    ("values", &*values),
+                    ("advice", &advice),
+                    ("instance", &instance),
+                    ("scalars", &scalars)
+                ],
+                alloc_info_list_2D![],
+            ) {
+                Err(_) => return Err(driver_error(drv_interface, "allocation")),
+                Ok(_) => {}
+            }
+            transfers.upload += upload_start.elapsed();
+            transfers.coset_bytes += coset_bytes;
+
+            count(|counters| counters.kernel_launches += 1);
+            match drv_interface.launch_kernel(
+                symbol,
+                kernel_param![
+                    "values", "fixed", "advice", "instance", "scalars", rot_scale, array_size
+                ],
+                threads,
+            ) {
+                Err(_) => return Err(driver_error(drv_interface, "kernel launch")),
+                Ok(_) => {}
+            }
+
+            check("kernel synchronization", unsafe { cuCtxSynchronize() })?;
+            let download_start = Instant::now();
+            match drv_interface.copy_vec_to_host("values", values) {
+                Err(_) => return Err(driver_error(drv_interface, "copy to host")),
+                Ok(_) => {}
+            }
+            transfers.download += download_start.elapsed();
+            transfers.values_bytes += mem::size_of_val(&values[..]);
+
+            end_timer!(custom_gates_start_timer);
+
+            Ok(())
+        },
+    )
+}
+
+impl CudaBackend {
+    /// Evaluates `graph` on its `size` rows, in a single kernel launch retried
+    /// following `retry` on transient errors
+    pub(in crate::plonk) fn evaluate_graph<F: FieldExt>(
+        &self,
+        graph: &DeviceGraph<F>,
+        size: usize,
+        rot_scale: i32,
+    ) -> Result<Vec<F>, EvalError> {
+        let mut values = vec![F::zero(); size];
+        if graph.calculations.is_empty() {
+            return Ok(values);
+        }
+
+        let num_calculations: i32 = graph.calculations.len().try_into().unwrap();
+        let array_size: i32 = size.try_into().unwrap();
+        // Only allocates the intermediates on the device, the kernel writes
+        // each one before reading it.
+        let intermediates = vec![F::zero(); graph.num_intermediates * size];
+        let threads = self.launch_threads(size);
+        let field = FieldLayout::checked::<F>()?;
+
+        with_module_retried(
+            EXPRESSION_KERNEL,
+            &field,
+            self.verbosity,
+            self.retry,
+            &mut values,
+            |drv_interface, _, values| {
+                let expression_start_timer = start_timer!(|| String::from("Expression : kernel"));
+
+                match drv_interface.add_allocations_2(
+                    alloc_info_list![
+                        ("values", &*values),
+                        ("intermediates", &intermediates),
+                        ("columns", &graph.columns),
+                        ("scalars", &graph.scalars),
+                        ("rotations", &graph.rotations),
+                        ("calculations", &graph.calculations)
+                    ],
+                    alloc_info_list_2D![],
+                ) {
+                    Err(_) => return Err(driver_error(drv_interface, "allocation")),
+                    Ok(_) => {}
+                }
+
+                count(|counters| counters.kernel_launches += 1);
+                match drv_interface.launch_kernel(
+                    EXPRESSION_KERNEL,
+                    kernel_param![
+                        "values",
+                        "intermediates",
+                        "columns",
+                        "scalars",
+                        "rotations",
+                        "calculations",
+                        num_calculations,
+                        rot_scale,
+                        array_size
+                    ],
+                    threads,
+                ) {
+                    Err(_) => return Err(driver_error(drv_interface, "kernel launch")),
+                    Ok(_) => {}
+                }
+
+                match drv_interface.copy_vec_to_host("values", values) {
+                    Err(_) => return Err(driver_error(drv_interface, "copy to host")),
+                    Ok(_) => {}
+                }
+
+                end_timer!(expression_start_timer);
+
+                Ok(())
+            },
+        )?;
+
+        Ok(values)
+    }
+
+    /// Returns the sum of `values[i] * r^i` over the rows, computed on the
+    /// device so that only the partial sums of its chunks are copied back, see
+    /// [`fold_chunks`].
+    pub(in crate::plonk) fn reduce_values<F: FieldExt>(
+        &self,
+        values: &[F],
+        r: F,
+    ) -> Result<F, EvalError> {
+        let partials = self.reduce_chunks(values, r)?;
+        Ok(fold_chunks(&partials, r, REDUCE_CHUNK_ROWS))
+    }
+
+    /// Returns the partial sums of the chunks of `REDUCE_CHUNK_ROWS` rows of
+    /// `values` computed on the device, those of [`reduce_chunks`] on the CPU,
+    /// the launch being retried following `retry` on transient errors
+    pub(in crate::plonk) fn reduce_chunks<F: FieldExt>(
+        &self,
+        values: &[F],
+        r: F,
+    ) -> Result<Vec<F>, EvalError> {
+        if values.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let chunk_size = REDUCE_CHUNK_ROWS;
+        let num_chunks = (values.len() + chunk_size - 1) / chunk_size;
+        let threads = self.launch_threads(num_chunks);
+        let mut partials = vec![F::zero(); num_chunks];
+        let factor = vec![r];
+        let field = FieldLayout::checked::<F>()?;
+
+        let chunk_size_param: i32 = chunk_size.try_into().unwrap();
+        let num_chunks_param: i32 = num_chunks.try_into().unwrap();
+        let array_size: i32 = values.len().try_into().unwrap();
+
+        with_module_retried(
+            REDUCE_KERNEL,
+            &field,
+            self.verbosity,
+            self.retry,
+            &mut partials,
+            |drv_interface, _, partials| {
+                let reduce_start_timer = start_timer!(|| String::from("Reduce : kernel"));
+
+                match drv_interface.add_allocations_2(
+                    alloc_info_list![("partials", &*partials), ("values", values), ("r", &factor)],
+                    alloc_info_list_2D![],
+                ) {
+                    Err(_) => return Err(driver_error(drv_interface, "allocation")),
+                    Ok(_) => {}
+                }
+
+                count(|counters| counters.kernel_launches += 1);
+                match drv_interface.launch_kernel(
+                    REDUCE_KERNEL,
+                    kernel_param![
+                        "partials",
+                        "values",
+                        "r",
+                        chunk_size_param,
+                        num_chunks_param,
+                        array_size
+                    ],
+                    threads,
+                ) {
+                    Err(_) => return Err(driver_error(drv_interface, "kernel launch")),
+                    Ok(_) => {}
+                }
+
+                match drv_interface.copy_vec_to_host("partials", partials) {
+                    Err(_) => return Err(driver_error(drv_interface, "copy to host")),
+                    Ok(_) => {}
+                }
+
+                end_timer!(reduce_start_timer);
+
+                Ok(())
+            },
+        )?;
+
+        Ok(partials)
+    }
+}
+
+/// The partial sums `REDUCE_KERNEL` computes: for each chunk of
+/// `REDUCE_CHUNK_ROWS` rows, the sum of its rows `j` times `r^j`, by Horner's
+/// rule from its last row, so that every intermediate value is the same as on
+/// the device
+pub(in crate::plonk) fn reduce_chunks<F: Field>(values: &[F], r: F) -> Vec<F> {
+    values
+        .chunks(REDUCE_CHUNK_ROWS)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .rev()
+                .fold(F::zero(), |value, row| value * r + row)
+        })
+        .collect()
+}
+
+/// Folds the partial sums of consecutive chunks of `chunk_size` rows, of the
+/// rows `j` of each chunk times `r^j`, into the sum over all the rows: the sum
+/// of `partials[c] * r^(c * chunk_size)`, by Horner's rule from the last
+/// chunk.
+pub(in crate::plonk) fn fold_chunks<F: Field>(partials: &[F], r: F, chunk_size: usize) -> F {
+    let factor = r.pow_vartime(&[chunk_size as u64]);
+    partials
+        .iter()
+        .rev()
+        .fold(F::zero(), |value, partial| value * factor + partial)
+}
+
+impl CudaBackend {
+    /// Creates the CUDA context of the current thread and loads the kernel
+    /// module by running the expression kernel on a single row, and loads it
+    /// on each of the `devices` too
+    pub(in crate::plonk) fn warmup<F: FieldExt>(&self) -> Result<(), EvalError> {
+        if !Self::is_available() {
+            return Err(EvalError::NoCudaDevice);
+        }
+        if !self.devices.is_empty() {
+            direct::warmup(&self.devices, &FieldLayout::checked::<F>()?)?;
+        }
+
+        // The graph `0 + 0`, with a rotation and a column that are never read
+        // so that no buffer is empty
+        let graph = DeviceGraph {
+            calculations: vec![DeviceCalculation {
+                op: OP_ADD,
+                target: 0,
+                a: DeviceSource::default(),
+                b: DeviceSource::default(),
+                c: DeviceSource::default(),
+            }],
+            num_intermediates: 1,
+            scalars: vec![F::zero()],
+            rotations: vec![0],
+            columns: vec![F::zero()],
+        };
+        self.evaluate_graph(&graph, 1, 1).map(|_| ())
+    }
+
+    /// Uploads the constant buffers of `pk` the launches read, `l0`, `l_last`
+    /// and `l_active_row`, and its fixed cosets if `fixed` is set, to the
+    /// context of the current thread, unless they are resident already
+    pub(in crate::plonk) fn upload_constants<C: CurveAffine>(
+        &mut self,
+        pk: &ProvingKey<C>,
+        fixed: bool,
+    ) -> Result<(), EvalError> {
+        if !Self::is_available() {
+            return Err(EvalError::NoCudaDevice);
+        }
+
+        let field = FieldLayout::checked::<C::ScalarExt>()?;
+        let transfers = &mut self.transfers;
+        with_module(
+            LOOKUPS_KERNEL,
+            &field,
+            self.verbosity,
+            |drv_interface, state| {
+                upload_lookup_constants(drv_interface, state, pk, transfers)?;
+                match fixed {
+                    true => upload_fixed(drv_interface, state, pk, transfers),
+                    false => Ok(()),
+                }
+            },
+        )
+    }
+}
+
+/// Memory of the CUDA device the evaluations run on, in bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceMemory {
+    /// The memory that is not allocated
+    pub free: usize,
+    /// The memory of the device
+    pub total: usize,
+}
+
+impl DeviceMemory {
+    /// The memory that is allocated
+    pub fn used(&self) -> usize {
+        self.total - self.free
+    }
+}
+
+/// Queries the memory of the CUDA device the evaluations run on. The counts
+/// are of the whole device, including the allocations of other processes.
+///
+/// The query runs in the primary context of the device, which is created for
+/// it if no other query holds it, so that it needs no evaluation to have run
+/// on this thread.
+pub fn cuda_device_memory() -> Result<DeviceMemory, EvalError> {
+    let mut device = 0;
+    check("driver initialization", unsafe { cuInit(0) })?;
+    check("device query", unsafe { cuDeviceGet(&mut device, 0) })?;
+
+    let mut context = std::ptr::null_mut();
+    check("memory query", unsafe {
+        cuDevicePrimaryCtxRetain(&mut context, device)
+    })?;
+    let mut memory = DeviceMemory { free: 0, total: 0 };
+    // Pushed over the context of the thread, if any, which the pop restores
+    let result = check("memory query", unsafe { cuCtxPushCurrent(context) }).and_then(|_| {
+        let result = check("memory query", unsafe {
+            cuMemGetInfo(&mut memory.free, &mut memory.total)
+        });
+        unsafe { cuCtxPopCurrent(&mut context) };
+        result
+    });
+    unsafe { cuDevicePrimaryCtxRelease(device) };
+
+    result.map(|_| memory)
+}
+
+/// Whether the CUDA device the evaluations run on supports managed memory
+/// that it pages in on demand while the host accesses it too, as
+/// [`MemoryMode::Unified`](super::MemoryMode::Unified) needs
+pub fn cuda_unified_memory_available() -> Result<bool, EvalError> {
+    let mut device = 0;
+    check("driver initialization", unsafe { cuInit(0) })?;
+    check("device query", unsafe { cuDeviceGet(&mut device, 0) })?;
+    supports_unified_memory(device)
+}
+
+/// Whether `device` supports managed memory the host accesses while the
+/// device runs, see [`cuda_unified_memory_available`]
+fn supports_unified_memory(device: c_int) -> Result<bool, EvalError> {
+    let attribute = |attribute: c_int| {
+        let mut value = 0;
+        check("device query", unsafe {
+            cuDeviceGetAttribute(&mut value, attribute, device)
+        })?;
+        Ok(value != 0)
+    };
+    Ok(attribute(CU_DEVICE_ATTRIBUTE_MANAGED_MEMORY)?
+        && attribute(CU_DEVICE_ATTRIBUTE_CONCURRENT_MANAGED_ACCESS)?)
+}
+
+/// Drops the CUDA kernel modules loaded by the current thread, along with
+/// their contexts and the constant buffers resident in them, so that the next
+/// evaluation on it creates a new context and loads and compiles its kernel
+/// again, on each device the lookups were split across too.
+pub fn clear_cuda_module_cache() {
+    MODULES.with(|modules| modules.borrow_mut().clear());
+    direct::clear_devices();
+}
+
+/// The key of a module in the caches of the thread: a hash of its contents and
+/// of the field it is checked against
+fn module_key(module: &KernelModule, field: &FieldLayout) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    module.contents().hash(&mut hasher);
+    field.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Runs `f` on a driver interface with the kernel module loaded, loading it on
+/// the first use in this thread of a module with these contents, after
+/// checking that it defines the `kernel` function `f` launches and that it is
+/// compiled for `field`. The driver wrapper is verbose if the module is loaded
+/// at the `Debug` verbosity.
+fn with_module<T>(
+    kernel: &'static str,
+    field: &FieldLayout,
+    verbosity: Verbosity,
+    f: impl FnOnce(&mut DriverInterface, &mut ModuleState) -> Result<T, EvalError>,
+) -> Result<T, EvalError> {
+    let module = KernelModule::select()?;
+    let key = module_key(&module, field);
+
+    MODULES.with(|modules| {
+        let mut modules = modules.borrow_mut();
+        if !modules.contains_key(&key) {
+            module.check_declarations()?;
+            module.check_entry_point(kernel)?;
+            module.check_entry_point(FIELD_KERNEL)?;
+            let module_load_start_timer = start_timer!(|| String::from("Lookups : Module load"));
+            let mut drv_interface = module.load(verbosity)?;
+            end_timer!(module_load_start_timer);
+            check_field(&mut drv_interface, field)?;
+            modules.insert(
+                key,
+                LoadedModule {
+                    state: ModuleState {
+                        pool: PinnedPool::new(Default::default(), 0),
+                        constants: None,
+                        fixed: None,
+                    },
+                    drv_interface,
+                },
+            );
+        }
+        let LoadedModule {
+            state,
+            drv_interface,
+        } = modules.get_mut(&key).unwrap();
+        let result = f(drv_interface, state);
+        // The interface may be left in a bad state, load the module again next time
+        if result.is_err() {
+            modules.remove(&key);
+        }
+        result
+    })
+}
+
+/// `with_module`, retrying `f` following `retry` while it fails with a
+/// transient error. `with_module` drops the failed driver interface, so each
+/// attempt initializes a new one, and `values`, which `f` uploads and copies
+/// back, are restored to what the first attempt started from.
+fn with_module_retried<F: Clone, T>(
+    kernel: &'static str,
+    field: &FieldLayout,
+    verbosity: Verbosity,
+    retry: RetryPolicy,
+    values: &mut Vec<F>,
+    mut f: impl FnMut(&mut DriverInterface, &mut ModuleState, &mut Vec<F>) -> Result<T, EvalError>,
+) -> Result<T, EvalError> {
+    // The values the kernel starts from, to restore them before a retry
+    let initial_values = match retry.max_retries {
+        0 => None,
+        _ => Some(values.clone()),
+    };
+    let mut attempt = 0;
+    loop {
+        let result = with_module(kernel, field, verbosity, |drv_interface, state| {
+            f(drv_interface, state, values)
+        });
+        match result {
+            Err(err) if attempt < retry.max_retries && is_transient(&err) => {
+                let backoff = retry.backoff(attempt);
+                tracing::warn!("CUDA evaluation failed: {}, retrying in {:?}", err, backoff);
+                thread::sleep(backoff);
+                values.clone_from(initial_values.as_ref().unwrap());
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Checks that the loaded module is compiled for `field`, with the
+/// `check_field` kernel
+fn check_field(drv_interface: &mut DriverInterface, field: &FieldLayout) -> Result<(), EvalError> {
+    let mut result = vec![0i32];
+    let field_bytes: i32 = field.bytes.try_into().unwrap();
+
+    match drv_interface.add_allocations_2(
+        alloc_info_list![("result", &result), ("modulus", &field.modulus)],
+        alloc_info_list_2D![],
+    ) {
+        Err(_) => return Err(driver_error(drv_interface, "allocation")),
+        Ok(_) => {}
+    }
+
+    count(|counters| counters.kernel_launches += 1);
+    match drv_interface.launch_kernel(
+        FIELD_KERNEL,
+        kernel_param!["result", "modulus", field_bytes],
+        1,
+    ) {
+        Err(_) => return Err(driver_error(drv_interface, "kernel launch")),
+        Ok(_) => {}
+    }
+
+    match drv_interface.copy_vec_to_host("result", &mut result) {
+        Err(_) => return Err(driver_error(drv_interface, "copy to host")),
+        Ok(_) => {}
+    }
+
+    match result[0] {
+        0 => Err(EvalError::FieldMismatch { bytes: field.bytes }),
+        _ => Ok(()),
+    }
+}
+
+/// A kernel module to load
+enum KernelModule {
+    /// The module at a path, with its contents
+    File(String, Vec<u8>),
+    /// The modules at several paths, with their contents, linked into one
+    Linked(Vec<(String, Vec<u8>)>),
+    /// An embedded module, with the extension of its format
+    Embedded(&'static [u8], &'static str),
+}
+
+impl KernelModule {
+    /// Selects the kernel module to load. A path set in `CU_KERNEL` overrides
+    /// the embedded kernels, but is required without one, and several paths,
+    /// separated as in `PATH`, are linked together. The embedded cubin is
+    /// preferred when the device can run it, and the PTX is JIT compiled
+    /// otherwise.
+    fn select() -> Result<Self, EvalError> {
+        if let Ok(paths) = env::var("CU_KERNEL") {
+            let mut files = env::split_paths(&paths)
+                .map(|path| {
+                    let contents = fs::read(&path).map_err(|_| EvalError::Driver("module load"))?;
+                    Ok((path.to_string_lossy().into_owned(), contents))
+                })
+                .collect::<Result<Vec<_>, EvalError>>()?;
+            return Ok(match files.len() {
+                1 => {
+                    let (path, contents) = files.pop().unwrap();
+                    KernelModule::File(path, contents)
+                }
+                _ => KernelModule::Linked(files),
+            });
+        }
+
+        let cubin = EMBEDDED_CUBIN.filter(|(_, arch)| {
+            let runs = DeviceProperties::cached().map_or(false, |device| device.runs_cubin(arch));
+            if !runs {
+                tracing::info!("CUDA device cannot run the {} cubin, using the PTX", arch);
+            }
+            runs
+        });
+        match (cubin, EMBEDDED_KERNEL) {
+            (Some((cubin, _)), _) => Ok(KernelModule::Embedded(cubin, "cubin")),
+            (None, Some(ptx)) => Ok(KernelModule::Embedded(ptx.as_bytes(), "ptx")),
+            (None, None) => Err(EvalError::MissingKernelPath("CU_KERNEL")),
+        }
+    }
+
+    /// Checks that one of the modules defines the `name` kernel function. The
+    /// entry points of PTX modules are listed in the error, while a cubin is
+    /// only searched for the name.
+    fn check_entry_point(&self, name: &'static str) -> Result<(), EvalError> {
+        let mut entry_points = vec![];
+        for contents in self.contents() {
+            let defined = match std::str::from_utf8(contents) {
+                Ok(ptx) => {
+                    let defined = ptx_entry_points(ptx);
+                    let found = defined.iter().any(|entry_point| entry_point == name);
+                    entry_points.extend(defined);
+                    found
+                }
+                Err(_) => contents
+                    .windows(name.len())
+                    .any(|window| window == name.as_bytes()),
+            };
+            if defined {
+                return Ok(());
+            }
+        }
+
+        Err(EvalError::MissingKernelSymbol {
+            name,
+            available: entry_points,
+        })
+    }
+
+    /// Checks that the functions the linked modules declare are defined by
+    /// one of them, when they are all PTX. The linker reports the symbols it
+    /// cannot resolve in the other modules.
+    fn check_declarations(&self) -> Result<(), EvalError> {
+        let files = match self {
+            KernelModule::Linked(files) => files,
+            _ => return Ok(()),
+        };
+        let ptx = files
+            .iter()
+            .map(|(path, contents)| Some((path.as_str(), std::str::from_utf8(contents).ok()?)))
+            .collect::<Option<Vec<_>>>();
+        match ptx {
+            Some(ptx) => check_ptx_declarations(&ptx),
+            None => Ok(()),
+        }
+    }
+
+    /// The contents of each module
+    fn contents(&self) -> Vec<&[u8]> {
+        match self {
+            KernelModule::File(_, contents) => vec![contents],
+            KernelModule::Linked(files) => files
+                .iter()
+                .map(|(_, contents)| contents.as_slice())
+                .collect(),
+            KernelModule::Embedded(contents, _) => vec![contents],
+        }
+    }
+
+    /// Loads the module into a new driver interface, which logs its calls at
+    /// the `Debug` verbosity
+    fn load(&self, verbosity: Verbosity) -> Result<DriverInterface, EvalError> {
+        let mut drv_interface = match self {
+            KernelModule::File(path, _) => DriverInterface::new(ModuleSource::FILE(path.clone())),
+            KernelModule::Linked(files) => load_extracted(&link_modules(files)?, "cubin")?,
+            KernelModule::Embedded(contents, extension) => load_extracted(contents, extension)?,
+        };
+
+        // The wrapper only has a verbose mode, and is quiet otherwise
+        if verbosity >= Verbosity::Debug {
+            drv_interface.high_verbosity();
+        }
+
+        if drv_interface.error_occured() {
+            return Err(driver_error(&mut drv_interface, "module load"));
+        }
+        count(|counters| counters.module_loads += 1);
+
+        Ok(drv_interface)
+    }
+}
+
+/// Loads a module from a temporary file with its `contents`, as the driver
+/// wrapper only loads modules from files
+fn load_extracted(contents: &[u8], extension: &str) -> Result<DriverInterface, EvalError> {
+    let path = env::temp_dir().join(format!(
+        "halo2-evaluate-h-{}-{}.{}",
+        process::id(),
+        EXTRACTIONS.fetch_add(1, Ordering::Relaxed),
+        extension
+    ));
+    fs::write(&path, contents).map_err(|_| EvalError::Driver("kernel extraction"))?;
+    let drv_interface =
+        DriverInterface::new(ModuleSource::FILE(path.to_string_lossy().into_owned()));
+    // The module is loaded, the extracted copy is not needed anymore
+    let _ = fs::remove_file(&path);
+    Ok(drv_interface)
+}
+
+/// Links the modules of `files` into a cubin for the device the evaluations
+/// run on, in its primary context. The error of a failed link ends with the
+/// log of the linker, which names the symbols it could not resolve.
+fn link_modules(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, EvalError> {
+    let mut device = 0;
+    check("driver initialization", unsafe { cuInit(0) })?;
+    check("device query", unsafe { cuDeviceGet(&mut device, 0) })?;
+
+    let mut context = std::ptr::null_mut();
+    check("module link", unsafe {
+        cuDevicePrimaryCtxRetain(&mut context, device)
+    })?;
+    // Pushed over the context of the thread, if any, which the pop restores
+    let result = check("module link", unsafe { cuCtxPushCurrent(context) }).and_then(|_| {
+        let result = link_in_current_context(files);
+        unsafe { cuCtxPopCurrent(&mut context) };
+        result
+    });
+    unsafe { cuDevicePrimaryCtxRelease(device) };
+
+    result
+}
+
+fn link_in_current_context(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, EvalError> {
+    let mut log = vec![0u8; LINK_LOG_BYTES];
+    let mut options = [CU_JIT_ERROR_LOG_BUFFER, CU_JIT_ERROR_LOG_BUFFER_SIZE_BYTES];
+    // The size is passed as the value of the pointer
+    let mut option_values = [
+        log.as_mut_ptr() as *mut c_void,
+        LINK_LOG_BYTES as *mut c_void,
+    ];
+    let mut state = std::ptr::null_mut();
+    check("module link", unsafe {
+        cuLinkCreate(
+            options.len() as c_uint,
+            options.as_mut_ptr(),
+            option_values.as_mut_ptr(),
+            &mut state,
+        )
+    })?;
+
+    let mut code = 0;
+    for (path, contents) in files {
+        let input_type = jit_input_type(path);
+        let mut data = contents.clone();
+        // The PTX is read up to its terminating nul
+        if input_type == CU_JIT_INPUT_PTX {
+            data.push(0);
+        }
+        let name = CString::new(path.as_str()).unwrap_or_default();
+        code = unsafe {
+            cuLinkAddData(
+                state,
+                input_type,
+                data.as_mut_ptr() as *mut c_void,
+                data.len(),
+                name.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if code != 0 {
+            break;
+        }
+    }
+    let mut cubin = vec![];
+    if code == 0 {
+        let mut image = std::ptr::null_mut();
+        let mut size = 0;
+        code = unsafe { cuLinkComplete(state, &mut image, &mut size) };
+        if code == 0 {
+            // The image belongs to the link state, copied before it is destroyed
+            cubin = unsafe { std::slice::from_raw_parts(image as *const u8, size) }.to_vec();
+        }
+    }
+    unsafe { cuLinkDestroy(state) };
+
+    match check("module link", code) {
+        Ok(()) => Ok(cubin),
+        Err(EvalError::Cuda {
+            operation,
+            code,
+            message,
+        }) => {
+            let end = log.iter().position(|&byte| byte == 0).unwrap_or(log.len());
+            Err(EvalError::Cuda {
+                operation,
+                code,
+                message: format!("{}: {}", message, String::from_utf8_lossy(&log[..end])),
+            })
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// The linker input type of a module, from the extension of its path, PTX
+/// being the default
+fn jit_input_type(path: &str) -> c_uint {
+    match std::path::Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("cubin") => CU_JIT_INPUT_CUBIN,
+        Some("fatbin") => CU_JIT_INPUT_FATBINARY,
+        Some("o") => CU_JIT_INPUT_OBJECT,
+        Some("a") => CU_JIT_INPUT_LIBRARY,
+        _ => CU_JIT_INPUT_PTX,
+    }
+}
+
+/// Checks that the functions each PTX module of `files`, given by path,
+/// declares `.extern` are defined by one of the modules
+pub(in crate::plonk) fn check_ptx_declarations(files: &[(&str, &str)]) -> Result<(), EvalError> {
+    let functions: Vec<_> = files.iter().map(|(_, ptx)| ptx_functions(ptx)).collect();
+    for ((path, _), (_, declared)) in files.iter().zip(functions.iter()) {
+        for name in declared {
+            if !functions.iter().any(|(defined, _)| defined.contains(name)) {
+                return Err(EvalError::UnresolvedKernelSymbol {
+                    name: name.clone(),
+                    file: path.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The functions, kernels included, a PTX module defines, and the functions it
+/// declares `.extern`
+fn ptx_functions(ptx: &str) -> (Vec<String>, Vec<String>) {
+    let mut defined = ptx_entry_points(ptx);
+    let mut declared = vec![];
+    for (start, _) in ptx.match_indices(".func") {
+        // The directives of the statement, back to the end of the previous one
+        let statement_start = ptx[..start]
+            .rfind(|c: char| c == ';' || c == '{' || c == '}')
+            .map_or(0, |idx| idx + 1);
+        let is_extern = ptx[statement_start..start].contains(".extern");
+
+        // The name follows the return parameters, if any
+        let mut rest = ptx[start + ".func".len()..].trim_start();
+        if rest.starts_with('(') {
+            rest = rest[rest.find(')').map_or(rest.len(), |idx| idx + 1)..].trim_start();
+        }
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+            .collect();
+        if name.is_empty() {
+            continue;
+        }
+        match is_extern {
+            true => declared.push(name),
+            false => defined.push(name),
+        }
+    }
+    (defined, declared)
+}
+
+/// The names of the kernel functions, the `.entry` directives, of a PTX module
+pub(in crate::plonk) fn ptx_entry_points(ptx: &str) -> Vec<String> {
+    let mut tokens = ptx.split(|c: char| c.is_whitespace() || c == '(');
+    let mut entry_points = vec![];
+    while let Some(token) = tokens.next() {
+        if token == ".entry" {
+            if let Some(name) = tokens.find(|token| !token.is_empty()) {
+                entry_points.push(name.to_string());
+            }
+        }
+    }
+    entry_points
+}
@@ -0,0 +1,758 @@
+//! The lookups evaluated across several CUDA devices, through the driver API
+//! rather than the driver wrapper, which only opens the first device.
+//!
+//! The rows of the extended domain are split into a slice per device of
+//! `EvaluatorConfig::devices`. Each device evaluates a window of the rows of
+//! its slice and of the rows the rotations of the lookups reach around it,
+//! see `LookupBuffers::window`, in its primary context with the kernel module
+//! loaded in it. The launches do not wait for the kernels, so that the devices
+//! run their windows while the next ones are uploaded, and the rows of each
+//! slice are copied back into place once every window is done.
+//!
+//! With `MemoryMode::Unified`, the buffers of the launches are allocated as
+//! managed memory instead, which the host writes and reads where it is, the
+//! devices paging it in as their kernels read it, so that nothing is copied
+//! to the devices and back by the driver. The devices must let the host
+//! access managed memory while they run, see `supports_unified_memory`.
+
+use super::{
+    check, count, cuCtxPopCurrent, cuCtxPushCurrent, cuCtxSynchronize, cuDeviceGet,
+    cuDevicePrimaryCtxRelease, cuDevicePrimaryCtxRetain, cuInit, cuda_error, is_transient,
+    jit_input_type, link_in_current_context, module_key, supports_unified_memory, ConstantsKey,
+    CudaBackend, DeviceProperties, KernelModule, CU_JIT_INPUT_PTX, FIELD_KERNEL, LOOKUPS_KERNEL,
+    LOOKUPS_SPLIT_KERNEL, LOOKUP_TABLE_VALUES_KERNEL, LOOKUP_TABLE_VALUES_SPLIT_KERNEL,
+};
+use crate::plonk::evaluation::device::{row_slices, window_rows, FieldLayout};
+use crate::plonk::evaluation::{
+    CircuitData, EvalError, LookupBuffers, LookupLayout, MemoryMode, Transfers,
+};
+use crate::{arithmetic::CurveAffine, plonk::ProvingKey};
+use group::ff::Field;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    convert::TryInto,
+    ffi::CString,
+    iter, mem,
+    ops::Range,
+    os::raw::{c_char, c_int, c_uint, c_void},
+    ptr, thread,
+    time::Instant,
+};
+
+/// `CUresult` of a kernel function the module does not define
+const CUDA_ERROR_NOT_FOUND: c_int = 500;
+/// `CUresult` of an operation the device does not support
+const CUDA_ERROR_NOT_SUPPORTED: c_int = 801;
+/// `CUmemAttach_flags` of managed memory any stream of any device accesses
+const CU_MEM_ATTACH_GLOBAL: c_uint = 1;
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cuModuleLoadData(module: *mut *mut c_void, image: *const c_void) -> c_int;
+    fn cuModuleUnload(module: *mut c_void) -> c_int;
+    fn cuModuleGetFunction(
+        function: *mut *mut c_void,
+        module: *mut c_void,
+        name: *const c_char,
+    ) -> c_int;
+    #[link_name = "cuMemAlloc_v2"]
+    fn cuMemAlloc(pointer: *mut u64, bytes: usize) -> c_int;
+    fn cuMemAllocManaged(pointer: *mut u64, bytes: usize, flags: c_uint) -> c_int;
+    #[link_name = "cuMemFree_v2"]
+    fn cuMemFree(pointer: u64) -> c_int;
+    #[link_name = "cuMemcpyHtoD_v2"]
+    fn cuMemcpyHtoD(device: u64, host: *const c_void, bytes: usize) -> c_int;
+    #[link_name = "cuMemcpyDtoH_v2"]
+    fn cuMemcpyDtoH(host: *mut c_void, device: u64, bytes: usize) -> c_int;
+    fn cuLaunchKernel(
+        function: *mut c_void,
+        grid_x: c_uint,
+        grid_y: c_uint,
+        grid_z: c_uint,
+        block_x: c_uint,
+        block_y: c_uint,
+        block_z: c_uint,
+        shared_bytes: c_uint,
+        stream: *mut c_void,
+        params: *mut *mut c_void,
+        extra: *mut *mut c_void,
+    ) -> c_int;
+}
+
+thread_local! {
+    /// The devices this thread evaluated on, keyed by the key of their module,
+    /// see `module_key`, and by their ordinal, each with its context and the
+    /// module loaded in it. Like the modules of the driver wrapper, they are
+    /// torn down when the thread exits or by `clear_cuda_module_cache`.
+    static DEVICES: RefCell<HashMap<(u64, usize), DeviceContext>> = RefCell::new(HashMap::new());
+}
+
+/// Drops the devices of the current thread, see `DEVICES`
+pub(super) fn clear_devices() {
+    DEVICES.with(|devices| devices.borrow_mut().clear());
+}
+
+/// An argument of a kernel launch
+#[derive(Clone, Copy)]
+enum KernelArg {
+    /// A device buffer, null for those the kernel does not read
+    Buffer(u64),
+    Int(i32),
+}
+
+/// The primary context of a device, retained by this thread, with the kernel
+/// module loaded in it and the buffers allocated in it
+struct DeviceContext {
+    device: c_int,
+    context: *mut c_void,
+    module: *mut c_void,
+    /// Whether the device supports the unified memory `memory_mode` may ask
+    /// for, see `supports_unified_memory`
+    unified_memory: bool,
+    /// How the buffers of the next launches are allocated
+    memory_mode: MemoryMode,
+    /// The buffers of `l0`, `l_active_row` and `l_last` on the windows of the
+    /// domains they were uploaded for, kept for the next launches on the same
+    /// window until a launch on another domain replaces them
+    constants: Vec<(ConstantsKey, Range<usize>, [u64; 3])>,
+    /// The other buffers of the launches in flight, freed once their values
+    /// are copied back
+    launch_buffers: Vec<u64>,
+}
+
+/// Runs `f` with `context` current, pushed over the context of the thread, if
+/// any, which the pop restores
+fn in_context<T>(
+    context: *mut c_void,
+    f: impl FnOnce() -> Result<T, EvalError>,
+) -> Result<T, EvalError> {
+    check("context push", unsafe { cuCtxPushCurrent(context) })?;
+    let result = f();
+    let mut popped = ptr::null_mut();
+    unsafe { cuCtxPopCurrent(&mut popped) };
+    result
+}
+
+impl KernelModule {
+    /// The image of the module `cuModuleLoadData` loads, the PTX being read up
+    /// to its terminating nul and the linked modules being linked in the
+    /// current context
+    fn image(&self) -> Result<Vec<u8>, EvalError> {
+        let (mut image, is_ptx) = match self {
+            KernelModule::File(path, contents) => {
+                (contents.clone(), jit_input_type(path) == CU_JIT_INPUT_PTX)
+            }
+            KernelModule::Linked(files) => (link_in_current_context(files)?, false),
+            KernelModule::Embedded(contents, extension) => (contents.to_vec(), *extension == "ptx"),
+        };
+        if is_ptx {
+            image.push(0);
+        }
+        Ok(image)
+    }
+}
+
+impl DeviceContext {
+    /// Retains the primary context of the device `ordinal`, loads `module` in
+    /// it and checks that it is compiled for `field`
+    fn load(ordinal: usize, module: &KernelModule, field: &FieldLayout) -> Result<Self, EvalError> {
+        let mut device = 0;
+        check("driver initialization", unsafe { cuInit(0) })?;
+        check("device query", unsafe {
+            cuDeviceGet(&mut device, ordinal.try_into().unwrap())
+        })?;
+        let unified_memory = supports_unified_memory(device)?;
+        let mut context = ptr::null_mut();
+        check("context creation", unsafe {
+            cuDevicePrimaryCtxRetain(&mut context, device)
+        })?;
+        // Released when dropped from here on, whatever fails next
+        let mut loaded = DeviceContext {
+            device,
+            context,
+            module: ptr::null_mut(),
+            unified_memory,
+            memory_mode: MemoryMode::Explicit,
+            constants: vec![],
+            launch_buffers: vec![],
+        };
+
+        loaded.module = in_context(context, || {
+            let image = module.image()?;
+            let mut handle = ptr::null_mut();
+            check("module load", unsafe {
+                cuModuleLoadData(&mut handle, image.as_ptr() as *const c_void)
+            })?;
+            Ok(handle)
+        })?;
+        count(|counters| counters.module_loads += 1);
+
+        loaded.check_field(field)?;
+        Ok(loaded)
+    }
+
+    /// Checks that the loaded module is compiled for `field`, with the
+    /// `check_field` kernel
+    fn check_field(&mut self, field: &FieldLayout) -> Result<(), EvalError> {
+        let field_bytes: i32 = field.bytes.try_into().unwrap();
+        let context = self.context;
+        let result = in_context(context, || {
+            let result = self.upload(&[0i32])?;
+            let modulus = self.upload(&field.modulus)?;
+            self.launch(
+                FIELD_KERNEL,
+                &[
+                    KernelArg::Buffer(result),
+                    KernelArg::Buffer(modulus),
+                    KernelArg::Int(field_bytes),
+                ],
+                1,
+            )?;
+            check("kernel synchronization", unsafe { cuCtxSynchronize() })?;
+            let mut value = [0i32];
+            self.download(result, &mut value)?;
+            Ok(value[0])
+        });
+        self.free_launches();
+
+        match result? {
+            0 => Err(EvalError::FieldMismatch { bytes: field.bytes }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Allocates `bytes` bytes on the device, or as managed memory in the
+    /// unified mode. The context must be current.
+    fn alloc(&self, bytes: usize) -> Result<u64, EvalError> {
+        let mut pointer = 0;
+        match self.memory_mode {
+            MemoryMode::Explicit => {
+                check("allocation", unsafe { cuMemAlloc(&mut pointer, bytes) })?
+            }
+            MemoryMode::Unified if !self.unified_memory => {
+                return Err(cuda_error("managed allocation", CUDA_ERROR_NOT_SUPPORTED))
+            }
+            MemoryMode::Unified => check("managed allocation", unsafe {
+                cuMemAllocManaged(&mut pointer, bytes, CU_MEM_ATTACH_GLOBAL)
+            })?,
+        }
+        Ok(pointer)
+    }
+
+    /// Copies `data` to a new buffer of the launches in flight, or to none, a
+    /// null pointer, if it is empty. The context must be current.
+    fn upload<T: Copy>(&mut self, data: &[T]) -> Result<u64, EvalError> {
+        let bytes = mem::size_of_val(data);
+        if bytes == 0 {
+            return Ok(0);
+        }
+        let pointer = self.alloc(bytes)?;
+        self.launch_buffers.push(pointer);
+        match self.memory_mode {
+            MemoryMode::Explicit => check("copy to device", unsafe {
+                cuMemcpyHtoD(pointer, data.as_ptr() as *const c_void, bytes)
+            })?,
+            // Written where the device pages it in from
+            MemoryMode::Unified => unsafe {
+                ptr::copy_nonoverlapping(data.as_ptr() as *const u8, pointer as *mut u8, bytes)
+            },
+        }
+        Ok(pointer)
+    }
+
+    /// Copies the buffer at `pointer` to `data`, which it fills. The context
+    /// must be current, and the kernels writing the buffer done.
+    fn download<T: Copy>(&self, pointer: u64, data: &mut [T]) -> Result<(), EvalError> {
+        let bytes = mem::size_of_val(data);
+        match self.memory_mode {
+            MemoryMode::Explicit => check("copy to host", unsafe {
+                cuMemcpyDtoH(data.as_mut_ptr() as *mut c_void, pointer, bytes)
+            }),
+            // Read where the device wrote it
+            MemoryMode::Unified => {
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        pointer as *const u8,
+                        data.as_mut_ptr() as *mut u8,
+                        bytes,
+                    )
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Frees the buffers of the launches in flight, waiting for them
+    fn free_launches(&mut self) {
+        let buffers = mem::take(&mut self.launch_buffers);
+        let _ = in_context(self.context, || {
+            for pointer in buffers {
+                unsafe { cuMemFree(pointer) };
+            }
+            Ok(())
+        });
+    }
+
+    /// Launches the kernel `name` over `threads` threads with `args`, without
+    /// waiting for it. The context must be current.
+    fn launch(
+        &self,
+        name: &'static str,
+        args: &[KernelArg],
+        threads: usize,
+    ) -> Result<(), EvalError> {
+        let symbol = CString::new(name).unwrap();
+        let mut function = ptr::null_mut();
+        match unsafe { cuModuleGetFunction(&mut function, self.module, symbol.as_ptr()) } {
+            0 => {}
+            CUDA_ERROR_NOT_FOUND => {
+                return Err(EvalError::MissingKernelSymbol {
+                    name,
+                    available: vec![],
+                })
+            }
+            code => return Err(cuda_error("kernel launch", code)),
+        }
+
+        // The driver reads each argument from where its pointer points
+        let mut args = args.to_vec();
+        let mut params: Vec<*mut c_void> = args
+            .iter_mut()
+            .map(|arg| match arg {
+                KernelArg::Buffer(pointer) => pointer as *mut u64 as *mut c_void,
+                KernelArg::Int(value) => value as *mut i32 as *mut c_void,
+            })
+            .collect();
+        let max_block =
+            DeviceProperties::cached().map_or(256, |device| device.max_threads_per_block);
+        let block = threads.clamp(1, max_block.max(1));
+        let grid = (threads + block - 1) / block;
+
+        count(|counters| counters.kernel_launches += 1);
+        check("kernel launch", unsafe {
+            cuLaunchKernel(
+                function,
+                grid.try_into().unwrap(),
+                1,
+                1,
+                block.try_into().unwrap(),
+                1,
+                1,
+                0,
+                ptr::null_mut(),
+                params.as_mut_ptr(),
+                ptr::null_mut(),
+            )
+        })
+    }
+
+    /// The buffers of `l0`, `l_active_row` and `l_last` of `pk` on the window
+    /// of `rows` and of the `halo` rows around them, uploaded unless they are
+    /// resident already. The context must be current.
+    fn constants<C: CurveAffine>(
+        &mut self,
+        pk: &ProvingKey<C>,
+        rows: &Range<usize>,
+        halo: usize,
+        transfers: &mut Transfers,
+    ) -> Result<[u64; 3], EvalError> {
+        let key = ConstantsKey::of(pk);
+        if let Some((_, _, buffers)) = self
+            .constants
+            .iter()
+            .find(|(resident, resident_rows, _)| *resident == key && resident_rows == rows)
+        {
+            return Ok(*buffers);
+        }
+        // Those of another domain are not read again
+        for (_, _, buffers) in self
+            .constants
+            .iter()
+            .filter(|(resident, _, _)| *resident != key)
+        {
+            for pointer in buffers.iter() {
+                unsafe { cuMemFree(*pointer) };
+            }
+        }
+        self.constants.retain(|(resident, _, _)| *resident == key);
+
+        let upload_start = Instant::now();
+        let size = pk.vk.domain.extended_len();
+        let first = self.launch_buffers.len();
+        for constant in [&pk.l0, &pk.l_active_row, &pk.l_last] {
+            let window = window_rows(&constant.values, size, rows.clone(), halo);
+            self.upload(&window)?;
+            transfers.constant_bytes += mem::size_of_val(&window[..]);
+        }
+        // Kept past the launch once all three are uploaded
+        let uploaded = self.launch_buffers.split_off(first);
+        let buffers = [uploaded[0], uploaded[1], uploaded[2]];
+        self.constants.push((key, rows.clone(), buffers));
+        count(|counters| counters.constant_uploads += 1);
+        transfers.upload += upload_start.elapsed();
+        Ok(buffers)
+    }
+
+    /// Uploads the window of `rows` of `buffers` and `values`, with the `halo`
+    /// rows around them, and launches the lookup kernels on it over `threads`
+    /// threads, without waiting for them. Returns the buffer the values of the
+    /// window are accumulated into.
+    fn launch_lookups<C: CurveAffine>(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        buffers: &LookupBuffers<C::ScalarExt>,
+        lookup_offsets: &[u64],
+        values: &[C::ScalarExt],
+        rows: &Range<usize>,
+        halo: usize,
+        threads: usize,
+        transfers: &mut Transfers,
+    ) -> Result<u64, EvalError> {
+        use KernelArg::{Buffer, Int};
+
+        let size = data.size();
+        let one = C::ScalarExt::one();
+        let window = buffers.window(size, rows.clone(), halo);
+        let window_values = window_rows(values, size, rows.clone(), halo);
+
+        let circuit_count: i32 = (lookup_offsets.len() - 1).try_into().unwrap();
+        let array_size: i32 = (rows.len() + 2 * halo).try_into().unwrap();
+        let rot_scale: i32 = halo.try_into().unwrap();
+        let y_beta_gamma_one = [data.y, data.beta, data.gamma, one];
+        let max_columns = window.max_device_columns();
+        let theta_powers: Vec<C::ScalarExt> =
+            iter::successors(Some(one), |power| Some(*power * data.theta))
+                .take(max_columns.max(1))
+                .collect();
+        let lookup_count: i32 = (window.column_offsets.len() - 1).try_into().unwrap();
+        let max_column_count: i32 = max_columns.try_into().unwrap();
+        let constant_bytes = mem::size_of_val(&y_beta_gamma_one);
+        let coset_bytes = mem::size_of_val(&window_values[..])
+            + mem::size_of_val(lookup_offsets)
+            + mem::size_of_val(&window.combined_data_in[..])
+            + mem::size_of_val(&window.table_values[..])
+            + mem::size_of_val(&window.a_minus_s[..])
+            + mem::size_of_val(&window.product_coset[..])
+            + mem::size_of_val(&window.permuted_input_coset[..])
+            + mem::size_of_val(&window.permuted_table_coset[..])
+            + mem::size_of_val(&window.input_columns[..])
+            + mem::size_of_val(&window.table_columns[..])
+            + mem::size_of_val(&window.column_offsets[..])
+            + mem::size_of_val(&theta_powers[..]);
+
+        let context = self.context;
+        in_context(context, || {
+            let [l0, l_active_row, l_last] = self.constants(data.pk, rows, halo, transfers)?;
+
+            let upload_start = Instant::now();
+            let values = self.upload(&window_values)?;
+            let y_beta_gamma_one = self.upload(&y_beta_gamma_one)?;
+            let lookup_offsets = self.upload(lookup_offsets)?;
+            let combined_data_in = self.upload(&window.combined_data_in)?;
+            let table_values = self.upload(&window.table_values)?;
+            let a_minus_s = self.upload(&window.a_minus_s)?;
+            let product_coset = self.upload(&window.product_coset)?;
+            let permuted_input_coset = self.upload(&window.permuted_input_coset)?;
+            let permuted_table_coset = self.upload(&window.permuted_table_coset)?;
+            let input_columns = self.upload(&window.input_columns)?;
+            let table_columns = self.upload(&window.table_columns)?;
+            let column_offsets = self.upload(&window.column_offsets)?;
+            let theta_powers_buffer = self.upload(&theta_powers)?;
+            transfers.upload += upload_start.elapsed();
+            transfers.constant_bytes += constant_bytes;
+            transfers.coset_bytes += coset_bytes;
+
+            if max_columns > 0 {
+                let (kernel, rows_buffer) = match window.layout {
+                    LookupLayout::Interleaved => (LOOKUP_TABLE_VALUES_KERNEL, combined_data_in),
+                    LookupLayout::Split => (LOOKUP_TABLE_VALUES_SPLIT_KERNEL, table_values),
+                };
+                self.launch(
+                    kernel,
+                    &[
+                        Buffer(rows_buffer),
+                        Buffer(input_columns),
+                        Buffer(table_columns),
+                        Buffer(column_offsets),
+                        Buffer(theta_powers_buffer),
+                        Buffer(y_beta_gamma_one),
+                        Int(lookup_count),
+                        Int(max_column_count),
+                        Int(array_size),
+                    ],
+                    threads,
+                )?;
+            }
+
+            match window.layout {
+                LookupLayout::Interleaved => self.launch(
+                    LOOKUPS_KERNEL,
+                    &[
+                        Buffer(values),
+                        Buffer(combined_data_in),
+                        Buffer(product_coset),
+                        Buffer(permuted_input_coset),
+                        Buffer(permuted_table_coset),
+                        Buffer(l0),
+                        Buffer(l_active_row),
+                        Buffer(l_last),
+                        Buffer(y_beta_gamma_one),
+                        Buffer(lookup_offsets),
+                        Int(circuit_count),
+                        Int(array_size),
+                    ],
+                    threads,
+                )?,
+                LookupLayout::Split => self.launch(
+                    LOOKUPS_SPLIT_KERNEL,
+                    &[
+                        Buffer(values),
+                        Buffer(table_values),
+                        Buffer(a_minus_s),
+                        Buffer(product_coset),
+                        Buffer(permuted_input_coset),
+                        Buffer(permuted_table_coset),
+                        Buffer(l0),
+                        Buffer(l_active_row),
+                        Buffer(l_last),
+                        Buffer(y_beta_gamma_one),
+                        Buffer(lookup_offsets),
+                        Int(circuit_count),
+                        Int(rot_scale),
+                        Int(array_size),
+                    ],
+                    threads,
+                )?,
+            }
+
+            Ok(values)
+        })
+    }
+
+    /// Waits for the launches in flight and copies the values of a window
+    /// from `pointer` to `window`
+    fn finish<F: Copy>(
+        &self,
+        pointer: u64,
+        window: &mut [F],
+        transfers: &mut Transfers,
+    ) -> Result<(), EvalError> {
+        in_context(self.context, || {
+            // Waits for the kernels first, for the copy to be timed alone
+            check("kernel synchronization", unsafe { cuCtxSynchronize() })?;
+            let download_start = Instant::now();
+            self.download(pointer, window)?;
+            transfers.download += download_start.elapsed();
+            transfers.values_bytes += mem::size_of_val(window);
+            Ok(())
+        })
+    }
+}
+
+impl Drop for DeviceContext {
+    fn drop(&mut self) {
+        self.free_launches();
+        let constants = mem::take(&mut self.constants);
+        let module = self.module;
+        let _ = in_context(self.context, || {
+            for (_, _, buffers) in constants {
+                for pointer in buffers.iter() {
+                    unsafe { cuMemFree(*pointer) };
+                }
+            }
+            if !module.is_null() {
+                unsafe { cuModuleUnload(module) };
+            }
+            Ok(())
+        });
+        unsafe { cuDevicePrimaryCtxRelease(self.device) };
+    }
+}
+
+/// Accumulates the lookups described by `buffers` into `values`, as
+/// `launch_lookups` does, with the rows split across the devices of
+/// `backend.devices`, or on the first device if none are listed, in the
+/// memory of `backend.memory_mode`, retried following `backend.retry` on
+/// transient errors. The same ordinal may be listed more than once, its
+/// windows then running on the same device one after the other.
+///
+/// `values` is only written once every window is done, so that a retry starts
+/// from what the first attempt did, on new contexts.
+pub(super) fn launch_lookups_across_devices<C: CurveAffine>(
+    backend: &mut CudaBackend,
+    data: &CircuitData<'_, C>,
+    buffers: &LookupBuffers<C::ScalarExt>,
+    lookup_offsets: &[u64],
+    values: &mut [C::ScalarExt],
+) -> Result<(), EvalError> {
+    let mut attempt = 0;
+    loop {
+        match launch_on_devices(backend, data, buffers, lookup_offsets, values) {
+            Err(err) if attempt < backend.retry.max_retries && is_transient(&err) => {
+                let backoff = backend.retry.backoff(attempt);
+                tracing::warn!("CUDA evaluation failed: {}, retrying in {:?}", err, backoff);
+                thread::sleep(backoff);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn launch_on_devices<C: CurveAffine>(
+    backend: &mut CudaBackend,
+    data: &CircuitData<'_, C>,
+    buffers: &LookupBuffers<C::ScalarExt>,
+    lookup_offsets: &[u64],
+    values: &mut [C::ScalarExt],
+) -> Result<(), EvalError> {
+    let field = FieldLayout::checked::<C::ScalarExt>()?;
+    let module = KernelModule::select()?;
+    let key = module_key(&module, &field);
+    let kernel = match (buffers.layout, buffers.max_device_columns()) {
+        (LookupLayout::Interleaved, 0) => LOOKUPS_KERNEL,
+        (LookupLayout::Interleaved, _) => LOOKUP_TABLE_VALUES_KERNEL,
+        (LookupLayout::Split, 0) => LOOKUPS_SPLIT_KERNEL,
+        (LookupLayout::Split, _) => LOOKUP_TABLE_VALUES_SPLIT_KERNEL,
+    };
+
+    let size = data.size();
+    // The rotations of the lookups reach a row of the domain before and after
+    let halo = data.rot_scale() as usize;
+    let circuit_count = lookup_offsets.len() - 1;
+    // The unified memory alone runs on the first device
+    let devices = match backend.devices.is_empty() {
+        true => vec![0],
+        false => backend.devices.clone(),
+    };
+    let slices = row_slices(size, devices.len());
+
+    DEVICES.with(|contexts| {
+        let mut contexts = contexts.borrow_mut();
+        let windows =
+            load_devices(&mut contexts, key, &devices, &module, &field, kernel).and_then(|_| {
+                run_windows(
+                    &mut contexts,
+                    key,
+                    &devices,
+                    &slices,
+                    backend,
+                    data,
+                    buffers,
+                    lookup_offsets,
+                    values,
+                )
+            });
+
+        // Freed once every window is copied back, as a device listed twice
+        // holds the buffers of both its windows
+        for &ordinal in &devices {
+            if let Some(context) = contexts.get_mut(&(key, ordinal)) {
+                context.free_launches();
+            }
+        }
+        let windows = match windows {
+            Ok(windows) => windows,
+            Err(err) => {
+                // The contexts may be left in a bad state, loaded again next time
+                for &ordinal in &devices {
+                    contexts.remove(&(key, ordinal));
+                }
+                return Err(err);
+            }
+        };
+
+        // The rows of each slice are past the `halo` rows of its window
+        for (rows, window) in slices.iter().zip(windows) {
+            let len = rows.len() + 2 * halo;
+            for circuit in 0..circuit_count {
+                let start = circuit * len + halo;
+                values[circuit * size + rows.start..circuit * size + rows.end]
+                    .copy_from_slice(&window[start..start + rows.len()]);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Loads the kernel module, keyed `key`, on each of the `devices` it is not
+/// loaded on yet, after checking that it defines the `kernel` function
+fn load_devices(
+    contexts: &mut HashMap<(u64, usize), DeviceContext>,
+    key: u64,
+    devices: &[usize],
+    module: &KernelModule,
+    field: &FieldLayout,
+    kernel: &'static str,
+) -> Result<(), EvalError> {
+    for &ordinal in devices {
+        if !contexts.contains_key(&(key, ordinal)) {
+            module.check_declarations()?;
+            module.check_entry_point(kernel)?;
+            module.check_entry_point(FIELD_KERNEL)?;
+            let loaded = DeviceContext::load(ordinal, module, field)?;
+            contexts.insert((key, ordinal), loaded);
+        }
+    }
+    Ok(())
+}
+
+/// Launches the window of each slice of `slices` on its device of `devices`,
+/// then waits for each of them and returns the values of their windows
+fn run_windows<C: CurveAffine>(
+    contexts: &mut HashMap<(u64, usize), DeviceContext>,
+    key: u64,
+    devices: &[usize],
+    slices: &[Range<usize>],
+    backend: &mut CudaBackend,
+    data: &CircuitData<'_, C>,
+    buffers: &LookupBuffers<C::ScalarExt>,
+    lookup_offsets: &[u64],
+    values: &[C::ScalarExt],
+) -> Result<Vec<Vec<C::ScalarExt>>, EvalError> {
+    let halo = data.rot_scale() as usize;
+    let circuit_count = lookup_offsets.len() - 1;
+
+    let mut launched = Vec::with_capacity(devices.len());
+    for (&ordinal, rows) in devices.iter().zip(slices.iter()) {
+        let threads = backend.launch_threads((rows.len() + 2 * halo) * circuit_count);
+        let context = contexts.get_mut(&(key, ordinal)).unwrap();
+        context.memory_mode = backend.memory_mode;
+        launched.push(context.launch_lookups(
+            data,
+            buffers,
+            lookup_offsets,
+            values,
+            rows,
+            halo,
+            threads,
+            &mut backend.transfers,
+        )?);
+    }
+
+    let mut windows = Vec::with_capacity(devices.len());
+    for ((&ordinal, rows), pointer) in devices.iter().zip(slices.iter()).zip(launched) {
+        let mut window = vec![C::ScalarExt::zero(); (rows.len() + 2 * halo) * circuit_count];
+        contexts[&(key, ordinal)].finish(pointer, &mut window, &mut backend.transfers)?;
+        windows.push(window);
+    }
+    Ok(windows)
+}
+
+/// Loads the kernel module on each of the `devices` in the current thread,
+/// checking that it is compiled for `field`, see `CudaBackend::warmup`
+pub(super) fn warmup(devices: &[usize], field: &FieldLayout) -> Result<(), EvalError> {
+    let module = KernelModule::select()?;
+    let key = module_key(&module, field);
+    DEVICES.with(|contexts| {
+        load_devices(
+            &mut contexts.borrow_mut(),
+            key,
+            devices,
+            &module,
+            field,
+            FIELD_KERNEL,
+        )
+    })
+}
@@ -0,0 +1,74 @@
+use super::{EvalControl, EvalError, EvalScratch, Evaluator};
+use crate::arithmetic::CurveAffine;
+use crate::multicore;
+use crate::plonk::{lookup, permutation, ProvingKey};
+use crate::poly::{Coeff, ExtendedLagrangeCoeff, Polynomial};
+
+/// The inputs of an evaluation of h(X) which depend on the challenges, known
+/// once they are squeezed from the transcript, for
+/// [`Evaluator::evaluate_h_deferred`]
+#[derive(Debug)]
+pub(in crate::plonk) struct DeferredChallenges<'a, C: CurveAffine> {
+    pub(in crate::plonk) challenges: Vec<C::ScalarExt>,
+    pub(in crate::plonk) y: C::ScalarExt,
+    pub(in crate::plonk) beta: C::ScalarExt,
+    pub(in crate::plonk) gamma: C::ScalarExt,
+    pub(in crate::plonk) theta: C::ScalarExt,
+    pub(in crate::plonk) lookups: &'a [Vec<lookup::prover::Committed<C>>],
+    pub(in crate::plonk) permutations: &'a [permutation::prover::Committed<C>],
+}
+
+impl<C: CurveAffine> Evaluator<C> {
+    /// `evaluate_h`, with the challenges and the inputs depending on them
+    /// returned by `challenges`, which runs on the calling thread while the
+    /// advice and instance cosets are computed on the others, so that the
+    /// transcript can be written to while the cosets are prepared. An error
+    /// of `challenges` is returned as is, once the cosets are computed. The
+    /// time of the cosets is not part of the timings of the evaluation.
+    pub(in crate::plonk) fn evaluate_h_deferred<'a, E: From<EvalError>>(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: impl FnOnce() -> Result<DeferredChallenges<'a, C>, E>,
+        scratch: &mut EvalScratch<C::ScalarExt>,
+        control: &mut EvalControl<'_>,
+    ) -> Result<Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>, E> {
+        // As in `evaluate_h_with_backend`, for the cosets to be those of every
+        // circuit
+        let no_instance: Vec<&[Polynomial<C::ScalarExt, Coeff>]> = vec![&[]; advice_polys.len()];
+        let instance_polys = match instance_polys {
+            [] if pk.vk.cs.num_instance_columns == 0 => &no_instance[..],
+            instance_polys => instance_polys,
+        };
+
+        let domain = &pk.vk.domain;
+        let inputs = multicore::in_place_scope(|scope| {
+            let cosets = &mut *scratch;
+            scope.spawn(move |_| {
+                cosets.cosets_ready = false;
+                cosets.compute_cosets(domain, advice_polys, instance_polys);
+            });
+            challenges()
+        });
+        let inputs = inputs?;
+
+        scratch.cosets_ready = true;
+        let result = self.evaluate_h(
+            pk,
+            advice_polys,
+            instance_polys,
+            &inputs.challenges,
+            inputs.y,
+            inputs.beta,
+            inputs.gamma,
+            inputs.theta,
+            inputs.lookups,
+            inputs.permutations,
+            scratch,
+            control,
+        );
+        scratch.cosets_ready = false;
+        result.map_err(E::from)
+    }
+}
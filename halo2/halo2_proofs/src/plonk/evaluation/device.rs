@@ -0,0 +1,734 @@
+use super::{
+    evaluate, get_rotation_idx, Calculation, CircuitData, EvalError, Evaluator, GraphEvaluator,
+    LookupLayout, ValueSource,
+};
+use crate::arithmetic::{parallelize, CurveAffine, FieldExt};
+use crate::halo2curves::bn256;
+use crate::multicore;
+use crate::plonk::lookup;
+use crate::poly::{Basis, ExtendedLagrangeCoeff, Polynomial};
+use group::ff::{Field, PrimeField};
+use std::any::TypeId;
+use std::ops::Range;
+
+/// Number of constraints each lookup argument adds to h(X)
+pub(in crate::plonk) const LOOKUP_CONSTRAINTS: u64 = 5;
+
+/// Per-row lookup data read by the device kernels, laid out as `combined_t`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(in crate::plonk) struct CombinedData<F> {
+    pub(in crate::plonk) table_value: F,
+    pub(in crate::plonk) a_minus_s: F,
+    pub(in crate::plonk) r_next: usize,
+    pub(in crate::plonk) r_prev: usize,
+}
+
+/// The field of the proof, as the kernels check it: the size of its elements
+/// and the little-endian 64-bit limbs of its modulus.
+///
+/// The kernels read the elements in their memory representation, so they are
+/// compiled for a given field, whatever its size, and the memory
+/// representation must be the kernel one, see `to_kernel_repr`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(in crate::plonk) struct FieldLayout {
+    pub(in crate::plonk) bytes: usize,
+    pub(in crate::plonk) modulus: Vec<u64>,
+}
+
+impl FieldLayout {
+    /// The layout of `F`, whose representation must be little-endian, as
+    /// those of the halo2curves fields are
+    pub(in crate::plonk) fn of<F: FieldExt>() -> Self {
+        // The modulus is one more than the largest element
+        let mut repr = (-F::one()).to_repr();
+        for byte in repr.as_mut().iter_mut() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+        FieldLayout {
+            bytes: std::mem::size_of::<F>(),
+            modulus: le_limbs(repr.as_ref()),
+        }
+    }
+
+    /// The layout of `F`, if its elements are laid out in memory as the
+    /// kernels read them, so that its buffers can be uploaded as they are
+    pub(in crate::plonk) fn checked<F: FieldExt>() -> Result<Self, EvalError> {
+        let bytes = std::mem::size_of::<F>();
+        let samples = [
+            F::one(),
+            F::from(0x0123_4567_89ab_cdef),
+            F::from(2).invert().unwrap(),
+            -F::one(),
+        ];
+        let matches = bytes % 8 == 0
+            && samples.iter().all(|value| {
+                // Safety: the `bytes` bytes of the value
+                let memory =
+                    unsafe { std::slice::from_raw_parts(value as *const F as *const u8, bytes) };
+                le_limbs(memory) == to_kernel_repr(value)
+            });
+        match matches {
+            true => Ok(Self::of::<F>()),
+            false => Err(EvalError::ReprMismatch { bytes }),
+        }
+    }
+}
+
+/// The little-endian 64-bit limbs of little-endian bytes, the last one padded
+/// with zeros
+fn le_limbs(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks(8)
+        .map(|chunk| {
+            let mut limb = [0; 8];
+            limb[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(limb)
+        })
+        .collect()
+}
+
+/// `R = 2^(8 * size_of::<F>())`, the Montgomery radix of the kernels
+fn montgomery_radix<F: FieldExt>() -> F {
+    F::from(2).pow_vartime(&[8 * std::mem::size_of::<F>() as u64])
+}
+
+/// The representation of `value` the kernels read, which is the ABI of the
+/// field elements between the host and the device: the Montgomery form
+/// `value * R mod p`, with `R = 2^(8 * size_of::<F>())`, in 64-bit limbs from
+/// the least significant one, each limb being little-endian.
+///
+/// It is computed from the canonical representation of the value, whatever
+/// its memory layout. The buffers are not converted when uploaded but copied
+/// as they are in memory, which `FieldLayout::checked` only allows when the
+/// memory layout is this representation, as for the halo2curves fields on a
+/// little-endian host.
+pub(in crate::plonk) fn to_kernel_repr<F: FieldExt>(value: &F) -> Vec<u64> {
+    let montgomery = *value * montgomery_radix::<F>();
+    le_limbs(montgomery.to_repr().as_ref())
+}
+
+/// The value the kernels represent as `limbs`, see `to_kernel_repr`, if the
+/// limbs are below the modulus
+pub(in crate::plonk) fn from_kernel_repr<F: FieldExt>(limbs: &[u64]) -> Option<F> {
+    let mut repr = F::Repr::default();
+    let bytes: Vec<u8> = limbs.iter().flat_map(|limb| limb.to_le_bytes()).collect();
+    if bytes.len() < repr.as_ref().len() {
+        return None;
+    }
+    let len = repr.as_ref().len();
+    repr.as_mut().copy_from_slice(&bytes[..len]);
+    let montgomery: F = Option::from(F::from_repr(repr))?;
+    Some(montgomery * montgomery_radix::<F>().invert().unwrap())
+}
+
+/// Appends `values` to `buffer` in the representation the kernels read, see
+/// `to_kernel_repr`.
+///
+/// The memory layout of the BN256 scalars is that representation, so they are
+/// copied in bulk. The elements of the other fields are converted one by one;
+/// for the fields `FieldLayout::checked` accepts, the conversion gives back
+/// the same bytes, so the buffer holds the same elements on the host.
+pub(in crate::plonk) fn extend_kernel_repr<F: FieldExt>(buffer: &mut Vec<F>, values: &[F]) {
+    if TypeId::of::<F>() == TypeId::of::<bn256::Fr>() {
+        buffer.extend_from_slice(values);
+        return;
+    }
+
+    let start = buffer.len();
+    buffer.resize(start + values.len(), F::zero());
+    let bytes = std::mem::size_of::<F>();
+    for (element, value) in buffer[start..].iter_mut().zip(values.iter()) {
+        let limbs = to_kernel_repr(value);
+        assert_eq!(
+            limbs.len() * 8,
+            bytes,
+            "the limbs fill the memory of an element"
+        );
+        // Safety: the limbs are `bytes` bytes, written over the element
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                limbs.as_ptr() as *const u8,
+                element as *mut F as *mut u8,
+                bytes,
+            )
+        };
+    }
+}
+
+/// Host buffers of the lookup phase, holding `size` rows per lookup with the
+/// rows of lookup `n` at `n * size..(n + 1) * size`, as the kernels index them.
+///
+/// The kernels run a thread per row that loops over the lookups, so for each
+/// lookup the threads of a warp read consecutive rows, which are contiguous in
+/// this layout. Storing the lookups of a row next to each other instead would
+/// make those reads strided by the number of lookups.
+///
+/// The per-row values are in `combined_data_in` with the interleaved layout,
+/// and in `table_values` and `a_minus_s` with the split one, see
+/// `LookupLayout`; the buffers of the other layout stay empty.
+///
+/// The lookups compressed on the device leave their `table_value` at zero,
+/// and lookup `n` has the values of its input and table expressions at
+/// `column_offsets[n]..column_offsets[n + 1]` of `input_columns` and
+/// `table_columns`, `size` rows each; the other lookups have no columns.
+pub(in crate::plonk) struct LookupBuffers<F> {
+    pub(in crate::plonk) layout: LookupLayout,
+    pub(in crate::plonk) combined_data_in: Vec<CombinedData<F>>,
+    pub(in crate::plonk) table_values: Vec<F>,
+    pub(in crate::plonk) a_minus_s: Vec<F>,
+    pub(in crate::plonk) product_coset: Vec<F>,
+    pub(in crate::plonk) permuted_input_coset: Vec<F>,
+    pub(in crate::plonk) permuted_table_coset: Vec<F>,
+    pub(in crate::plonk) input_columns: Vec<F>,
+    pub(in crate::plonk) table_columns: Vec<F>,
+    pub(in crate::plonk) column_offsets: Vec<u64>,
+}
+
+impl<F> LookupBuffers<F> {
+    /// Buffers of the interleaved layout, the one OpenCL and WebGPU read
+    pub(in crate::plonk) fn new() -> Self {
+        Self::with_layout(LookupLayout::Interleaved)
+    }
+
+    /// Buffers of `layout`, which only the CUDA kernels read split
+    pub(in crate::plonk) fn with_layout(layout: LookupLayout) -> Self {
+        LookupBuffers {
+            layout,
+            combined_data_in: vec![],
+            table_values: vec![],
+            a_minus_s: vec![],
+            product_coset: vec![],
+            permuted_input_coset: vec![],
+            permuted_table_coset: vec![],
+            input_columns: vec![],
+            table_columns: vec![],
+            column_offsets: vec![0],
+        }
+    }
+
+    /// The most expressions of a lookup compressed on the device, 0 if none
+    pub(in crate::plonk) fn max_device_columns(&self) -> usize {
+        self.column_offsets
+            .windows(2)
+            .map(|offsets| (offsets[1] - offsets[0]) as usize)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl<F: Copy> LookupBuffers<F> {
+    /// The buffers of the `rows` of each lookup of `size` rows, with the
+    /// `halo` rows before and after them that the rotations of the lookups
+    /// reach, `halo` being the rotation scale, see `window_rows`. The kernels
+    /// evaluate `rows.len() + 2 * halo` rows from them, the rows of `rows`
+    /// being right where their rotations stay in the window.
+    pub(in crate::plonk) fn window(&self, size: usize, rows: Range<usize>, halo: usize) -> Self {
+        let len = rows.len() + 2 * halo;
+        let mut combined_data_in = window_rows(&self.combined_data_in, size, rows.clone(), halo);
+        for (i, combined) in combined_data_in.iter_mut().enumerate() {
+            let row = i % len;
+            combined.r_next = (row + halo) % len;
+            combined.r_prev = (row + len - halo) % len;
+        }
+
+        LookupBuffers {
+            layout: self.layout,
+            combined_data_in,
+            table_values: window_rows(&self.table_values, size, rows.clone(), halo),
+            a_minus_s: window_rows(&self.a_minus_s, size, rows.clone(), halo),
+            product_coset: window_rows(&self.product_coset, size, rows.clone(), halo),
+            permuted_input_coset: window_rows(&self.permuted_input_coset, size, rows.clone(), halo),
+            permuted_table_coset: window_rows(&self.permuted_table_coset, size, rows.clone(), halo),
+            input_columns: window_rows(&self.input_columns, size, rows.clone(), halo),
+            table_columns: window_rows(&self.table_columns, size, rows, halo),
+            column_offsets: self.column_offsets.clone(),
+        }
+    }
+}
+
+/// The rows `rows` of each list of `size` rows of `lists`, one list after the
+/// other, with the `halo` rows before and after them, wrapping around the end
+/// of the list as the rotations do
+pub(in crate::plonk) fn window_rows<T: Copy>(
+    lists: &[T],
+    size: usize,
+    rows: Range<usize>,
+    halo: usize,
+) -> Vec<T> {
+    let len = rows.len() + 2 * halo;
+    let first = rows.start + size - halo % size;
+    let mut window = Vec::with_capacity(lists.len() / size * len);
+    for list in lists.chunks(size) {
+        window.extend((0..len).map(|j| list[(first + j) % size]));
+    }
+    window
+}
+
+/// The `parts` ranges of rows the `size` rows are split into, as evenly as
+/// they can be, the last ones being the longest
+pub(in crate::plonk) fn row_slices(size: usize, parts: usize) -> Vec<Range<usize>> {
+    (0..parts)
+        .map(|part| part * size / parts..(part + 1) * size / parts)
+        .collect()
+}
+
+/// Computes on the CPU everything the lookup kernels need for a single
+/// circuit, appending its lookups to `buffers`. The lookups with at least
+/// `device_columns` input expressions, if it is not 0, are left for the device
+/// to compress, with the values of their expressions in `buffers`.
+pub(in crate::plonk) fn prepare_lookups<C: CurveAffine>(
+    ev: &Evaluator<C>,
+    data: &CircuitData<'_, C>,
+    lookups: &[lookup::prover::Committed<C>],
+    device_columns: usize,
+    buffers: &mut LookupBuffers<C::ScalarExt>,
+) {
+    for (n, lookup) in lookups.iter().enumerate() {
+        prepare_lookup(ev, data, n, lookup, device_columns, buffers);
+    }
+}
+
+/// Appends the lookup `n` of a circuit to `buffers`, as `prepare_lookups`
+/// does for all of them
+pub(in crate::plonk) fn prepare_lookup<C: CurveAffine>(
+    ev: &Evaluator<C>,
+    data: &CircuitData<'_, C>,
+    n: usize,
+    lookup: &lookup::prover::Committed<C>,
+    device_columns: usize,
+    buffers: &mut LookupBuffers<C::ScalarExt>,
+) {
+    let pk = data.pk;
+    let size = data.size();
+    let rot_scale = data.rot_scale();
+    let isize = size as i32;
+    let fixed = data.fixed();
+    let advice = data.advice;
+    let instance = data.instance;
+    let challenges = data.challenges;
+    let (y, beta, gamma, theta) = (data.y, data.beta, data.gamma, data.theta);
+    let num_threads = multicore::current_num_threads();
+    let chunk_size = (size + num_threads - 1) / num_threads;
+    // The argument of the lookup graph `n` of `ev`, the graphs being those of
+    // the arguments in the same order
+    let argument = &pk.vk.cs.lookups[n];
+
+    // The layout of `combined_t`, without padding whatever the field size
+    assert_eq!(
+        std::mem::size_of::<CombinedData<C::ScalarExt>>(),
+        2 * std::mem::size_of::<C::ScalarExt>() + 2 * std::mem::size_of::<usize>()
+    );
+    let zero = CombinedData {
+        table_value: C::ScalarExt::zero(),
+        a_minus_s: C::ScalarExt::zero(),
+        r_next: 0,
+        r_prev: 0,
+    };
+    let offset = buffers.product_coset.len();
+    match buffers.layout {
+        LookupLayout::Interleaved => buffers.combined_data_in.resize(offset + size, zero),
+        LookupLayout::Split => {
+            buffers
+                .table_values
+                .resize(offset + size, C::ScalarExt::zero());
+            buffers
+                .a_minus_s
+                .resize(offset + size, C::ScalarExt::zero());
+        }
+    }
+    buffers.product_coset.reserve(size);
+    buffers.permuted_input_coset.reserve(size);
+    buffers.permuted_table_coset.reserve(size);
+
+    // Polynomials required for this lookup.
+    // Calculated here so these only have to be kept in memory for the short time
+    // they are actually needed.
+    let product_coset = pk.vk.domain.coeff_to_extended(lookup.product_poly.clone());
+    let permuted_input_coset = pk
+        .vk
+        .domain
+        .coeff_to_extended(lookup.permuted_input_poly.clone());
+    let permuted_table_coset = pk
+        .vk
+        .domain
+        .coeff_to_extended(lookup.permuted_table_poly.clone());
+
+    let on_device = device_columns > 0 && argument.input_expressions.len() >= device_columns;
+    if on_device {
+        for (columns, expressions) in [
+            (&mut buffers.input_columns, &argument.input_expressions),
+            (&mut buffers.table_columns, &argument.table_expressions),
+        ] {
+            for expression in expressions {
+                let values = evaluate(
+                    expression, size, rot_scale, fixed, advice, instance, challenges,
+                );
+                extend_kernel_repr(columns, &values);
+            }
+        }
+    }
+    let last_offset = *buffers.column_offsets.last().unwrap();
+    buffers.column_offsets.push(match on_device {
+        true => last_offset + argument.input_expressions.len() as u64,
+        false => last_offset,
+    });
+
+    // The rows of the lookup in the buffers of the layout, a chunk per thread
+    let chunks: Vec<LookupRows<'_, C::ScalarExt>> = match buffers.layout {
+        LookupLayout::Interleaved => buffers.combined_data_in[offset..offset + size]
+            .chunks_mut(chunk_size)
+            .map(LookupRows::Interleaved)
+            .collect(),
+        LookupLayout::Split => buffers.table_values[offset..offset + size]
+            .chunks_mut(chunk_size)
+            .zip(buffers.a_minus_s[offset..offset + size].chunks_mut(chunk_size))
+            .map(|(table_values, a_minus_s)| LookupRows::Split(table_values, a_minus_s))
+            .collect(),
+    };
+    multicore::scope(|scope| {
+        for (thread_idx, mut rows) in chunks.into_iter().enumerate() {
+            let start = thread_idx * chunk_size;
+            let permuted_input_coset_ref = &permuted_input_coset.values;
+            let permuted_table_coset_ref = &permuted_table_coset.values;
+            let lookup_evaluator = &ev.lookups[n];
+            let mut eval_data = lookup_evaluator.instance();
+
+            scope.spawn(move |_| {
+                for i in 0..rows.len() {
+                    let idx = start + i;
+
+                    if !on_device {
+                        let table_value = lookup_evaluator.evaluate(
+                            &mut eval_data,
+                            fixed,
+                            advice,
+                            instance,
+                            challenges,
+                            &beta,
+                            &gamma,
+                            &theta,
+                            &y,
+                            &C::ScalarExt::zero(),
+                            idx,
+                            rot_scale,
+                            isize,
+                        );
+                        rows.set_table_value(i, table_value);
+                    }
+                    let a_minus_s = permuted_input_coset_ref[idx] - permuted_table_coset_ref[idx];
+                    match &mut rows {
+                        LookupRows::Interleaved(combined) => {
+                            combined[i].a_minus_s = a_minus_s;
+                            combined[i].r_next = get_rotation_idx(idx, 1, rot_scale, isize);
+                            combined[i].r_prev = get_rotation_idx(idx, -1, rot_scale, isize);
+                        }
+                        LookupRows::Split(_, a_minus_s_rows) => a_minus_s_rows[i] = a_minus_s,
+                    }
+                }
+            });
+        }
+    });
+
+    extend_kernel_repr(&mut buffers.product_coset, &product_coset.values);
+    extend_kernel_repr(
+        &mut buffers.permuted_input_coset,
+        &permuted_input_coset.values,
+    );
+    extend_kernel_repr(
+        &mut buffers.permuted_table_coset,
+        &permuted_table_coset.values,
+    );
+}
+
+/// Rows of a lookup in `LookupBuffers`, as its layout holds them
+enum LookupRows<'a, F> {
+    Interleaved(&'a mut [CombinedData<F>]),
+    /// The table values and a'(X) - s'(X) of the rows
+    Split(&'a mut [F], &'a mut [F]),
+}
+
+impl<F> LookupRows<'_, F> {
+    fn len(&self) -> usize {
+        match self {
+            LookupRows::Interleaved(combined) => combined.len(),
+            LookupRows::Split(table_values, _) => table_values.len(),
+        }
+    }
+
+    fn set_table_value(&mut self, i: usize, table_value: F) {
+        match self {
+            LookupRows::Interleaved(combined) => combined[i].table_value = table_value,
+            LookupRows::Split(table_values, _) => table_values[i] = table_value,
+        }
+    }
+}
+
+/// Accumulates the lookup `contribution` of a circuit with `num_lookups`
+/// lookups, as returned by `HEvaluatorBackend::evaluate_lookups_batched`
+pub(in crate::plonk) fn accumulate_lookups<F: FieldExt>(
+    values: &mut Polynomial<F, ExtendedLagrangeCoeff>,
+    y: F,
+    num_lookups: usize,
+    contribution: &[F],
+) {
+    let y_power = y.pow_vartime(&[LOOKUP_CONSTRAINTS * num_lookups as u64, 0, 0, 0]);
+    parallelize(values, |values, start| {
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = *value * y_power + contribution[start + i];
+        }
+    });
+}
+
+/// Views `data` as raw bytes, to upload it with its in-memory layout.
+///
+/// The values are only ever field elements and `CombinedData`, which have no
+/// padding, so every byte is initialized.
+#[cfg(any(feature = "opencl", feature = "webgpu"))]
+pub(in crate::plonk) fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+/// Views `data` as raw bytes, to download into it
+#[cfg(any(feature = "opencl", feature = "webgpu"))]
+pub(in crate::plonk) fn as_bytes_mut<T: Copy>(data: &mut [T]) -> &mut [u8] {
+    unsafe {
+        std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, std::mem::size_of_val(data))
+    }
+}
+
+/// `source_t.kind` of a value of `DeviceGraph::scalars`
+pub(in crate::plonk) const SOURCE_SCALAR: u32 = 0;
+/// `source_t.kind` of an intermediate of the row
+pub(in crate::plonk) const SOURCE_INTERMEDIATE: u32 = 1;
+/// `source_t.kind` of a value of `DeviceGraph::columns`, at a rotation
+pub(in crate::plonk) const SOURCE_COLUMN: u32 = 2;
+
+/// `calculation_t.op` computing `a + b`
+pub(in crate::plonk) const OP_ADD: u32 = 0;
+/// `calculation_t.op` computing `a - b`
+pub(in crate::plonk) const OP_SUB: u32 = 1;
+/// `calculation_t.op` computing `a * b`
+pub(in crate::plonk) const OP_MUL: u32 = 2;
+/// `calculation_t.op` computing `a * b + c`
+pub(in crate::plonk) const OP_MUL_ADD: u32 = 3;
+
+/// A value read by a `DeviceCalculation`, laid out as `source_t`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(in crate::plonk) struct DeviceSource {
+    /// One of the `SOURCE_*` kinds
+    pub(in crate::plonk) kind: u32,
+    /// The index of the scalar, the intermediate or the column
+    pub(in crate::plonk) index: u32,
+    /// The index in `DeviceGraph::rotations` of the rotation of a column
+    pub(in crate::plonk) rotation: u32,
+}
+
+/// A calculation of a `DeviceGraph`, laid out as `calculation_t`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(in crate::plonk) struct DeviceCalculation {
+    /// One of the `OP_*` operations
+    pub(in crate::plonk) op: u32,
+    pub(in crate::plonk) target: u32,
+    pub(in crate::plonk) a: DeviceSource,
+    pub(in crate::plonk) b: DeviceSource,
+    /// Only read by `OP_MUL_ADD`
+    pub(in crate::plonk) c: DeviceSource,
+}
+
+/// A `GraphEvaluator` lowered for a device kernel to interpret, along with
+/// the values it reads.
+///
+/// The calculations are reduced to additions, subtractions, products and
+/// fused multiply-adds, a Horner's rule becoming one multiply-add per part and
+/// a linear combination a product followed by one multiply-add per term, all
+/// accumulating into the intermediate of the calculation.
+/// The columns the graph reads are copied one after the other, so that only
+/// those are uploaded.
+#[derive(Debug)]
+pub(in crate::plonk) struct DeviceGraph<F> {
+    pub(in crate::plonk) calculations: Vec<DeviceCalculation>,
+    pub(in crate::plonk) num_intermediates: usize,
+    /// The constants of the graph followed by the challenges
+    pub(in crate::plonk) scalars: Vec<F>,
+    pub(in crate::plonk) rotations: Vec<i32>,
+    /// The `size` rows of each column the graph reads
+    pub(in crate::plonk) columns: Vec<F>,
+}
+
+impl<F: FieldExt> DeviceGraph<F> {
+    /// Lowers `graph`, which must end with the calculation of its result, to
+    /// be evaluated on the first `size` rows of the columns.
+    ///
+    /// A single expression has no value for beta, gamma, theta, y nor for a
+    /// previous value, so a graph reading one of them is rejected.
+    pub(in crate::plonk) fn lower<C: CurveAffine<ScalarExt = F>, B: Basis>(
+        graph: &GraphEvaluator<C>,
+        size: usize,
+        fixed: &[Polynomial<F, B>],
+        advice: &[Polynomial<F, B>],
+        instance: &[Polynomial<F, B>],
+        challenges: &[F],
+    ) -> Result<Self, EvalError> {
+        let mut scalars = graph.constants.clone();
+        scalars.extend_from_slice(challenges);
+        let mut device_graph = DeviceGraph {
+            calculations: Vec::with_capacity(graph.calculations.len()),
+            num_intermediates: graph.num_intermediates,
+            scalars,
+            rotations: graph.rotations.clone(),
+            columns: vec![],
+        };
+
+        // The columns copied so far, by kind and index
+        let mut slots: Vec<(u8, usize)> = vec![];
+        let mut source = |value: &ValueSource| -> Result<DeviceSource, EvalError> {
+            let (kind, index, rotation) = match *value {
+                ValueSource::Constant(idx) => (SOURCE_SCALAR, idx, 0),
+                ValueSource::Challenge(idx) => (SOURCE_SCALAR, graph.constants.len() + idx, 0),
+                ValueSource::Intermediate(idx) => (SOURCE_INTERMEDIATE, idx, 0),
+                ValueSource::Fixed(column, rotation)
+                | ValueSource::Advice(column, rotation)
+                | ValueSource::Instance(column, rotation) => {
+                    let (kind, values) = match value {
+                        ValueSource::Fixed(_, _) => (0, fixed),
+                        ValueSource::Advice(_, _) => (1, advice),
+                        _ => (2, instance),
+                    };
+                    let slot = match slots.iter().position(|&slot| slot == (kind, column)) {
+                        Some(slot) => slot,
+                        None => {
+                            slots.push((kind, column));
+                            extend_kernel_repr(&mut device_graph.columns, &values[column][..size]);
+                            slots.len() - 1
+                        }
+                    };
+                    (SOURCE_COLUMN, slot, rotation)
+                }
+                _ => {
+                    return Err(EvalError::InvalidGraph(format!(
+                        "a single expression has no value for {}",
+                        value
+                    )))
+                }
+            };
+            Ok(DeviceSource {
+                kind,
+                index: index as u32,
+                rotation: rotation as u32,
+            })
+        };
+
+        // The first constant of a graph is always zero
+        let zero = DeviceSource::default();
+        let mut calculations = vec![];
+        for info in graph.calculations.iter() {
+            let target = info.target as u32;
+            let mut push = |op, a, b, c| {
+                calculations.push(DeviceCalculation {
+                    op,
+                    target,
+                    a,
+                    b,
+                    c,
+                })
+            };
+            match &info.calculation {
+                Calculation::Add(a, b) => push(OP_ADD, source(a)?, source(b)?, zero),
+                Calculation::Sub(a, b) => push(OP_SUB, source(a)?, source(b)?, zero),
+                Calculation::Mul(a, b) => push(OP_MUL, source(a)?, source(b)?, zero),
+                Calculation::Square(v) => {
+                    let v = source(v)?;
+                    push(OP_MUL, v, v, zero)
+                }
+                Calculation::Double(v) => {
+                    let v = source(v)?;
+                    push(OP_ADD, v, v, zero)
+                }
+                Calculation::Negate(v) => push(OP_SUB, zero, source(v)?, zero),
+                Calculation::MulAdd(a, b, c) => {
+                    push(OP_MUL_ADD, source(a)?, source(b)?, source(c)?)
+                }
+                Calculation::Store(v) => push(OP_ADD, source(v)?, zero, zero),
+                Calculation::Inverse(_) => {
+                    return Err(EvalError::InvalidGraph(format!(
+                        "{} is only evaluated on the CPU",
+                        info.calculation
+                    )))
+                }
+                Calculation::Horner(start, parts, factor) => {
+                    let value = DeviceSource {
+                        kind: SOURCE_INTERMEDIATE,
+                        index: target,
+                        rotation: 0,
+                    };
+                    let factor = source(factor)?;
+                    push(OP_ADD, source(start)?, zero, zero);
+                    for part in parts.iter() {
+                        push(OP_MUL_ADD, value, factor, source(part)?);
+                    }
+                }
+                Calculation::LinearCombination(terms) => {
+                    let value = DeviceSource {
+                        kind: SOURCE_INTERMEDIATE,
+                        index: target,
+                        rotation: 0,
+                    };
+                    let mut terms = terms.iter();
+                    match terms.next() {
+                        Some((a, b)) => push(OP_MUL, source(a)?, source(b)?, zero),
+                        None => push(OP_ADD, zero, zero, zero),
+                    }
+                    for (a, b) in terms {
+                        push(OP_MUL_ADD, source(a)?, source(b)?, value);
+                    }
+                }
+            }
+        }
+        device_graph.calculations = calculations;
+
+        Ok(device_graph)
+    }
+
+    /// Evaluates the graph on the host the way the device kernel does, with
+    /// `size` rows
+    #[cfg(test)]
+    pub(in crate::plonk) fn evaluate(&self, size: usize, rot_scale: i32) -> Vec<F> {
+        let mut intermediates = vec![F::zero(); self.num_intermediates * size];
+        let mut values = vec![F::zero(); size];
+        for (row, value) in values.iter_mut().enumerate() {
+            let read = |source: &DeviceSource, intermediates: &[F]| {
+                let index = source.index as usize;
+                match source.kind {
+                    SOURCE_SCALAR => self.scalars[index],
+                    SOURCE_INTERMEDIATE => intermediates[index * size + row],
+                    _ => {
+                        let rotation = self.rotations[source.rotation as usize];
+                        let rotated = get_rotation_idx(row, rotation, rot_scale, size as i32);
+                        self.columns[index * size + rotated]
+                    }
+                }
+            };
+            for calc in self.calculations.iter() {
+                let a = read(&calc.a, &intermediates);
+                let b = read(&calc.b, &intermediates);
+                let result = match calc.op {
+                    OP_ADD => a + b,
+                    OP_SUB => a - b,
+                    OP_MUL => a * b,
+                    _ => a * b + read(&calc.c, &intermediates),
+                };
+                intermediates[calc.target as usize * size + row] = result;
+            }
+            if let Some(calc) = self.calculations.last() {
+                *value = intermediates[calc.target as usize * size + row];
+            }
+        }
+        values
+    }
+}
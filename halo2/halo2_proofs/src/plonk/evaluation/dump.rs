@@ -0,0 +1,68 @@
+use super::spill::{read_rows, write_rows};
+use crate::arithmetic::FieldExt;
+use crate::poly::{ExtendedLagrangeCoeff, Polynomial};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Magic bytes starting a dumped polynomial
+const MAGIC: [u8; 4] = *b"h2hx";
+
+/// Version of the dump format, bumped whenever its encoding changes
+const FORMAT_VERSION: u32 = 1;
+
+/// Writes the values of h(X) over the extended domain to `path`, so that an
+/// evaluation known to be good can be compared with another one offline, on
+/// another run or another machine. The file holds the magic bytes `h2hx`, the
+/// format version as a little-endian `u32`, the number of values as a
+/// little-endian `u64`, then each value in its canonical form.
+pub fn dump_extended_poly<F: FieldExt, P: AsRef<Path>>(
+    values: &Polynomial<F, ExtendedLagrangeCoeff>,
+    path: P,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(values.len() as u64).to_le_bytes())?;
+    write_rows(&mut writer, values)?;
+    writer.flush()
+}
+
+/// Reads back the values written by [`dump_extended_poly`]. A file of another
+/// format or version, or holding bytes which are not the canonical form of a
+/// field element, is rejected with `io::ErrorKind::InvalidData`.
+pub fn load_extended_poly<F: FieldExt, P: AsRef<Path>>(path: P) -> io::Result<Vec<F>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_data("not a dumped polynomial".to_string()));
+    }
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(format!(
+            "dump format version {} is not supported, expected {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let len = usize::try_from(u64::from_le_bytes(len))
+        .map_err(|_| invalid_data("the number of values does not fit in memory".to_string()))?;
+
+    let mut values = vec![F::zero(); len];
+    read_rows(&mut reader, &mut values)?;
+    if reader.read(&mut [0u8])? != 0 {
+        return Err(invalid_data(format!(
+            "trailing bytes after the {} values",
+            len
+        )));
+    }
+    Ok(values)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
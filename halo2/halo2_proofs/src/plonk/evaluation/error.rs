@@ -0,0 +1,184 @@
+use std::error;
+use std::fmt;
+
+/// This is an error that could occur while evaluating the h(X) polynomial.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EvalError {
+    /// CUDA was requested but no CUDA device is available.
+    NoCudaDevice,
+    /// The named environment variable pointing at the kernel module is not set.
+    MissingKernelPath(&'static str),
+    /// A call into the CUDA driver failed during the named operation.
+    Driver(&'static str),
+    /// A CUDA driver call failed during the named operation, with the
+    /// `CUresult` code it returned and its name and description.
+    Cuda {
+        /// The operation that failed
+        operation: &'static str,
+        /// The `CUresult` code, e.g. 2 for `CUDA_ERROR_OUT_OF_MEMORY`
+        code: i32,
+        /// The name and description of the code reported by the driver
+        message: String,
+    },
+    /// The CUDA kernel module does not define the named kernel function, e.g.
+    /// when it was compiled from another version of the kernel source.
+    MissingKernelSymbol {
+        /// The kernel function that was looked for
+        name: &'static str,
+        /// The kernel functions the module defines, if they could be listed
+        available: Vec<String>,
+    },
+    /// A function declared by one of the CUDA modules linked together is not
+    /// defined by any of them.
+    UnresolvedKernelSymbol {
+        /// The function that was declared
+        name: String,
+        /// The module declaring it, which expects another one to provide it
+        file: String,
+    },
+    /// The CUDA kernel module is compiled for another field than the one of the
+    /// proof, whose elements have the given size in bytes.
+    FieldMismatch {
+        /// The size of the elements of the field of the proof
+        bytes: usize,
+    },
+    /// The elements of the field of the proof, of the given size in bytes, are
+    /// not laid out in memory as the kernels read them, in Montgomery form in
+    /// little-endian 64-bit limbs.
+    ReprMismatch {
+        /// The size of the elements of the field of the proof
+        bytes: usize,
+    },
+    /// An OpenCL call failed, with the message reported by the runtime.
+    OpenCl(String),
+    /// A wgpu call failed, with the message reported by wgpu.
+    Wgpu(String),
+    /// An evaluation graph reads a value that does not exist, with a
+    /// description of the offending calculation.
+    InvalidGraph(String),
+    /// The values of h(X) could not be spilled to or read back from the file,
+    /// with the path and the I/O error.
+    Spill(String),
+    /// The evaluation was cancelled through its `CancellationToken`.
+    Cancelled,
+    /// A row of h(X) evaluated on the device differs from the same row
+    /// evaluated again on the CPU, with `Backend::CudaSampledVerify`.
+    VerificationFailed {
+        /// The row of the extended domain
+        row: usize,
+    },
+    /// The inputs of an evaluation are not given for the same number of
+    /// circuits, with the number of circuits of each of them.
+    InputLengthMismatch {
+        /// The circuits with advice polynomials
+        advice: usize,
+        /// The circuits with instance polynomials, 0 for those of a circuit
+        /// without instance columns which come without any
+        instance: usize,
+        /// The circuits with lookups
+        lookups: usize,
+        /// The circuits with a permutation
+        permutations: usize,
+    },
+    /// The buffer h(X) is evaluated into does not have a value for each row
+    /// of the extended domain.
+    OutputLengthMismatch {
+        /// The size of the extended domain
+        expected: usize,
+        /// The length of the buffer
+        actual: usize,
+    },
+    /// The rows asked for are not a range of the rows of the extended domain.
+    RowsOutOfRange {
+        /// The first row
+        start: usize,
+        /// The row after the last one
+        end: usize,
+        /// The size of the extended domain
+        size: usize,
+    },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::NoCudaDevice => write!(f, "CUDA requested but no device found"),
+            EvalError::MissingKernelPath("CU_KERNEL") => write!(
+                f,
+                "'CU_KERNEL' env variable not found, build the kernel with `make cuda_kernels`, which needs nvcc"
+            ),
+            EvalError::MissingKernelPath(var) => write!(f, "'{}' env variable not found", var),
+            EvalError::Driver(operation) => write!(f, "CUDA driver error during {}", operation),
+            EvalError::Cuda {
+                operation,
+                code,
+                message,
+            } => write!(
+                f,
+                "CUDA driver error during {}: {} ({})",
+                operation, message, code
+            ),
+            EvalError::MissingKernelSymbol { name, available } => {
+                write!(f, "kernel function '{}' not found in the CUDA module", name)?;
+                match available.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, ", which defines: {}", available.join(", ")),
+                }
+            }
+            EvalError::UnresolvedKernelSymbol { name, file } => write!(
+                f,
+                "function '{}' declared by {} is not defined by any of the linked CUDA modules",
+                name, file
+            ),
+            EvalError::FieldMismatch { bytes } => write!(
+                f,
+                "the CUDA kernel module is not compiled for the field of the proof ({} bytes)",
+                bytes
+            ),
+            EvalError::ReprMismatch { bytes } => write!(
+                f,
+                "the field elements of the proof ({} bytes) are not in the memory layout of the CUDA kernels",
+                bytes
+            ),
+            EvalError::OpenCl(message) => write!(f, "OpenCL error: {}", message),
+            EvalError::Wgpu(message) => write!(f, "wgpu error: {}", message),
+            EvalError::InvalidGraph(message) => write!(f, "invalid evaluation graph: {}", message),
+            EvalError::Spill(message) => write!(f, "cannot spill h(X): {}", message),
+            EvalError::Cancelled => write!(f, "the evaluation of h(X) was cancelled"),
+            EvalError::VerificationFailed { row } => write!(
+                f,
+                "the device and the CPU disagree on row {} of h(X)",
+                row
+            ),
+            EvalError::InputLengthMismatch {
+                advice,
+                instance,
+                lookups,
+                permutations,
+            } => write!(
+                f,
+                "the inputs of h(X) are for different numbers of circuits: advice for {}, instance for {}, lookups for {}, permutations for {}",
+                advice, instance, lookups, permutations
+            ),
+            EvalError::OutputLengthMismatch { expected, actual } => write!(
+                f,
+                "the buffer of h(X) has {} values instead of the {} of the extended domain",
+                actual, expected
+            ),
+            EvalError::RowsOutOfRange { start, end, size } => write!(
+                f,
+                "rows {}..{} are not in the extended domain of size {}",
+                start, end, size
+            ),
+        }
+    }
+}
+
+impl error::Error for EvalError {}
+
+#[cfg(feature = "opencl")]
+impl From<ocl::Error> for EvalError {
+    fn from(error: ocl::Error) -> Self {
+        EvalError::OpenCl(error.to_string())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::arithmetic::FieldExt;
+use crate::plonk::{Any, ConstraintSystem, Expression};
+use group::ff::PrimeField;
+
+/// The 64-bit FNV-1a hash of `bytes`
+pub(in crate::plonk) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A fingerprint of what of `cs` the evaluation of h(X) depends on: the
+/// numbers of columns, selectors and challenges, the polynomials of the gates,
+/// the expressions of the lookups and the columns of the permutation, in
+/// order. The names of the gates and of the lookups are left out. It is the
+/// 64-bit FNV-1a hash of their encoding, fixed-size integers in little-endian
+/// and the constants in their canonical form, so that it is the same across
+/// runs and platforms.
+pub(in crate::plonk) fn cs_fingerprint<F: FieldExt>(cs: &ConstraintSystem<F>) -> u64 {
+    let mut bytes = vec![];
+    for count in [
+        cs.num_fixed_columns,
+        cs.num_advice_columns,
+        cs.num_instance_columns,
+        cs.num_selectors,
+        cs.num_challenges,
+    ] {
+        write_usize(&mut bytes, count);
+    }
+
+    write_usize(&mut bytes, cs.gates.len());
+    for gate in cs.gates.iter() {
+        write_usize(&mut bytes, gate.polynomials().len());
+        for poly in gate.polynomials() {
+            write_expression(&mut bytes, poly);
+        }
+    }
+
+    write_usize(&mut bytes, cs.lookups.len());
+    for lookup in cs.lookups.iter() {
+        for expressions in [&lookup.input_expressions, &lookup.table_expressions] {
+            write_usize(&mut bytes, expressions.len());
+            for expression in expressions.iter() {
+                write_expression(&mut bytes, expression);
+            }
+        }
+    }
+
+    write_usize(&mut bytes, cs.permutation.columns.len());
+    for column in cs.permutation.columns.iter() {
+        match column.column_type() {
+            Any::Advice(advice) => bytes.extend_from_slice(&[0, advice.phase()]),
+            Any::Fixed => bytes.push(1),
+            Any::Instance => bytes.push(2),
+        }
+        write_usize(&mut bytes, column.index());
+    }
+
+    fnv1a(&bytes)
+}
+
+fn write_usize(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+fn write_query(bytes: &mut Vec<u8>, tag: u8, column_index: usize, rotation: i32) {
+    bytes.push(tag);
+    write_usize(bytes, column_index);
+    bytes.extend_from_slice(&rotation.to_le_bytes());
+}
+
+fn write_expression<F: FieldExt>(bytes: &mut Vec<u8>, expression: &Expression<F>) {
+    match expression {
+        Expression::Constant(constant) => {
+            bytes.push(0);
+            bytes.extend_from_slice(constant.to_repr().as_ref());
+        }
+        Expression::Selector(selector) => {
+            bytes.push(1);
+            write_usize(bytes, selector.0);
+            bytes.push(selector.is_simple() as u8);
+        }
+        Expression::Fixed(query) => write_query(bytes, 2, query.column_index, query.rotation.0),
+        Expression::Advice(query) => {
+            write_query(bytes, 3, query.column_index, query.rotation.0);
+            bytes.push(query.phase());
+        }
+        Expression::Instance(query) => write_query(bytes, 4, query.column_index, query.rotation.0),
+        Expression::Challenge(challenge) => {
+            bytes.push(5);
+            write_usize(bytes, challenge.index());
+            bytes.push(challenge.phase());
+        }
+        Expression::Negated(a) => {
+            bytes.push(6);
+            write_expression(bytes, a);
+        }
+        Expression::Sum(a, b) => {
+            bytes.push(7);
+            write_expression(bytes, a);
+            write_expression(bytes, b);
+        }
+        Expression::Product(a, b) => {
+            bytes.push(8);
+            write_expression(bytes, a);
+            write_expression(bytes, b);
+        }
+        Expression::Scaled(a, scalar) => {
+            bytes.push(9);
+            write_expression(bytes, a);
+            bytes.extend_from_slice(scalar.to_repr().as_ref());
+        }
+    }
+}
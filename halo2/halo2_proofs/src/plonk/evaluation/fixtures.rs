@@ -0,0 +1,298 @@
+use super::{EvalControl, EvalError, EvalScratch, Evaluator};
+use crate::circuit::{Layouter, SimpleFloorPlanner, Value};
+use crate::plonk::{
+    keygen_pk, keygen_vk, lookup, permutation, Advice, ChallengeBeta, ChallengeGamma,
+    ChallengeTheta, ChallengeY, Circuit, Column, ConstraintSystem, Error, Expression, Fixed,
+    Instance, ProvingKey, TableColumn,
+};
+use crate::poly::{
+    commitment::ParamsProver, ipa::commitment::ParamsIPA, Coeff, ExtendedLagrangeCoeff,
+    LagrangeCoeff, Polynomial, Rotation,
+};
+use crate::transcript::{Blake2bWrite, Challenge255, Transcript, TranscriptWriterBuffer};
+use group::ff::Field;
+use halo2curves::pasta::{EqAffine, Fp};
+use rand_core::OsRng;
+use std::cell::Cell;
+
+thread_local! {
+    /// The numbers of custom gates and lookups of the `FixtureCircuit` being
+    /// configured, as `configure` takes no parameters
+    static SHAPE: Cell<(usize, usize)> = Cell::new((1, 0));
+}
+
+#[derive(Clone, Debug)]
+struct FixtureConfig {
+    a: Column<Advice>,
+    b: Vec<Column<Advice>>,
+    q: Column<Fixed>,
+    q_next: Column<Fixed>,
+    instance: Column<Instance>,
+    table: TableColumn,
+}
+
+/// `a` counts the rows, `b_i = a * (a + i + 1)` on every row for the gate
+/// `i`, `a` is copied from the first instance row and `a + j` is looked up in
+/// a table of the usable rows for the lookup `j`
+#[derive(Clone, Debug)]
+struct FixtureCircuit {
+    rows: usize,
+    table_rows: usize,
+}
+
+impl Circuit<Fp> for FixtureCircuit {
+    type Config = FixtureConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> FixtureConfig {
+        let (num_gates, num_lookups) = SHAPE.with(|shape| shape.get());
+        let a = meta.advice_column();
+        let b: Vec<_> = (0..num_gates).map(|_| meta.advice_column()).collect();
+        let q = meta.fixed_column();
+        let q_next = meta.fixed_column();
+        let instance = meta.instance_column();
+        let table = meta.lookup_table_column();
+
+        for (idx, b) in b.iter().enumerate() {
+            meta.create_gate("product", |meta| {
+                let q = meta.query_fixed(q, Rotation::cur());
+                let a = meta.query_advice(a, Rotation::cur());
+                let b = meta.query_advice(*b, Rotation::cur());
+                let c = Expression::Constant(Fp::from(idx as u64 + 1));
+                vec![q * (b - a.clone() * (a + c))]
+            });
+        }
+        meta.create_gate("count", |meta| {
+            let q_next = meta.query_fixed(q_next, Rotation::cur());
+            let a = meta.query_advice(a, Rotation::cur());
+            let next = meta.query_advice(a, Rotation::next());
+            vec![q_next * (next - a - Expression::Constant(Fp::one()))]
+        });
+
+        meta.enable_equality(a);
+        meta.enable_equality(b[0]);
+        meta.enable_equality(instance);
+
+        for idx in 0..num_lookups {
+            meta.lookup("range", |meta| {
+                let a = meta.query_advice(a, Rotation::cur());
+                vec![(a + Expression::Constant(Fp::from(idx as u64)), table)]
+            });
+        }
+
+        FixtureConfig {
+            a,
+            b,
+            q,
+            q_next,
+            instance,
+            table,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: FixtureConfig,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        // Only the fixed columns and the copies are assigned, the witness is
+        // computed by `Fixture::new`
+        let a = layouter.assign_region(
+            || "gates",
+            |mut region| {
+                for row in 0..self.rows {
+                    region.assign_fixed(|| "q", config.q, row, || Value::known(Fp::one()))?;
+                    if row + 1 < self.rows {
+                        region.assign_fixed(
+                            || "q_next",
+                            config.q_next,
+                            row,
+                            || Value::known(Fp::one()),
+                        )?;
+                    }
+                }
+                region.assign_advice(|| "a", config.a, 0, Value::<Fp>::unknown)
+            },
+        )?;
+        layouter.constrain_instance(a.cell(), config.instance, 0)?;
+        layouter.assign_table(
+            || "range",
+            |mut table| {
+                for row in 0..self.table_rows {
+                    table.assign_cell(
+                        || "range",
+                        config.table,
+                        row,
+                        || Value::known(Fp::from(row as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// A circuit with `num_gates` custom gates, `num_lookups` lookups and a
+/// permutation over three columns, a witness satisfying it and the inputs of
+/// `evaluate_h` computed from the witness as the prover does, so that h(X)
+/// vanishes on the domain
+pub(in crate::plonk) struct Fixture {
+    pub(in crate::plonk) pk: ProvingKey<EqAffine>,
+    pub(in crate::plonk) advice: Vec<Polynomial<Fp, Coeff>>,
+    pub(in crate::plonk) instance: Vec<Polynomial<Fp, Coeff>>,
+    pub(in crate::plonk) y: Fp,
+    pub(in crate::plonk) beta: Fp,
+    pub(in crate::plonk) gamma: Fp,
+    pub(in crate::plonk) theta: Fp,
+    pub(in crate::plonk) lookups: Vec<Vec<lookup::prover::Committed<EqAffine>>>,
+    pub(in crate::plonk) permutations: Vec<permutation::prover::Committed<EqAffine>>,
+}
+
+impl Fixture {
+    /// Builds the fixture for a circuit of `2^k` rows
+    pub(in crate::plonk) fn new(k: u32, num_gates: usize, num_lookups: usize) -> Self {
+        assert!(
+            num_gates > 0,
+            "the permutation copies the first gate column"
+        );
+        SHAPE.with(|shape| shape.set((num_gates, num_lookups)));
+
+        let n = 1 << k;
+        let mut cs = ConstraintSystem::default();
+        FixtureCircuit::configure(&mut cs);
+        let usable_rows = n - (cs.blinding_factors() + 1);
+        // The lookups of the last rows stay in the table
+        let rows = usable_rows - num_lookups;
+        let circuit = FixtureCircuit {
+            rows,
+            table_rows: usable_rows,
+        };
+
+        let params = ParamsIPA::<EqAffine>::new(k);
+        let vk = keygen_vk(&params, &circuit).expect("keygen_vk should not fail");
+        let pk = keygen_pk(&params, vk, &circuit).expect("keygen_pk should not fail");
+        let domain = &pk.vk.domain;
+
+        // The witness: zero on the usable rows without gates, random on the
+        // blinding rows
+        let column = |value: &dyn Fn(u64) -> Fp| {
+            let mut values = domain.empty_lagrange();
+            for (row, cell) in values.iter_mut().enumerate() {
+                *cell = match row {
+                    row if row < rows => value(row as u64),
+                    row if row < usable_rows => Fp::zero(),
+                    _ => Fp::random(OsRng),
+                };
+            }
+            values
+        };
+        let advice_values: Vec<Polynomial<Fp, LagrangeCoeff>> =
+            Some(column(&<Fp as From<u64>>::from))
+                .into_iter()
+                .chain(
+                    (0..num_gates)
+                        .map(|idx| column(&|row| Fp::from(row) * Fp::from(row + idx as u64 + 1))),
+                )
+                .collect();
+        let instance_values = vec![domain.empty_lagrange()];
+
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        let theta: ChallengeTheta<_> = transcript.squeeze_challenge_scalar();
+        let permuted: Vec<_> = pk
+            .vk
+            .cs
+            .lookups
+            .iter()
+            .map(|lookup| {
+                lookup
+                    .commit_permuted(
+                        &pk,
+                        &params,
+                        domain,
+                        theta,
+                        &advice_values,
+                        &pk.fixed_values,
+                        &instance_values,
+                        &[],
+                        OsRng,
+                        &mut transcript,
+                    )
+                    .expect("the lookups should be satisfied")
+            })
+            .collect();
+        let beta: ChallengeBeta<_> = transcript.squeeze_challenge_scalar();
+        let gamma: ChallengeGamma<_> = transcript.squeeze_challenge_scalar();
+        let permutation = pk
+            .vk
+            .cs
+            .permutation
+            .commit(
+                &params,
+                &pk,
+                &pk.permutation,
+                &advice_values,
+                &pk.fixed_values,
+                &instance_values,
+                beta,
+                gamma,
+                OsRng,
+                &mut transcript,
+            )
+            .expect("the permutation should commit");
+        let lookups = permuted
+            .into_iter()
+            .map(|lookup| {
+                lookup
+                    .commit_product(&pk, &params, beta, gamma, OsRng, &mut transcript)
+                    .expect("the lookup products should commit")
+            })
+            .collect();
+        let y: ChallengeY<_> = transcript.squeeze_challenge_scalar();
+
+        let advice = advice_values
+            .into_iter()
+            .map(|values| domain.lagrange_to_coeff(values))
+            .collect();
+        let instance = instance_values
+            .into_iter()
+            .map(|values| domain.lagrange_to_coeff(values))
+            .collect();
+
+        Fixture {
+            advice,
+            instance,
+            y: *y,
+            beta: *beta,
+            gamma: *gamma,
+            theta: *theta,
+            lookups: vec![lookups],
+            permutations: vec![permutation],
+            pk,
+        }
+    }
+
+    /// Evaluates h(X) for the fixture with `ev`
+    pub(in crate::plonk) fn evaluate_h(
+        &self,
+        ev: &Evaluator<EqAffine>,
+    ) -> Result<Polynomial<Fp, ExtendedLagrangeCoeff>, EvalError> {
+        ev.evaluate_h(
+            &self.pk,
+            &[&self.advice],
+            &[&self.instance],
+            &[],
+            self.y,
+            self.beta,
+            self.gamma,
+            self.theta,
+            &self.lookups,
+            &self.permutations,
+            &mut EvalScratch::default(),
+            &mut EvalControl::default(),
+        )
+    }
+}
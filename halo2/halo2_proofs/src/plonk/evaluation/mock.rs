@@ -0,0 +1,108 @@
+use super::{CancellationToken, CircuitData, CpuBackend, EvalError, Evaluator, HEvaluatorBackend};
+use crate::arithmetic::CurveAffine;
+use crate::plonk::{lookup, permutation};
+use crate::poly::{ExtendedLagrangeCoeff, Polynomial};
+
+/// A phase of the evaluation of h(X)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(in crate::plonk) enum Phase {
+    CustomGates,
+    Permutations,
+    Lookups,
+}
+
+/// A backend recording the phases it is asked to evaluate.
+///
+/// Every phase is delegated to the `CpuBackend` unless the backend was
+/// programmed to fail at it, in which case `values` is left untouched and the
+/// programmed error is returned. The failing phase may first succeed for some
+/// circuits, to fail in the middle of an evaluation. It may also cancel a
+/// token when a phase is called, as a client giving up during the evaluation.
+#[derive(Debug, Default)]
+pub(in crate::plonk) struct MockBackend {
+    /// The phase to fail at, and the error to fail with
+    pub(in crate::plonk) fail_at: Option<(Phase, EvalError)>,
+    /// The calls of the failing phase that succeed before it fails
+    pub(in crate::plonk) succeed_first: usize,
+    /// The phases called so far, with the size of `values` they were called with
+    pub(in crate::plonk) calls: Vec<(Phase, usize)>,
+    /// The phase cancelling the token when it is evaluated
+    pub(in crate::plonk) cancel_at: Option<(Phase, CancellationToken)>,
+}
+
+impl MockBackend {
+    /// Creates a backend failing at `phase` with `error`
+    pub(in crate::plonk) fn failing_at(phase: Phase, error: EvalError) -> Self {
+        Self {
+            fail_at: Some((phase, error)),
+            succeed_first: 0,
+            calls: vec![],
+            cancel_at: None,
+        }
+    }
+
+    /// Creates a backend cancelling `cancel` when `phase` is evaluated
+    pub(in crate::plonk) fn cancelling_at(phase: Phase, cancel: &CancellationToken) -> Self {
+        Self {
+            cancel_at: Some((phase, cancel.clone())),
+            ..Self::default()
+        }
+    }
+
+    /// Lets the failing phase succeed `calls` times before it fails
+    pub(in crate::plonk) fn after(self, calls: usize) -> Self {
+        Self {
+            succeed_first: calls,
+            ..self
+        }
+    }
+
+    fn record(&mut self, phase: Phase, size: usize) -> Result<(), EvalError> {
+        let previous = self.calls.iter().filter(|call| call.0 == phase).count();
+        self.calls.push((phase, size));
+        if let Some((cancel_phase, cancel)) = &self.cancel_at {
+            if *cancel_phase == phase {
+                cancel.cancel();
+            }
+        }
+        match &self.fail_at {
+            Some((fail_phase, error)) if *fail_phase == phase && previous >= self.succeed_first => {
+                Err(error.clone())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<C: CurveAffine> HEvaluatorBackend<C> for MockBackend {
+    fn evaluate_custom_gates(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        self.record(Phase::CustomGates, values.len())?;
+        CpuBackend.evaluate_custom_gates(ev, data, values)
+    }
+
+    fn evaluate_permutations(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        self.record(Phase::Permutations, values.len())?;
+        CpuBackend.evaluate_permutations(data, permutation, values)
+    }
+
+    fn evaluate_lookups(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        self.record(Phase::Lookups, values.len())?;
+        CpuBackend.evaluate_lookups(ev, data, lookups, values)
+    }
+}
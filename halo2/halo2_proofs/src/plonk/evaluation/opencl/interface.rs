@@ -0,0 +1,79 @@
+use super::super::{as_bytes, as_bytes_mut, EvalError};
+use ocl::{Buffer, ProQue};
+use std::collections::HashMap;
+
+/// An OpenCL program and the device buffers its kernels are launched with.
+///
+/// Buffers are addressed by name and hold raw bytes, mirroring the CUDA
+/// `DriverInterface`, so host vectors are uploaded with their in-memory layout.
+pub(in crate::plonk) struct ClInterface {
+    pro_que: ProQue,
+    buffers: HashMap<&'static str, Buffer<u8>>,
+}
+
+impl ClInterface {
+    /// Builds `src` for the first device of the default platform
+    pub(in crate::plonk) fn new(src: &str) -> Result<Self, EvalError> {
+        let pro_que = ProQue::builder().src(src).build()?;
+        Ok(Self {
+            pro_que,
+            buffers: HashMap::new(),
+        })
+    }
+
+    /// Allocates a device buffer named `name` holding a copy of `data`
+    pub(in crate::plonk) fn add_allocation<T: Copy>(
+        &mut self,
+        name: &'static str,
+        data: &[T],
+    ) -> Result<(), EvalError> {
+        let bytes = as_bytes(data);
+        let buffer = Buffer::<u8>::builder()
+            .queue(self.pro_que.queue().clone())
+            .len(bytes.len())
+            .copy_host_slice(bytes)
+            .build()?;
+        self.buffers.insert(name, buffer);
+        Ok(())
+    }
+
+    /// Launches `kernel` over `global_work_size` work items, passing the named
+    /// buffers followed by `scalars` as arguments
+    pub(in crate::plonk) fn launch_kernel(
+        &self,
+        kernel: &str,
+        buffers: &[&'static str],
+        scalars: &[i32],
+        global_work_size: usize,
+    ) -> Result<(), EvalError> {
+        let mut builder = self.pro_que.kernel_builder(kernel);
+        builder.global_work_size(global_work_size);
+        for &name in buffers {
+            builder.arg(self.buffer(name)?);
+        }
+        for scalar in scalars {
+            builder.arg(*scalar);
+        }
+        let kernel = builder.build()?;
+
+        // Safety: the kernel only accesses the buffers it is given, which are
+        // sized by the caller for `global_work_size` work items.
+        unsafe { kernel.enq()? };
+        Ok(())
+    }
+
+    /// Copies the device buffer named `name` back into `data`
+    pub(in crate::plonk) fn copy_vec_to_host<T: Copy>(
+        &self,
+        name: &'static str,
+        data: &mut [T],
+    ) -> Result<(), EvalError> {
+        Ok(self.buffer(name)?.read(as_bytes_mut(data)).enq()?)
+    }
+
+    fn buffer(&self, name: &'static str) -> Result<&Buffer<u8>, EvalError> {
+        self.buffers
+            .get(name)
+            .ok_or_else(|| EvalError::OpenCl(format!("no buffer named '{}'", name)))
+    }
+}
@@ -0,0 +1,219 @@
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_int, c_uint, c_void};
+use std::ptr;
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cuMemHostAlloc(pp: *mut *mut c_void, bytesize: usize, flags: c_uint) -> c_int;
+    fn cuMemFreeHost(p: *mut c_void) -> c_int;
+}
+
+/// Allocates the buffers of a `PinnedPool`
+pub(in crate::plonk) trait HostAllocator {
+    /// Allocates `bytes` bytes aligned for any field element, or returns
+    /// `None` if they cannot be allocated
+    fn allocate(&mut self, bytes: usize) -> Option<*mut u8>;
+
+    /// Frees the `bytes` bytes at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` was returned by `allocate` for `bytes` bytes and is not freed yet.
+    unsafe fn free(&mut self, ptr: *mut u8, bytes: usize);
+}
+
+/// Page-locked memory of the current CUDA context, which is freed along with
+/// the context if it is not freed before
+#[derive(Debug, Default)]
+pub(in crate::plonk) struct CudaHostAllocator;
+
+impl HostAllocator for CudaHostAllocator {
+    fn allocate(&mut self, bytes: usize) -> Option<*mut u8> {
+        let mut ptr = ptr::null_mut();
+        match unsafe { cuMemHostAlloc(&mut ptr, bytes, 0) } {
+            0 if !ptr.is_null() => Some(ptr as *mut u8),
+            _ => None,
+        }
+    }
+
+    unsafe fn free(&mut self, ptr: *mut u8, _bytes: usize) {
+        cuMemFreeHost(ptr as *mut c_void);
+    }
+}
+
+/// A buffer of a `PinnedPool`
+#[derive(Debug)]
+pub(in crate::plonk) struct PinnedBuffer {
+    ptr: *mut u8,
+    bytes: usize,
+}
+
+/// Data to upload, copied into a buffer of the pool or left where it is
+#[derive(Debug)]
+pub(in crate::plonk) enum Staged<'a, T> {
+    /// Copied into a page-locked buffer of the pool
+    Pinned {
+        buffer: PinnedBuffer,
+        len: usize,
+        _marker: PhantomData<&'a [T]>,
+    },
+    /// Left in pageable memory, the pool having no room for it
+    Pageable(&'a [T]),
+}
+
+impl<'a, T> Staged<'a, T> {
+    /// The staged data
+    pub(in crate::plonk) fn as_slice(&self) -> &[T] {
+        match self {
+            // Safety: the buffer holds `len` elements copied by `stage`, and
+            // is only reused once released
+            Staged::Pinned { buffer, len, .. } => unsafe {
+                std::slice::from_raw_parts(buffer.ptr as *const T, *len)
+            },
+            Staged::Pageable(data) => data,
+        }
+    }
+
+    /// Whether the data was copied into page-locked memory
+    #[cfg(test)]
+    pub(in crate::plonk) fn is_pinned(&self) -> bool {
+        matches!(self, Staged::Pinned { .. })
+    }
+}
+
+/// Page-locked host buffers of at most `capacity` bytes in total, kept from
+/// one launch to the next, that the uploads are staged in. The device copies
+/// page-locked memory directly, while the driver copies pageable memory
+/// through page-locked buffers of its own, chunk by chunk.
+///
+/// Page-locked memory is taken from the memory the OS can page out, so the
+/// pool only grows up to its capacity, and the data that does not fit is
+/// uploaded from where it is.
+#[derive(Debug)]
+pub(in crate::plonk) struct PinnedPool<A: HostAllocator = CudaHostAllocator> {
+    pub(in crate::plonk) allocator: A,
+    capacity: usize,
+    /// The bytes of the buffers allocated, released or not
+    allocated: usize,
+    /// The released buffers, available to stage more data
+    free: Vec<PinnedBuffer>,
+}
+
+impl<A: HostAllocator> PinnedPool<A> {
+    pub(in crate::plonk) fn new(allocator: A, capacity: usize) -> Self {
+        PinnedPool {
+            allocator,
+            capacity,
+            allocated: 0,
+            free: vec![],
+        }
+    }
+
+    /// The bytes of the buffers allocated, released or not
+    #[cfg(test)]
+    pub(in crate::plonk) fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Changes the capacity, freeing the released buffers if more than the new
+    /// capacity is allocated
+    pub(in crate::plonk) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if self.allocated > capacity {
+            self.free_released();
+        }
+    }
+
+    /// Copies `data` into a buffer of the pool, reusing a released one or
+    /// allocating one if the capacity allows it. Otherwise, with a warning if
+    /// the pool is enabled, `data` is left in pageable memory.
+    pub(in crate::plonk) fn stage<'a, T: Copy>(&mut self, data: &'a [T]) -> Staged<'a, T> {
+        let bytes = mem::size_of_val(data);
+        if bytes == 0 || self.capacity == 0 {
+            return Staged::Pageable(data);
+        }
+        if bytes > self.capacity {
+            tracing::warn!(
+                "{} bytes do not fit in a pinned pool of {} bytes, uploading them from pageable memory",
+                bytes,
+                self.capacity
+            );
+            return Staged::Pageable(data);
+        }
+
+        let buffer = match self.take_released(bytes) {
+            Some(buffer) => Some(buffer),
+            None => {
+                // The released buffers are too small, make room for a larger one
+                if self.allocated + bytes > self.capacity {
+                    self.free_released();
+                }
+                self.allocate(bytes)
+            }
+        };
+        match buffer {
+            Some(buffer) => {
+                // Safety: the buffer holds at least `bytes` bytes, aligned for
+                // any field element, and is not otherwise in use
+                unsafe {
+                    ptr::copy_nonoverlapping(data.as_ptr(), buffer.ptr as *mut T, data.len())
+                };
+                Staged::Pinned {
+                    buffer,
+                    len: data.len(),
+                    _marker: PhantomData,
+                }
+            }
+            None => {
+                tracing::warn!(
+                    "pinned pool of {} bytes is full, uploading {} bytes from pageable memory",
+                    self.capacity,
+                    bytes
+                );
+                Staged::Pageable(data)
+            }
+        }
+    }
+
+    /// Returns the buffer of `staged`, if it has one, to the pool
+    pub(in crate::plonk) fn release<T>(&mut self, staged: Staged<'_, T>) {
+        if let Staged::Pinned { buffer, .. } = staged {
+            self.free.push(buffer);
+        }
+    }
+
+    /// The smallest released buffer of at least `bytes` bytes
+    fn take_released(&mut self, bytes: usize) -> Option<PinnedBuffer> {
+        let (idx, _) = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.bytes >= bytes)
+            .min_by_key(|(_, buffer)| buffer.bytes)?;
+        Some(self.free.swap_remove(idx))
+    }
+
+    fn allocate(&mut self, bytes: usize) -> Option<PinnedBuffer> {
+        if self.allocated + bytes > self.capacity {
+            return None;
+        }
+        let ptr = self.allocator.allocate(bytes)?;
+        self.allocated += bytes;
+        Some(PinnedBuffer { ptr, bytes })
+    }
+
+    fn free_released(&mut self) {
+        for buffer in self.free.drain(..) {
+            // Safety: the buffers were allocated by `allocate` and released
+            unsafe { self.allocator.free(buffer.ptr, buffer.bytes) };
+            self.allocated -= buffer.bytes;
+        }
+    }
+}
+
+impl<A: HostAllocator> Drop for PinnedPool<A> {
+    fn drop(&mut self) {
+        self.free_released();
+    }
+}
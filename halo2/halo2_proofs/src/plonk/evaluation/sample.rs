@@ -0,0 +1,24 @@
+use std::collections::BTreeSet;
+
+/// `rows` distinct rows of a domain of `size` rows, in increasing order,
+/// chosen uniformly from `seed` with a splitmix64 generator so that the same
+/// seed always gives the same rows. Every row when `rows` is at least `size`.
+pub(in crate::plonk) fn sample_rows(seed: u64, rows: usize, size: usize) -> Vec<usize> {
+    if rows >= size {
+        return (0..size).collect();
+    }
+
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut sampled = BTreeSet::new();
+    while sampled.len() < rows {
+        sampled.insert((next() % size as u64) as usize);
+    }
+    sampled.into_iter().collect()
+}
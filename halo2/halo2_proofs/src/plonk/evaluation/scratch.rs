@@ -0,0 +1,170 @@
+use crate::arithmetic::FieldExt;
+use crate::poly::{Coeff, EvaluationDomain, ExtendedLagrangeCoeff, Polynomial};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+use std::any::Any;
+use std::cell::RefCell;
+
+thread_local! {
+    /// The scratch buffers kept by this thread, for the field of its last
+    /// evaluation
+    static THREAD_SCRATCH: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+/// Buffers of the evaluation of h(X), kept from one evaluation to the next so
+/// that evaluating several times in a row only allocates them once. They grow
+/// to the sizes of the largest evaluation and are never shrunk.
+#[derive(Debug)]
+pub(in crate::plonk) struct EvalScratch<F: FieldExt> {
+    /// The advice cosets of each circuit
+    pub(in crate::plonk) advice: Vec<Vec<Polynomial<F, ExtendedLagrangeCoeff>>>,
+    /// The instance cosets of each circuit
+    pub(in crate::plonk) instance: Vec<Vec<Polynomial<F, ExtendedLagrangeCoeff>>>,
+    /// Whether the cosets were computed ahead of the evaluation, for the
+    /// polynomials it is given, by `Evaluator::evaluate_h_deferred`
+    pub(in crate::plonk) cosets_ready: bool,
+    /// The buffer of the caller of `Evaluator::evaluate_h_into`, which the
+    /// values of h(X) are evaluated into instead of a new one
+    pub(in crate::plonk) output: Option<Vec<F>>,
+    /// The buffer of h(X) of the previous proof, given back once divided by
+    /// the vanishing polynomial, for the next proof to evaluate into
+    pub(in crate::plonk) h_buffer: Option<Vec<F>>,
+}
+
+impl<F: FieldExt> Default for EvalScratch<F> {
+    fn default() -> Self {
+        Self {
+            advice: vec![],
+            instance: vec![],
+            cosets_ready: false,
+            output: None,
+            h_buffer: None,
+        }
+    }
+}
+
+impl<F: FieldExt> EvalScratch<F> {
+    /// Runs `f` with the scratch buffers kept by this thread, which live until
+    /// the thread exits. Buffers kept for another field are replaced.
+    pub(in crate::plonk) fn with_thread_scratch<T>(f: impl FnOnce(&mut Self) -> T) -> T {
+        THREAD_SCRATCH.with(|scratch| {
+            let mut scratch = scratch.borrow_mut();
+            if !scratch
+                .as_ref()
+                .map_or(false, |scratch| scratch.is::<Self>())
+            {
+                *scratch = Some(Box::new(Self::default()));
+            }
+            f(scratch.as_mut().unwrap().downcast_mut().unwrap())
+        })
+    }
+
+    /// The zeroed values of h(X) to evaluate into: the buffer of the caller if
+    /// there is one, a new one otherwise
+    pub(in crate::plonk) fn take_output(
+        &mut self,
+        domain: &EvaluationDomain<F>,
+    ) -> Polynomial<F, ExtendedLagrangeCoeff> {
+        match self.output.take() {
+            Some(mut values) => {
+                values.par_iter_mut().for_each(|value| *value = F::zero());
+                domain.extended_from_vec(values)
+            }
+            None => domain.empty_extended_parallel(),
+        }
+    }
+
+    /// The buffer of h(X) kept from the previous proof, resized to the
+    /// extended domain, or a new one. Its values are left as they are, for
+    /// `Evaluator::evaluate_h_into` to zero them.
+    pub(in crate::plonk) fn take_h_buffer(
+        &mut self,
+        domain: &EvaluationDomain<F>,
+    ) -> Polynomial<F, ExtendedLagrangeCoeff> {
+        match self.h_buffer.take() {
+            Some(mut values) => {
+                values.resize(domain.extended_len(), F::zero());
+                domain.extended_from_vec(values)
+            }
+            None => domain.empty_extended_parallel(),
+        }
+    }
+
+    /// Computes the advice and instance cosets of each circuit into the
+    /// scratch buffers, unless they were computed ahead of the evaluation
+    pub(in crate::plonk) fn compute_cosets(
+        &mut self,
+        domain: &EvaluationDomain<F>,
+        advice_polys: &[&[Polynomial<F, Coeff>]],
+        instance_polys: &[&[Polynomial<F, Coeff>]],
+    ) {
+        if self.cosets_ready {
+            return;
+        }
+        self.allocate_cosets(domain, advice_polys, instance_polys);
+        for (polys, cosets) in advice_polys.iter().zip(self.advice.iter_mut()) {
+            extended_cosets_into(domain, polys, cosets);
+        }
+        for (polys, cosets) in instance_polys.iter().zip(self.instance.iter_mut()) {
+            extended_cosets_into(domain, polys, cosets);
+        }
+    }
+
+    /// Allocates the buffers of the advice and instance cosets of each
+    /// circuit, reusing those already allocated, without computing them
+    pub(in crate::plonk) fn allocate_cosets(
+        &mut self,
+        domain: &EvaluationDomain<F>,
+        advice_polys: &[&[Polynomial<F, Coeff>]],
+        instance_polys: &[&[Polynomial<F, Coeff>]],
+    ) {
+        allocate_cosets(domain, advice_polys, &mut self.advice);
+        allocate_cosets(domain, instance_polys, &mut self.instance);
+    }
+}
+
+/// Allocates a coset for each of `polys` in `cosets`, reusing its polynomials
+fn allocate_cosets<F: FieldExt>(
+    domain: &EvaluationDomain<F>,
+    polys: &[&[Polynomial<F, Coeff>]],
+    cosets: &mut Vec<Vec<Polynomial<F, ExtendedLagrangeCoeff>>>,
+) {
+    cosets.resize_with(polys.len(), Vec::new);
+    for (polys, cosets) in polys.iter().zip(cosets.iter_mut()) {
+        cosets.truncate(polys.len());
+        while cosets.len() < polys.len() {
+            cosets.push(domain.empty_extended_parallel());
+        }
+    }
+}
+
+/// Computes the cosets of the `polys` of a circuit into its allocated
+/// `cosets`.
+///
+/// The columns are transformed in parallel, each FFT being parallel itself, so
+/// that the many small FFTs of a wide circuit keep all the cores busy. The
+/// cosets are all allocated beforehand, so this needs no more memory than
+/// transforming the columns one after the other. The columns which are all
+/// zero, e.g. unused by the witness, are not transformed, their cosets being
+/// zeroed instead.
+pub(in crate::plonk) fn extended_cosets_into<F: FieldExt>(
+    domain: &EvaluationDomain<F>,
+    polys: &[Polynomial<F, Coeff>],
+    cosets: &mut [Polynomial<F, ExtendedLagrangeCoeff>],
+) {
+    polys
+        .par_iter()
+        .zip(cosets.par_iter_mut())
+        .for_each(|(poly, coset)| match is_zero_poly(poly) {
+            true => coset.par_iter_mut().for_each(|value| *value = F::zero()),
+            false => domain.coeff_to_extended_into(poly, coset),
+        });
+}
+
+/// Whether all the coefficients of `poly` are zero, so that its coset is zero
+/// too. It stops at the first nonzero coefficient, the first one for most
+/// columns of a witness.
+pub(in crate::plonk) fn is_zero_poly<F: FieldExt>(poly: &Polynomial<F, Coeff>) -> bool {
+    poly.par_iter().all(|coeff| *coeff == F::zero())
+}
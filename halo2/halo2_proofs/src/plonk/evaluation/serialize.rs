@@ -0,0 +1,317 @@
+use super::{
+    cs_fingerprint, fnv1a, Calculation, CalculationInfo, EvalError, Evaluator, EvaluatorConfig,
+    GraphEvaluator, HCache, ValueSource,
+};
+use crate::arithmetic::CurveAffine;
+use crate::plonk::ConstraintSystem;
+use group::ff::PrimeField;
+use std::io;
+
+/// Magic bytes starting a serialized evaluator
+const MAGIC: [u8; 4] = *b"h2ev";
+
+/// Version of the serialized evaluator, bumped whenever the graphs or their
+/// encoding change
+const FORMAT_VERSION: u32 = 4;
+
+impl<C: CurveAffine> Evaluator<C> {
+    /// Writes the evaluation graphs, so that they can be read back with
+    /// [`Evaluator::read`] instead of being rebuilt from the constraint system,
+    /// along with the [`Evaluator::fingerprint`] of that constraint system.
+    /// The configuration is not written.
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        write_u32(writer, FORMAT_VERSION)?;
+        writer.write_all(&self.cs_fingerprint.to_le_bytes())?;
+        write_graph(writer, &self.custom_gates)?;
+        write_len(writer, self.lookups.len())?;
+        for graph in self.lookups.iter() {
+            write_graph(writer, graph)?;
+        }
+        Ok(())
+    }
+
+    /// Reads evaluation graphs written by [`Evaluator::write`] and checks them
+    /// against `cs`, the constraint system of the verifying key they are used
+    /// with: its [`Evaluator::fingerprint`] has to be the one written, and the
+    /// graphs are validated, see [`Evaluator::validate`]. Graphs written in
+    /// another format or for another circuit are rejected with
+    /// `io::ErrorKind::InvalidData`.
+    /// The configuration is read from the environment, as in
+    /// [`Evaluator::new`].
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+        cs: &ConstraintSystem<C::ScalarExt>,
+    ) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not a serialized evaluator"));
+        }
+        let version = read_u32(reader)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "evaluator format version {} is not supported, expected {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        let mut fingerprint = [0u8; 8];
+        reader.read_exact(&mut fingerprint)?;
+        let fingerprint = u64::from_le_bytes(fingerprint);
+        if fingerprint != cs_fingerprint(cs) {
+            return Err(invalid_data(format!(
+                "evaluator of the constraint system {:016x}, not of {:016x}",
+                fingerprint,
+                cs_fingerprint(cs)
+            )));
+        }
+
+        let custom_gates = read_graph(reader)?;
+        let lookups = (0..read_u32(reader)?)
+            .map(|_| read_graph(reader))
+            .collect::<io::Result<_>>()?;
+
+        let ev = Evaluator {
+            custom_gates,
+            lookups,
+            config: EvaluatorConfig::from_env(),
+            custom_kernels: vec![],
+            h_cache: HCache::default(),
+            cs_fingerprint: fingerprint,
+        };
+        ev.validate(cs).map_err(invalid_data)?;
+        Ok(ev)
+    }
+
+    /// A fingerprint of the custom gates, the 64-bit FNV-1a hash of their
+    /// serialized graph, which identifies a gate set for
+    /// [`Evaluator::register_custom_kernel`]. It is the same for the
+    /// evaluators of a constraint system on every platform, but changes with
+    /// the format of the graphs, so it is best computed at startup, from an
+    /// evaluator of the gate set, rather than hard-coded.
+    pub fn gates_fingerprint(&self) -> u64 {
+        let mut bytes = FORMAT_VERSION.to_le_bytes().to_vec();
+        write_graph(&mut bytes, &self.custom_gates).expect("writing to a Vec cannot fail");
+        fnv1a(&bytes)
+    }
+
+    /// Checks that the evaluator can evaluate the constraint system `cs`: it
+    /// has a graph for each of its lookup arguments, and every graph only
+    /// reads the columns and challenges `cs` has, see
+    /// [`GraphEvaluator::validate`]. This catches evaluators built for another
+    /// version of the circuit, but not ones whose constraints differ while
+    /// reading the same values.
+    pub fn validate(&self, cs: &ConstraintSystem<C::ScalarExt>) -> Result<(), EvalError> {
+        if self.lookups.len() != cs.lookups.len() {
+            return Err(EvalError::InvalidGraph(format!(
+                "the evaluator has {} lookup graphs, the constraint system has {} arguments",
+                self.lookups.len(),
+                cs.lookups.len()
+            )));
+        }
+
+        let graphs = Some(&self.custom_gates)
+            .into_iter()
+            .chain(self.lookups.iter());
+        for graph in graphs {
+            graph.validate(
+                cs.num_fixed_columns(),
+                cs.num_advice_columns(),
+                cs.num_instance_columns(),
+                cs.num_challenges(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn write_u32<W: io::Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_len<W: io::Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| invalid_data("length does not fit in 32 bits"))?;
+    write_u32(writer, len)
+}
+
+fn read_u32<R: io::Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_len<R: io::Read>(reader: &mut R) -> io::Result<usize> {
+    Ok(read_u32(reader)? as usize)
+}
+
+/// Writes the constants, rotations and calculations of a graph
+fn write_graph<W: io::Write, C: CurveAffine>(
+    writer: &mut W,
+    graph: &GraphEvaluator<C>,
+) -> io::Result<()> {
+    write_len(writer, graph.constants.len())?;
+    for constant in graph.constants.iter() {
+        writer.write_all(constant.to_repr().as_ref())?;
+    }
+    write_len(writer, graph.rotations.len())?;
+    for rotation in graph.rotations.iter() {
+        writer.write_all(&rotation.to_le_bytes())?;
+    }
+    write_len(writer, graph.calculations.len())?;
+    for calc in graph.calculations.iter() {
+        write_calculation(writer, &calc.calculation)?;
+        write_len(writer, calc.target)?;
+    }
+    write_len(writer, graph.num_intermediates)
+}
+
+fn read_graph<R: io::Read, C: CurveAffine>(reader: &mut R) -> io::Result<GraphEvaluator<C>> {
+    let constants = (0..read_u32(reader)?)
+        .map(|_| {
+            let mut repr = <C::ScalarExt as PrimeField>::Repr::default();
+            reader.read_exact(repr.as_mut())?;
+            Option::from(C::ScalarExt::from_repr(repr))
+                .ok_or_else(|| invalid_data("constant is not a field element"))
+        })
+        .collect::<io::Result<_>>()?;
+    let rotations = (0..read_u32(reader)?)
+        .map(|_| {
+            let mut bytes = [0u8; 4];
+            reader.read_exact(&mut bytes)?;
+            Ok(i32::from_le_bytes(bytes))
+        })
+        .collect::<io::Result<_>>()?;
+    let calculations = (0..read_u32(reader)?)
+        .map(|_| {
+            Ok(CalculationInfo {
+                calculation: read_calculation(reader)?,
+                target: read_len(reader)?,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(GraphEvaluator {
+        constants,
+        rotations,
+        calculations,
+        num_intermediates: read_len(reader)?,
+    })
+}
+
+/// Writes a calculation as its tag followed by its operands
+fn write_calculation<W: io::Write>(writer: &mut W, calculation: &Calculation) -> io::Result<()> {
+    let (tag, sources): (u8, Vec<&ValueSource>) = match calculation {
+        Calculation::Add(a, b) => (0, vec![a, b]),
+        Calculation::Sub(a, b) => (1, vec![a, b]),
+        Calculation::Mul(a, b) => (2, vec![a, b]),
+        Calculation::Square(v) => (3, vec![v]),
+        Calculation::Double(v) => (4, vec![v]),
+        Calculation::Negate(v) => (5, vec![v]),
+        Calculation::MulAdd(a, b, c) => (6, vec![a, b, c]),
+        Calculation::Horner(start_value, parts, factor) => {
+            writer.write_all(&[7])?;
+            write_source(writer, start_value)?;
+            write_len(writer, parts.len())?;
+            for part in parts.iter() {
+                write_source(writer, part)?;
+            }
+            return write_source(writer, factor);
+        }
+        Calculation::Store(v) => (8, vec![v]),
+        Calculation::Inverse(v) => (10, vec![v]),
+        Calculation::LinearCombination(terms) => {
+            writer.write_all(&[9])?;
+            write_len(writer, terms.len())?;
+            for (a, b) in terms.iter() {
+                write_source(writer, a)?;
+                write_source(writer, b)?;
+            }
+            return Ok(());
+        }
+    };
+    writer.write_all(&[tag])?;
+    for source in sources {
+        write_source(writer, source)?;
+    }
+    Ok(())
+}
+
+fn read_calculation<R: io::Read>(reader: &mut R) -> io::Result<Calculation> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    Ok(match tag[0] {
+        0 => Calculation::Add(read_source(reader)?, read_source(reader)?),
+        1 => Calculation::Sub(read_source(reader)?, read_source(reader)?),
+        2 => Calculation::Mul(read_source(reader)?, read_source(reader)?),
+        3 => Calculation::Square(read_source(reader)?),
+        4 => Calculation::Double(read_source(reader)?),
+        5 => Calculation::Negate(read_source(reader)?),
+        6 => Calculation::MulAdd(
+            read_source(reader)?,
+            read_source(reader)?,
+            read_source(reader)?,
+        ),
+        7 => {
+            let start_value = read_source(reader)?;
+            let parts = (0..read_u32(reader)?)
+                .map(|_| read_source(reader))
+                .collect::<io::Result<_>>()?;
+            Calculation::Horner(start_value, parts, read_source(reader)?)
+        }
+        8 => Calculation::Store(read_source(reader)?),
+        9 => Calculation::LinearCombination(
+            (0..read_u32(reader)?)
+                .map(|_| -> io::Result<_> { Ok((read_source(reader)?, read_source(reader)?)) })
+                .collect::<io::Result<_>>()?,
+        ),
+        10 => Calculation::Inverse(read_source(reader)?),
+        tag => return Err(invalid_data(format!("unknown calculation tag {}", tag))),
+    })
+}
+
+/// Writes a value source as its tag followed by its two indices, unused ones
+/// being zero
+fn write_source<W: io::Write>(writer: &mut W, source: &ValueSource) -> io::Result<()> {
+    let (tag, a, b) = match *source {
+        ValueSource::Constant(idx) => (0, idx, 0),
+        ValueSource::Intermediate(idx) => (1, idx, 0),
+        ValueSource::Fixed(column, rotation) => (2, column, rotation),
+        ValueSource::Advice(column, rotation) => (3, column, rotation),
+        ValueSource::Instance(column, rotation) => (4, column, rotation),
+        ValueSource::Challenge(idx) => (5, idx, 0),
+        ValueSource::Beta() => (6, 0, 0),
+        ValueSource::Gamma() => (7, 0, 0),
+        ValueSource::Theta() => (8, 0, 0),
+        ValueSource::Y() => (9, 0, 0),
+        ValueSource::PreviousValue() => (10, 0, 0),
+        ValueSource::ThetaPower(power) => (11, power, 0),
+    };
+    writer.write_all(&[tag])?;
+    write_len(writer, a)?;
+    write_len(writer, b)
+}
+
+fn read_source<R: io::Read>(reader: &mut R) -> io::Result<ValueSource> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    let a = read_len(reader)?;
+    let b = read_len(reader)?;
+    Ok(match tag[0] {
+        0 => ValueSource::Constant(a),
+        1 => ValueSource::Intermediate(a),
+        2 => ValueSource::Fixed(a, b),
+        3 => ValueSource::Advice(a, b),
+        4 => ValueSource::Instance(a, b),
+        5 => ValueSource::Challenge(a),
+        6 => ValueSource::Beta(),
+        7 => ValueSource::Gamma(),
+        8 => ValueSource::Theta(),
+        9 => ValueSource::Y(),
+        10 => ValueSource::PreviousValue(),
+        11 => ValueSource::ThetaPower(a),
+        tag => return Err(invalid_data(format!("unknown value source tag {}", tag))),
+    })
+}
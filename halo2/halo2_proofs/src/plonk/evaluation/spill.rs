@@ -0,0 +1,28 @@
+use crate::arithmetic::FieldExt;
+use group::ff::PrimeField;
+use std::io;
+
+/// Writes `values` in their canonical form, one after the other
+pub(in crate::plonk) fn write_rows<F: FieldExt, W: io::Write>(
+    writer: &mut W,
+    values: &[F],
+) -> io::Result<()> {
+    for value in values {
+        writer.write_all(value.to_repr().as_ref())?;
+    }
+    Ok(())
+}
+
+/// Reads back into `values` the values written by `write_rows`
+pub(in crate::plonk) fn read_rows<F: FieldExt, R: io::Read>(
+    reader: &mut R,
+    values: &mut [F],
+) -> io::Result<()> {
+    for value in values.iter_mut() {
+        let mut repr = F::Repr::default();
+        reader.read_exact(repr.as_mut())?;
+        *value = Option::<F>::from(F::from_repr(repr))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a field element"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::ops::{Add, Mul};
+
+/// Number of field operations done by (a part of) an evaluation of h(X).
+/// Subtractions, doublings and negations are counted as additions, squarings
+/// as multiplications.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpCounts {
+    /// Field multiplications
+    pub multiplications: u64,
+    /// Field additions
+    pub additions: u64,
+}
+
+impl OpCounts {
+    pub(in crate::plonk) fn new(multiplications: usize, additions: usize) -> Self {
+        OpCounts {
+            multiplications: multiplications as u64,
+            additions: additions as u64,
+        }
+    }
+}
+
+impl Add for OpCounts {
+    type Output = OpCounts;
+
+    fn add(self, rhs: OpCounts) -> OpCounts {
+        OpCounts {
+            multiplications: self.multiplications + rhs.multiplications,
+            additions: self.additions + rhs.additions,
+        }
+    }
+}
+
+impl Mul<u64> for OpCounts {
+    type Output = OpCounts;
+
+    fn mul(self, rows: u64) -> OpCounts {
+        OpCounts {
+            multiplications: self.multiplications * rows,
+            additions: self.additions * rows,
+        }
+    }
+}
+
+impl fmt::Display for OpCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} mul, {} add", self.multiplications, self.additions)
+    }
+}
+
+/// Field operations of an evaluation of h(X), per phase.
+///
+/// These are the operations of the constraints, whichever backend evaluates
+/// them. The evaluation of the graphs the device backends run on the CPU, like
+/// the compressed lookup expressions, is counted in the phase it belongs to.
+/// The coset FFTs are not counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvalStats {
+    /// The custom gates
+    pub custom_gates: OpCounts,
+    /// The permutation argument
+    pub permutations: OpCounts,
+    /// The lookup arguments
+    pub lookups: OpCounts,
+}
+
+impl EvalStats {
+    /// The operations of all the phases
+    pub fn total(&self) -> OpCounts {
+        self.custom_gates + self.permutations + self.lookups
+    }
+}
+
+impl fmt::Display for EvalStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "custom gates: {}, permutations: {}, lookups: {}, total: {}",
+            self.custom_gates,
+            self.permutations,
+            self.lookups,
+            self.total()
+        )
+    }
+}
+
+/// The sizes of the graphs of an evaluator, which the cost of evaluating a
+/// row grows with, to notice a change of the circuit blowing them up
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvaluatorStats {
+    /// The intermediate values of the custom gates graph
+    pub gate_intermediates: usize,
+    /// The constants of the custom gates graph, including the zero, one and
+    /// two every graph starts with
+    pub gate_constants: usize,
+    /// The distinct rotations the custom gates query
+    pub gate_rotations: usize,
+    /// The intermediate values of the graph of each lookup
+    pub per_lookup_intermediates: Vec<usize>,
+}
+
+/// Per row operations of the permutation constraints, for sets of
+/// `set_sizes` columns, as evaluated by `CpuBackend::permutations_rows`
+pub(in crate::plonk) fn permutation_row_counts(set_sizes: &[usize]) -> OpCounts {
+    if set_sizes.is_empty() {
+        return OpCounts::default();
+    }
+    let sets = set_sizes.len();
+    let columns: usize = set_sizes.iter().sum();
+    // l_0(X) * (1 - z_0(X))
+    let first = OpCounts::new(2, 2);
+    // l_last(X) * (z_l(X)^2 - z_l(X))
+    let last = OpCounts::new(3, 2);
+    // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) for all the sets but the first
+    let links = OpCounts::new(2, 2) * (sets - 1) as u64;
+    // The products of both sides, with the precomputed delta terms scaled by
+    // the power of omega of the row, then their difference times
+    // (1 - (l_last(X) + l_blind(X)))
+    let products = OpCounts::new(4, 4) * columns as u64 + OpCounts::new(2, 2) * sets as u64;
+    // The next power of omega
+    let powers = OpCounts::new(1, 0);
+    first + last + links + products + powers
+}
+
+/// Per row operations of the constraints of a lookup, as evaluated by
+/// `CpuBackend::lookups_rows`, without those of its compressed expressions
+pub(in crate::plonk) fn lookup_row_counts() -> OpCounts {
+    // a'(X) - s'(X)
+    let a_minus_s = OpCounts::new(0, 1);
+    // l_0(X) * (1 - z(X))
+    let first = OpCounts::new(2, 2);
+    // l_last(X) * (z(X)^2 - z(X))
+    let last = OpCounts::new(3, 2);
+    // (1 - (l_last(X) + l_blind(X))) * (z(\omega X) (a'(X) + \beta) (s'(X) + \gamma) - ...)
+    let product = OpCounts::new(5, 4);
+    // l_0(X) * (a'(X) - s'(X))
+    let start = OpCounts::new(2, 1);
+    // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X))
+    let permuted = OpCounts::new(3, 2);
+    a_minus_s + first + last + product + start + permuted
+}
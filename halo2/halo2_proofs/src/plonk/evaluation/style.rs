@@ -0,0 +1,41 @@
+#[cfg(feature = "pretty-logs")]
+pub(in crate::plonk) use colored::Colorize;
+
+/// The styles of `colored` the diagnostic messages use, leaving the text as
+/// is without the `pretty-logs` feature so that no ANSI escape ends up in
+/// captured logs
+#[cfg(not(feature = "pretty-logs"))]
+pub(in crate::plonk) trait Colorize: Sized {
+    /// The text itself
+    fn plain(self) -> String;
+
+    fn red(self) -> String {
+        self.plain()
+    }
+
+    fn green(self) -> String {
+        self.plain()
+    }
+
+    fn bold(self) -> String {
+        self.plain()
+    }
+
+    fn dimmed(self) -> String {
+        self.plain()
+    }
+}
+
+#[cfg(not(feature = "pretty-logs"))]
+impl Colorize for &str {
+    fn plain(self) -> String {
+        self.to_string()
+    }
+}
+
+#[cfg(not(feature = "pretty-logs"))]
+impl Colorize for String {
+    fn plain(self) -> String {
+        self
+    }
+}
@@ -0,0 +1,270 @@
+use super::{Backend, CudaBackend, EvalControl, EvalError, EvalScratch, LookupLayout};
+use crate::circuit::{Layouter, SimpleFloorPlanner, Value};
+use crate::halo2curves::bn256::{Bn256, Fr, G1Affine};
+use crate::plonk::{
+    keygen_pk, keygen_vk, lookup, permutation, Advice, Circuit, Column, ConstraintSystem, Error,
+    Expression, ProvingKey, Selector, TableColumn,
+};
+use crate::poly::{commitment::ParamsProver, kzg::commitment::ParamsKZG, Coeff, Polynomial};
+use crate::poly::{EvaluationDomain, Rotation};
+use group::ff::Field;
+use rand_core::OsRng;
+use std::cell::Cell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// The shape of the `SyntheticCircuit` being configured, as `configure`
+    /// takes no parameters: the degree of its product gate and its number of
+    /// lookups
+    static SHAPE: Cell<(usize, usize)> = Cell::new((3, 0));
+}
+
+#[derive(Clone, Debug)]
+struct SyntheticConfig {
+    advice: Vec<Column<Advice>>,
+    q: Selector,
+    table: TableColumn,
+}
+
+/// A circuit of a given shape, whose constraints are not satisfied: a gate
+/// multiplying advice columns up to the degree of the shape, a gate with a
+/// rotation, a permutation over two advice columns and range lookups of the
+/// advice columns
+#[derive(Clone, Debug)]
+struct SyntheticCircuit {
+    rows: usize,
+}
+
+impl Circuit<Fr> for SyntheticCircuit {
+    type Config = SyntheticConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        self.clone()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> SyntheticConfig {
+        let (degree, num_lookups) = SHAPE.with(|shape| shape.get());
+        let advice: Vec<_> = (0..(degree - 1).max(2))
+            .map(|_| meta.advice_column())
+            .collect();
+        let q = meta.selector();
+        let table = meta.lookup_table_column();
+
+        meta.create_gate("product", |meta| {
+            let q = meta.query_selector(q);
+            let product = advice[..degree - 1]
+                .iter()
+                .fold(Expression::Constant(Fr::one()), |product, column| {
+                    product * meta.query_advice(*column, Rotation::cur())
+                });
+            vec![q * product]
+        });
+        meta.create_gate("sum", |meta| {
+            let q = meta.query_selector(q);
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let next = meta.query_advice(advice[0], Rotation::next());
+            vec![q * (next - a - b)]
+        });
+
+        meta.enable_equality(advice[0]);
+        meta.enable_equality(advice[1]);
+
+        for idx in 0..num_lookups {
+            let column = advice[idx % advice.len()];
+            meta.lookup("range", |meta| {
+                vec![(meta.query_advice(column, Rotation::cur()), table)]
+            });
+        }
+
+        SyntheticConfig { advice, q, table }
+    }
+
+    fn synthesize(
+        &self,
+        config: SyntheticConfig,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "gates",
+            |mut region| {
+                for row in 0..self.rows - 1 {
+                    config.q.enable(&mut region, row)?;
+                }
+                Ok(())
+            },
+        )?;
+        layouter.assign_table(
+            || "range",
+            |mut table| {
+                for row in 0..self.rows {
+                    table.assign_cell(
+                        || "range",
+                        config.table,
+                        row,
+                        || Value::known(Fr::from(row as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// The proving key of a synthetic circuit over BN256 and random inputs for
+/// the evaluation of its h(X) polynomial, to time `evaluate_h` on the
+/// backends without running a prover.
+///
+/// The inputs are not a witness of the circuit, which does not change the
+/// work of the evaluation.
+pub struct SyntheticEvaluation {
+    pub(in crate::plonk) pk: ProvingKey<G1Affine>,
+    pub(in crate::plonk) advice: Vec<Polynomial<Fr, Coeff>>,
+    pub(in crate::plonk) lookups: Vec<Vec<lookup::prover::Committed<G1Affine>>>,
+    pub(in crate::plonk) permutation: permutation::prover::Committed<G1Affine>,
+}
+
+impl fmt::Debug for SyntheticEvaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyntheticEvaluation")
+            .field("k", &self.pk.vk.domain.k())
+            .field("extended_k", &self.pk.vk.domain.extended_k())
+            .field("num_lookups", &self.lookups[0].len())
+            .finish()
+    }
+}
+
+impl SyntheticEvaluation {
+    /// Builds a circuit of `2^k` rows whose h(X) is evaluated over `2^extended_k`
+    /// rows, with `num_lookups` lookup arguments, and random inputs for it.
+    ///
+    /// The extended domain is `2^ceil(log2(degree - 1))` times larger, the
+    /// degree of the lookups and of the permutation being at least 4 and 3, so
+    /// `extended_k` is at least `k + 1` and `k + 2` with lookups. Panics if
+    /// `extended_k` is out of these bounds or `k` leaves no usable rows.
+    pub fn new(k: u32, extended_k: u32, num_lookups: usize) -> Self {
+        assert!(
+            extended_k > k && (num_lookups == 0 || extended_k > k + 1),
+            "a circuit of 2^{} rows with {} lookups has no extended domain of 2^{} rows",
+            k,
+            num_lookups,
+            extended_k
+        );
+        SHAPE.with(|shape| shape.set(((1 << (extended_k - k)) + 1, num_lookups)));
+
+        let mut cs = ConstraintSystem::default();
+        SyntheticCircuit::configure(&mut cs);
+        let circuit = SyntheticCircuit {
+            rows: (1 << k) - cs.blinding_factors() - 1,
+        };
+        let params = ParamsKZG::<Bn256>::new(k);
+        let vk = keygen_vk(&params, &circuit).expect("keygen_vk should not fail");
+        let pk = keygen_pk(&params, vk, &circuit).expect("keygen_pk should not fail");
+        let domain = &pk.vk.domain;
+        assert_eq!(domain.extended_k(), extended_k);
+
+        let random_poly = |domain: &EvaluationDomain<Fr>| {
+            let mut poly = domain.empty_coeff();
+            for coeff in poly.iter_mut() {
+                *coeff = Fr::random(OsRng);
+            }
+            poly
+        };
+        let advice = (0..pk.vk.cs.num_advice_columns)
+            .map(|_| random_poly(domain))
+            .collect();
+        let lookups = vec![(0..num_lookups)
+            .map(|_| {
+                lookup::prover::Committed::from_polys(
+                    random_poly(domain),
+                    random_poly(domain),
+                    random_poly(domain),
+                )
+            })
+            .collect()];
+        let sets = pk
+            .vk
+            .cs
+            .permutation
+            .columns
+            .chunks(pk.vk.cs.degree() - 2)
+            .map(|_| permutation::prover::CommittedSet::from_poly(domain, random_poly(domain)))
+            .collect();
+
+        SyntheticEvaluation {
+            advice,
+            lookups,
+            permutation: permutation::prover::Committed { sets },
+            pk,
+        }
+    }
+
+    /// Zeroes the first `num_columns` advice columns, as in a sparse circuit
+    /// whose witness leaves some of its columns unused, for the cosets of
+    /// these columns not to be computed
+    pub fn with_zero_columns(mut self, num_columns: usize) -> Self {
+        for column in self.advice.iter_mut().take(num_columns) {
+            *column = self.pk.vk.domain.empty_coeff();
+        }
+        self
+    }
+
+    /// Lays out the per-row values of the lookups as `layout` on the CUDA
+    /// device, which has no effect on the other backends
+    pub fn with_lookup_layout(mut self, layout: LookupLayout) -> Self {
+        self.pk.ev.config.lookup_layout = layout;
+        self
+    }
+
+    /// The number of advice columns of the circuit
+    pub fn num_advice_columns(&self) -> usize {
+        self.advice.len()
+    }
+
+    /// Evaluates h(X) on `backend`, without falling back to the CPU, and
+    /// returns how long it took. The CUDA module is loaded beforehand, so that
+    /// its loading is not timed and a missing device or kernel is reported.
+    pub fn evaluate_h(&self, backend: Backend) -> Result<Duration, EvalError> {
+        let mut ev = self.pk.ev.clone();
+        ev.config.backend = backend;
+        ev.config.fallback_to_cpu = false;
+        if backend.is_cuda() {
+            if !CudaBackend::is_available() {
+                return Err(EvalError::NoCudaDevice);
+            }
+            ev.warmup_cuda()?;
+        }
+
+        let start = Instant::now();
+        ev.evaluate_h(
+            &self.pk,
+            &[&self.advice],
+            &[&[]],
+            &[],
+            Fr::random(OsRng),
+            Fr::random(OsRng),
+            Fr::random(OsRng),
+            Fr::random(OsRng),
+            &self.lookups,
+            std::slice::from_ref(&self.permutation),
+            &mut EvalScratch::default(),
+            &mut EvalControl::default(),
+        )?;
+        Ok(start.elapsed())
+    }
+}
+
+/// Builds a [`SyntheticEvaluation`] of the given shape and returns how long
+/// the evaluation of its h(X) takes on `backend`, see
+/// [`SyntheticEvaluation::evaluate_h`]. Building the proving key dominates,
+/// so a harness timing several runs of a shape builds it once instead.
+pub fn bench_evaluate_h(
+    k: u32,
+    extended_k: u32,
+    num_lookups: usize,
+    backend: Backend,
+) -> Result<Duration, EvalError> {
+    SyntheticEvaluation::new(k, extended_k, num_lookups).evaluate_h(backend)
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+use std::time::Duration;
+
+/// Durations of the phases of an evaluation of h(X), summed over its circuits,
+/// along with the parameters of the evaluation.
+///
+/// With the `eval-reports` feature, it serializes to a JSON object with the
+/// durations in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "eval-reports", derive(serde::Serialize))]
+pub struct EvalTimings {
+    /// The backend the evaluation was configured with
+    pub backend: &'static str,
+    /// Whether the evaluation fell back to the CPU after the backend failed
+    pub fallback: bool,
+    /// The CUDA device the evaluation ran on, as named in
+    /// `CUDA_VISIBLE_DEVICES`, if it ran on one
+    pub device: Option<String>,
+    /// The circuit has 2^k rows
+    pub k: u32,
+    /// The extended domain has 2^extended_k rows
+    pub extended_k: u32,
+    /// The number of circuits evaluated together
+    pub num_circuits: usize,
+    /// The number of lookup arguments of each circuit
+    pub num_lookups: usize,
+    /// Computing the advice and instance cosets
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub cosets: Duration,
+    /// The custom gates
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub custom_gates: Duration,
+    /// The permutation argument
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub permutations: Duration,
+    /// The lookup arguments
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub lookups: Duration,
+    /// The whole evaluation, including a failed attempt before a fallback
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub total: Duration,
+    /// The copies to and from the CUDA device, if the evaluation ran on one.
+    /// They are part of the durations of the lookups.
+    pub transfers: Transfers,
+}
+
+/// The copies between the host and the CUDA device of the lookup kernel
+/// launches of an evaluation, summed over the launches and their retries.
+///
+/// The driver wrapper uploads all the buffers of a launch in a single call, so
+/// the uploads of the challenges and of the cosets are only timed together.
+/// `l0`, `l_last` and `l_active_row` are only uploaded by the first launch of
+/// a thread on a domain, and stay resident for the following ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "eval-reports", derive(serde::Serialize))]
+pub struct Transfers {
+    /// The bytes of the buffers that are the same for every proof of the
+    /// circuit, `l0`, `l_last` and `l_active_row`, when they are not resident
+    /// yet, and of the challenges uploaded
+    pub constant_bytes: usize,
+    /// The bytes of the cosets and of the other buffers depending on the
+    /// advice uploaded, with the values the kernel accumulates into
+    pub coset_bytes: usize,
+    /// Uploading the constant buffers and the cosets
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub upload: Duration,
+    /// The bytes of the values copied back
+    pub values_bytes: usize,
+    /// Copying the values back
+    #[cfg_attr(feature = "eval-reports", serde(serialize_with = "millis"))]
+    pub download: Duration,
+}
+
+impl Transfers {
+    /// The effective bandwidth of the uploads, in GB/s, if there were any
+    pub fn upload_gbps(&self) -> Option<f64> {
+        gbps(self.constant_bytes + self.coset_bytes, self.upload)
+    }
+
+    /// The effective bandwidth of the copies back, in GB/s, if there were any
+    pub fn download_gbps(&self) -> Option<f64> {
+        gbps(self.values_bytes, self.download)
+    }
+}
+
+fn gbps(bytes: usize, duration: Duration) -> Option<f64> {
+    match (bytes, duration.as_secs_f64()) {
+        (0, _) => None,
+        (_, seconds) if seconds == 0.0 => None,
+        (bytes, seconds) => Some(bytes as f64 / seconds / 1e9),
+    }
+}
+
+impl EvalTimings {
+    /// Forgets the phase durations, keeping the total, for the evaluation to
+    /// start over
+    pub(in crate::plonk) fn restart(&mut self) {
+        *self = EvalTimings {
+            total: self.total,
+            ..Default::default()
+        };
+    }
+
+    /// Appends the timings as a line of JSON to the file at `path`, creating it
+    /// if needed
+    #[cfg(feature = "eval-reports")]
+    pub(in crate::plonk) fn append_to(&self, path: &Path) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(line.as_bytes())
+    }
+
+    /// Reports without the `eval-reports` feature are not written
+    #[cfg(not(feature = "eval-reports"))]
+    pub(in crate::plonk) fn append_to(&self, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "timing reports need the `eval-reports` feature",
+        ))
+    }
+}
+
+#[cfg(feature = "eval-reports")]
+fn millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,120 @@
+use super::{
+    as_bytes, prepare_lookups, CircuitData, CpuBackend, EvalError, Evaluator, HEvaluatorBackend,
+    LookupBuffers,
+};
+use crate::plonk::{lookup, permutation};
+use crate::{
+    arithmetic::CurveAffine,
+    poly::{ExtendedLagrangeCoeff, Polynomial},
+};
+use ark_std::{end_timer, start_timer};
+use group::ff::Field;
+use std::{convert::TryInto, env, fs};
+
+mod interface;
+
+use interface::WgpuInterface;
+
+/// Evaluates the lookup phase of h(X) with a wgpu compute shader.
+///
+/// This runs `compute_evaluate_h_lookups_codeblock` from the WGSL source
+/// pointed at by `WGSL_KERNEL`, on the same buffers as the `CudaBackend`. Like
+/// the CUDA kernel the shader only implements the BN256 scalar field. The
+/// custom gates and the permutations are evaluated on the CPU.
+#[derive(Clone, Copy, Debug, Default)]
+pub(in crate::plonk) struct WgpuBackend;
+
+impl<C: CurveAffine> HEvaluatorBackend<C> for WgpuBackend {
+    fn evaluate_custom_gates(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        CpuBackend.evaluate_custom_gates(ev, data, values)
+    }
+
+    fn evaluate_permutations(
+        &mut self,
+        data: &CircuitData<'_, C>,
+        permutation: &permutation::prover::Committed<C>,
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        CpuBackend.evaluate_permutations(data, permutation, values)
+    }
+
+    fn evaluate_lookups(
+        &mut self,
+        ev: &Evaluator<C>,
+        data: &CircuitData<'_, C>,
+        lookups: &[lookup::prover::Committed<C>],
+        values: &mut Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+    ) -> Result<(), EvalError> {
+        // Bindings can't be empty
+        if lookups.is_empty() {
+            return Ok(());
+        }
+
+        let pk = data.pk;
+        let (y, beta, gamma) = (data.y, data.beta, data.gamma);
+        let one = C::ScalarExt::one();
+
+        let lookup_count: u32 = (lookups.len()).try_into().unwrap();
+        let array_size: u32 = (values.values.len()).try_into().unwrap();
+
+        // `Params` of the shader: the four field elements, the two counts and
+        // the padding to the 16 byte alignment of uniforms.
+        let mut params = as_bytes(&[y, beta, gamma, one]).to_vec();
+        params.extend_from_slice(&lookup_count.to_le_bytes());
+        params.extend_from_slice(&array_size.to_le_bytes());
+        params.resize((params.len() + 15) / 16 * 16, 0);
+
+        let wgsl_kernel_path =
+            env::var("WGSL_KERNEL").map_err(|_| EvalError::MissingKernelPath("WGSL_KERNEL"))?;
+        let src = fs::read_to_string(&wgsl_kernel_path).map_err(|err| {
+            EvalError::Wgpu(format!("cannot read '{}': {}", wgsl_kernel_path, err))
+        })?;
+        let mut interface = WgpuInterface::new(&src)?;
+
+        let block_1_start_timer = start_timer!(|| String::from("Lookups : Block 1"));
+
+        let mut buffers = LookupBuffers::new();
+        prepare_lookups(ev, data, lookups, 0, &mut buffers);
+
+        end_timer!(block_1_start_timer);
+
+        let block_2_start_timer = start_timer!(|| String::from("Lookups : Block 2"));
+
+        interface.add_allocation("values", &values.values);
+        interface.add_allocation("combined_data_in", &buffers.combined_data_in);
+        interface.add_allocation("product_coset", &buffers.product_coset);
+        interface.add_allocation("permuted_input_coset", &buffers.permuted_input_coset);
+        interface.add_allocation("permuted_table_coset", &buffers.permuted_table_coset);
+        interface.add_allocation("l0", &pk.l0.values);
+        interface.add_allocation("l_active_row", &pk.l_active_row.values);
+        interface.add_allocation("l_last", &pk.l_last.values);
+        interface.add_uniform("params", &params);
+
+        interface.launch_kernel(
+            "compute_evaluate_h_lookups_codeblock",
+            &[
+                "values",
+                "combined_data_in",
+                "product_coset",
+                "permuted_input_coset",
+                "permuted_table_coset",
+                "l0",
+                "l_active_row",
+                "l_last",
+                "params",
+            ],
+            values.len(),
+        )?;
+
+        interface.copy_vec_to_host("values", &mut values.values)?;
+
+        end_timer!(block_2_start_timer);
+
+        Ok(())
+    }
+}
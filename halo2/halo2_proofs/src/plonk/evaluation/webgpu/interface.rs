@@ -0,0 +1,185 @@
+use super::super::{as_bytes, as_bytes_mut, EvalError};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use wgpu::util::DeviceExt;
+
+/// Number of invocations per workgroup, `@workgroup_size` of the shaders
+const WORKGROUP_SIZE: usize = 64;
+
+/// A wgpu device and the buffers its compute shaders are dispatched with.
+///
+/// Buffers are addressed by name and hold raw bytes, like the CUDA
+/// `DriverInterface`, so host vectors are uploaded with their in-memory layout.
+pub(in crate::plonk) struct WgpuInterface {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    module: wgpu::ShaderModule,
+    buffers: HashMap<&'static str, wgpu::Buffer>,
+}
+
+impl WgpuInterface {
+    /// Compiles the WGSL `src` on the default high performance adapter
+    pub(in crate::plonk) fn new(src: &str) -> Result<Self, EvalError> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or_else(|| EvalError::Wgpu(String::from("no adapter found")))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                // The buffers are as large as the extended domain
+                limits: adapter.limits(),
+            },
+            None,
+        ))
+        .map_err(|err| EvalError::Wgpu(err.to_string()))?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(src.into()),
+        });
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            return Err(EvalError::Wgpu(err.to_string()));
+        }
+
+        Ok(Self {
+            device,
+            queue,
+            module,
+            buffers: HashMap::new(),
+        })
+    }
+
+    /// Allocates a storage buffer named `name` holding a copy of `data`
+    pub(in crate::plonk) fn add_allocation<T: Copy>(&mut self, name: &'static str, data: &[T]) {
+        self.add_buffer(
+            name,
+            as_bytes(data),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+    }
+
+    /// Allocates a uniform buffer named `name` holding `bytes`
+    pub(in crate::plonk) fn add_uniform(&mut self, name: &'static str, bytes: &[u8]) {
+        self.add_buffer(name, bytes, wgpu::BufferUsages::UNIFORM);
+    }
+
+    fn add_buffer(&mut self, name: &'static str, contents: &[u8], usage: wgpu::BufferUsages) {
+        let buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(name),
+                contents,
+                usage,
+            });
+        self.buffers.insert(name, buffer);
+    }
+
+    /// Dispatches the entry point `kernel` over `invocations` invocations,
+    /// binding the named buffers to group 0 in order
+    pub(in crate::plonk) fn launch_kernel(
+        &self,
+        kernel: &str,
+        buffers: &[&'static str],
+        invocations: usize,
+    ) -> Result<(), EvalError> {
+        let entries = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, &name)| {
+                Ok(wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: self.buffer(name)?.as_entire_binding(),
+                })
+            })
+            .collect::<Result<Vec<_>, EvalError>>()?;
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(kernel),
+                layout: None,
+                module: &self.module,
+                entry_point: kernel,
+            });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        // Workgroups beyond the per dimension limit are laid out in rows.
+        let workgroups = (invocations + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+        let max_workgroups = self.device.limits().max_compute_workgroups_per_dimension as usize;
+        let x = workgroups.min(max_workgroups);
+        let y = (workgroups + x - 1) / x;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(x as u32, y as u32, 1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        let validation_error = pollster::block_on(self.device.pop_error_scope());
+        let out_of_memory_error = pollster::block_on(self.device.pop_error_scope());
+        match validation_error.or(out_of_memory_error) {
+            Some(err) => Err(EvalError::Wgpu(err.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Copies the buffer named `name` back into `data`
+    pub(in crate::plonk) fn copy_vec_to_host<T: Copy>(
+        &self,
+        name: &'static str,
+        data: &mut [T],
+    ) -> Result<(), EvalError> {
+        let bytes = as_bytes_mut(data);
+        let size = bytes.len() as wgpu::BufferAddress;
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(self.buffer(name)?, 0, &staging, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|err| EvalError::Wgpu(err.to_string()))?
+            .map_err(|err| EvalError::Wgpu(err.to_string()))?;
+
+        bytes.copy_from_slice(&slice.get_mapped_range());
+        staging.unmap();
+        Ok(())
+    }
+
+    fn buffer(&self, name: &'static str) -> Result<&wgpu::Buffer, EvalError> {
+        self.buffers
+            .get(name)
+            .ok_or_else(|| EvalError::Wgpu(format!("no buffer named '{}'", name)))
+    }
+}
@@ -255,6 +255,25 @@ pub fn keygen_pk<'params, C, P, ConcreteCircuit>(
     vk: VerifyingKey<C>,
     circuit: &ConcreteCircuit,
 ) -> Result<ProvingKey<C>, Error>
+where
+    C: CurveAffine,
+    P: Params<'params, C>,
+    ConcreteCircuit: Circuit<C::Scalar>,
+{
+    // Compute the optimized evaluation data structure
+    let ev = Evaluator::new(&vk.cs);
+    keygen_pk_with_evaluator(params, vk, ev, circuit)
+}
+
+/// Generate a `ProvingKey` from a `VerifyingKey`, an `Evaluator` and an
+/// instance of `Circuit`, for an evaluator precomputed for the constraint
+/// system of `vk` and read with [`Evaluator::read`] rather than rebuilt.
+pub fn keygen_pk_with_evaluator<'params, C, P, ConcreteCircuit>(
+    params: &P,
+    vk: VerifyingKey<C>,
+    ev: Evaluator<C>,
+    circuit: &ConcreteCircuit,
+) -> Result<ProvingKey<C>, Error>
 where
     C: CurveAffine,
     P: Params<'params, C>,
@@ -341,9 +360,6 @@ where
         }
     });
 
-    // Compute the optimized evaluation data structure
-    let ev = Evaluator::new(&vk.cs);
-
     Ok(ProvingKey {
         vk,
         l0,
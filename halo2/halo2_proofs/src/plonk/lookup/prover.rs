@@ -3,7 +3,6 @@ use super::super::{
     ProvingKey,
 };
 use super::Argument;
-use crate::plonk::evaluation::evaluate;
 use crate::{
     arithmetic::{eval_polynomial, parallelize, CurveAffine, FieldExt},
     poly::{
@@ -47,6 +46,24 @@ pub(in crate::plonk) struct Committed<C: CurveAffine> {
     product_blind: Blind<C::Scalar>,
 }
 
+impl<C: CurveAffine> Committed<C> {
+    /// Builds a committed lookup from its polynomials, with default blinds
+    pub(in crate::plonk) fn from_polys(
+        permuted_input_poly: Polynomial<C::Scalar, Coeff>,
+        permuted_table_poly: Polynomial<C::Scalar, Coeff>,
+        product_poly: Polynomial<C::Scalar, Coeff>,
+    ) -> Self {
+        Committed {
+            permuted_input_poly,
+            permuted_input_blind: Blind::default(),
+            permuted_table_poly,
+            permuted_table_blind: Blind::default(),
+            product_poly,
+            product_blind: Blind::default(),
+        }
+    }
+}
+
 pub(in crate::plonk) struct Evaluated<C: CurveAffine> {
     constructed: Committed<C>,
 }
@@ -88,10 +105,10 @@ impl<F: FieldExt> Argument<F> {
     {
         // Closure to get values of expressions and compress them
         let compress_expressions = |expressions: &[Expression<C::Scalar>]| {
-            let compressed_expression = expressions
-                .iter()
-                .map(|expression| {
-                    pk.vk.domain.lagrange_from_vec(evaluate(
+            expressions.iter().try_fold(
+                domain.empty_lagrange(),
+                |acc, expression| -> Result<_, Error> {
+                    let values = pk.ev.evaluate_expression(
                         expression,
                         params.n() as usize,
                         1,
@@ -99,19 +116,17 @@ impl<F: FieldExt> Argument<F> {
                         advice_values,
                         instance_values,
                         challenges,
-                    ))
-                })
-                .fold(domain.empty_lagrange(), |acc, expression| {
-                    acc * *theta + &expression
-                });
-            compressed_expression
+                    )?;
+                    Ok(acc * *theta + &pk.vk.domain.lagrange_from_vec(values))
+                },
+            )
         };
 
         // Get values of input expressions involved in the lookup and compress them
-        let compressed_input_expression = compress_expressions(&self.input_expressions);
+        let compressed_input_expression = compress_expressions(&self.input_expressions)?;
 
         // Get values of table expressions involved in the lookup and compress them
-        let compressed_table_expression = compress_expressions(&self.table_expressions);
+        let compressed_table_expression = compress_expressions(&self.table_expressions)?;
 
         // Permute compressed (InputExpression, TableExpression) pair
         let (permuted_input_expression, permuted_table_expression) = permute_expression_pair(
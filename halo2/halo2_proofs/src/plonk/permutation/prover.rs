@@ -24,6 +24,20 @@ pub(crate) struct CommittedSet<C: CurveAffine> {
     permutation_product_blind: Blind<C::Scalar>,
 }
 
+impl<C: CurveAffine> CommittedSet<C> {
+    /// Builds a committed set from its product polynomial, with a default blind
+    pub(crate) fn from_poly(
+        domain: &poly::EvaluationDomain<C::Scalar>,
+        permutation_product_poly: Polynomial<C::Scalar, Coeff>,
+    ) -> Self {
+        CommittedSet {
+            permutation_product_coset: domain.coeff_to_extended(permutation_product_poly.clone()),
+            permutation_product_poly,
+            permutation_product_blind: Blind::default(),
+        }
+    }
+}
+
 pub(crate) struct Committed<C: CurveAffine> {
     pub(crate) sets: Vec<CommittedSet<C>>,
 }
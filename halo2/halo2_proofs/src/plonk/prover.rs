@@ -15,6 +15,7 @@ use super::{
         Advice, Any, Assignment, Challenge, Circuit, Column, ConstraintSystem, FirstPhase, Fixed,
         FloorPlanner, Instance, Selector,
     },
+    evaluation::{CancellationToken, EvalControl, EvalScratch, Progress},
     lookup, permutation, vanishing, ChallengeBeta, ChallengeGamma, ChallengeTheta, ChallengeX,
     ChallengeY, Error, Expression, ProvingKey,
 };
@@ -46,6 +47,61 @@ pub fn create_proof<
     R: RngCore,
     T: TranscriptWrite<Scheme::Curve, E>,
     ConcreteCircuit: Circuit<Scheme::Scalar>,
+>(
+    params: &'params Scheme::ParamsProver,
+    pk: &ProvingKey<Scheme::Curve>,
+    circuits: &[ConcreteCircuit],
+    instances: &[&[&[Scheme::Scalar]]],
+    rng: R,
+    transcript: &mut T,
+) -> Result<(), Error> {
+    create_proof_with_cancellation::<Scheme, P, _, _, _, _>(
+        params,
+        pk,
+        circuits,
+        instances,
+        rng,
+        transcript,
+        &CancellationToken::default(),
+    )
+}
+
+/// [`create_proof`], failing with `Error::Evaluation(EvalError::Cancelled)`
+/// if `cancel` is cancelled while h(X) is evaluated, e.g. by a server whose
+/// client gave up, to release the evaluation backend without waiting for it.
+pub fn create_proof_with_cancellation<
+    'params,
+    Scheme: CommitmentScheme,
+    P: Prover<'params, Scheme>,
+    E: EncodedChallenge<Scheme::Curve>,
+    R: RngCore,
+    T: TranscriptWrite<Scheme::Curve, E>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
+>(
+    params: &'params Scheme::ParamsProver,
+    pk: &ProvingKey<Scheme::Curve>,
+    circuits: &[ConcreteCircuit],
+    instances: &[&[&[Scheme::Scalar]]],
+    rng: R,
+    transcript: &mut T,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    create_proof_with_progress::<Scheme, P, _, _, _, _>(
+        params, pk, circuits, instances, rng, transcript, cancel, None,
+    )
+}
+
+/// [`create_proof_with_cancellation`], reporting the progress of the
+/// evaluation of h(X) to `progress`, if any, before each of its phases. It is
+/// only called between the parallel loops of the evaluation, never from them.
+pub fn create_proof_with_progress<
+    'params,
+    Scheme: CommitmentScheme,
+    P: Prover<'params, Scheme>,
+    E: EncodedChallenge<Scheme::Curve>,
+    R: RngCore,
+    T: TranscriptWrite<Scheme::Curve, E>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
 >(
     params: &'params Scheme::ParamsProver,
     pk: &ProvingKey<Scheme::Curve>,
@@ -53,6 +109,8 @@ pub fn create_proof<
     instances: &[&[&[Scheme::Scalar]]],
     mut rng: R,
     transcript: &mut T,
+    cancel: &CancellationToken,
+    progress: Option<&mut (dyn FnMut(Progress) + Send)>,
 ) -> Result<(), Error> {
     for instance in instances.iter() {
         if instance.len() != pk.vk.cs.num_instance_columns {
@@ -478,28 +536,72 @@ pub fn create_proof<
         )
         .collect();
 
-    // Evaluate the h(X) polynomial
-    let h_poly = pk.ev.evaluate_h(
-        pk,
-        &advice
-            .iter()
-            .map(|a| a.advice_polys.as_slice())
-            .collect::<Vec<_>>(),
-        &instance
-            .iter()
-            .map(|i| i.instance_polys.as_slice())
-            .collect::<Vec<_>>(),
-        &challenges,
-        *y,
-        *beta,
-        *gamma,
-        *theta,
-        &lookups,
-        &permutations,
+    // Evaluate the h(X) polynomial, into the buffer of the previous proof on
+    // this thread if the scratch is kept
+    let mut control = EvalControl::new(cancel, progress);
+    let evaluate_h = |scratch: &mut EvalScratch<Scheme::Scalar>| {
+        let mut h_poly = scratch.take_h_buffer(domain);
+        match (&advice[..], &instance[..], &lookups[..], &permutations[..]) {
+            ([advice], [instance], [lookups], [permutation]) => pk.ev.evaluate_h_single(
+                &mut h_poly,
+                pk,
+                &advice.advice_polys,
+                &instance.instance_polys,
+                &challenges,
+                *y,
+                *beta,
+                *gamma,
+                *theta,
+                lookups,
+                permutation,
+                scratch,
+                &mut control,
+            ),
+            _ => pk.ev.evaluate_h_into(
+                &mut h_poly,
+                pk,
+                &advice
+                    .iter()
+                    .map(|a| a.advice_polys.as_slice())
+                    .collect::<Vec<_>>(),
+                &instance
+                    .iter()
+                    .map(|i| i.instance_polys.as_slice())
+                    .collect::<Vec<_>>(),
+                &challenges,
+                *y,
+                *beta,
+                *gamma,
+                *theta,
+                &lookups,
+                &permutations,
+                scratch,
+                &mut control,
+            ),
+        }
+        .map(|context| (h_poly, context))
+    };
+    let (h_poly, context) = match pk.ev.config.keep_scratch {
+        true => EvalScratch::with_thread_scratch(evaluate_h),
+        false => evaluate_h(&mut EvalScratch::default()),
+    }?;
+    assert!(
+        context.matches(domain),
+        "h(X) is evaluated on another extended domain than the one it is divided on"
     );
 
     // Construct the vanishing argument's h(X) commitments
-    let vanishing = vanishing.construct(params, domain, h_poly, &mut rng, transcript)?;
+    let (vanishing, h_buffer) = vanishing.construct(
+        params,
+        domain,
+        h_poly,
+        pk.ev.config.keep_scratch,
+        &mut rng,
+        transcript,
+    )?;
+    if let Some(h_buffer) = h_buffer {
+        EvalScratch::with_thread_scratch(|scratch| scratch.h_buffer = Some(h_buffer));
+    }
 
     let x: ChallengeX<_> = transcript.squeeze_challenge_scalar();
     let xn = x.pow(&[params.n() as u64, 0, 0, 0]);
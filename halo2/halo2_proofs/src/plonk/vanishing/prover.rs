@@ -66,6 +66,9 @@ impl<C: CurveAffine> Argument<C> {
 }
 
 impl<C: CurveAffine> Committed<C> {
+    /// Divides `h_poly` by the vanishing polynomial and commits to its pieces,
+    /// giving back its buffer for another h(X) to be evaluated into if
+    /// `keep_buffer`, and dropping it as soon as it is split otherwise
     pub(in crate::plonk) fn construct<
         'params,
         P: ParamsProver<'params, C>,
@@ -77,9 +80,10 @@ impl<C: CurveAffine> Committed<C> {
         params: &P,
         domain: &EvaluationDomain<C::Scalar>,
         h_poly: Polynomial<C::Scalar, ExtendedLagrangeCoeff>,
+        keep_buffer: bool,
         mut rng: R,
         transcript: &mut T,
-    ) -> Result<Constructed<C>, Error> {
+    ) -> Result<(Constructed<C>, Option<Vec<C::Scalar>>), Error> {
         // Divide by t(X) = X^{params.n} - 1.
         let h_poly = domain.divide_by_vanishing_poly(h_poly);
 
@@ -91,7 +95,13 @@ impl<C: CurveAffine> Committed<C> {
             .chunks_exact(params.n() as usize)
             .map(|v| domain.coeff_from_vec(v.to_vec()))
             .collect::<Vec<_>>();
-        drop(h_poly);
+        let h_buffer = match keep_buffer {
+            true => Some(h_poly),
+            false => {
+                drop(h_poly);
+                None
+            }
+        };
         let h_blinds: Vec<_> = h_pieces
             .iter()
             .map(|_| Blind(C::Scalar::random(&mut rng)))
@@ -112,11 +122,14 @@ impl<C: CurveAffine> Committed<C> {
             transcript.write_point(*c)?;
         }
 
-        Ok(Constructed {
-            h_pieces,
-            h_blinds,
-            committed: self,
-        })
+        Ok((
+            Constructed {
+                h_pieces,
+                h_blinds,
+                committed: self,
+            },
+            h_buffer,
+        ))
     }
 }
 
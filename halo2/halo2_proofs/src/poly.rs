@@ -14,6 +14,9 @@ use std::ops::{Add, Deref, DerefMut, Index, IndexMut, Mul, RangeFrom, RangeFull,
 /// Generic commitment scheme structures
 pub mod commitment;
 mod domain;
+#[cfg(unix)]
+#[allow(unsafe_code)]
+mod mapped;
 mod query;
 mod strategy;
 
@@ -27,6 +30,8 @@ pub mod kzg;
 mod multiopen_test;
 
 pub use domain::*;
+#[cfg(unix)]
+pub use mapped::MappedAllocator;
 pub use query::{ProverQuery, VerifierQuery};
 pub use strategy::{Guard, VerificationStrategy};
 
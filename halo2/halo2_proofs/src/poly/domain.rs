@@ -9,6 +9,7 @@ use crate::{
 use super::{Coeff, ExtendedLagrangeCoeff, LagrangeCoeff, Polynomial, Rotation};
 
 use group::ff::{BatchInvert, Field, PrimeField};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use std::marker::PhantomData;
 
@@ -153,6 +154,18 @@ impl<G: Group> EvaluationDomain<G> {
         }
     }
 
+    /// Obtains a polynomial in the extended Lagrange coefficient basis when
+    /// given a vector of its values on the extended domain; panics if the
+    /// provided vector is the wrong length.
+    pub(crate) fn extended_from_vec(&self, values: Vec<G>) -> Polynomial<G, ExtendedLagrangeCoeff> {
+        assert_eq!(values.len(), self.extended_len());
+
+        Polynomial {
+            values,
+            _marker: PhantomData,
+        }
+    }
+
     /// Obtains a polynomial in coefficient form when given a vector of
     /// coefficients of size `n`; panics if the provided vector is the wrong
     /// length.
@@ -210,6 +223,20 @@ impl<G: Group> EvaluationDomain<G> {
         }
     }
 
+    /// Returns an empty (zero) polynomial in the extended Lagrange coefficient
+    /// basis, zeroed by the threads of the current pool, each writing the
+    /// memory of a contiguous range of rows first, so that the operating
+    /// system places its pages on the NUMA node of that thread
+    pub(crate) fn empty_extended_parallel(&self) -> Polynomial<G, ExtendedLagrangeCoeff> {
+        Polynomial {
+            values: (0..self.extended_len())
+                .into_par_iter()
+                .map(|_| G::group_zero())
+                .collect(),
+            _marker: PhantomData,
+        }
+    }
+
     /// Returns a constant polynomial in the extended Lagrange coefficient
     /// basis
     pub fn constant_extended(&self, scalar: G) -> Polynomial<G, ExtendedLagrangeCoeff> {
@@ -253,6 +280,22 @@ impl<G: Group> EvaluationDomain<G> {
         }
     }
 
+    /// Like [`EvaluationDomain::coeff_to_extended`], writing the result into
+    /// `out` so as to reuse its allocation.
+    pub fn coeff_to_extended_into(
+        &self,
+        a: &Polynomial<G, Coeff>,
+        out: &mut Polynomial<G, ExtendedLagrangeCoeff>,
+    ) {
+        assert_eq!(a.values.len(), 1 << self.k);
+
+        out.values.clear();
+        out.values.extend_from_slice(&a.values);
+        self.distribute_powers_zeta(&mut out.values, true);
+        out.values.resize(self.extended_len(), G::group_zero());
+        best_fft(&mut out.values, self.extended_omega, self.extended_k);
+    }
+
     /// Rotate the extended domain polynomial over the original domain.
     pub fn rotate_extended(
         &self,
@@ -391,6 +434,15 @@ impl<G: Group> EvaluationDomain<G> {
         self.extended_omega
     }
 
+    /// Get the inverses of the vanishing polynomial $t(X) = X^n - 1$ on the
+    /// extended coset, which `divide_by_vanishing_poly` scales by. They are
+    /// computed once with the domain. The evaluations of $t(X)$ repeat with a
+    /// period of $2^{extended_k - k}$ rows, so row $i$ of the extended domain
+    /// uses the inverse at $i \bmod 2^{extended_k - k}$.
+    pub fn vanishing_inverses(&self) -> &[G::Scalar] {
+        &self.t_evaluations
+    }
+
     /// Multiplies a value by some power of $\omega$, essentially rotating over
     /// the domain.
     pub fn rotate_omega(&self, value: G::Scalar, rotation: Rotation) -> G::Scalar {
@@ -555,3 +607,46 @@ fn test_l_i() {
         assert_eq!(eval_polynomial(&l[(8 - i) % 8][..], x), evaluations[7 - i]);
     }
 }
+
+#[test]
+fn test_coeff_to_extended_into() {
+    use rand_core::OsRng;
+
+    use halo2curves::pasta::pallas::Scalar;
+
+    let domain = EvaluationDomain::<Scalar>::new(3, 3);
+    let rng = OsRng;
+
+    let mut poly = domain.empty_coeff();
+    for value in poly.iter_mut() {
+        *value = Scalar::random(rng);
+    }
+
+    // Into a buffer of another size, as well as one already holding a coset
+    let mut out = domain.empty_extended();
+    out.values.truncate(3);
+    domain.coeff_to_extended_into(&poly, &mut out);
+    assert_eq!(out.values, domain.coeff_to_extended(poly.clone()).values);
+    domain.coeff_to_extended_into(&poly, &mut out);
+    assert_eq!(out.values, domain.coeff_to_extended(poly).values);
+}
+
+#[test]
+fn test_vanishing_inverses() {
+    use halo2curves::pasta::pallas::Scalar;
+
+    let domain = EvaluationDomain::<Scalar>::new(3, 3);
+    let inverses = domain.vanishing_inverses();
+    assert_eq!(inverses.len(), 1 << (domain.extended_k() - domain.k()));
+
+    // t(zeta * extended_omega^i)^-1, from the point of each row
+    let mut point = Scalar::ZETA;
+    for row in 0..domain.extended_len() {
+        let t = point.pow_vartime(&[1 << domain.k(), 0, 0, 0]) - Scalar::one();
+        assert_eq!(inverses[row % inverses.len()], t.invert().unwrap());
+        point *= domain.get_extended_omega();
+    }
+
+    // The same table on every call
+    assert_eq!(domain.vanishing_inverses().as_ptr(), inverses.as_ptr());
+}
@@ -0,0 +1,268 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::process;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const MAP_SHARED: i32 = 1;
+
+/// The alignment of the pages `mmap` returns, the smallest page size of the
+/// platforms it runs on
+const PAGE_SIZE: usize = 4096;
+
+extern "C" {
+    fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+    fn munmap(addr: *mut u8, len: usize) -> i32;
+}
+
+/// The files of the mappings made so far by the process, to name the next one
+static MAPPINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// An allocator backing the allocations of at least `min_bytes` bytes, such as
+/// the values of the polynomials over the extended domain returned by
+/// `EvaluationDomain::empty_extended` and the cosets of the evaluation of h(X),
+/// with memory-mapped files rather than with memory, so that the operating
+/// system writes their pages back to the files and reads them again as they
+/// are touched, when they do not all fit in memory. The other allocations are
+/// made by the system allocator.
+///
+/// A polynomial keeps its `Vec` of values, so that indexing it, `parallelize`
+/// and `chunks_mut` over its values, and the uploads of the device backends
+/// read and write the mapping as they would any memory. It is installed as the
+/// allocator of the prover:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: MappedAllocator = MappedAllocator::new(1 << 30);
+/// ```
+///
+/// Each file is created in the directory `POLY_MMAP_DIR` names, or in the
+/// temporary directory of the system without it, unless [`Self::in_dir`]
+/// names one, and is removed as soon as it is mapped, so that the files do not
+/// outlive the process. Allocating fails, and aborts the process as any failed
+/// allocation does, when the file cannot be created or mapped.
+#[derive(Debug)]
+pub struct MappedAllocator {
+    min_bytes: usize,
+    dir: Option<&'static str>,
+    mapped_bytes: AtomicUsize,
+}
+
+impl MappedAllocator {
+    /// Returns an allocator mapping files for the allocations of at least
+    /// `min_bytes` bytes
+    pub const fn new(min_bytes: usize) -> Self {
+        MappedAllocator {
+            min_bytes,
+            dir: None,
+            mapped_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Creates the files in `dir` rather than in the directory of
+    /// `POLY_MMAP_DIR`
+    pub const fn in_dir(self, dir: &'static str) -> Self {
+        MappedAllocator {
+            dir: Some(dir),
+            ..self
+        }
+    }
+
+    /// Returns the bytes of the allocations currently backed by files
+    pub fn mapped_bytes(&self) -> usize {
+        self.mapped_bytes.load(Ordering::Relaxed)
+    }
+
+    fn is_mapped(&self, size: usize) -> bool {
+        size >= self.min_bytes.max(1)
+    }
+
+    fn dir(&self) -> PathBuf {
+        match self.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => env::var_os("POLY_MMAP_DIR").map_or_else(env::temp_dir, PathBuf::from),
+        }
+    }
+
+    /// Maps a new file of `size` bytes, which are zero, or returns null
+    fn map(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > PAGE_SIZE {
+            return ptr::null_mut();
+        }
+        let path = self.dir().join(format!(
+            "halo2-poly-{}-{}",
+            process::id(),
+            MAPPINGS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => file,
+            Err(_) => return ptr::null_mut(),
+        };
+        let _ = fs::remove_file(&path);
+        if file.set_len(layout.size() as u64).is_err() {
+            return ptr::null_mut();
+        }
+        // The mapping keeps the file alive once it is closed and removed
+        let mapping = unsafe {
+            mmap(
+                ptr::null_mut(),
+                layout.size(),
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if mapping as usize == usize::MAX {
+            return ptr::null_mut();
+        }
+        self.mapped_bytes
+            .fetch_add(layout.size(), Ordering::Relaxed);
+        mapping
+    }
+}
+
+unsafe impl GlobalAlloc for MappedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.is_mapped(layout.size()) {
+            self.map(layout)
+        } else {
+            System.alloc(layout)
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if self.is_mapped(layout.size()) {
+            // The pages of a new file are zero
+            self.map(layout)
+        } else {
+            System.alloc_zeroed(layout)
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !self.is_mapped(layout.size()) && !self.is_mapped(new_size) {
+            return System.realloc(ptr, layout, new_size);
+        }
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if self.is_mapped(layout.size()) {
+            munmap(ptr, layout.size());
+            self.mapped_bytes
+                .fetch_sub(layout.size(), Ordering::Relaxed);
+        } else {
+            System.dealloc(ptr, layout)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MappedAllocator;
+    use std::alloc::{GlobalAlloc, Layout};
+    use std::env;
+    use std::fs;
+
+    /// Returns an allocator mapping the allocations of a page or more in a
+    /// directory of its own, named after `name`
+    fn allocator(name: &str) -> (MappedAllocator, &'static str) {
+        let dir = env::temp_dir().join(format!("halo2-mapped-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir: &'static str = Box::leak(dir.to_str().unwrap().to_owned().into_boxed_str());
+        (MappedAllocator::new(4096).in_dir(dir), dir)
+    }
+
+    #[test]
+    fn large_allocations_are_mapped_files_removed_once_mapped() {
+        let (allocator, dir) = allocator("large");
+        let layout = Layout::from_size_align(3 << 20, 32).unwrap();
+        unsafe {
+            let ptr = allocator.alloc_zeroed(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 4096, 0);
+            assert_eq!(allocator.mapped_bytes(), 3 << 20);
+            assert!(fs::read_dir(dir).unwrap().next().is_none());
+
+            let values = std::slice::from_raw_parts_mut(ptr, layout.size());
+            assert!(values.iter().all(|value| *value == 0));
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = i as u8;
+            }
+            assert!(values
+                .iter()
+                .enumerate()
+                .all(|(i, value)| *value == i as u8));
+
+            allocator.dealloc(ptr, layout);
+        }
+        assert_eq!(allocator.mapped_bytes(), 0);
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn small_allocations_stay_in_memory() {
+        let (allocator, dir) = allocator("small");
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(allocator.mapped_bytes(), 0);
+            allocator.dealloc(ptr, layout);
+        }
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn growing_into_a_mapping_keeps_the_values() {
+        let (allocator, dir) = allocator("grow");
+        let small = Layout::from_size_align(1024, 8).unwrap();
+        unsafe {
+            let ptr = allocator.alloc(small);
+            for i in 0..small.size() {
+                *ptr.add(i) = i as u8;
+            }
+
+            // Into a mapping, then a larger one, then back into memory
+            let mut layout = small;
+            let mut ptr = ptr;
+            for &size in &[8192, 1 << 20, 512] {
+                ptr = allocator.realloc(ptr, layout, size);
+                assert!(!ptr.is_null());
+                layout = Layout::from_size_align(size, 8).unwrap();
+                let kept = size.min(small.size());
+                assert!((0..kept).all(|i| *ptr.add(i) == i as u8));
+            }
+            assert_eq!(allocator.mapped_bytes(), 0);
+            allocator.dealloc(ptr, layout);
+        }
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn an_unusable_directory_fails_the_allocation() {
+        let allocator = MappedAllocator::new(4096).in_dir("/nonexistent/halo2-mapped");
+        let layout = Layout::from_size_align(8192, 8).unwrap();
+        unsafe {
+            assert!(allocator.alloc(layout).is_null());
+        }
+        assert_eq!(allocator.mapped_bytes(), 0);
+    }
+}
@@ -0,0 +1,227 @@
+// OpenCL port of cuda-kernel-src/evaluate_h.cu.
+//
+// Field elements are 4 little-endian 64-bit limbs in Montgomery form, exactly
+// as they are laid out in host memory.
+
+typedef struct Fr
+{
+    ulong v[4];
+} Fr;
+
+// BN256 scalar field
+__constant ulong FR_MODULUS[4] = {
+    0x43e1f593f0000001UL,
+    0x2833e84879b97091UL,
+    0xb85045b68181585dUL,
+    0x30644e72e131a029UL};
+#define FR_INV 0xc2e1f593efffffffUL
+
+/// Compute a + b + carry, returning the result and the new carry over.
+inline ulong adc(ulong a, ulong b, ulong *carry)
+{
+    const ulong sum = a + b;
+    const ulong result = sum + *carry;
+    *carry = (ulong)(sum < a) + (ulong)(result < sum);
+    return result;
+}
+
+/// Compute a - (b + borrow), returning the result and the new borrow.
+inline ulong sbb(ulong a, ulong b, ulong *borrow)
+{
+    const ulong diff = a - b;
+    const ulong result = diff - *borrow;
+    *borrow = (ulong)(a < b) + (ulong)(diff < *borrow);
+    return result;
+}
+
+/// Compute a + (b * c) + carry, returning the result and the new carry over.
+inline ulong mac(ulong a, ulong b, ulong c, ulong *carry)
+{
+    ulong lo = b * c;
+    ulong hi = mul_hi(b, c);
+    lo += a;
+    hi += (ulong)(lo < a);
+    lo += *carry;
+    hi += (ulong)(lo < *carry);
+    *carry = hi;
+    return lo;
+}
+
+/// Subtracts the modulus if the borrow of `r - MODULUS` shows `r` >= MODULUS.
+inline Fr fr_reduce_once(ulong r0, ulong r1, ulong r2, ulong r3, ulong carry)
+{
+    Fr d;
+    ulong borrow = 0;
+    d.v[0] = sbb(r0, FR_MODULUS[0], &borrow);
+    d.v[1] = sbb(r1, FR_MODULUS[1], &borrow);
+    d.v[2] = sbb(r2, FR_MODULUS[2], &borrow);
+    d.v[3] = sbb(r3, FR_MODULUS[3], &borrow);
+    sbb(carry, 0, &borrow);
+
+    // If underflow occurred, add the modulus back.
+    const ulong mask = 0 - borrow;
+    ulong c = 0;
+    d.v[0] = adc(d.v[0], FR_MODULUS[0] & mask, &c);
+    d.v[1] = adc(d.v[1], FR_MODULUS[1] & mask, &c);
+    d.v[2] = adc(d.v[2], FR_MODULUS[2] & mask, &c);
+    d.v[3] = adc(d.v[3], FR_MODULUS[3] & mask, &c);
+    return d;
+}
+
+// The Montgomery reduction here is based on Algorithm 14.32 in
+// Handbook of Applied Cryptography
+// <http://cacr.uwaterloo.ca/hac/about/chap14.pdf>.
+inline Fr fr_montgomery_reduce(ulong r[8])
+{
+    ulong carry2 = 0;
+    for (int i = 0; i < 4; i++)
+    {
+        const ulong k = r[i] * FR_INV;
+        ulong carry = 0;
+        mac(r[i], k, FR_MODULUS[0], &carry);
+        for (int j = 1; j < 4; j++)
+        {
+            r[i + j] = mac(r[i + j], k, FR_MODULUS[j], &carry);
+        }
+        r[i + 4] = adc(r[i + 4], carry2, &carry);
+        carry2 = carry;
+    }
+
+    // Result may be within MODULUS of the correct value
+    return fr_reduce_once(r[4], r[5], r[6], r[7], carry2);
+}
+
+inline Fr fr_add(Fr a, Fr b)
+{
+    ulong carry = 0;
+    const ulong r0 = adc(a.v[0], b.v[0], &carry);
+    const ulong r1 = adc(a.v[1], b.v[1], &carry);
+    const ulong r2 = adc(a.v[2], b.v[2], &carry);
+    const ulong r3 = adc(a.v[3], b.v[3], &carry);
+
+    // Attempt to subtract the modulus, to ensure the value
+    // is smaller than the modulus.
+    return fr_reduce_once(r0, r1, r2, r3, carry);
+}
+
+inline Fr fr_sub(Fr a, Fr b)
+{
+    Fr d;
+    ulong borrow = 0;
+    d.v[0] = sbb(a.v[0], b.v[0], &borrow);
+    d.v[1] = sbb(a.v[1], b.v[1], &borrow);
+    d.v[2] = sbb(a.v[2], b.v[2], &borrow);
+    d.v[3] = sbb(a.v[3], b.v[3], &borrow);
+
+    // If underflow occurred on the final limb, add the modulus back.
+    const ulong mask = 0 - borrow;
+    ulong carry = 0;
+    d.v[0] = adc(d.v[0], FR_MODULUS[0] & mask, &carry);
+    d.v[1] = adc(d.v[1], FR_MODULUS[1] & mask, &carry);
+    d.v[2] = adc(d.v[2], FR_MODULUS[2] & mask, &carry);
+    d.v[3] = adc(d.v[3], FR_MODULUS[3] & mask, &carry);
+    return d;
+}
+
+// Schoolbook multiplication followed by a Montgomery reduction
+inline Fr fr_mul(Fr a, Fr b)
+{
+    ulong r[8] = {0, 0, 0, 0, 0, 0, 0, 0};
+    for (int i = 0; i < 4; i++)
+    {
+        ulong carry = 0;
+        for (int j = 0; j < 4; j++)
+        {
+            r[i + j] = mac(r[i + j], a.v[i], b.v[j], &carry);
+        }
+        r[i + 4] = carry;
+    }
+    return fr_montgomery_reduce(r);
+}
+
+typedef struct combined_t
+{
+    Fr table_value;
+    Fr a_minus;
+    ulong r_next;
+    ulong r_prev;
+} combined_t;
+
+__kernel void compute_evaluate_h_lookups_codeblock(
+    __global Fr *values,
+    __global const combined_t *combined_data_in,
+    __global const Fr *product_coset_list,
+    __global const Fr *permuted_input_coset_list,
+    __global const Fr *permuted_table_coset_list,
+    __global const Fr *l0,
+    __global const Fr *l_active_row,
+    __global const Fr *l_last,
+    __global const Fr *y_beta_gamma_one,
+    const int lookup_count,
+    const int array_size)
+{
+    const int thd_i = get_global_id(0);
+    if (thd_i >= array_size)
+    {
+        return;
+    }
+
+    const Fr y = y_beta_gamma_one[0];
+    const Fr beta = y_beta_gamma_one[1];
+    const Fr gamma = y_beta_gamma_one[2];
+    const Fr one = y_beta_gamma_one[3];
+
+    const Fr l0_ = l0[thd_i];
+    const Fr l_active_row_ = l_active_row[thd_i];
+    const Fr l_last_ = l_last[thd_i];
+
+    Fr value = values[thd_i];
+
+    for (int n = 0; n < lookup_count; n++)
+    {
+        const int list_offset = array_size * n;
+
+        const Fr table_value = combined_data_in[list_offset + thd_i].table_value;
+        const Fr a_minus_s = combined_data_in[list_offset + thd_i].a_minus;
+        const ulong r_next = combined_data_in[list_offset + thd_i].r_next;
+        const ulong r_prev = combined_data_in[list_offset + thd_i].r_prev;
+
+        __global const Fr *product_coset = &product_coset_list[list_offset];
+        __global const Fr *permuted_input_coset = &permuted_input_coset_list[list_offset];
+        __global const Fr *permuted_table_coset = &permuted_table_coset_list[list_offset];
+
+        const Fr z = product_coset[thd_i];
+
+        // l_0(X) * (1 - z(X)) = 0
+        value = fr_add(fr_mul(value, y), fr_mul(fr_sub(one, z), l0_));
+        // l_last(X) * (z(X)^2 - z(X)) = 0
+        value = fr_add(fr_mul(value, y), fr_mul(fr_sub(fr_mul(z, z), z), l_last_));
+        // (1 - (l_last(X) + l_blind(X))) * (
+        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta) (\theta^{m-1}
+        //     s_0(X) + ... + s_{m-1}(X) + \gamma)
+        // ) = 0
+        const Fr left = fr_mul(
+            fr_mul(product_coset[r_next], fr_add(permuted_input_coset[thd_i], beta)),
+            fr_add(permuted_table_coset[thd_i], gamma));
+        value = fr_add(
+            fr_mul(value, y),
+            fr_mul(fr_sub(left, fr_mul(z, table_value)), l_active_row_));
+        // Check that the first values in the permuted input expression and permuted
+        // fixed expression are the same.
+        // l_0(X) * (a'(X) - s'(X)) = 0
+        value = fr_add(fr_mul(value, y), fr_mul(a_minus_s, l0_));
+        // Check that each value in the permuted lookup input expression is either
+        // equal to the value above it, or the value at the same index in the
+        // permuted table expression.
+        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) =
+        // 0
+        value = fr_add(
+            fr_mul(value, y),
+            fr_mul(
+                fr_mul(a_minus_s, fr_sub(permuted_input_coset[thd_i], permuted_input_coset[r_prev])),
+                l_active_row_));
+    }
+
+    values[thd_i] = value;
+}
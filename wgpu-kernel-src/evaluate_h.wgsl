@@ -0,0 +1,266 @@
+// WGSL port of cuda-kernel-src/evaluate_h.cu.
+//
+// WGSL has no 64-bit integers, so field elements are 8 little-endian 32-bit
+// limbs in Montgomery form. This is the same byte layout as the 4 64-bit limbs
+// used on the host, and R = 2^256 is unchanged, so the results are
+// bit-identical to the CPU.
+
+struct Fr {
+    l: array<u32, 8>,
+}
+
+// The host `CombinedData`: row indices are 64-bit, only their low word is used.
+struct Combined {
+    table_value: Fr,
+    a_minus: Fr,
+    r_next: vec2<u32>,
+    r_prev: vec2<u32>,
+}
+
+// y, beta, gamma and one, two vec4 per field element to satisfy the uniform
+// layout rules.
+struct Params {
+    y_beta_gamma_one: array<vec4<u32>, 8>,
+    lookup_count: u32,
+    array_size: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> values: array<Fr>;
+@group(0) @binding(1) var<storage, read> combined_data_in: array<Combined>;
+@group(0) @binding(2) var<storage, read> product_coset_list: array<Fr>;
+@group(0) @binding(3) var<storage, read> permuted_input_coset_list: array<Fr>;
+@group(0) @binding(4) var<storage, read> permuted_table_coset_list: array<Fr>;
+@group(0) @binding(5) var<storage, read> l0: array<Fr>;
+@group(0) @binding(6) var<storage, read> l_active_row: array<Fr>;
+@group(0) @binding(7) var<storage, read> l_last: array<Fr>;
+@group(0) @binding(8) var<uniform> params: Params;
+
+// BN256 scalar field. Kept in private memory so it can be indexed dynamically.
+var<private> MODULUS: array<u32, 8> = array<u32, 8>(
+    0xf0000001u, 0x43e1f593u, 0x79b97091u, 0x2833e848u,
+    0x8181585du, 0xb85045b6u, 0xe131a029u, 0x30644e72u,
+);
+// -MODULUS^{-1} mod 2^32
+var<private> INV: u32 = 0xefffffffu;
+
+// Compute a * b, returning the low and the high word.
+fn mul_wide(a: u32, b: u32) -> vec2<u32> {
+    let a0 = a & 0xffffu;
+    let a1 = a >> 16u;
+    let b0 = b & 0xffffu;
+    let b1 = b >> 16u;
+
+    let p00 = a0 * b0;
+    let p01 = a0 * b1;
+    let p10 = a1 * b0;
+    let p11 = a1 * b1;
+
+    let mid = p01 + (p00 >> 16u);
+    let mid2 = p10 + (mid & 0xffffu);
+    let lo = (mid2 << 16u) | (p00 & 0xffffu);
+    let hi = p11 + (mid >> 16u) + (mid2 >> 16u);
+    return vec2<u32>(lo, hi);
+}
+
+// Compute a + b + carry, returning the result and the new carry over.
+fn adc(a: u32, b: u32, carry: u32) -> vec2<u32> {
+    let sum = a + b;
+    let result = sum + carry;
+    return vec2<u32>(result, select(0u, 1u, sum < a) + select(0u, 1u, result < sum));
+}
+
+// Compute a - (b + borrow), returning the result and the new borrow.
+fn sbb(a: u32, b: u32, borrow: u32) -> vec2<u32> {
+    let diff = a - b;
+    let result = diff - borrow;
+    return vec2<u32>(result, select(0u, 1u, a < b) + select(0u, 1u, diff < borrow));
+}
+
+// Compute a + (b * c) + carry, returning the result and the new carry over.
+fn mac(a: u32, b: u32, c: u32, carry: u32) -> vec2<u32> {
+    let wide = mul_wide(b, c);
+    let lo = wide.x + a;
+    let hi = wide.y + select(0u, 1u, lo < a);
+    let result = lo + carry;
+    return vec2<u32>(result, hi + select(0u, 1u, result < carry));
+}
+
+// Subtracts the modulus from `r` (with the extra top word `top`) unless that
+// underflows.
+fn reduce_once(value: array<u32, 8>, top: u32) -> Fr {
+    // Arrays are copied to function memory so they can be indexed dynamically.
+    var r = value;
+    var d: array<u32, 8>;
+    var borrow = 0u;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let t = sbb(r[i], MODULUS[i], borrow);
+        d[i] = t.x;
+        borrow = t.y;
+    }
+    borrow = sbb(top, 0u, borrow).y;
+
+    // If underflow occurred, add the modulus back.
+    let mask = 0u - borrow;
+    var carry = 0u;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let t = adc(d[i], MODULUS[i] & mask, carry);
+        d[i] = t.x;
+        carry = t.y;
+    }
+    return Fr(d);
+}
+
+fn fr_add(lhs: Fr, rhs: Fr) -> Fr {
+    var a = lhs;
+    var b = rhs;
+    var r: array<u32, 8>;
+    var carry = 0u;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let t = adc(a.l[i], b.l[i], carry);
+        r[i] = t.x;
+        carry = t.y;
+    }
+
+    // Attempt to subtract the modulus, to ensure the value
+    // is smaller than the modulus.
+    return reduce_once(r, carry);
+}
+
+fn fr_sub(lhs: Fr, rhs: Fr) -> Fr {
+    var a = lhs;
+    var b = rhs;
+    var d: array<u32, 8>;
+    var borrow = 0u;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let t = sbb(a.l[i], b.l[i], borrow);
+        d[i] = t.x;
+        borrow = t.y;
+    }
+
+    // If underflow occurred on the final limb, add the modulus back.
+    let mask = 0u - borrow;
+    var carry = 0u;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let t = adc(d[i], MODULUS[i] & mask, carry);
+        d[i] = t.x;
+        carry = t.y;
+    }
+    return Fr(d);
+}
+
+// Schoolbook multiplication followed by a Montgomery reduction, based on
+// Algorithm 14.32 in Handbook of Applied Cryptography
+// <http://cacr.uwaterloo.ca/hac/about/chap14.pdf>.
+fn fr_mul(lhs: Fr, rhs: Fr) -> Fr {
+    var a = lhs;
+    var b = rhs;
+    var r: array<u32, 16>;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        var carry = 0u;
+        for (var j = 0u; j < 8u; j = j + 1u) {
+            let t = mac(r[i + j], a.l[i], b.l[j], carry);
+            r[i + j] = t.x;
+            carry = t.y;
+        }
+        r[i + 8u] = carry;
+    }
+
+    var carry2 = 0u;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let k = r[i] * INV;
+        var carry = mac(r[i], k, MODULUS[0], 0u).y;
+        for (var j = 1u; j < 8u; j = j + 1u) {
+            let t = mac(r[i + j], k, MODULUS[j], carry);
+            r[i + j] = t.x;
+            carry = t.y;
+        }
+        let t = adc(r[i + 8u], carry2, carry);
+        r[i + 8u] = t.x;
+        carry2 = t.y;
+    }
+
+    // Result may be within MODULUS of the correct value
+    var high: array<u32, 8>;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        high[i] = r[i + 8u];
+    }
+    return reduce_once(high, carry2);
+}
+
+fn param(n: u32) -> Fr {
+    let lo = params.y_beta_gamma_one[2u * n];
+    let hi = params.y_beta_gamma_one[2u * n + 1u];
+    return Fr(array<u32, 8>(lo.x, lo.y, lo.z, lo.w, hi.x, hi.y, hi.z, hi.w));
+}
+
+@compute @workgroup_size(64)
+fn compute_evaluate_h_lookups_codeblock(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let thd_i = global_id.y * num_workgroups.x * 64u + global_id.x;
+    let array_size = params.array_size;
+    if (thd_i >= array_size) {
+        return;
+    }
+
+    let y = param(0u);
+    let beta = param(1u);
+    let gamma = param(2u);
+    let one = param(3u);
+
+    let l0_ = l0[thd_i];
+    let l_active_row_ = l_active_row[thd_i];
+    let l_last_ = l_last[thd_i];
+
+    var value = values[thd_i];
+
+    for (var n = 0u; n < params.lookup_count; n = n + 1u) {
+        let list_offset = array_size * n;
+
+        let combined = combined_data_in[list_offset + thd_i];
+        let table_value = combined.table_value;
+        let a_minus_s = combined.a_minus;
+        let r_next = list_offset + combined.r_next.x;
+        let r_prev = list_offset + combined.r_prev.x;
+
+        let z = product_coset_list[list_offset + thd_i];
+        let permuted_input = permuted_input_coset_list[list_offset + thd_i];
+        let permuted_table = permuted_table_coset_list[list_offset + thd_i];
+
+        // l_0(X) * (1 - z(X)) = 0
+        value = fr_add(fr_mul(value, y), fr_mul(fr_sub(one, z), l0_));
+        // l_last(X) * (z(X)^2 - z(X)) = 0
+        value = fr_add(fr_mul(value, y), fr_mul(fr_sub(fr_mul(z, z), z), l_last_));
+        // (1 - (l_last(X) + l_blind(X))) * (
+        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta) (\theta^{m-1}
+        //     s_0(X) + ... + s_{m-1}(X) + \gamma)
+        // ) = 0
+        let left = fr_mul(
+            fr_mul(product_coset_list[r_next], fr_add(permuted_input, beta)),
+            fr_add(permuted_table, gamma),
+        );
+        value = fr_add(
+            fr_mul(value, y),
+            fr_mul(fr_sub(left, fr_mul(z, table_value)), l_active_row_),
+        );
+        // Check that the first values in the permuted input expression and permuted
+        // fixed expression are the same.
+        // l_0(X) * (a'(X) - s'(X)) = 0
+        value = fr_add(fr_mul(value, y), fr_mul(a_minus_s, l0_));
+        // Check that each value in the permuted lookup input expression is either
+        // equal to the value above it, or the value at the same index in the
+        // permuted table expression.
+        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
+        value = fr_add(
+            fr_mul(value, y),
+            fr_mul(
+                fr_mul(a_minus_s, fr_sub(permuted_input, permuted_input_coset_list[r_prev])),
+                l_active_row_,
+            ),
+        );
+    }
+
+    values[thd_i] = value;
+}